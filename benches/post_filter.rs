@@ -195,6 +195,7 @@ fn seed_data(num: u64) -> (Vec<Post>, AHashSet<String>) {
             extension: Extension::guess_format(&ext),
             rating,
             tags,
+            frame_delays: None,
         };
 
         v2.push(pst)
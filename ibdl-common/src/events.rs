@@ -0,0 +1,239 @@
+//! Machine-readable events of a download, for scripts that drive the downloader
+//!
+//! # JSON events
+//! Once [`enable_json`] is called, every [`Event`] is written as a single line of JSON, with its
+//! name in the `event` field. Otherwise events are only counted, so a [`Summary`] of the run can
+//! still be built at the end.
+//!
+//! ```json
+//! {"event":"page_fetched","server":"danbooru","page":1,"posts":200}
+//! {"event":"download_finished","id":5689892,"file":"danbooru/kroos_(arknights)/6f4ba8c6.png"}
+//! ```
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+static SINK: OnceLock<Mutex<Box<dyn Write + Send>>> = OnceLock::new();
+
+static QUEUED: AtomicU64 = AtomicU64::new(0);
+static DOWNLOADED: AtomicU64 = AtomicU64::new(0);
+static SKIPPED: AtomicU64 = AtomicU64::new(0);
+static FAILED: AtomicU64 = AtomicU64::new(0);
+static REMOVED: AtomicU64 = AtomicU64::new(0);
+static FAILED_JOBS: AtomicU64 = AtomicU64::new(0);
+
+/// Minimum time between two `download_progress` events of the same file.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Why a post wasn't downloaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    /// The file is already in the output directory
+    Exists,
+    /// The post is in the download archive
+    Archived,
+    /// The file looks like an image saved before
+    Similar,
+}
+
+/// Something that happened while searching or downloading.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    /// A queue started saving posts into `output`
+    JobStarted {
+        server: &'a str,
+        output: &'a str,
+    },
+    /// A page of posts was fetched from the server, before any filter
+    PageFetched {
+        server: &'a str,
+        page: u16,
+        posts: usize,
+    },
+    /// Posts of a page were removed by the blacklist
+    BlacklistRemoved {
+        server: &'a str,
+        page: u16,
+        removed: u64,
+    },
+    /// A post reached the download queue
+    PostQueued {
+        server: &'a str,
+        id: u64,
+        md5: &'a str,
+    },
+    /// The server answered with the file. `offset` is where a resumed download starts
+    DownloadStarted {
+        id: u64,
+        file: &'a str,
+        size: u64,
+        offset: u64,
+    },
+    /// Bytes of the file received so far
+    DownloadProgress {
+        id: u64,
+        file: &'a str,
+        downloaded: u64,
+        size: u64,
+    },
+    DownloadFinished {
+        id: u64,
+        file: &'a str,
+    },
+    DownloadSkipped {
+        id: u64,
+        file: &'a str,
+        reason: SkipReason,
+    },
+    DownloadFailed {
+        id: u64,
+        file: &'a str,
+        error: String,
+    },
+    /// Posts of a queue failed to download and were saved to the `report` file, to be retried
+    FailuresSaved {
+        output: &'a str,
+        report: &'a str,
+        failed: u64,
+    },
+    /// One of the servers of a multi-server search is done
+    ServerFinished {
+        server: &'a str,
+        found: u64,
+        duplicates: u64,
+        removed: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<&'a str>,
+    },
+    /// A job of a batch file or a subscription update is done
+    JobFinished {
+        server: &'a str,
+        job: &'a str,
+        downloaded: u64,
+        removed: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<&'a str>,
+    },
+    /// Totals of the whole run, always the last event
+    Summary(Summary),
+}
+
+/// Number of posts in every outcome, counted from the events of the run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Summary {
+    /// Posts that reached the download queue
+    pub queued: u64,
+    /// Files downloaded and saved
+    pub downloaded: u64,
+    /// Posts not downloaded for a [`SkipReason`]
+    pub skipped: u64,
+    pub failed: u64,
+    /// Posts removed by the blacklist
+    pub removed: u64,
+    /// Servers of a multi-server search, batch jobs or subscriptions that stopped with an error
+    pub failed_jobs: u64,
+}
+
+impl Summary {
+    /// Returns `true` if a post failed to download or a job stopped before it was done.
+    pub const fn partial_failure(&self) -> bool {
+        self.failed > 0 || self.failed_jobs > 0
+    }
+}
+
+/// Writes every event from now on to `writer` as JSON.
+///
+/// Returns `false` if events were already being written somewhere else.
+pub fn enable_json(writer: Box<dyn Write + Send>) -> bool {
+    SINK.set(Mutex::new(writer)).is_ok()
+}
+
+/// Returns `true` if events are written as JSON.
+pub fn json_enabled() -> bool {
+    SINK.get().is_some()
+}
+
+/// Counts `event` and writes it if JSON events are enabled.
+pub fn emit(event: &Event) {
+    match event {
+        Event::PostQueued { .. } => QUEUED.fetch_add(1, Ordering::Relaxed),
+        Event::DownloadFinished { .. } => DOWNLOADED.fetch_add(1, Ordering::Relaxed),
+        Event::DownloadSkipped { .. } => SKIPPED.fetch_add(1, Ordering::Relaxed),
+        Event::DownloadFailed { .. } => FAILED.fetch_add(1, Ordering::Relaxed),
+        Event::BlacklistRemoved { removed, .. } => REMOVED.fetch_add(*removed, Ordering::Relaxed),
+        Event::ServerFinished { error: Some(_), .. }
+        | Event::JobFinished { error: Some(_), .. } => FAILED_JOBS.fetch_add(1, Ordering::Relaxed),
+        _ => 0,
+    };
+
+    let Some(sink) = SINK.get() else {
+        return;
+    };
+
+    if let Ok(line) = serde_json::to_string(event) {
+        let mut sink = sink.lock().unwrap();
+        // Nothing sensible to do if whoever reads the events went away
+        let _ = writeln!(sink, "{line}").and_then(|()| sink.flush());
+    }
+}
+
+/// Totals of every event emitted so far.
+pub fn summary() -> Summary {
+    Summary {
+        queued: QUEUED.load(Ordering::Relaxed),
+        downloaded: DOWNLOADED.load(Ordering::Relaxed),
+        skipped: SKIPPED.load(Ordering::Relaxed),
+        failed: FAILED.load(Ordering::Relaxed),
+        removed: REMOVED.load(Ordering::Relaxed),
+        failed_jobs: FAILED_JOBS.load(Ordering::Relaxed),
+    }
+}
+
+/// Emits the `download_started` and `download_progress` events of a single file.
+pub struct DownloadTracker<'a> {
+    id: u64,
+    file: &'a str,
+    size: u64,
+    downloaded: u64,
+    last: Instant,
+}
+
+impl<'a> DownloadTracker<'a> {
+    /// Emits `download_started` for a file of `size` bytes, starting at byte `offset`.
+    pub fn start(id: u64, file: &'a str, size: u64, offset: u64) -> Self {
+        emit(&Event::DownloadStarted {
+            id,
+            file,
+            size,
+            offset,
+        });
+
+        Self {
+            id,
+            file,
+            size,
+            downloaded: offset,
+            last: Instant::now(),
+        }
+    }
+
+    /// Counts `bytes` more of the file, emitting `download_progress` at most every half second.
+    pub fn inc(&mut self, bytes: u64) {
+        self.downloaded += bytes;
+
+        if json_enabled() && self.last.elapsed() >= PROGRESS_INTERVAL {
+            self.last = Instant::now();
+            emit(&Event::DownloadProgress {
+                id: self.id,
+                file: self.file,
+                downloaded: self.downloaded,
+                size: self.size,
+            });
+        }
+    }
+}
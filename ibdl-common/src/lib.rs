@@ -20,8 +20,10 @@ use directories::ProjectDirs;
 
 use serde::{Deserialize, Serialize};
 
+pub mod events;
 pub mod macros;
 pub mod post;
+pub mod retry;
 
 /// All currently supported imageboards and their underlying attributes
 #[derive(Debug, Copy, Clone, Ord, PartialOrd, PartialEq, Eq, Serialize, Deserialize)]
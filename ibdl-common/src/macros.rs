@@ -21,7 +21,7 @@ macro_rules! client_imgb {
 #[macro_export]
 macro_rules! join_tags {
     ($x:expr) => {{
-        let tl = $x.join("+");
+        let tl = $x.join(" ");
         tl
     }};
 }
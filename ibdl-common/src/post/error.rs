@@ -26,6 +26,9 @@ pub enum PostError {
     #[error("Post URL is valid but original file doesn't exist")]
     RemoteFileNotFound,
 
+    #[error("Downloaded file is corrupted. Expected MD5 {expected}, got {found}")]
+    HashMismatch { expected: String, found: String },
+
     #[error("Error while fetching chunk: {message}")]
     ChunkDownloadFail { message: String },
 
@@ -85,6 +85,12 @@ pub struct Post {
     ///
     /// Used to exclude posts according to a blacklist
     pub tags: Vec<Tag>,
+    /// Delay of every frame of an ugoira post in milliseconds, in the same order as the files
+    /// inside its zip.
+    ///
+    /// Only set by extractors able to fetch it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frame_delays: Option<Vec<u32>>,
 }
 
 impl Debug for Post {
@@ -97,6 +103,7 @@ impl Debug for Post {
             .field("File Extension", &self.extension)
             .field("Rating", &self.rating)
             .field("Tag List", &self.tags)
+            .field("Frame Delays", &self.frame_delays)
             .finish()
     }
 }
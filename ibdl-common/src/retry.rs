@@ -0,0 +1,208 @@
+//! Retry and rate limiting policy shared by all requests sent to imageboards
+//!
+//! # Retries
+//! A [`RetryPolicy`] resends requests that failed to connect, timed out or got a response that
+//! usually means the server is temporarily unavailable (`408`, `429`, `500`, `502`, `503` and `504`).
+//!
+//! The delay between attempts doubles every time, starting at [`backoff_ms`](RetryPolicy::backoff_ms),
+//! unless the server asks for a specific delay with the `Retry-After` header. Either way, it never
+//! goes past [`max_backoff_ms`](RetryPolicy::max_backoff_ms).
+//!
+//! # Rate limiting
+//! A [`RateLimiter`] spaces requests to keep them under a fixed number per second. The limiters
+//! returned by [`RateLimiter::for_server`] are shared by everything talking to the same server.
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, SystemTime};
+
+use log::debug;
+use reqwest::{header::RETRY_AFTER, RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex as AsyncMutex;
+use tokio::time::{sleep, sleep_until, Instant};
+
+static LIMITERS: OnceLock<Mutex<HashMap<String, Arc<RateLimiter>>>> = OnceLock::new();
+
+/// How failed requests are retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// How many times a failed request is sent again before giving up.
+    pub attempts: u32,
+    /// Delay before the first retry, in milliseconds.
+    pub backoff_ms: u64,
+    /// Upper bound for the delay between retries, in milliseconds, including delays asked for
+    /// with `Retry-After`.
+    pub max_backoff_ms: u64,
+    /// Randomize the delay between retries, so parallel downloads don't retry all at once.
+    pub jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            backoff_ms: 1000,
+            max_backoff_ms: 60_000,
+            jitter: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Policy that sends every request a single time.
+    pub const fn never() -> Self {
+        Self {
+            attempts: 0,
+            backoff_ms: 0,
+            max_backoff_ms: 0,
+            jitter: false,
+        }
+    }
+
+    /// Sends `request`, retrying it according to this policy.
+    ///
+    /// If a `limiter` is supplied, every attempt waits for its turn before being sent.
+    ///
+    /// Once out of attempts, returns the last response or error received, so a final `429` or
+    /// `5xx` response still reaches the caller.
+    pub async fn send(
+        &self,
+        request: RequestBuilder,
+        limiter: Option<&RateLimiter>,
+    ) -> Result<Response, reqwest::Error> {
+        let mut attempt = 0;
+
+        loop {
+            if let Some(limiter) = limiter {
+                limiter.wait().await;
+            }
+
+            // Requests with streamed bodies can't be cloned, so they are sent only once.
+            let Some(current) = request.try_clone() else {
+                return request.send().await;
+            };
+
+            let result = current.send().await;
+
+            let retry_after = match &result {
+                Ok(response) if is_retryable_status(response.status()) => {
+                    debug!("Server returned {}", response.status());
+                    retry_after(response)
+                }
+                Err(error) if is_retryable_error(error) => {
+                    debug!("Request failed: {error}");
+                    None
+                }
+                _ => return result,
+            };
+
+            if attempt >= self.attempts {
+                return result;
+            }
+
+            let delay = retry_after.map_or_else(
+                || self.delay(attempt),
+                |delay| delay.min(Duration::from_millis(self.max_backoff_ms)),
+            );
+            attempt += 1;
+
+            debug!(
+                "Retrying in {}ms (attempt {attempt} of {})",
+                delay.as_millis(),
+                self.attempts
+            );
+            sleep(delay).await;
+        }
+    }
+
+    /// Delay before retry number `attempt` (starting at 0).
+    fn delay(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .backoff_ms
+            .saturating_mul(2u64.saturating_pow(attempt))
+            .min(self.max_backoff_ms);
+
+        if self.jitter && backoff > 1 {
+            // Anywhere between half and the full delay
+            return Duration::from_millis(backoff / 2 + fastrand::u64(0..=backoff / 2));
+        }
+
+        Duration::from_millis(backoff)
+    }
+}
+
+/// Spaces requests so no more than a fixed number of them is sent every second.
+#[derive(Debug)]
+pub struct RateLimiter {
+    interval: Duration,
+    next: AsyncMutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_second: f32) -> Self {
+        Self {
+            interval: Duration::from_secs_f32(1.0 / requests_per_second.max(f32::EPSILON)),
+            next: AsyncMutex::new(Instant::now()),
+        }
+    }
+
+    /// Limiter shared by every request sent to the server called `name`.
+    ///
+    /// The limit set on the first call for a given server is kept for the rest of the run.
+    pub fn for_server(name: &str, requests_per_second: f32) -> Arc<Self> {
+        let mut limiters = LIMITERS
+            .get_or_init(|| Mutex::new(HashMap::new()))
+            .lock()
+            .unwrap();
+
+        limiters
+            .entry(name.to_string())
+            .or_insert_with(|| Arc::new(Self::new(requests_per_second)))
+            .clone()
+    }
+
+    /// Waits until another request can be sent.
+    pub async fn wait(&self) {
+        let mut next = self.next.lock().await;
+
+        let now = Instant::now();
+        if *next > now {
+            sleep_until(*next).await;
+        }
+
+        *next = now.max(*next) + self.interval;
+    }
+}
+
+const fn is_retryable_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::REQUEST_TIMEOUT
+            | StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::INTERNAL_SERVER_ERROR
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+fn is_retryable_error(error: &reqwest::Error) -> bool {
+    error.is_connect() || error.is_timeout() || error.is_request()
+}
+
+/// Delay asked by the server, either in seconds or as a date.
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = httpdate::parse_http_date(value).ok()?;
+
+    Some(
+        date.duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO),
+    )
+}
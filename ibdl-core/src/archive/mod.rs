@@ -0,0 +1,229 @@
+//! Persistent record of every post downloaded so far.
+//!
+//! # Download Archive
+//! When enabled with `--archive`, the [`Queue`](crate::async_queue::Queue) looks up every post in
+//! this archive before fetching it and records it after it's saved. Since entries are keyed by
+//! `(server, post id, md5)`, already downloaded posts are skipped even if the files were moved
+//! out of the output directory afterwards.
+//!
+//! The archive lives in `$XDG_CONFIG_HOME/imageboard-downloader/archive.redb` (or inside
+//! `IBDL_CACHE_DIR` when set) and can be inspected with the `archive` subcommand, which can also
+//! export it as JSON Lines and import it again on another machine.
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ibdl_common::{
+    log::debug,
+    post::Post,
+    serde::{self, Deserialize, Serialize},
+    serde_json, ImageBoards,
+};
+use redb::{Database, ReadableTable, ReadableTableMetadata, TableDefinition};
+
+use crate::error::QueueError;
+
+/// `(server name, post id, md5)` -> `(unix timestamp, file name)`
+const DOWNLOADS: TableDefinition<(&str, u64, &str), (u64, &str)> =
+    TableDefinition::new("downloads");
+
+const ARCHIVE_FILE: &str = "archive.redb";
+
+fn db_error<E: Into<redb::Error>>(error: E) -> QueueError {
+    QueueError::ArchiveError {
+        message: error.into().to_string(),
+    }
+}
+
+/// A single post recorded in the archive.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "self::serde")]
+pub struct ArchiveEntry {
+    pub server: String,
+    pub id: u64,
+    pub md5: String,
+    /// Unix timestamp of when the post was downloaded.
+    pub downloaded_at: u64,
+    pub file_name: String,
+}
+
+/// Handle to the download archive. Cheap to clone and safe to share between download tasks.
+#[derive(Clone)]
+pub struct DownloadArchive {
+    db: Arc<Database>,
+}
+
+impl DownloadArchive {
+    /// Returns the default location of the archive file.
+    pub fn default_path() -> Result<PathBuf, QueueError> {
+        Ok(ImageBoards::auth_cache_dir()?.join(Path::new(ARCHIVE_FILE)))
+    }
+
+    /// Opens the archive at the default location, creating it if needed.
+    pub fn open_default() -> Result<Self, QueueError> {
+        Self::open(&Self::default_path()?)
+    }
+
+    /// Opens the archive at `path`, creating it if needed.
+    pub fn open(path: &Path) -> Result<Self, QueueError> {
+        debug!("Opening download archive at {}", path.display());
+        let db = Database::create(path).map_err(db_error)?;
+
+        // Make sure the table exists so read transactions never fail on a fresh archive.
+        let txn = db.begin_write().map_err(db_error)?;
+        txn.open_table(DOWNLOADS).map_err(db_error)?;
+        txn.commit().map_err(db_error)?;
+
+        Ok(Self { db: Arc::new(db) })
+    }
+
+    /// Checks if `post` from `server` was already downloaded.
+    pub fn contains(&self, server: &str, post: &Post) -> Result<bool, QueueError> {
+        let txn = self.db.begin_read().map_err(db_error)?;
+        let table = txn.open_table(DOWNLOADS).map_err(db_error)?;
+
+        let found = table
+            .get((server, post.id, post.md5.as_str()))
+            .map_err(db_error)?
+            .is_some();
+
+        Ok(found)
+    }
+
+    /// Records `post` from `server` as downloaded into `file_name`.
+    pub fn insert(&self, server: &str, post: &Post, file_name: &str) -> Result<(), QueueError> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+
+        let txn = self.db.begin_write().map_err(db_error)?;
+        {
+            let mut table = txn.open_table(DOWNLOADS).map_err(db_error)?;
+            table
+                .insert((server, post.id, post.md5.as_str()), (now, file_name))
+                .map_err(db_error)?;
+        }
+        txn.commit().map_err(db_error)?;
+
+        debug!("Recorded post {} from {} in archive", post.id, server);
+        Ok(())
+    }
+
+    /// Lists all entries, optionally only the ones from `server`.
+    pub fn entries(&self, server: Option<&str>) -> Result<Vec<ArchiveEntry>, QueueError> {
+        let txn = self.db.begin_read().map_err(db_error)?;
+        let table = txn.open_table(DOWNLOADS).map_err(db_error)?;
+
+        let mut entries = Vec::with_capacity(table.len().map_err(db_error)? as usize);
+
+        for item in table.iter().map_err(db_error)? {
+            let (key, value) = item.map_err(db_error)?;
+            let (srv, id, md5) = key.value();
+
+            if server.is_some_and(|name| name != srv) {
+                continue;
+            }
+
+            let (downloaded_at, file_name) = value.value();
+
+            entries.push(ArchiveEntry {
+                server: srv.to_string(),
+                id,
+                md5: md5.to_string(),
+                downloaded_at,
+                file_name: file_name.to_string(),
+            });
+        }
+
+        Ok(entries)
+    }
+
+    /// Writes every entry, optionally only the ones from `server`, to `writer` as JSON Lines.
+    ///
+    /// Returns the number of written entries.
+    pub fn export(&self, server: Option<&str>, mut writer: impl Write) -> Result<u64, QueueError> {
+        let entries = self.entries(server)?;
+
+        for entry in &entries {
+            serde_json::to_writer(&mut writer, entry).map_err(|error| {
+                QueueError::ArchiveError {
+                    message: error.to_string(),
+                }
+            })?;
+            writeln!(writer)?;
+        }
+        writer.flush()?;
+
+        Ok(entries.len() as u64)
+    }
+
+    /// Records the entries written by [`export`](Self::export) to `reader`, keeping the ones
+    /// already in the archive as they are.
+    ///
+    /// Returns the number of entries that weren't in the archive yet.
+    pub fn import(&self, reader: impl BufRead) -> Result<u64, QueueError> {
+        let mut entries = Vec::new();
+
+        for (idx, line) in reader.lines().enumerate() {
+            let line = line?;
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let entry: ArchiveEntry =
+                serde_json::from_str(&line).map_err(|error| QueueError::ArchiveError {
+                    message: format!("line {}: {error}", idx + 1),
+                })?;
+            entries.push(entry);
+        }
+
+        let txn = self.db.begin_write().map_err(db_error)?;
+        let mut added = 0;
+        {
+            let mut table = txn.open_table(DOWNLOADS).map_err(db_error)?;
+
+            for entry in &entries {
+                let key = (entry.server.as_str(), entry.id, entry.md5.as_str());
+
+                if table.get(key).map_err(db_error)?.is_none() {
+                    table
+                        .insert(key, (entry.downloaded_at, entry.file_name.as_str()))
+                        .map_err(db_error)?;
+                    added += 1;
+                }
+            }
+        }
+        txn.commit().map_err(db_error)?;
+
+        debug!("Imported {} entries into archive", added);
+        Ok(added)
+    }
+
+    /// Removes entries from `server` (or from every server if `None`) that were downloaded before
+    /// the unix timestamp `before` (or all of them if `None`).
+    ///
+    /// Returns the number of removed entries.
+    pub fn prune(&self, server: Option<&str>, before: Option<u64>) -> Result<u64, QueueError> {
+        let txn = self.db.begin_write().map_err(db_error)?;
+        let removed = {
+            let mut table = txn.open_table(DOWNLOADS).map_err(db_error)?;
+            let original = table.len().map_err(db_error)?;
+
+            table
+                .retain(|(srv, _, _), (downloaded_at, _)| {
+                    let server_match = server.is_none_or(|name| name == srv);
+                    let age_match = before.is_none_or(|limit| downloaded_at < limit);
+                    !(server_match && age_match)
+                })
+                .map_err(db_error)?;
+
+            original - table.len().map_err(db_error)?
+        };
+        txn.commit().map_err(db_error)?;
+
+        debug!("Pruned {} entries from archive", removed);
+        Ok(removed)
+    }
+}
@@ -2,9 +2,11 @@ use std::{fs::File, io::Write, path::PathBuf, sync::Arc, sync::Mutex};
 
 use futures::StreamExt;
 use ibdl_common::{
+    events::{emit, DownloadTracker, Event, SkipReason},
     log::debug,
-    post::{error::PostError, rating::Rating, NameType, Post},
+    post::{error::PostError, rating::Rating, Post},
     reqwest::Client,
+    retry::RetryPolicy,
     tokio::{
         io::AsyncWriteExt,
         sync::mpsc::Sender,
@@ -12,42 +14,43 @@ use ibdl_common::{
     },
     ImageBoards,
 };
-use owo_colors::OwoColorize;
 use tokio_stream::wrappers::UnboundedReceiverStream;
 use zip::{write::FileOptions, CompressionMethod, ZipWriter};
 
-use crate::{async_queue::get_counters, error::QueueError};
+use crate::{
+    async_queue::{
+        check_status,
+        comic_info::{comic_info_xml, ComicPage},
+        get_counters,
+        metadata::{post_metadata, PostMetadata},
+    },
+    error::QueueError,
+    report::FailedPost,
+};
 
-use super::Queue;
+use super::{GroupBy, Queue};
 
 impl Queue {
     pub(crate) async fn fetch_cbz_pool(
         client: Client,
+        retry: RetryPolicy,
         variant: ImageBoards,
         post: Post,
         zip: Arc<Mutex<ZipWriter<File>>>,
-        num_digits: usize,
-    ) -> Result<(), PostError> {
+        filename: String,
+        metadata: Option<PostMetadata>,
+    ) -> Result<ComicPage, PostError> {
         let counters = get_counters();
 
-        let filename = post.seq_file_name(num_digits);
         debug!("Fetching {}", &post.url);
-        let res = client.get(&post.url).send().await?;
-
-        if res.status().is_client_error() {
-            counters.multi.println(format!(
-                "{} {}{}",
-                "Image source returned status".bold().red(),
-                res.status().as_str().bold().red(),
-                ". Skipping download.".bold().red()
-            ))?;
-            counters.main.inc(1);
-            return Err(PostError::RemoteFileNotFound);
-        }
+        let res = retry.send(client.get(&post.url), None).await?;
+
+        let res = check_status(res)?;
 
         let size = res.content_length().unwrap_or_default();
 
         let pb = counters.add_download_bar(size, variant);
+        let mut tracker = DownloadTracker::start(post.id, &filename, size, 0);
 
         // Download the file chunk by chunk.
         debug!("Retrieving chunks for {}", &filename);
@@ -70,64 +73,81 @@ impl Queue {
                 }
             };
             pb.inc(chunk.len().try_into()?);
+            tracker.inc(chunk.len().try_into()?);
 
             // Write to file.
             AsyncWriteExt::write_all(&mut fvec, &chunk).await?;
         }
 
-        spawn_blocking(move || -> Result<(), PostError> {
+        let page = spawn_blocking(move || -> Result<ComicPage, PostError> {
             let mut un_mut = zip.lock().unwrap();
 
             debug!("Writing {} to cbz file", filename);
-            if let Err(error) = un_mut.start_file(filename, options) {
+            if let Err(error) = un_mut.start_file(&filename, options) {
                 return Err(PostError::ZipFileWriteError {
                     message: error.to_string(),
                 });
             }
 
-            un_mut.write_all(&fvec)?;
+            let embedded = metadata.as_ref().and_then(|meta| meta.embed_into(&fvec));
+            let data = embedded.as_deref().unwrap_or(&fvec);
+
+            un_mut.write_all(data)?;
+            let size = data.len() as u64;
+
+            if let Some(metadata) = metadata.filter(|_| embedded.is_none()) {
+                debug!("Writing metadata for {} to cbz file", filename);
+                if let Err(error) = un_mut.start_file(
+                    format!("{}.json", entry_stem(&filename)),
+                    FileOptions::default().compression_method(CompressionMethod::Deflated),
+                ) {
+                    return Err(PostError::ZipFileWriteError {
+                        message: error.to_string(),
+                    });
+                }
+
+                un_mut.write_all(&metadata.json)?;
+            }
 
             drop(un_mut);
 
-            Ok(())
+            Ok(ComicPage {
+                file: filename,
+                size,
+                post,
+            })
         })
-        .await??;
+        .await?;
 
         pb.finish_and_clear();
 
-        Ok(())
+        page
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub(crate) async fn fetch_cbz(
         client: Client,
+        retry: RetryPolicy,
         variant: ImageBoards,
-        name_type: NameType,
         post: Post,
         annotate: bool,
         zip: Arc<Mutex<ZipWriter<File>>>,
-    ) -> Result<(), PostError> {
+        zip_path: String,
+        metadata: Option<PostMetadata>,
+    ) -> Result<ComicPage, PostError> {
         let counters = get_counters();
-        let filename = post.file_name(name_type);
         debug!("Fetching {}", &post.url);
-        let res = client.get(&post.url).send().await?;
-
-        if res.status().is_client_error() {
-            counters.multi.println(format!(
-                "{} {}{}",
-                "Image source returned status".bold().red(),
-                res.status().as_str().bold().red(),
-                ". Skipping download.".bold().red()
-            ))?;
-            counters.main.inc(1);
-            return Err(PostError::RemoteFileNotFound);
-        }
+        let res = retry.send(client.get(&post.url), None).await?;
+
+        let res = check_status(res)?;
 
         let size = res.content_length().unwrap_or_default();
 
         let pb = counters.add_download_bar(size, variant);
+        let mut tracker = DownloadTracker::start(post.id, &zip_path, size, 0);
 
         // Download the file chunk by chunk.
-        debug!("Retrieving chunks for {}", &filename);
+        debug!("Retrieving chunks for {}", &zip_path);
         let mut stream = res.bytes_stream();
 
         let buf_size: usize = size.try_into()?;
@@ -150,32 +170,34 @@ impl Queue {
                 }
             };
             pb.inc(chunk.len().try_into()?);
+            tracker.inc(chunk.len().try_into()?);
 
             // Write to file.
             AsyncWriteExt::write_all(&mut fvec, &chunk).await?;
         }
 
-        spawn_blocking(move || -> Result<(), PostError> {
+        let page = spawn_blocking(move || -> Result<ComicPage, PostError> {
             let mut un_mut = zip.lock().unwrap();
 
-            debug!("Writing {} to cbz file", filename);
-            if let Err(error) =
-                un_mut.start_file(format!("{}/{}", post.rating.to_string(), filename), options)
-            {
+            debug!("Writing {} to cbz file", zip_path);
+            if let Err(error) = un_mut.start_file(&zip_path, options) {
                 drop(un_mut);
                 return Err(PostError::ZipFileWriteError {
                     message: error.to_string(),
                 });
             };
 
-            un_mut.write_all(&fvec)?;
+            let embedded = metadata.as_ref().and_then(|meta| meta.embed_into(&fvec));
+            let data = embedded.as_deref().unwrap_or(&fvec);
+
+            un_mut.write_all(data)?;
+            let size = data.len() as u64;
 
             if annotate {
-                debug!("Writing caption for {} to cbz file", filename);
-                if let Err(error) = un_mut.start_file(
-                    format!("{}/{}.txt", post.rating.to_string(), post.name(name_type)),
-                    cap_options,
-                ) {
+                debug!("Writing caption for {} to cbz file", zip_path);
+                if let Err(error) =
+                    un_mut.start_file(format!("{}.txt", entry_stem(&zip_path)), cap_options)
+                {
                     drop(un_mut);
 
                     return Err(PostError::ZipFileWriteError {
@@ -195,16 +217,44 @@ impl Queue {
                 let f1 = prompt.replace('_', " ");
 
                 un_mut.write_all(f1.as_bytes())?;
+            }
 
-                drop(un_mut);
+            if let Some(metadata) = metadata.filter(|_| embedded.is_none()) {
+                debug!("Writing metadata for {} to cbz file", zip_path);
+                if let Err(error) =
+                    un_mut.start_file(format!("{}.json", entry_stem(&zip_path)), cap_options)
+                {
+                    drop(un_mut);
+
+                    return Err(PostError::ZipFileWriteError {
+                        message: error.to_string(),
+                    });
+                };
+
+                un_mut.write_all(&metadata.json)?;
             }
-            Ok(())
+
+            drop(un_mut);
+            Ok(ComicPage {
+                file: zip_path,
+                size,
+                post,
+            })
         })
-        .await??;
+        .await?;
 
         pb.finish_and_clear();
 
-        Ok(())
+        page
+    }
+
+    /// Path of the post inside the cbz file.
+    fn zip_entry(&self, post: &Post, pool: bool) -> String {
+        if self.filename.is_some() || self.group_by.is_some() || pool {
+            self.output_name(post, pool)
+        } else {
+            format!("{}/{}", post.rating, self.output_name(post, pool))
+        }
     }
 
     pub(crate) fn write_zip_structure(
@@ -234,41 +284,127 @@ impl Queue {
         let file = File::create(&path)?;
         let zip = Arc::new(Mutex::new(ZipWriter::new(file)));
 
-        if !pool {
+        // Templates and other groups pick their own directories
+        if !pool && self.filename.is_none() && matches!(self.group_by, None | Some(GroupBy::Rating))
+        {
             self.write_zip_structure(zip.clone())?;
         }
         let sender = progress_channel.clone();
 
-        channel
+        let mut pages = channel
             .map(|d| {
-                let nt = self.name_type;
-
-                let cli = self.client.clone();
+                let (source, cli) = self.source(&d);
+                let retry = source.retry;
+                let fname = self.output_name(&d, pool);
+                let entry = self.zip_entry(&d, pool);
                 let zip = zip.clone();
-                let variant = self.imageboard.server;
+                let variant = source.server;
                 let annotate = self.annotate;
+                let server = source.name.clone();
+                let archive = self.archive.clone();
+                let failures = self.failures.clone();
                 let sender = sender.clone();
+                let metadata = self
+                    .metadata
+                    .map(|format| post_metadata(&d, &source, format));
 
                 task::spawn(async move {
-                    if pool {
-                        Self::fetch_cbz_pool(cli, variant, d, zip, 6).await?;
-                    } else {
-                        Self::fetch_cbz(cli, variant, nt, d, annotate, zip).await?;
+                    emit(&Event::PostQueued {
+                        server: &server,
+                        id: d.id,
+                        md5: &d.md5,
+                    });
+
+                    let result = async {
+                        if Self::archived(archive.as_ref(), &server, &d, &fname)? {
+                            emit(&Event::DownloadSkipped {
+                                id: d.id,
+                                file: &entry,
+                                reason: SkipReason::Archived,
+                            });
+                            let _ = sender.send(true).await;
+                            return Ok(None);
+                        }
+
+                        let metadata = metadata.transpose()?;
+
+                        let page = if pool {
+                            Self::fetch_cbz_pool(
+                                cli,
+                                retry,
+                                variant,
+                                d.clone(),
+                                zip,
+                                entry.clone(),
+                                metadata,
+                            )
+                            .await?
+                        } else {
+                            Self::fetch_cbz(
+                                cli,
+                                retry,
+                                variant,
+                                d.clone(),
+                                annotate,
+                                zip,
+                                entry.clone(),
+                                metadata,
+                            )
+                            .await?
+                        };
+
+                        if let Some(archive) = &archive {
+                            archive.insert(&server, &d, &fname)?;
+                        }
+
+                        emit(&Event::DownloadFinished {
+                            id: d.id,
+                            file: &entry,
+                        });
+
+                        let _ = sender.send(true).await;
+                        Ok::<Option<ComicPage>, QueueError>(Some(page))
+                    }
+                    .await;
+
+                    if let Err(error) = &result {
+                        emit(&Event::DownloadFailed {
+                            id: d.id,
+                            file: &entry,
+                            error: error.to_string(),
+                        });
+                        failures
+                            .lock()
+                            .unwrap()
+                            .push(FailedPost::new(&server, &d, error));
                     }
 
-                    let _ = sender.send(true).await;
-                    Ok::<(), QueueError>(())
+                    result
                 })
             })
             .buffer_unordered(self.sim_downloads.into())
-            .for_each(|_| async {})
+            .filter_map(|page| async move { page.ok()?.ok()? })
+            .collect::<Vec<ComicPage>>()
             .await;
 
+        let comic_info = comic_info_xml(&self.comic, &mut pages);
+
         {
             let mut mtx = zip.lock().unwrap();
 
+            mtx.start_file(
+                "ComicInfo.xml",
+                FileOptions::default().compression_method(CompressionMethod::Deflated),
+            )?;
+            mtx.write_all(comic_info.as_bytes())?;
+
             mtx.finish()?;
         }
         Ok(())
     }
 }
+
+/// Path of a file inside the cbz file, without the extension.
+fn entry_stem(entry: &str) -> &str {
+    entry.rsplit_once('.').map_or(entry, |(stem, _)| stem)
+}
@@ -0,0 +1,137 @@
+//! `ComicInfo.xml` generation for CBZ files, as read by Komga, Kavita, YACReader and others.
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use ibdl_common::post::{rating::Rating, tags::TagType, Post};
+
+use super::embed::escape_xml;
+
+/// Max number of general tags listed in `<Tags>`, picking the most common ones.
+const MAX_TAGS: usize = 100;
+
+/// A single image saved inside the CBZ file.
+#[derive(Debug)]
+pub struct ComicPage {
+    /// Path of the image inside the archive
+    pub file: String,
+    pub size: u64,
+    pub post: Post,
+}
+
+/// Title and description of the CBZ file
+#[derive(Debug, Clone, Default)]
+pub struct ComicDetails {
+    pub title: Option<String>,
+    /// Also sets the title as the series name
+    pub series: bool,
+    pub summary: Option<String>,
+}
+
+/// Unique tags of the given type across all pages, most common first.
+fn collect_tags(pages: &[ComicPage], tag_type: TagType) -> Vec<String> {
+    let mut counts: HashMap<String, (usize, usize)> = HashMap::new();
+
+    for tag in pages
+        .iter()
+        .flat_map(|page| page.post.tags.iter())
+        .filter(|tag| tag.tag_type() == tag_type)
+    {
+        let order = counts.len();
+        counts.entry(tag.tag()).or_insert((0, order)).0 += 1;
+    }
+
+    let mut tags: Vec<(String, (usize, usize))> = counts.into_iter().collect();
+    // Most common first, keeping the order they were found in for ties
+    tags.sort_by(|(_, (count_a, order_a)), (_, (count_b, order_b))| {
+        count_b.cmp(count_a).then(order_a.cmp(order_b))
+    });
+
+    tags.into_iter()
+        .map(|(tag, _)| tag.replace('_', " "))
+        .collect()
+}
+
+/// Age rating of the most explicit post in the file.
+fn age_rating(pages: &[ComicPage]) -> &'static str {
+    let highest = pages
+        .iter()
+        .map(|page| page.post.rating)
+        .filter(|rating| *rating != Rating::Unknown)
+        .max();
+
+    match highest {
+        Some(Rating::Safe) => "Everyone",
+        Some(Rating::Questionable) => "Mature 17+",
+        Some(Rating::Explicit) => "Adults Only 18+",
+        _ => "Unknown",
+    }
+}
+
+fn push_element(xml: &mut String, name: &str, value: &str) {
+    if !value.is_empty() {
+        let _ = writeln!(xml, "  <{name}>{}</{name}>", escape_xml(value));
+    }
+}
+
+/// Builds the `ComicInfo.xml` of a CBZ file with the given `pages`.
+///
+/// Pages are sorted by their path inside the archive, the same order comic readers use.
+pub fn comic_info_xml(details: &ComicDetails, pages: &mut [ComicPage]) -> String {
+    pages.sort_by(|a, b| a.file.cmp(&b.file));
+
+    let authors = collect_tags(pages, TagType::Author).join(", ");
+
+    let mut xml = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n",
+        "<ComicInfo xmlns:xsd=\"http://www.w3.org/2001/XMLSchema\" ",
+        "xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\">\n"
+    ));
+
+    if let Some(title) = &details.title {
+        push_element(&mut xml, "Title", title);
+
+        if details.series {
+            push_element(&mut xml, "Series", title);
+        }
+    }
+
+    if let Some(summary) = &details.summary {
+        push_element(&mut xml, "Summary", summary);
+    }
+
+    push_element(&mut xml, "Writer", &authors);
+    push_element(&mut xml, "Penciller", &authors);
+    push_element(
+        &mut xml,
+        "Genre",
+        &collect_tags(pages, TagType::Copyright).join(", "),
+    );
+
+    let mut tags = collect_tags(pages, TagType::General);
+    tags.truncate(MAX_TAGS);
+    push_element(&mut xml, "Tags", &tags.join(", "));
+
+    push_element(
+        &mut xml,
+        "Characters",
+        &collect_tags(pages, TagType::Character).join(", "),
+    );
+    push_element(&mut xml, "PageCount", &pages.len().to_string());
+    push_element(&mut xml, "AgeRating", age_rating(pages));
+
+    if !pages.is_empty() {
+        xml.push_str("  <Pages>\n");
+        for (idx, page) in pages.iter().enumerate() {
+            let kind = if idx == 0 { " Type=\"FrontCover\"" } else { "" };
+            let _ = writeln!(
+                xml,
+                "    <Page Image=\"{idx}\"{kind} ImageSize=\"{}\" />",
+                page.size
+            );
+        }
+        xml.push_str("  </Pages>\n");
+    }
+
+    xml.push_str("</ComicInfo>\n");
+    xml
+}
@@ -0,0 +1,264 @@
+//! Embeds post metadata into downloaded files without touching the image data.
+//!
+//! Supported formats:
+//! - JPEG: XMP packet in an `APP1` segment
+//! - PNG: XMP packet in an `iTXt` chunk plus a `tEXt` chunk with the source URL
+//! - WEBP: XMP packet in an `XMP ` chunk (adding a `VP8X` header to simple files)
+use std::fmt::Write;
+
+use ibdl_common::post::{tags::TagType, Post};
+
+const XMP_NAMESPACE: &str = "https://gitlab.com/FerrahWolfeh/imageboard-downloader-rs/xmp/1.0/";
+const JPEG_XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Builds the XMP packet with the tags (grouped by [`TagType`]), rating and source of `post`.
+pub fn xmp_packet(post: &Post, source: &str) -> String {
+    let mut subjects = String::new();
+    let mut hierarchical = String::new();
+    let mut creators = String::new();
+
+    for tag in &post.tags {
+        let text = escape_xml(&tag.tag());
+        let _ = write!(subjects, "<rdf:li>{text}</rdf:li>");
+        let _ = write!(hierarchical, "<rdf:li>{:?}|{text}</rdf:li>", tag.tag_type());
+
+        if tag.tag_type() == TagType::Author {
+            let _ = write!(creators, "<rdf:li>{text}</rdf:li>");
+        }
+    }
+
+    let _ = write!(hierarchical, "<rdf:li>Rating|{}</rdf:li>", post.rating);
+
+    let creator = if creators.is_empty() {
+        String::new()
+    } else {
+        format!("<dc:creator><rdf:Seq>{creators}</rdf:Seq></dc:creator>")
+    };
+
+    format!(
+        concat!(
+            "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>",
+            "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">",
+            "<rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">",
+            "<rdf:Description rdf:about=\"\" xmlns:dc=\"http://purl.org/dc/elements/1.1/\" ",
+            "xmlns:lr=\"http://ns.adobe.com/lightroom/1.0/\" xmlns:ibdl=\"{ns}\">",
+            "<dc:source>{source}</dc:source>",
+            "{creator}",
+            "<dc:subject><rdf:Bag>{subjects}</rdf:Bag></dc:subject>",
+            "<lr:hierarchicalSubject><rdf:Bag>{hierarchical}</rdf:Bag></lr:hierarchicalSubject>",
+            "<ibdl:rating>{rating}</ibdl:rating>",
+            "{md5_tag}",
+            "</rdf:Description></rdf:RDF></x:xmpmeta>",
+            "<?xpacket end=\"w\"?>"
+        ),
+        ns = XMP_NAMESPACE,
+        source = escape_xml(source),
+        creator = creator,
+        subjects = subjects,
+        hierarchical = hierarchical,
+        rating = post.rating,
+        md5_tag = md5_tag(&post.md5),
+    )
+}
+
+/// Checks if `data` already has the metadata of the post with the given `md5` embedded.
+///
+/// Since embedding changes the file hash, this is used to recognize files that were already
+/// downloaded.
+pub fn is_embedded(data: &[u8], md5: &str) -> bool {
+    if md5.is_empty() {
+        return false;
+    }
+
+    let needle = md5_tag(md5);
+    data.windows(needle.len())
+        .any(|window| window == needle.as_bytes())
+}
+
+/// Returns a copy of `data` with `xmp` embedded into it, or `None` if the format is not supported
+/// or the file is malformed.
+pub fn embed(data: &[u8], xmp: &str, source: &str) -> Option<Vec<u8>> {
+    if data.starts_with(&[0xFF, 0xD8]) {
+        embed_jpeg(data, xmp)
+    } else if data.starts_with(PNG_SIGNATURE) {
+        embed_png(data, xmp, source)
+    } else if data.len() > 12 && &data[0..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        embed_webp(data, xmp)
+    } else {
+        None
+    }
+}
+
+fn md5_tag(md5: &str) -> String {
+    format!("<ibdl:md5>{md5}</ibdl:md5>")
+}
+
+pub fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn embed_jpeg(data: &[u8], xmp: &str) -> Option<Vec<u8>> {
+    let segment_len = u16::try_from(2 + JPEG_XMP_HEADER.len() + xmp.len()).ok()?;
+
+    // JFIF and EXIF headers must stay as the first segments
+    let mut pos = 2;
+    loop {
+        let marker = data.get(pos..pos + 2)?;
+        let is_header = marker == [0xFF, 0xE0]
+            || (marker == [0xFF, 0xE1] && data.get(pos + 4..pos + 10)? == b"Exif\0\0");
+
+        if !is_header {
+            break;
+        }
+
+        let len = u16::from_be_bytes([*data.get(pos + 2)?, *data.get(pos + 3)?]);
+        pos += 2 + len as usize;
+    }
+
+    let head = data.get(..pos)?;
+
+    let mut out = Vec::with_capacity(data.len() + segment_len as usize + 2);
+    out.extend_from_slice(head);
+    out.extend_from_slice(&[0xFF, 0xE1]);
+    out.extend_from_slice(&segment_len.to_be_bytes());
+    out.extend_from_slice(JPEG_XMP_HEADER);
+    out.extend_from_slice(xmp.as_bytes());
+    out.extend_from_slice(&data[pos..]);
+
+    Some(out)
+}
+
+fn png_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) -> Option<()> {
+    let len = u32::try_from(data.len()).ok()?;
+
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(kind);
+    crc.update(data);
+
+    out.extend_from_slice(&len.to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    out.extend_from_slice(&crc.finalize().to_be_bytes());
+    Some(())
+}
+
+fn embed_png(data: &[u8], xmp: &str, source: &str) -> Option<Vec<u8>> {
+    // IHDR is always the first chunk, right after the signature
+    let ihdr = data.get(8..16)?;
+    if &ihdr[4..8] != b"IHDR" {
+        return None;
+    }
+    let ihdr_len = u32::from_be_bytes([ihdr[0], ihdr[1], ihdr[2], ihdr[3]]) as usize;
+    let pos = 8 + 12 + ihdr_len;
+    let head = data.get(..pos)?;
+
+    let mut out = Vec::with_capacity(data.len() + xmp.len() + source.len() + 64);
+    out.extend_from_slice(head);
+
+    // keyword, null separator, no compression, empty language and translated keyword
+    let mut itxt = b"XML:com.adobe.xmp\0\0\0\0\0".to_vec();
+    itxt.extend_from_slice(xmp.as_bytes());
+    png_chunk(&mut out, b"iTXt", &itxt)?;
+
+    if source.is_ascii() {
+        let mut text = b"Source\0".to_vec();
+        text.extend_from_slice(source.as_bytes());
+        png_chunk(&mut out, b"tEXt", &text)?;
+    }
+
+    out.extend_from_slice(&data[pos..]);
+    Some(out)
+}
+
+fn riff_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) -> Option<()> {
+    let len = u32::try_from(data.len()).ok()?;
+
+    out.extend_from_slice(kind);
+    out.extend_from_slice(&len.to_le_bytes());
+    out.extend_from_slice(data);
+
+    // Chunks are padded to an even size
+    if data.len() % 2 == 1 {
+        out.push(0);
+    }
+    Some(())
+}
+
+/// Reads the canvas size and alpha flag from a simple (lossy or lossless) webp image.
+fn webp_info(kind: &[u8], payload: &[u8]) -> Option<(u32, u32, bool)> {
+    match kind {
+        b"VP8 " => {
+            if payload.get(3..6)? != [0x9D, 0x01, 0x2A] {
+                return None;
+            }
+            let width = u16::from_le_bytes([*payload.get(6)?, *payload.get(7)?]) & 0x3FFF;
+            let height = u16::from_le_bytes([*payload.get(8)?, *payload.get(9)?]) & 0x3FFF;
+            Some((u32::from(width), u32::from(height), false))
+        }
+        b"VP8L" => {
+            if *payload.first()? != 0x2F {
+                return None;
+            }
+            let bits = u32::from_le_bytes(payload.get(1..5)?.try_into().ok()?);
+            let width = (bits & 0x3FFF) + 1;
+            let height = ((bits >> 14) & 0x3FFF) + 1;
+            let alpha = (bits >> 28) & 1 == 1;
+            Some((width, height, alpha))
+        }
+        _ => None,
+    }
+}
+
+fn embed_webp(data: &[u8], xmp: &str) -> Option<Vec<u8>> {
+    const XMP_FLAG: u8 = 0x04;
+    const ALPHA_FLAG: u8 = 0x10;
+
+    let kind = data.get(12..16)?;
+    let chunk_len = u32::from_le_bytes(data.get(16..20)?.try_into().ok()?) as usize;
+
+    let mut out = Vec::with_capacity(data.len() + xmp.len() + 32);
+    out.extend_from_slice(b"RIFF\0\0\0\0WEBP");
+
+    if kind == b"VP8X" {
+        // Don't add a second XMP chunk if the file already has one
+        if data.get(20)? & XMP_FLAG != 0 {
+            return None;
+        }
+
+        out.extend_from_slice(&data[12..]);
+        out[20] |= XMP_FLAG;
+    } else {
+        let payload = data.get(20..20 + chunk_len)?;
+        let (width, height, alpha) =
+            webp_info(kind, payload).filter(|(width, height, _)| *width > 0 && *height > 0)?;
+
+        let mut header = [0_u8; 10];
+        header[0] = if alpha {
+            XMP_FLAG | ALPHA_FLAG
+        } else {
+            XMP_FLAG
+        };
+        header[4..7].copy_from_slice(&(width - 1).to_le_bytes()[..3]);
+        header[7..10].copy_from_slice(&(height - 1).to_le_bytes()[..3]);
+
+        riff_chunk(&mut out, b"VP8X", &header)?;
+        out.extend_from_slice(&data[12..]);
+    }
+
+    // The padding byte of the last chunk might be missing in some files
+    if out.len() % 2 == 1 {
+        out.push(0);
+    }
+
+    riff_chunk(&mut out, b"XMP ", xmp.as_bytes())?;
+
+    let riff_len = u32::try_from(out.len() - 8).ok()?;
+    out[4..8].copy_from_slice(&riff_len.to_le_bytes());
+
+    Some(out)
+}
@@ -0,0 +1,271 @@
+//! Output file names built from a template.
+//!
+//! A template is a path with fields between braces, like `{artist}/{id}_{md5:8}.{ext}`. Every `/`
+//! creates a subdirectory inside the output directory, and `{{` or `}}` write a literal brace.
+//!
+//! # Fields
+//! * `{id}`: ID of the post
+//! * `{index}`: Position of the post in the pool. Same as `{id}` outside of pool downloads
+//!   (pool downloads only keep the position of each post)
+//! * `{md5}`: MD5 hash of the file
+//! * `{ext}`: File extension
+//! * `{server}`: Name of the server the post was downloaded from
+//! * `{rating}`: Rating of the post
+//! * `{artist}`, `{copyright}`, `{character}`: Tags of that type, separated by commas
+//!
+//! Fields can take a number after a colon. Numeric fields (`{id}` and `{index}`) are padded with
+//! zeros to that width, while the others are cut to that many characters, so `{index:4}` turns
+//! into `0012` and `{md5:8}` into the first 8 characters of the hash.
+//!
+//! The extension is added at the end if the template doesn't have an `{ext}` field.
+use std::str::FromStr;
+
+use ibdl_common::post::{tags::TagType, Post};
+
+/// Value used by tag fields if the post has no tags of that type.
+const EMPTY_FIELD: &str = "unknown";
+
+/// Longest file or directory name, in bytes. Most filesystems stop at 255.
+const MAX_COMPONENT_LEN: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Id,
+    Index,
+    Md5,
+    Ext,
+    Server,
+    Rating,
+    Artist,
+    Copyright,
+    Character,
+}
+
+impl FromStr for Field {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "id" => Ok(Self::Id),
+            "index" => Ok(Self::Index),
+            "md5" => Ok(Self::Md5),
+            "ext" => Ok(Self::Ext),
+            "server" => Ok(Self::Server),
+            "rating" => Ok(Self::Rating),
+            "artist" => Ok(Self::Artist),
+            "copyright" => Ok(Self::Copyright),
+            "character" => Ok(Self::Character),
+            _ => Err(format!("Unknown field {{{s}}}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Separator,
+    Field(Field, Option<usize>),
+}
+
+/// Parsed `--filename` template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilenameTemplate {
+    parts: Vec<Part>,
+}
+
+impl FromStr for FilenameTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = s.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut field = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => field.push(c),
+                            None => return Err(format!("Unclosed field {{{field}")),
+                        }
+                    }
+
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+
+                    let (name, width) = match field.split_once(':') {
+                        Some((name, width)) => {
+                            let width = width
+                                .parse::<usize>()
+                                .map_err(|_| format!("Invalid width in field {{{field}}}"))?;
+                            (name, Some(width))
+                        }
+                        None => (field.as_str(), None),
+                    };
+
+                    parts.push(Part::Field(name.trim().parse()?, width));
+                }
+                '}' => return Err(String::from("Unmatched '}'. Use '}}' for a literal brace")),
+                '/' | '\\' => {
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Separator);
+                }
+                c => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+
+        if !parts.iter().any(|part| matches!(part, Part::Field(..))) {
+            return Err(String::from(
+                "Template must have at least one field, like {id} or {md5}",
+            ));
+        }
+
+        if parts.first() == Some(&Part::Separator) {
+            return Err(String::from("Template must be a relative path"));
+        }
+
+        if !parts
+            .iter()
+            .any(|part| matches!(part, Part::Field(Field::Ext, _)))
+        {
+            parts.push(Part::Literal(String::from(".")));
+            parts.push(Part::Field(Field::Ext, None));
+        }
+
+        Ok(Self { parts })
+    }
+}
+
+impl FilenameTemplate {
+    /// Path of `post` relative to the output directory, with `/` between directories.
+    pub fn render(&self, post: &Post, server: &str) -> String {
+        let mut components = vec![String::new()];
+
+        for part in &self.parts {
+            let current = components.last_mut().unwrap();
+
+            match part {
+                Part::Separator => components.push(String::new()),
+                Part::Literal(text) => current.push_str(&sanitize(text)),
+                Part::Field(field, width) => {
+                    current.push_str(&sanitize(&field_value(*field, *width, post, server)));
+                }
+            }
+        }
+
+        let last = components.len() - 1;
+
+        components
+            .into_iter()
+            .enumerate()
+            .map(|(idx, component)| finish_component(&component, idx == last))
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+fn field_value(field: Field, width: Option<usize>, post: &Post, server: &str) -> String {
+    let tags = |tag_type: TagType| {
+        let list = post
+            .tags
+            .iter()
+            .filter(|tag| tag.tag_type() == tag_type)
+            .map(|tag| tag.tag())
+            .collect::<Vec<_>>();
+
+        if list.is_empty() {
+            String::from(EMPTY_FIELD)
+        } else {
+            list.join(", ")
+        }
+    };
+
+    let text = match field {
+        Field::Id | Field::Index => {
+            let width = width.unwrap_or_default();
+            return format!("{:0width$}", post.id);
+        }
+        Field::Md5 => post.md5.clone(),
+        Field::Ext => post.extension.to_string(),
+        Field::Server => server.to_string(),
+        Field::Rating => post.rating.to_string(),
+        Field::Artist => tags(TagType::Author),
+        Field::Copyright => tags(TagType::Copyright),
+        Field::Character => tags(TagType::Character),
+    };
+
+    match width {
+        Some(width) => text.chars().take(width).collect(),
+        None => text,
+    }
+}
+
+/// Turns any text into a valid directory name.
+pub(super) fn path_component(text: &str) -> String {
+    finish_component(&sanitize(text), false)
+}
+
+/// Replaces characters that can't be used in file names on some systems.
+fn sanitize(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// Trims and shortens a single file or directory name, keeping the extension of file names.
+fn finish_component(component: &str, is_file: bool) -> String {
+    let component = component.trim().trim_end_matches('.');
+
+    if component.is_empty() || component == "." || component == ".." {
+        return String::from("_");
+    }
+
+    let (stem, ext) = match component.rsplit_once('.') {
+        Some((stem, ext)) if is_file && !stem.is_empty() => (stem, ext),
+        _ => (component, ""),
+    };
+
+    let max_stem = MAX_COMPONENT_LEN.saturating_sub(ext.len() + 1);
+
+    if stem.len() <= max_stem {
+        return component.to_string();
+    }
+
+    let mut short = String::with_capacity(MAX_COMPONENT_LEN);
+    for c in stem.chars() {
+        if short.len() + c.len_utf8() > max_stem {
+            break;
+        }
+        short.push(c);
+    }
+
+    let short = short.trim_end();
+
+    if ext.is_empty() {
+        short.to_string()
+    } else {
+        format!("{short}.{ext}")
+    }
+}
@@ -2,11 +2,13 @@ use std::path::{Path, PathBuf};
 
 use futures::StreamExt;
 use ibdl_common::{
+    events::{emit, DownloadTracker, Event, SkipReason},
     log::debug,
     post::{error::PostError, NameType, Post},
-    reqwest::Client,
+    reqwest::{header::RANGE, Client, StatusCode},
+    retry::RetryPolicy,
     tokio::{
-        fs::{read, remove_file, rename, OpenOptions},
+        fs::{create_dir_all, hard_link, metadata, read, remove_file, rename, write, OpenOptions},
         io::{AsyncWriteExt, BufWriter},
         sync::mpsc::Sender,
         task,
@@ -17,9 +19,20 @@ use md5::compute;
 use owo_colors::OwoColorize;
 use tokio_stream::wrappers::UnboundedReceiverStream;
 
-use crate::error::QueueError;
+use crate::{
+    error::QueueError,
+    report::FailedPost,
+    similar::{SimilarAction, SimilarImages},
+};
 
-use super::{get_counters, Queue};
+use super::{
+    check_status,
+    embed::is_embedded,
+    get_counters,
+    metadata::{post_metadata, write_metadata},
+    ugoira::{convert_ugoira, UgoiraFormat},
+    Queue,
+};
 
 impl Queue {
     pub(crate) async fn download_channel(
@@ -30,115 +43,222 @@ impl Queue {
         pool: bool,
     ) {
         let sender = progress.clone();
+        let file_hooks = self.hooks.file_runner();
 
         channel
             .map(|d| {
-                let nt = self.name_type;
-
-                let cli = self.client.clone();
+                let (source, cli) = self.source(&d);
+                let retry = source.retry;
                 let output = output_dir.clone();
-                let file_path = output_dir.join(d.file_name(self.name_type));
-                let variant = self.imageboard.server;
+                let fname = self.output_name(&d, pool);
+                let file_path = output_dir.join(&fname);
+                let variant = source.server;
+                let server = source.name.clone();
+                let archive = self.archive.clone();
+                let similar = self.similar.clone();
+                let ugoira = self.ugoira_format(&d);
+                let converted = output_dir.join(self.saved_name(&d, pool));
+                let keep_zip = self.keep_ugoira_zip;
+                let failures = self.failures.clone();
                 let sender_chn = sender.clone();
 
                 task::spawn(async move {
-                    if !Self::check_file_exists(&d, &file_path, nt).await? {
-                        Self::fetch(cli, variant, &d, &output, nt, pool).await?;
+                    let file = converted.display().to_string();
+
+                    emit(&Event::PostQueued {
+                        server: &server,
+                        id: d.id,
+                        md5: &d.md5,
+                    });
+
+                    let skipped = |reason| {
+                        emit(&Event::DownloadSkipped {
+                            id: d.id,
+                            file: &file,
+                            reason,
+                        });
+                    };
+
+                    let result = async {
+                        if Self::archived(archive.as_ref(), &server, &d, &fname)? {
+                            skipped(SkipReason::Archived);
+                            let _ = sender_chn.send(true).await;
+                            return Ok::<Option<(Post, bool)>, QueueError>(None);
+                        }
+
+                        // The zip of converted ugoira posts is usually gone by the next run
+                        if ugoira.is_some() && converted.exists() {
+                            debug!("Converted file {} found.", converted.display());
+                            skipped(SkipReason::Exists);
+                            let _ = sender_chn.send(true).await;
+                            return Ok(Some((d.clone(), false)));
+                        }
+
+                        let saved =
+                            !Self::check_file_exists(&d, &output, &file_path, &fname).await?;
+
+                        let placed = !saved
+                            || Self::fetch(cli, retry, variant, &d, &file_path, similar.as_ref())
+                                .await?;
+
+                        if let Some(format) = ugoira.filter(|_| placed) {
+                            Self::convert_ugoira_file(&d, &file_path, &converted, format, keep_zip)
+                                .await?;
+                        }
+
+                        // Skipped similar images can still be downloaded once the match is gone
+                        if let Some(archive) = archive.as_ref().filter(|_| placed) {
+                            archive.insert(&server, &d, &fname)?;
+                        }
+
+                        let _ = sender_chn.send(true).await;
+
+                        if !placed {
+                            skipped(SkipReason::Similar);
+                            return Ok(None);
+                        }
+
+                        if saved {
+                            emit(&Event::DownloadFinished {
+                                id: d.id,
+                                file: &file,
+                            });
+                        } else {
+                            skipped(SkipReason::Exists);
+                        }
+
+                        Ok(Some((d.clone(), saved)))
+                    }
+                    .await;
+
+                    if let Err(error) = &result {
+                        emit(&Event::DownloadFailed {
+                            id: d.id,
+                            file: &file,
+                            error: error.to_string(),
+                        });
+                        failures
+                            .lock()
+                            .unwrap()
+                            .push(FailedPost::new(&server, &d, error));
                     }
-                    let _ = sender_chn.send(true).await;
 
-                    Ok::<Post, QueueError>(d)
+                    result
                 })
             })
             .buffer_unordered(self.sim_downloads as usize)
             .for_each(|task| async {
-                if let Ok(Ok(post)) = task {
+                if let Ok(Ok(Some((post, saved)))) = task {
+                    let (source, _) = self.source(&post);
+                    let fname = self.saved_name(&post, pool);
+                    let file = output_dir.join(&fname);
+
                     if self.annotate {
-                        if let Err(error) =
-                            Self::write_caption(&post, self.name_type, &output_dir).await
-                        {
+                        if let Err(error) = Self::write_caption(&post, &file).await {
                             let ctrs = get_counters();
                             ctrs.multi
                                 .println(format!(
                                     "{} {}: {}",
                                     "Failed to write caption file for".red().bold(),
-                                    post.file_name(self.name_type).red().bold(),
+                                    fname.red().bold(),
                                     error
                                 ))
                                 .unwrap();
                         };
                     }
+
+                    if let Some(format) = self.metadata {
+                        let name = file
+                            .file_stem()
+                            .map(|stem| stem.to_string_lossy().to_string())
+                            .unwrap_or_default();
+                        let dir = file.parent().unwrap_or(&output_dir);
+
+                        let written = match post_metadata(&post, &source, format) {
+                            Ok(metadata) => write_metadata(&metadata, &post.md5, &file, &name, dir)
+                                .await
+                                .map_err(QueueError::from),
+                            Err(error) => Err(error),
+                        };
+
+                        if let Err(error) = written {
+                            let ctrs = get_counters();
+                            ctrs.multi
+                                .println(format!(
+                                    "{} {}: {}",
+                                    "Failed to write metadata file for".red().bold(),
+                                    name.red().bold(),
+                                    error
+                                ))
+                                .unwrap();
+                        }
+                    }
+
+                    if let Some(hooks) = file_hooks.as_ref().filter(|_| saved) {
+                        let post_url = source.post_page_url(post.id);
+                        hooks.file_saved(file, post, source.name.clone(), post_url);
+                    }
                 }
             })
-            .await
+            .await;
+
+        if let Some(hooks) = file_hooks {
+            hooks.finish().await;
+        }
     }
 
+    /// Checks if `post` was already downloaded to `output`, the path `name` inside `output_dir`.
+    ///
+    /// Files saved with the ID or MD5 of the post as name in `output_dir` are moved to `output`.
     async fn check_file_exists(
         post: &Post,
+        output_dir: &Path,
         output: &Path,
-        name_type: NameType,
+        name: &str,
     ) -> Result<bool, QueueError> {
         let counters = get_counters();
-        let id_name = post.file_name(NameType::ID);
-        let md5_name = post.file_name(NameType::MD5);
 
-        let name = post.file_name(name_type);
+        let (actual, file_is_same) = if output.exists() {
+            debug!("File {} found.", name);
+            (output.to_path_buf(), false)
+        } else {
+            debug!("File {} not found.", name);
 
-        let raw_path = output.parent().unwrap();
+            let similar = [NameType::MD5, NameType::ID]
+                .into_iter()
+                .map(|name_type| output_dir.join(post.file_name(name_type)))
+                .find(|path| path != output && path.exists());
 
-        let (actual, file_is_same) = match (name_type, output.exists()) {
-            (NameType::ID, true) | (NameType::MD5, true) => {
-                debug!("File {} found.", &name);
-                (output.to_path_buf(), false)
-            }
-            (NameType::ID, false) => {
-                debug!("File {} not found.", &name);
-                debug!("Trying possibly matching file: {}", &md5_name);
-                (raw_path.join(Path::new(&md5_name)), true)
-            }
-            (NameType::MD5, false) => {
-                debug!("File {} not found.", &name);
-                debug!("Trying possibly matching file: {}", &id_name);
-                (raw_path.join(Path::new(&id_name)), true)
+            match similar {
+                Some(path) => {
+                    debug!("Trying possibly matching file: {}", path.display());
+                    (path, true)
+                }
+                None => return Ok(false),
             }
         };
 
-        if actual.exists() {
-            debug!(
-                "Found file {}",
-                actual.file_name().unwrap().to_str().unwrap()
-            );
-            let file_digest = compute(read(&actual).await?);
-            let hash = format!("{:x}", file_digest);
-            if hash == post.md5 {
-                if file_is_same {
-                    match counters.multi.println(format!(
-                        "{} {} {}",
-                        "A file similar to".bold().green(),
-                        name.bold().blue().italic(),
-                        "already exists and will be renamed accordingly."
-                            .bold()
-                            .green()
-                    )) {
-                        Ok(_) => {
-                            rename(&actual, output).await?;
-                        }
-                        Err(error) => {
-                            return Err(QueueError::ProgressBarPrintFail {
-                                message: error.to_string(),
-                            })
-                        }
-                    };
+        debug!("Found file {}", actual.display());
+        let data = read(&actual).await?;
+        let hash = format!("{:x}", compute(&data));
 
-                    return Ok(true);
-                }
+        // Files with embedded metadata no longer match the original hash
+        if hash == post.md5 || is_embedded(&data, &post.md5) {
+            if file_is_same {
                 match counters.multi.println(format!(
                     "{} {} {}",
-                    "File".bold().green(),
+                    "A file similar to".bold().green(),
                     name.bold().blue().italic(),
-                    "already exists. Skipping.".bold().green()
+                    "already exists and will be renamed accordingly."
+                        .bold()
+                        .green()
                 )) {
-                    Ok(_) => (),
+                    Ok(_) => {
+                        if let Some(parent) = output.parent() {
+                            create_dir_all(parent).await?;
+                        }
+                        rename(&actual, output).await?;
+                    }
                     Err(error) => {
                         return Err(QueueError::ProgressBarPrintFail {
                             message: error.to_string(),
@@ -148,64 +268,149 @@ impl Queue {
 
                 return Ok(true);
             }
-            remove_file(&actual).await?;
-            counters.multi.println(format!(
+            match counters.multi.println(format!(
                 "{} {} {}",
-                "File".bold().red(),
-                name.bold().yellow().italic(),
-                "MD5 mismatch. Redownloading...".bold().red()
-            ))?;
+                "File".bold().green(),
+                name.bold().blue().italic(),
+                "already exists. Skipping.".bold().green()
+            )) {
+                Ok(_) => (),
+                Err(error) => {
+                    return Err(QueueError::ProgressBarPrintFail {
+                        message: error.to_string(),
+                    })
+                }
+            };
+
+            return Ok(true);
         }
+        remove_file(&actual).await?;
+        counters.multi.println(format!(
+            "{} {} {}",
+            "File".bold().red(),
+            name.bold().yellow().italic(),
+            "MD5 mismatch. Redownloading...".bold().red()
+        ))?;
         Ok(false)
     }
 
+    /// Converts the ugoira zip of `post` at `zip` into an animation at `out`, removing the zip
+    /// unless `keep_zip` is set.
+    async fn convert_ugoira_file(
+        post: &Post,
+        zip: &Path,
+        out: &Path,
+        format: UgoiraFormat,
+        keep_zip: bool,
+    ) -> Result<(), QueueError> {
+        let data = read(zip).await?;
+        let delays = post.frame_delays.clone().unwrap_or_default();
+
+        debug!("Converting {} to {}", zip.display(), format.extension());
+
+        // Decoding and encoding every frame takes a while, so keep it off the async threads
+        let animation = task::spawn_blocking(move || convert_ugoira(&data, &delays, format))
+            .await
+            .map_err(|error| QueueError::UgoiraConversion {
+                message: error.to_string(),
+            })??;
+
+        write(out, animation).await?;
+
+        if !keep_zip {
+            remove_file(zip).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Downloads `post` into `out`.
+    ///
+    /// Returns `false` if the file was skipped for looking like one downloaded before.
     async fn fetch(
         client: Client,
+        retry: RetryPolicy,
         variant: ImageBoards,
         post: &Post,
-        output: &Path,
-        name_type: NameType,
-        pool: bool,
-    ) -> Result<(), PostError> {
+        out: &Path,
+        similar: Option<&SimilarImages>,
+    ) -> Result<bool, PostError> {
         debug!("Fetching {}", &post.url);
 
         let counters = get_counters();
 
-        let res = client.get(&post.url).send().await?;
+        let fname = out
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let part = out.with_file_name(format!("{fname}.part"));
+
+        if let Some(parent) = out.parent() {
+            create_dir_all(parent).await?;
+        }
+
+        // Pick up where a previous run left off, if there's anything to resume.
+        let offset = metadata(&part).await.map_or(0, |meta| meta.len());
+
+        let mut request = client.get(&post.url);
 
-        if res.status().is_client_error() {
+        if offset > 0 {
+            debug!("Requesting {} from byte {}", &fname, offset);
+            request = request.header(RANGE, format!("bytes={offset}-"));
+        }
+
+        let res = retry.send(request, None).await?;
+
+        if res.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+            // The partial file already holds the whole body.
+            debug!("Server reports {} as fully downloaded", &fname);
+            return Self::finish_part(post, &part, out, similar).await;
+        }
+
+        let res = check_status(res)?;
+
+        let resumed = offset > 0 && res.status() == StatusCode::PARTIAL_CONTENT;
+
+        if resumed {
             counters.multi.println(format!(
-                "{} {}{}",
-                "Image source returned status".bold().red(),
-                res.status().as_str().bold().red(),
-                ". Skipping download.".bold().red()
+                "{} {}",
+                "Resuming download of".bold().green(),
+                fname.bold().blue().italic()
             ))?;
-            counters.main.inc(1);
-            return Err(PostError::RemoteFileNotFound);
+        } else if offset > 0 {
+            debug!("Server ignored range request for {}. Restarting.", &fname);
         }
 
         let size = res.content_length().unwrap_or_default();
 
-        let pb = counters.add_download_bar(size, variant);
+        let pb = if resumed {
+            let pb = counters.add_download_bar(size + offset, variant);
+            pb.set_position(offset);
+            pb
+        } else {
+            counters.add_download_bar(size, variant)
+        };
+
+        let file = out.display().to_string();
+        let mut tracker = if resumed {
+            DownloadTracker::start(post.id, &file, size + offset, offset)
+        } else {
+            DownloadTracker::start(post.id, &file, size, 0)
+        };
 
         // Download the file chunk by chunk.
         let mut stream = res.bytes_stream();
 
         let buf_size: usize = size.try_into()?;
 
-        let fname = if pool {
-            post.seq_file_name(6)
-        } else {
-            post.file_name(name_type)
-        };
-
-        let out = output.join(fname);
-
-        debug!("Creating {:?}", &out);
+        debug!("Writing to {:?}", &part);
         let file = OpenOptions::new()
-            .append(true)
             .create(true)
-            .open(out)
+            .write(true)
+            .append(resumed)
+            .truncate(!resumed)
+            .open(&part)
             .await?;
 
         let mut bw = BufWriter::with_capacity(buf_size, file);
@@ -215,12 +420,14 @@ impl Queue {
             let mut chunk = match item {
                 Ok(chunk) => chunk,
                 Err(e) => {
+                    bw.flush().await?;
                     return Err(PostError::ChunkDownloadFail {
                         message: e.to_string(),
-                    })
+                    });
                 }
             };
             pb.inc(chunk.len().try_into()?);
+            tracker.inc(chunk.len().try_into()?);
 
             // Write to file.
             bw.write_all_buf(&mut chunk).await?;
@@ -229,6 +436,112 @@ impl Queue {
 
         pb.finish_and_clear();
 
-        Ok(())
+        Self::finish_part(post, &part, out, similar).await
+    }
+
+    /// Verifies the MD5 of a finished `.part` file, checks it against the index of similar images
+    /// and moves it into its final place.
+    ///
+    /// A corrupted partial file is removed so the next run starts from scratch. Returns `false` if
+    /// the file was skipped for looking like one downloaded before.
+    async fn finish_part(
+        post: &Post,
+        part: &Path,
+        out: &Path,
+        similar: Option<&SimilarImages>,
+    ) -> Result<bool, PostError> {
+        if !post.md5.is_empty() {
+            let hash = format!("{:x}", compute(read(part).await?));
+
+            if hash != post.md5 {
+                remove_file(part).await?;
+                return Err(PostError::HashMismatch {
+                    expected: post.md5.clone(),
+                    found: hash,
+                });
+            }
+        }
+
+        if let Some(similar) = similar {
+            if let Some(placed) = Self::place_similar(similar, part, out).await? {
+                return Ok(placed);
+            }
+        }
+
+        rename(part, out).await?;
+        debug!("Saved {:?}", out);
+
+        Ok(true)
+    }
+
+    /// Applies the [`SimilarAction`] to `part` if it looks like an image saved before.
+    ///
+    /// Returns whether a file was placed at `out`, or `None` if `part` still has to be moved there.
+    async fn place_similar(
+        similar: &SimilarImages,
+        part: &Path,
+        out: &Path,
+    ) -> Result<Option<bool>, PostError> {
+        let counters = get_counters();
+
+        let name = out
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let found = match similar.check(part, out).await {
+            Ok(Some(found)) => found,
+            Ok(None) => return Ok(None),
+            Err(error) => {
+                counters.multi.println(format!(
+                    "{} {}: {}",
+                    "Failed to check for similar images to".red().bold(),
+                    name.red().bold(),
+                    error
+                ))?;
+                return Ok(None);
+            }
+        };
+
+        let message = format!(
+            "{} {} {} {} ({}%)",
+            "File".bold().yellow(),
+            name.bold().blue().italic(),
+            "looks like".bold().yellow(),
+            found.path.display().bold().blue().italic(),
+            found.similarity,
+        );
+
+        match similar.action {
+            SimilarAction::Skip => {
+                counters
+                    .multi
+                    .println(format!("{message}{}", ". Skipping.".bold().yellow()))?;
+                remove_file(part).await?;
+                Ok(Some(false))
+            }
+            SimilarAction::Hardlink => {
+                if hard_link(&found.path, out).await.is_err() {
+                    // Files in another filesystem can't be linked, so keep the download instead
+                    counters.multi.println(format!(
+                        "{message}{}",
+                        ", but can't be linked to it. Saving a copy."
+                            .bold()
+                            .yellow()
+                    ))?;
+                    return Ok(None);
+                }
+
+                counters
+                    .multi
+                    .println(format!("{message}{}", ". Saved as a link.".bold().yellow()))?;
+                remove_file(part).await?;
+                Ok(Some(true))
+            }
+            SimilarAction::Report => {
+                counters.multi.println(message)?;
+                Ok(None)
+            }
+        }
     }
 }
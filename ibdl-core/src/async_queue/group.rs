@@ -0,0 +1,44 @@
+//! Subdirectories used to sort downloaded posts.
+use clap::ValueEnum;
+use ibdl_common::post::{rating::Rating, tags::TagType, Post};
+
+use super::filename::path_component;
+
+/// Property of a post used to pick the subdirectory it's saved to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum GroupBy {
+    /// `Safe`, `Questionable`, `Explicit` or `Unknown`
+    Rating,
+    /// First artist tag of the post
+    Artist,
+    /// First copyright tag of the post
+    Copyright,
+    /// First character tag of the post
+    Character,
+    /// File extension, like `png` or `webm`
+    Extension,
+}
+
+impl GroupBy {
+    /// Name of the subdirectory `post` belongs to.
+    ///
+    /// Posts without a tag of the selected type go to `Unknown`, same as posts with an unknown
+    /// rating.
+    pub fn directory(self, post: &Post) -> String {
+        let tag_type = match self {
+            Self::Rating => return post.rating.to_string(),
+            Self::Extension => return post.extension.to_string(),
+            Self::Artist => TagType::Author,
+            Self::Copyright => TagType::Copyright,
+            Self::Character => TagType::Character,
+        };
+
+        post.tags
+            .iter()
+            .find(|tag| tag.tag_type() == tag_type)
+            .map_or_else(
+                || Rating::Unknown.to_string(),
+                |tag| path_component(&tag.tag()),
+            )
+    }
+}
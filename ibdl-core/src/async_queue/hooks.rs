@@ -0,0 +1,243 @@
+//! User commands run after downloads.
+//!
+//! Commands are run through the system shell (`sh -c` or `cmd /C`). Every command receives the
+//! details of what was downloaded both as `IBDL_*` environment variables and as a JSON object in
+//! its standard input.
+//!
+//! `on_file` commands run in the background, one file at a time, so downloads don't wait for
+//! them. The queue only finishes, and runs the `on_job` commands, after all of them are done.
+//!
+//! A failing command is reported in the progress bar without stopping the queue.
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+
+use ibdl_common::{
+    log::debug,
+    post::Post,
+    serde::{self, Serialize},
+    serde_json,
+    tokio::{
+        io::AsyncWriteExt,
+        join,
+        process::Command,
+        sync::mpsc::{unbounded_channel, UnboundedSender},
+        task::{self, JoinHandle},
+    },
+};
+use owo_colors::OwoColorize;
+
+use super::get_counters;
+
+/// Commands to run after downloading
+#[derive(Debug, Clone, Default)]
+pub struct Hooks {
+    /// Run after every file saved to the output directory
+    pub on_file: Vec<String>,
+    /// Run once the whole queue is done
+    pub on_job: Vec<String>,
+}
+
+/// Runs the `on_file` commands of the files sent to it in a background task.
+pub struct FileHooks {
+    sender: UnboundedSender<SavedFile>,
+    runner: JoinHandle<()>,
+}
+
+struct SavedFile {
+    file: PathBuf,
+    post: Post,
+    server: String,
+    post_url: String,
+}
+
+impl FileHooks {
+    /// Queues the `on_file` commands for `post`, saved as `file`.
+    pub fn file_saved(&self, file: PathBuf, post: Post, server: String, post_url: String) {
+        let _ = self.sender.send(SavedFile {
+            file,
+            post,
+            server,
+            post_url,
+        });
+    }
+
+    /// Waits for the commands of every file queued so far.
+    pub async fn finish(self) {
+        drop(self.sender);
+        let _ = self.runner.await;
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "self::serde")]
+struct FileInfo<'a> {
+    /// Path of the saved file
+    file: &'a Path,
+    /// Name of the server the post was downloaded from
+    server: &'a str,
+    /// Web page of the post
+    post_url: &'a str,
+    post: &'a Post,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "self::serde")]
+struct JobInfo<'a> {
+    /// Output directory or cbz file
+    output: &'a Path,
+    /// Name of the server the posts were downloaded from
+    server: &'a str,
+    /// Number of files downloaded
+    downloaded: u64,
+}
+
+impl Hooks {
+    /// Starts running `on_file` commands in the background, if there are any.
+    pub(crate) fn file_runner(self: &Arc<Self>) -> Option<FileHooks> {
+        if self.on_file.is_empty() {
+            return None;
+        }
+
+        let hooks = self.clone();
+        let (sender, mut receiver) = unbounded_channel::<SavedFile>();
+
+        let runner = task::spawn(async move {
+            while let Some(saved) = receiver.recv().await {
+                hooks
+                    .file_saved(&saved.file, &saved.post, &saved.server, &saved.post_url)
+                    .await;
+            }
+        });
+
+        Some(FileHooks { sender, runner })
+    }
+
+    /// Runs the `on_file` commands for `post`, saved as `file`.
+    pub async fn file_saved(&self, file: &Path, post: &Post, server: &str, post_url: &str) {
+        if self.on_file.is_empty() {
+            return;
+        }
+
+        let tags = post
+            .tags
+            .iter()
+            .map(|tag| tag.tag())
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let env = [
+            ("IBDL_FILE", file.display().to_string()),
+            ("IBDL_SERVER", server.to_string()),
+            ("IBDL_POST_ID", post.id.to_string()),
+            ("IBDL_POST_MD5", post.md5.clone()),
+            ("IBDL_POST_URL", post_url.to_string()),
+            ("IBDL_FILE_URL", post.url.clone()),
+            ("IBDL_RATING", post.rating.to_string()),
+            ("IBDL_TAGS", tags),
+        ];
+
+        let info = FileInfo {
+            file,
+            server,
+            post_url,
+            post,
+        };
+
+        run(&self.on_file, &env, &info).await;
+    }
+
+    /// Runs the `on_job` commands after `downloaded` posts were saved to `output`.
+    pub async fn job_done(&self, output: &Path, server: &str, downloaded: u64) {
+        if self.on_job.is_empty() {
+            return;
+        }
+
+        let env = [
+            ("IBDL_OUTPUT", output.display().to_string()),
+            ("IBDL_SERVER", server.to_string()),
+            ("IBDL_DOWNLOADED", downloaded.to_string()),
+        ];
+
+        let info = JobInfo {
+            output,
+            server,
+            downloaded,
+        };
+
+        run(&self.on_job, &env, &info).await;
+    }
+}
+
+async fn run<T: Serialize + Sync>(commands: &[String], env: &[(&str, String)], info: &T) {
+    let input = match serde_json::to_vec(info) {
+        Ok(input) => input,
+        Err(error) => {
+            report(&commands.join(", "), &error.to_string());
+            return;
+        }
+    };
+
+    for command in commands {
+        debug!("Running hook: {command}");
+
+        if let Err(error) = spawn(command, env, &input).await {
+            report(command, &error);
+        }
+    }
+}
+
+fn report(command: &str, error: &str) {
+    let _ = get_counters().multi.println(format!(
+        "{} {}: {}",
+        "Hook".red().bold(),
+        command.red().bold(),
+        error
+    ));
+}
+
+async fn spawn(command: &str, env: &[(&str, String)], input: &[u8]) -> Result<(), String> {
+    let mut child = shell(command)
+        .envs(env.iter().map(|(key, value)| (key, value)))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|error| format!("failed to start: {error}"))?;
+
+    let stdin = child.stdin.take();
+
+    // Feed the input while reading the output, so a command filling its stderr pipe before
+    // reading everything can't block both sides.
+    let write = async move {
+        if let Some(mut stdin) = stdin {
+            // The command might not read its input at all, which is fine
+            let _ = stdin.write_all(input).await;
+        }
+    };
+
+    let (_, output) = join!(write, child.wait_with_output());
+    let output = output.map_err(|error| error.to_string())?;
+
+    if output.status.success() {
+        return Ok(());
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    Err(format!("{} {}", output.status, stderr.trim()))
+}
+
+#[cfg(not(windows))]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
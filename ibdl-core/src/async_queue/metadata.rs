@@ -0,0 +1,113 @@
+use std::path::Path;
+
+use clap::ValueEnum;
+use ibdl_common::{
+    log::debug,
+    post::{error::PostError, Post},
+    serde::{self, Serialize},
+    serde_json,
+    tokio::fs::{read, rename, write},
+};
+use ibdl_extractors::extractor_config::ServerConfig;
+
+use crate::error::QueueError;
+
+use super::embed::{embed, is_embedded, xmp_packet};
+
+/// Format of the metadata file saved next to every downloaded post
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum MetadataFormat {
+    /// Write a `<name>.json` file with the full post info and typed tags
+    Json,
+    /// Embed the tags, rating and source URL into the downloaded file itself.
+    ///
+    /// Supports JPEG, PNG and WEBP files. Other formats get a `<name>.json` file instead
+    Embed,
+}
+
+/// Metadata of a single post, ready to be saved.
+#[derive(Debug, Clone)]
+pub struct PostMetadata {
+    /// Contents of the JSON sidecar file
+    pub json: Vec<u8>,
+    /// XMP packet to embed into the file, if selected
+    pub xmp: Option<String>,
+    /// Web page of the post
+    pub source: String,
+}
+
+impl PostMetadata {
+    /// Returns a copy of `data` with the metadata embedded, or `None` if the metadata should be
+    /// saved to a sidecar file instead.
+    pub fn embed_into(&self, data: &[u8]) -> Option<Vec<u8>> {
+        embed(data, self.xmp.as_ref()?, &self.source)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "self::serde")]
+struct JsonMetadata<'a> {
+    #[serde(flatten)]
+    post: &'a Post,
+    /// Name of the server the post was downloaded from
+    server: &'a str,
+    /// Web page of the post
+    post_url: String,
+}
+
+/// Gathers the metadata of `post` in the selected format.
+pub fn post_metadata(
+    post: &Post,
+    imageboard: &ServerConfig,
+    format: MetadataFormat,
+) -> Result<PostMetadata, QueueError> {
+    let source = imageboard.post_page_url(post.id);
+
+    let json = serde_json::to_vec_pretty(&JsonMetadata {
+        post,
+        server: &imageboard.name,
+        post_url: source.clone(),
+    })
+    .map_err(|error| QueueError::MetadataSerializeFail {
+        error: error.to_string(),
+    })?;
+
+    let xmp = (format == MetadataFormat::Embed).then(|| xmp_packet(post, &source));
+
+    Ok(PostMetadata { json, xmp, source })
+}
+
+/// Saves `metadata` for the downloaded `file`.
+///
+/// The metadata is embedded into the file when possible, otherwise it's written into
+/// `<name>.json` inside `output`.
+pub async fn write_metadata(
+    metadata: &PostMetadata,
+    md5: &str,
+    file: &Path,
+    name: &str,
+    output: &Path,
+) -> Result<(), PostError> {
+    if metadata.xmp.is_some() {
+        let data = read(file).await?;
+
+        if is_embedded(&data, md5) {
+            debug!("Metadata already embedded in {}", name);
+            return Ok(());
+        }
+
+        if let Some(embedded) = metadata.embed_into(&data) {
+            // Write to a temporary file first so an interrupted write doesn't corrupt the download
+            let tmp = file.with_extension("meta.part");
+            write(&tmp, embedded).await?;
+            rename(&tmp, file).await?;
+            debug!("Embedded metadata into {}", name);
+            return Ok(());
+        }
+    }
+
+    let outpath = output.join(format!("{name}.json"));
+    write(&outpath, &metadata.json).await?;
+    debug!("Wrote metadata file for {}", name);
+    Ok(())
+}
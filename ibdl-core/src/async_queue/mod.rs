@@ -4,80 +4,141 @@
 //!
 //! Conveniently using the same example from [here](ibdl-extractors::websites)
 //!
-//! ```rust
-//! use imageboard_downloader::*;
+//! ```rust,no_run
+//! use ibdl_common::post::NameType;
+//! use ibdl_common::tokio::{join, sync::mpsc};
+//! use ibdl_core::async_queue::Queue;
+//! use ibdl_extractors::extractor_config::DEFAULT_SERVERS;
+//! use ibdl_extractors::imageboards::danbooru::DanbooruExtractor;
+//! use ibdl_extractors::prelude::*;
 //! use std::path::PathBuf;
+//! use std::sync::{atomic::AtomicU64, Arc};
 //!
 //! async fn download_posts() {
 //!     let tags = ["umbreon", "espeon"]; // The tags to search
-//!     
-//!     let safe_mode = false; // Setting this to true, will ignore searching NSFW posts
 //!
 //!     let disable_blacklist = false; // Will filter all items according to what's set in GBL
 //!
-//!     let mut unit = DanbooruExtractor::new(&tags, safe_mode, disable_blacklist); // Initialize
+//!     let map_videos = false; // Only relevant for ugoira posts
 //!
-//!     let prompt = true; // If true, will ask the user to input thei username and API key.
+//!     let server = DEFAULT_SERVERS.get("danbooru").unwrap().clone();
 //!
-//!     unit.auth(prompt).await.unwrap(); // Try to authenticate
+//!     // Initialize
+//!     let unit = DanbooruExtractor::new_with_config(&tags, &[], disable_blacklist, map_videos, server.clone());
 //!
-//!     let start_page = Some(1); // Start searching from the first page
+//!     let client = unit.client(); // Re-use the client from the extractor
+//!
+//!     let (post_tx, post_rx) = mpsc::unbounded_channel();
+//!     let (length_tx, length_rx) = mpsc::channel(32);
 //!
 //!     let limit = Some(50); // Max number of posts to download
 //!
-//!     let posts = unit.full_search(start_page, limit).await.unwrap(); // and then, finally search
+//!     // Start searching from the first page in the background
+//!     let extractor = unit.setup_fetch_thread(post_tx, None, limit, Some(length_tx));
 //!
 //!     let sd = 10; // Number of simultaneous downloads.
 //!
-//!     let limit = Some(1000); // Max number of posts to download
-//!
 //!     let cbz = false; // Set to true to download everything into a .cbz file
 //!
-//!     let mut qw = Queue::new( // Initialize the queue
-//!         ImageBoards::Danbooru,
-//!         posts,
-//!         sd,
-//!         Some(unit.client()), // Re-use the client from the extractor
-//!         limit,
-//!         cbz,
-//!     );
+//!     let pool = false; // Set to true to name files after their position in a pool
+//!
+//!     let annotate = false; // Set to true to save the tags of every post in a .txt file
 //!
-//!     let output = Some(PathBuf::from("./")); // Where to save the downloaded files or .cbz file
+//!     let qw = Queue::new(server, sd, Some(client), cbz, pool, NameType::ID, annotate);
 //!
-//!     let id = true; // Save file with their ID as the filename instead of MD5
+//!     let output = PathBuf::from("./"); // Where to save the downloaded files or .cbz file
 //!
-//!     qw.download(output, id).await.unwrap(); // Start downloading
+//!     let downloader = qw.setup_async_downloader(output, Arc::new(AtomicU64::new(0)), post_rx, length_rx);
+//!
+//!     let (removed, downloaded) = join!(extractor, downloader); // Start downloading
 //! }
 //! ```
 
 mod cbz;
+mod comic_info;
+pub(crate) mod embed;
+mod filename;
 mod folder;
+mod group;
+mod hooks;
+pub(crate) mod metadata;
+mod sources;
+mod ugoira;
+
+pub use comic_info::ComicDetails;
+pub use filename::FilenameTemplate;
+pub use group::GroupBy;
+pub use hooks::Hooks;
+pub use metadata::MetadataFormat;
+pub use sources::PostSources;
+pub use ugoira::{convert_ugoira, UgoiraFormat};
 
+use crate::archive::DownloadArchive;
 use crate::error::QueueError;
 use crate::progress_bars::ProgressCounter;
+use crate::report::{FailedPost, FailureReport};
+use crate::similar::SimilarImages;
+use ibdl_common::events::{emit, Event};
 use ibdl_common::log::debug;
 use ibdl_common::post::error::PostError;
-use ibdl_common::post::{NameType, Post};
-use ibdl_common::reqwest::Client;
+use ibdl_common::post::{extension::Extension, NameType, Post};
+use ibdl_common::reqwest::{Client, Response};
 use ibdl_common::tokio::spawn;
 use ibdl_common::tokio::sync::mpsc::{channel, Receiver, UnboundedReceiver};
 use ibdl_common::tokio::task::JoinHandle;
-use ibdl_common::{client, tokio};
+use ibdl_common::{client, tokio, ImageBoards};
 use ibdl_extractors::extractor_config::ServerConfig;
 use once_cell::sync::OnceCell;
+use owo_colors::OwoColorize;
 use std::path::{Path, PathBuf};
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 use tokio::fs::{create_dir_all, OpenOptions};
 use tokio::io::AsyncWriteExt;
 use tokio_stream::wrappers::UnboundedReceiverStream;
 
 static PROGRESS_COUNTERS: OnceCell<ProgressCounter> = OnceCell::new();
 
+/// Number of queues currently sharing [`PROGRESS_COUNTERS`]
+static ACTIVE_QUEUES: AtomicUsize = AtomicUsize::new(0);
+
 pub(crate) fn get_counters() -> &'static ProgressCounter {
     PROGRESS_COUNTERS.get().unwrap()
 }
 
+/// Progress bars shared by every queue, created with the style of `imageboard` on first use.
+pub(crate) fn init_counters(len: u64, imageboard: ImageBoards) -> &'static ProgressCounter {
+    PROGRESS_COUNTERS.get_or_init(|| ProgressCounter::initialize(len, imageboard))
+}
+
+/// Passes `res` through if the server answered with a success status (including `206`).
+///
+/// Anything else skips the post: server errors count as connection failures, so they can be
+/// retried later, while other statuses mean the file is gone.
+pub(crate) fn check_status(res: Response) -> Result<Response, PostError> {
+    let status = res.status();
+
+    if status.is_success() {
+        return Ok(res);
+    }
+
+    let counters = get_counters();
+
+    counters.multi.println(format!(
+        "{} {}{}",
+        "Image source returned status".bold().red(),
+        status.as_str().bold().red(),
+        ". Skipping download.".bold().red()
+    ))?;
+    counters.main.inc(1);
+
+    if status.is_server_error() {
+        return Err(res.error_for_status().unwrap_err().into());
+    }
+
+    Err(PostError::RemoteFileNotFound)
+}
+
 #[derive(Debug, Copy, Clone)]
 enum DownloadFormat {
     Cbz,
@@ -110,12 +171,23 @@ impl DownloadFormat {
 
 /// Struct where all the downloading will take place
 pub struct Queue {
-    imageboard: ServerConfig,
+    imageboard: Arc<ServerConfig>,
     sim_downloads: u8,
     client: Client,
     download_fmt: DownloadFormat,
     name_type: NameType,
     annotate: bool,
+    archive: Option<DownloadArchive>,
+    metadata: Option<MetadataFormat>,
+    comic: ComicDetails,
+    hooks: Arc<Hooks>,
+    filename: Option<FilenameTemplate>,
+    group_by: Option<GroupBy>,
+    sources: Option<PostSources>,
+    similar: Option<SimilarImages>,
+    ugoira: Option<UgoiraFormat>,
+    keep_ugoira_zip: bool,
+    failures: Arc<Mutex<Vec<FailedPost>>>,
 }
 
 impl Queue {
@@ -147,14 +219,97 @@ impl Queue {
 
         Self {
             download_fmt,
-            imageboard,
+            imageboard: Arc::new(imageboard),
             sim_downloads,
             annotate,
             client,
             name_type,
+            archive: None,
+            metadata: None,
+            comic: ComicDetails {
+                series: pool_download,
+                ..Default::default()
+            },
+            hooks: Arc::default(),
+            filename: None,
+            group_by: None,
+            sources: None,
+            similar: None,
+            ugoira: None,
+            keep_ugoira_zip: false,
+            failures: Arc::default(),
         }
     }
 
+    /// Skip posts already recorded in the [download archive](DownloadArchive) and record every
+    /// new download into it.
+    pub fn use_archive(&mut self, archive: DownloadArchive) -> &mut Self {
+        self.archive = Some(archive);
+        self
+    }
+
+    /// Check every downloaded image against the [index of similar images](crate::similar) before
+    /// saving it.
+    ///
+    /// Only applies to downloads into a folder.
+    pub fn detect_similar(&mut self, similar: SimilarImages) -> &mut Self {
+        self.similar = Some(similar);
+        self
+    }
+
+    /// Convert ugoira posts with known frame delays into an animation in `format`, keeping the
+    /// original zip next to it if `keep_zip` is set.
+    ///
+    /// Only applies to downloads into a folder.
+    pub const fn convert_ugoira(&mut self, format: UgoiraFormat, keep_zip: bool) -> &mut Self {
+        self.ugoira = Some(format);
+        self.keep_ugoira_zip = keep_zip;
+        self
+    }
+
+    /// Save a metadata file in the selected format next to every downloaded post.
+    pub const fn save_metadata(&mut self, format: MetadataFormat) -> &mut Self {
+        self.metadata = Some(format);
+        self
+    }
+
+    /// Title and summary written into the `ComicInfo.xml` of cbz files.
+    ///
+    /// On pool downloads, the title is also used as the series name.
+    pub fn comic_info(&mut self, title: String, summary: Option<String>) -> &mut Self {
+        self.comic.title = Some(title);
+        self.comic.summary = summary;
+        self
+    }
+
+    /// Name files after `template` instead of their ID or MD5.
+    pub fn filename_template(&mut self, template: FilenameTemplate) -> &mut Self {
+        self.filename = Some(template);
+        self
+    }
+
+    /// Sort files into subdirectories by rating, tag or extension.
+    ///
+    /// On cbz downloads, replaces the default rating directories.
+    pub const fn group_by(&mut self, group: GroupBy) -> &mut Self {
+        self.group_by = Some(group);
+        self
+    }
+
+    /// Look up the server of every post in `sources`, for posts coming from many servers at once.
+    ///
+    /// Posts missing from `sources` are treated as coming from the server of the queue.
+    pub fn post_sources(&mut self, sources: PostSources) -> &mut Self {
+        self.sources = Some(sources);
+        self
+    }
+
+    /// Commands to run after every saved file and after the whole queue is done.
+    pub fn hooks(&mut self, hooks: Hooks) -> &mut Self {
+        self.hooks = Arc::new(hooks);
+        self
+    }
+
     pub fn setup_async_downloader(
         self,
         output_dir: PathBuf,
@@ -165,47 +320,148 @@ impl Queue {
         spawn(async move {
             debug!("Async Downloader thread initialized");
 
-            let counters = PROGRESS_COUNTERS.get_or_init(|| {
-                ProgressCounter::initialize(
-                    post_counter.load(Ordering::Relaxed),
-                    self.imageboard.server,
-                )
+            emit(&Event::JobStarted {
+                server: &self.imageboard.name,
+                output: &output_dir.display().to_string(),
             });
 
-            self.create_out(&output_dir).await?;
-
-            let post_channel = UnboundedReceiverStream::new(channel_rx);
-            let (progress_sender, progress_channel) = channel(self.sim_downloads as usize);
-
-            counters.init_length_updater(length_rx).await;
-            counters.init_download_counter(progress_channel).await;
-
-            if self.download_fmt.download_cbz() {
-                self.cbz_path(
-                    output_dir,
-                    progress_sender,
-                    post_channel,
-                    self.download_fmt.download_pool(),
-                )
-                .await?;
-            } else {
-                self.download_channel(
-                    post_channel,
-                    progress_sender,
-                    output_dir,
-                    self.download_fmt.download_pool(),
-                )
-                .await;
+            let counters =
+                init_counters(post_counter.load(Ordering::Relaxed), self.imageboard.server);
+
+            // Other queues might still be using the progress bar when running multiple jobs at once
+            if ACTIVE_QUEUES.fetch_add(1, Ordering::SeqCst) == 0 && counters.main.is_finished() {
+                counters.restart(post_counter.load(Ordering::Relaxed));
             }
 
-            counters.main.finish_and_clear();
+            let result = self
+                .run_downloader(counters, output_dir.clone(), channel_rx, length_rx)
+                .await
+                .and_then(|downloaded| {
+                    self.save_failures(counters, &output_dir)?;
+                    Ok(downloaded)
+                });
 
-            let tot = counters.downloaded_mtx.load(Ordering::SeqCst);
+            if let Ok(downloaded) = result {
+                self.hooks
+                    .job_done(&output_dir, &self.imageboard.name, downloaded)
+                    .await;
+            }
+
+            if ACTIVE_QUEUES.fetch_sub(1, Ordering::SeqCst) == 1 {
+                counters.main.finish_and_clear();
+            }
 
-            Ok(tot)
+            result
         })
     }
 
+    async fn run_downloader(
+        &self,
+        counters: &ProgressCounter,
+        output_dir: PathBuf,
+        channel_rx: UnboundedReceiver<Post>,
+        length_rx: Receiver<u64>,
+    ) -> Result<u64, QueueError> {
+        self.create_out(&output_dir).await?;
+
+        let post_channel = UnboundedReceiverStream::new(channel_rx);
+        let (progress_sender, progress_channel) = channel(self.sim_downloads as usize);
+
+        counters.init_length_updater(length_rx).await;
+        let downloaded = counters.init_download_counter(progress_channel).await;
+
+        if self.download_fmt.download_cbz() {
+            self.cbz_path(
+                output_dir,
+                progress_sender,
+                post_channel,
+                self.download_fmt.download_pool(),
+            )
+            .await?;
+        } else {
+            self.download_channel(
+                post_channel,
+                progress_sender,
+                output_dir,
+                self.download_fmt.download_pool(),
+            )
+            .await;
+        }
+
+        Ok(downloaded.await.unwrap_or_default())
+    }
+
+    /// Saves the [report](FailureReport) of the posts that failed to download into `output_dir`,
+    /// or removes the one left by an earlier run if all of them were downloaded this time.
+    ///
+    /// Downloads into cbz files only print how many posts failed, since `retry` can't add them to
+    /// the finished file.
+    fn save_failures(
+        &self,
+        counters: &ProgressCounter,
+        output_dir: &Path,
+    ) -> Result<(), QueueError> {
+        let posts = std::mem::take(&mut *self.failures.lock().unwrap());
+
+        if self.download_fmt.download_cbz() {
+            return Self::report_cbz_failures(counters, posts.len());
+        }
+
+        if posts.is_empty() {
+            return FailureReport::remove(output_dir);
+        }
+
+        let failed = posts.len() as u64;
+        let report = FailureReport {
+            output: output_dir.to_path_buf(),
+            cbz: false,
+            pool: self.download_fmt.download_pool(),
+            posts,
+        };
+
+        let path = report.save()?;
+
+        emit(&Event::FailuresSaved {
+            output: &output_dir.display().to_string(),
+            report: &path.display().to_string(),
+            failed,
+        });
+
+        if let Err(error) = counters.multi.println(format!(
+            "{} {} {}",
+            failed.to_string().bold().red(),
+            "files failed to download. Retry them with".bold().red(),
+            format!("imageboard_downloader retry \"{}\"", path.display())
+                .bold()
+                .blue()
+        )) {
+            return Err(QueueError::ProgressBarPrintFail {
+                message: error.to_string(),
+            });
+        }
+
+        Ok(())
+    }
+
+    fn report_cbz_failures(counters: &ProgressCounter, failed: usize) -> Result<(), QueueError> {
+        if failed == 0 {
+            return Ok(());
+        }
+
+        counters
+            .multi
+            .println(format!(
+                "{} {}",
+                failed.to_string().bold().red(),
+                "files failed to download and were left out of the cbz file"
+                    .bold()
+                    .red(),
+            ))
+            .map_err(|error| QueueError::ProgressBarPrintFail {
+                message: error.to_string(),
+            })
+    }
+
     async fn create_out(&self, dir: &Path) -> Result<(), QueueError> {
         if self.download_fmt.download_cbz() {
             let output_file = dir.parent().unwrap().to_path_buf();
@@ -234,14 +490,87 @@ impl Queue {
         Ok(())
     }
 
-    async fn write_caption(
+    /// Checks the [download archive](DownloadArchive), if one is in use, for an earlier download
+    /// of `post`.
+    fn archived(
+        archive: Option<&DownloadArchive>,
+        server: &str,
         post: &Post,
-        name_type: NameType,
-        output: &Path,
-    ) -> Result<(), PostError> {
-        let outpath = output.join(format!("{}.txt", post.name(name_type)));
+        file_name: &str,
+    ) -> Result<bool, QueueError> {
+        let Some(archive) = archive else {
+            return Ok(false);
+        };
+
+        if !archive.contains(server, post)? {
+            return Ok(false);
+        }
+
+        if let Err(error) = get_counters().multi.println(format!(
+            "{} {} {}",
+            "File".bold().green(),
+            file_name.bold().blue().italic(),
+            "is already in the download archive. Skipping."
+                .bold()
+                .green()
+        )) {
+            return Err(QueueError::ProgressBarPrintFail {
+                message: error.to_string(),
+            });
+        }
+
+        Ok(true)
+    }
+
+    /// Server `post` is downloaded from and the client to use with it.
+    fn source(&self, post: &Post) -> (Arc<ServerConfig>, Client) {
+        self.sources
+            .as_ref()
+            .and_then(|sources| sources.get(post))
+            .unwrap_or_else(|| (self.imageboard.clone(), self.client.clone()))
+    }
+
+    /// Path of the post relative to the output directory.
+    fn output_name(&self, post: &Post, pool: bool) -> String {
+        let name = match &self.filename {
+            Some(template) => template.render(post, &self.source(post).0.name),
+            None if pool => post.seq_file_name(6),
+            None => post.file_name(self.name_type),
+        };
+
+        match self.group_by {
+            Some(group) => format!("{}/{name}", group.directory(post)),
+            None => name,
+        }
+    }
+
+    /// Format `post` is converted to, if it's an ugoira that can be converted.
+    fn ugoira_format(&self, post: &Post) -> Option<UgoiraFormat> {
+        self.ugoira
+            .filter(|_| post.extension == Extension::Ugoira && post.frame_delays.is_some())
+    }
+
+    /// Path of the file the post ends up as, relative to the output directory.
+    ///
+    /// Same as [`output_name`](Self::output_name), except for converted ugoira posts.
+    fn saved_name(&self, post: &Post, pool: bool) -> String {
+        let name = self.output_name(post, pool);
+
+        match self.ugoira_format(post) {
+            Some(format) => Path::new(&name)
+                .with_extension(format.extension())
+                .to_string_lossy()
+                .to_string(),
+            None => name,
+        }
+    }
+
+    /// Writes the tags of `post` into a `.txt` file next to the downloaded `file`.
+    async fn write_caption(post: &Post, file: &Path) -> Result<(), PostError> {
+        let outpath = file.with_extension("txt");
         let mut prompt_file = OpenOptions::new()
             .create(true)
+            .truncate(true)
             .write(true)
             .open(outpath)
             .await?;
@@ -258,7 +587,7 @@ impl Queue {
         let f1 = prompt.replace('_', " ");
 
         prompt_file.write_all(f1.as_bytes()).await?;
-        debug!("Wrote caption file for {}", post.file_name(name_type));
+        debug!("Wrote caption file for {}", file.display());
         Ok(())
     }
 }
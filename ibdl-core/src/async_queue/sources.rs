@@ -0,0 +1,41 @@
+//! Servers the posts of a multi-server search come from.
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use ibdl_common::post::Post;
+use ibdl_common::reqwest::Client;
+use ibdl_extractors::extractor_config::ServerConfig;
+
+/// Server a post is downloaded from, with its client.
+type Source = (Arc<ServerConfig>, Client);
+
+/// Server where each post of a multi-server search was first found, by MD5, with the client used
+/// to download from it.
+///
+/// Shared by every extractor feeding the same [`Queue`](super::Queue), so files found in more than
+/// one server are only downloaded once.
+#[derive(Debug, Clone, Default)]
+pub struct PostSources {
+    servers: Arc<Mutex<HashMap<String, Source>>>,
+}
+
+impl PostSources {
+    /// Records `post` as found in `server`.
+    ///
+    /// Returns `false` if the same file was already found, in this server or another one.
+    pub fn insert(&self, post: &Post, server: &Arc<ServerConfig>, client: &Client) -> bool {
+        let mut servers = self.servers.lock().unwrap();
+
+        if servers.contains_key(&post.md5) {
+            return false;
+        }
+
+        servers.insert(post.md5.clone(), (server.clone(), client.clone()));
+        true
+    }
+
+    /// Server where `post` was first found and its client.
+    pub fn get(&self, post: &Post) -> Option<Source> {
+        self.servers.lock().unwrap().get(&post.md5).cloned()
+    }
+}
@@ -0,0 +1,221 @@
+//! Conversion of ugoira zips into animated images.
+//!
+//! Ugoira posts are a zip with one image per frame, and the time each frame is shown comes
+//! separately in [`Post::frame_delays`](ibdl_common::post::Post::frame_delays). Frames are read in
+//! the order of their file names.
+use std::io::{Cursor, Read, Write};
+
+use clap::ValueEnum;
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    Delay, Frame, RgbaImage,
+};
+use image_webp::{ColorType, WebPEncoder};
+use zip::ZipArchive;
+
+use crate::error::QueueError;
+
+/// Animated format ugoira posts are converted to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum UgoiraFormat {
+    /// Lossless animated WebP
+    Webp,
+    /// Animated PNG
+    Apng,
+    /// GIF, limited to 256 colors per frame
+    Gif,
+}
+
+impl UgoiraFormat {
+    /// Extension of the converted file.
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Webp => "webp",
+            Self::Apng => "png",
+            Self::Gif => "gif",
+        }
+    }
+}
+
+fn conversion_error(error: impl ToString) -> QueueError {
+    QueueError::UgoiraConversion {
+        message: error.to_string(),
+    }
+}
+
+/// Converts the frames in `zip` into an animation in `format`, showing every frame for its delay
+/// in `delays`, in milliseconds.
+pub fn convert_ugoira(
+    zip: &[u8],
+    delays: &[u32],
+    format: UgoiraFormat,
+) -> Result<Vec<u8>, QueueError> {
+    let frames = read_frames(zip)?;
+
+    if frames.len() != delays.len() {
+        return Err(conversion_error(format!(
+            "zip has {} frames, but {} frame delays were given",
+            frames.len(),
+            delays.len()
+        )));
+    }
+
+    let (width, height) = frames[0].dimensions();
+
+    if frames
+        .iter()
+        .any(|frame| frame.dimensions() != (width, height))
+    {
+        return Err(conversion_error("frames have different sizes"));
+    }
+
+    match format {
+        UgoiraFormat::Webp => encode_webp(&frames, delays),
+        UgoiraFormat::Apng => encode_apng(&frames, delays),
+        UgoiraFormat::Gif => encode_gif(frames, delays),
+    }
+}
+
+fn read_frames(zip: &[u8]) -> Result<Vec<RgbaImage>, QueueError> {
+    let mut archive = ZipArchive::new(Cursor::new(zip))?;
+
+    let mut names = archive
+        .file_names()
+        .filter(|name| !name.ends_with('/'))
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    names.sort();
+
+    if names.is_empty() {
+        return Err(conversion_error("zip has no frames"));
+    }
+
+    names
+        .iter()
+        .map(|name| {
+            let mut data = Vec::new();
+            archive.by_name(name)?.read_to_end(&mut data)?;
+
+            let frame = image::load_from_memory(&data)
+                .map_err(|error| conversion_error(format!("invalid frame {name}: {error}")))?;
+
+            Ok(frame.into_rgba8())
+        })
+        .collect()
+}
+
+fn encode_gif(frames: Vec<RgbaImage>, delays: &[u32]) -> Result<Vec<u8>, QueueError> {
+    let mut output = Vec::new();
+
+    {
+        let mut encoder = GifEncoder::new_with_speed(&mut output, 10);
+        encoder
+            .set_repeat(Repeat::Infinite)
+            .map_err(conversion_error)?;
+
+        encoder
+            .encode_frames(frames.into_iter().zip(delays).map(|(frame, delay)| {
+                Frame::from_parts(frame, 0, 0, Delay::from_numer_denom_ms(*delay, 1))
+            }))
+            .map_err(conversion_error)?;
+    }
+
+    Ok(output)
+}
+
+fn encode_apng(frames: &[RgbaImage], delays: &[u32]) -> Result<Vec<u8>, QueueError> {
+    let (width, height) = frames[0].dimensions();
+    let count = u32::try_from(frames.len())?;
+
+    let mut output = Vec::new();
+
+    let mut encoder = png::Encoder::new(&mut output, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(count, 0).map_err(conversion_error)?;
+
+    let mut writer = encoder.write_header().map_err(conversion_error)?;
+
+    for (frame, delay) in frames.iter().zip(delays) {
+        let delay = u16::try_from(*delay).unwrap_or(u16::MAX);
+
+        writer
+            .set_frame_delay(delay, 1000)
+            .map_err(conversion_error)?;
+        writer
+            .write_image_data(frame.as_raw())
+            .map_err(conversion_error)?;
+    }
+
+    writer.finish().map_err(conversion_error)?;
+
+    Ok(output)
+}
+
+/// Builds an animated WebP out of the lossless bitstreams of every frame, since the encoder only
+/// writes still images.
+fn encode_webp(frames: &[RgbaImage], delays: &[u32]) -> Result<Vec<u8>, QueueError> {
+    const VP8X_ANIMATION: u8 = 1 << 1;
+    const VP8X_ALPHA: u8 = 1 << 4;
+    const ANMF_NO_BLEND: u8 = 1 << 1;
+    /// Durations are stored in 24 bits
+    const MAX_DURATION: u32 = (1 << 24) - 1;
+
+    let (width, height) = frames[0].dimensions();
+
+    let mut vp8x = vec![VP8X_ANIMATION | VP8X_ALPHA, 0, 0, 0];
+    vp8x.extend_from_slice(&uint24(width - 1));
+    vp8x.extend_from_slice(&uint24(height - 1));
+
+    let mut body = Vec::from(*b"WEBP");
+    write_chunk(&mut body, b"VP8X", &vp8x)?;
+    // Transparent background, looping forever
+    write_chunk(&mut body, b"ANIM", &[0, 0, 0, 0, 0, 0])?;
+
+    for (frame, delay) in frames.iter().zip(delays) {
+        let mut still = Vec::new();
+        WebPEncoder::new(&mut still)
+            .encode(frame.as_raw(), width, height, ColorType::Rgba8)
+            .map_err(conversion_error)?;
+
+        // A still lossless image is `RIFF <size> WEBP` followed by a single `VP8L` chunk
+        let bitstream = still
+            .get(12..)
+            .filter(|chunk| chunk.starts_with(b"VP8L"))
+            .ok_or_else(|| conversion_error("unexpected WebP encoder output"))?;
+
+        let mut anmf = Vec::with_capacity(16 + bitstream.len());
+        anmf.extend_from_slice(&uint24(0)); // X offset
+        anmf.extend_from_slice(&uint24(0)); // Y offset
+        anmf.extend_from_slice(&uint24(width - 1));
+        anmf.extend_from_slice(&uint24(height - 1));
+        anmf.extend_from_slice(&uint24((*delay).min(MAX_DURATION)));
+        anmf.push(ANMF_NO_BLEND);
+        anmf.extend_from_slice(bitstream);
+
+        write_chunk(&mut body, b"ANMF", &anmf)?;
+    }
+
+    let mut output = Vec::with_capacity(body.len() + 8);
+    write_chunk(&mut output, b"RIFF", &body)?;
+
+    Ok(output)
+}
+
+/// Writes a RIFF chunk, padded to an even size.
+fn write_chunk(writer: &mut Vec<u8>, name: &[u8; 4], data: &[u8]) -> Result<(), QueueError> {
+    writer.write_all(name)?;
+    writer.write_all(&u32::try_from(data.len())?.to_le_bytes())?;
+    writer.write_all(data)?;
+
+    if data.len() % 2 == 1 {
+        writer.push(0);
+    }
+
+    Ok(())
+}
+
+const fn uint24(value: u32) -> [u8; 3] {
+    let [a, b, c, _] = value.to_le_bytes();
+    [a, b, c]
+}
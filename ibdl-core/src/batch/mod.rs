@@ -0,0 +1,230 @@
+//! Manifest of download jobs to be run by the `batch` subcommand.
+//!
+//! # Batch Manifest
+//! A manifest is a TOML or JSON file (picked by the file extension) listing any number of jobs.
+//! Each job is equivalent to a single run of the `search`, `pool` or `post` subcommands:
+//!
+//! ```toml
+//! parallel = 2
+//!
+//! [[job]]
+//! imageboard = "danbooru"
+//! mode = "search"
+//! tags = ["kroos_(arknights)"]
+//! ratings = ["safe", "questionable"]
+//! limit = 100
+//!
+//! [[job]]
+//! imageboard = "e621"
+//! mode = "pool"
+//! pool_id = 36957
+//! output = "./pools/my_pool"
+//! cbz = true
+//! ```
+//!
+//! Jobs without an `imageboard` use the one selected with `-i`, and jobs without an `output` are
+//! saved in `<current dir>/<imageboard>/<tags>` like a normal search.
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use ibdl_common::{
+    serde::{self, Deserialize},
+    serde_json, ImageBoards,
+};
+
+use crate::{
+    cli::commands::{pool::Pool, post::Post, search::TagSearch},
+    error::CliError,
+    generate_output_path, generate_output_path_precise, RatingArg,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(crate = "self::serde", rename_all = "lowercase")]
+pub enum JobMode {
+    Search,
+    Pool,
+    Post,
+}
+
+/// A single download job of the manifest.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "self::serde")]
+pub struct BatchJob {
+    /// Name of the server, as listed by `--servers`
+    pub imageboard: Option<String>,
+    pub mode: JobMode,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub pool_id: Option<u32>,
+    #[serde(default)]
+    pub posts: Vec<u32>,
+    pub output: Option<PathBuf>,
+    /// Ratings to download, using the same names as `--rating`
+    #[serde(default)]
+    pub ratings: Vec<String>,
+    #[serde(default)]
+    pub cbz: bool,
+    pub limit: Option<u16>,
+    pub start_page: Option<u16>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    pub force_extension: Option<String>,
+    #[serde(default)]
+    pub no_animated: bool,
+    #[serde(default)]
+    pub safe_mode: bool,
+    #[serde(default)]
+    pub ignore_unknown: bool,
+    #[serde(default)]
+    pub disable_blacklist: bool,
+    /// Download pool posts in reverse order
+    #[serde(default)]
+    pub latest_first: bool,
+}
+
+impl BatchJob {
+    /// Short description of what this job downloads.
+    pub fn label(&self) -> String {
+        match self.mode {
+            JobMode::Search => self.tags.join(" "),
+            JobMode::Pool => format!("pool {}", self.pool_id.unwrap_or_default()),
+            JobMode::Post => format!("{} posts", self.posts.len()),
+        }
+    }
+
+    /// Returns where the posts of this job will be saved.
+    pub fn output_path(&self, imageboard: ImageBoards) -> Result<PathBuf, CliError> {
+        if let Some(path) = &self.output {
+            return Ok(generate_output_path_precise(path, self.cbz));
+        }
+
+        let tags = if self.mode == JobMode::Search {
+            self.tags.as_slice()
+        } else {
+            &[]
+        };
+
+        Ok(generate_output_path(
+            &std::env::current_dir()?,
+            imageboard,
+            tags,
+            self.cbz,
+            self.pool_id,
+        ))
+    }
+
+    /// Ratings of the job, checked when the manifest is loaded.
+    fn rating_args(&self) -> Vec<RatingArg> {
+        self.ratings
+            .iter()
+            .filter_map(|rating| RatingArg::from_str(rating, true).ok())
+            .collect()
+    }
+
+    pub fn search(&self) -> TagSearch {
+        TagSearch {
+            tags: self.tags.clone(),
+            limit: self.limit,
+            disable_blacklist: self.disable_blacklist,
+            start_page: self.start_page,
+            exclude: self.exclude.clone(),
+            force_extension: self.force_extension.clone(),
+            no_animated: self.no_animated,
+            safe_mode: self.safe_mode,
+            rating: self.rating_args(),
+            ignore_unknown: self.ignore_unknown,
+        }
+    }
+
+    pub fn pool(&self) -> Result<Pool, CliError> {
+        let Some(pool_id) = self.pool_id else {
+            return Err(CliError::InvalidBatchJob {
+                message: String::from("pool jobs need a `pool_id`"),
+            });
+        };
+
+        Ok(Pool {
+            pool_id,
+            latest_first: self.latest_first,
+            limit: self.limit,
+            disable_blacklist: self.disable_blacklist,
+            start_page: self.start_page,
+            exclude: self.exclude.clone(),
+            force_extension: self.force_extension.clone(),
+            no_animated: self.no_animated,
+            safe_mode: self.safe_mode,
+            rating: self.rating_args(),
+            ignore_unknown: self.ignore_unknown,
+        })
+    }
+
+    pub fn post(&self) -> Result<Post, CliError> {
+        if self.posts.is_empty() {
+            return Err(CliError::NoPostsInInput);
+        }
+
+        Ok(Post {
+            posts: self.posts.clone(),
+            post_file: None,
+        })
+    }
+}
+
+/// Highest `parallel` a manifest can set, the same as `--parallel`
+const MAX_PARALLEL: usize = 10;
+
+/// List of jobs read from a manifest file.
+#[derive(Debug, Deserialize)]
+#[serde(crate = "self::serde")]
+pub struct BatchManifest {
+    /// How many jobs can run at the same time
+    pub parallel: Option<usize>,
+    #[serde(default, rename = "job", alias = "jobs")]
+    pub jobs: Vec<BatchJob>,
+}
+
+impl BatchManifest {
+    /// Reads a manifest from `path`. Files ending in `.json` are parsed as JSON, everything else as TOML.
+    pub fn load(path: &Path) -> Result<Self, CliError> {
+        let contents = read_to_string(path)?;
+
+        let is_json = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+
+        let manifest = if is_json {
+            serde_json::from_str(&contents).map_err(|error| error.to_string())
+        } else {
+            toml::from_str(&contents).map_err(|error| error.to_string())
+        };
+
+        let manifest: Self = manifest.map_err(|message| CliError::BatchFileError { message })?;
+
+        if let Some(parallel) = manifest.parallel {
+            if !(1..=MAX_PARALLEL).contains(&parallel) {
+                return Err(CliError::BatchFileError {
+                    message: format!("`parallel` must be between 1 and {MAX_PARALLEL}"),
+                });
+            }
+        }
+
+        for (idx, job) in manifest.jobs.iter().enumerate() {
+            if let Some(rating) = job
+                .ratings
+                .iter()
+                .find(|rating| RatingArg::from_str(rating, true).is_err())
+            {
+                return Err(CliError::InvalidBatchJob {
+                    message: format!(
+                        "job {} ({}) has an unknown rating `{rating}`",
+                        idx + 1,
+                        job.label()
+                    ),
+                });
+            }
+        }
+
+        Ok(manifest)
+    }
+}
@@ -0,0 +1,142 @@
+use std::fs::File;
+use std::io::{stdout, BufReader, BufWriter};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use clap::{Args, Subcommand};
+use dialoguer::{theme::ColorfulTheme, Confirm};
+use owo_colors::OwoColorize;
+
+use crate::{archive::DownloadArchive, cli::Cli, error::CliError};
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+#[derive(Debug, Args)]
+pub struct Archive {
+    #[clap(subcommand)]
+    pub action: ArchiveAction,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ArchiveAction {
+    /// List all posts recorded in the download archive
+    List {
+        /// Only list posts downloaded from this server
+        #[clap(long, value_name = "SERVER")]
+        server: Option<String>,
+    },
+    /// Remove posts from the download archive so they can be downloaded again
+    Prune {
+        /// Only remove posts downloaded from this server
+        #[clap(long, value_name = "SERVER")]
+        server: Option<String>,
+
+        /// Only remove posts downloaded more than this many days ago
+        #[clap(long, value_name = "DAYS")]
+        older_than: Option<u64>,
+    },
+    /// Write the posts in the download archive as JSON Lines, to be imported somewhere else
+    Export {
+        /// Only export posts downloaded from this server
+        #[clap(long, value_name = "SERVER")]
+        server: Option<String>,
+
+        /// File to write the posts to. Defaults to stdout
+        #[clap(value_name = "FILE")]
+        file: Option<PathBuf>,
+    },
+    /// Add the posts of a file written by `archive export` to the download archive
+    Import {
+        #[clap(value_name = "FILE")]
+        file: PathBuf,
+    },
+}
+
+impl Archive {
+    pub fn run(&self, args: &Cli) -> Result<(), CliError> {
+        self.run_with(args, &DownloadArchive::open_default()?)
+    }
+
+    /// Same as [`run`](Self::run), on `archive` instead of the default one.
+    pub(crate) fn run_with(&self, args: &Cli, archive: &DownloadArchive) -> Result<(), CliError> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+
+        match &self.action {
+            ArchiveAction::List { server } => {
+                let entries = archive.entries(server.as_deref())?;
+
+                for entry in &entries {
+                    let age = now.saturating_sub(entry.downloaded_at) / SECONDS_PER_DAY;
+                    println!(
+                        "{:<16} {:<10} {} {} ({} days ago)",
+                        format!("[{}]", entry.server).bold().green(),
+                        entry.id.bold().blue(),
+                        entry.md5.purple(),
+                        entry.file_name.bold(),
+                        age.yellow()
+                    );
+                }
+
+                println!(
+                    "{} {}",
+                    entries.len().to_string().bold().blue(),
+                    "posts in the download archive".bold()
+                );
+            }
+            ArchiveAction::Prune { server, older_than } => {
+                if server.is_none() && older_than.is_none() && !args.overwrite {
+                    let confirm = Confirm::with_theme(&ColorfulTheme::default())
+                        .with_prompt(
+                            "This will remove every post from the download archive. Continue?",
+                        )
+                        .wait_for_newline(true)
+                        .interact()?;
+
+                    if !confirm {
+                        println!("{}", "Prune cancelled".bold().blue());
+                        return Ok(());
+                    }
+                }
+
+                let before =
+                    older_than.map(|days| now.saturating_sub(days.saturating_mul(SECONDS_PER_DAY)));
+
+                let removed = archive.prune(server.as_deref(), before)?;
+
+                println!(
+                    "{} {}",
+                    removed.to_string().bold().blue(),
+                    "posts removed from the download archive".bold()
+                );
+            }
+            ArchiveAction::Export { server, file } => {
+                let exported = match file {
+                    Some(file) => {
+                        archive.export(server.as_deref(), BufWriter::new(File::create(file)?))?
+                    }
+                    None => archive.export(server.as_deref(), stdout().lock())?,
+                };
+
+                // Keep stdout clean for the exported posts
+                eprintln!(
+                    "{} {}",
+                    exported.to_string().bold().blue(),
+                    "posts exported from the download archive".bold()
+                );
+            }
+            ArchiveAction::Import { file } => {
+                let added = archive.import(BufReader::new(File::open(file)?))?;
+
+                println!(
+                    "{} {}",
+                    added.to_string().bold().blue(),
+                    "posts added to the download archive".bold()
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,260 @@
+use std::env;
+use std::io::stdin;
+
+use clap::{Args, Subcommand};
+use dialoguer::{theme::ColorfulTheme, Input, Password};
+use ibdl_common::{client, reqwest::Client};
+use ibdl_extractors::auth::{self, Credentials, ImageboardConfig, SecretStore};
+use ibdl_extractors::extractor_config::ServerConfig;
+use owo_colors::OwoColorize;
+
+use crate::{
+    cli::{
+        extra::{api_key_prompt, get_servers, login, secret_store, username_prompt},
+        Cli,
+    },
+    error::CliError,
+};
+
+#[derive(Debug, Args)]
+pub struct Auth {
+    #[clap(subcommand)]
+    pub action: AuthAction,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AuthAction {
+    /// Log into the selected server and save the login for later runs
+    ///
+    /// The username is taken from `--username` or `IBDL_USERNAME`, and the API key from stdin with
+    /// `--api-key-stdin` or from `IBDL_API_KEY`. Whatever is missing is asked for.
+    Login {
+        /// Username of the account, or the user id on Gelbooru and Rule34
+        #[clap(long, value_name = "NAME")]
+        username: Option<String>,
+
+        /// Read the API key, or the password on Moebooru, from the first line of stdin
+        #[clap(long)]
+        api_key_stdin: bool,
+    },
+    /// Remove the saved login of the selected server, with its credentials
+    Logout {
+        /// Remove the saved login of every server
+        #[clap(long)]
+        all: bool,
+    },
+    /// Show the saved login of every server and where its credentials are kept
+    Status,
+    /// Fetch the blacklist of the account again for the selected server
+    Refresh {
+        /// Refresh every server with a saved login
+        #[clap(long)]
+        all: bool,
+    },
+}
+
+impl Auth {
+    pub async fn run(&self, args: &Cli) -> Result<(), CliError> {
+        match &self.action {
+            AuthAction::Login {
+                username,
+                api_key_stdin,
+            } => {
+                let imageboard = auth_server(&args.imageboard)?;
+                let credentials =
+                    read_credentials(imageboard, username.as_deref(), *api_key_stdin)?;
+
+                let config = login(
+                    imageboard,
+                    &client!(imageboard),
+                    &credentials,
+                    secret_store()?,
+                )
+                .await?;
+
+                println!(
+                    "{} {} {} {} {}",
+                    "Logged into".bold(),
+                    imageboard.to_string().green().bold(),
+                    "as".bold(),
+                    config.user_data.name.blue().bold(),
+                    format!(
+                        "({} blacklisted tags, credentials saved to the {})",
+                        config.user_data.blacklisted_tags.len(),
+                        secret_store()?.name()
+                    )
+                    .bold()
+                );
+            }
+            AuthAction::Logout { all } => {
+                let servers = if *all {
+                    auth_servers()
+                } else {
+                    vec![&args.imageboard]
+                };
+
+                for server in servers {
+                    let message = if ImageboardConfig::logout(server, secret_store()?).await? {
+                        "Logged out of"
+                    } else {
+                        "Not logged into"
+                    };
+
+                    println!("{} {}", message.bold(), server.to_string().green().bold());
+                }
+            }
+            AuthAction::Status => {
+                for line in Self::status(&auth_servers(), secret_store()?).await? {
+                    println!("{line}");
+                }
+            }
+            AuthAction::Refresh { all } => {
+                let servers = if *all {
+                    let mut logged_in = Vec::new();
+                    for server in auth_servers() {
+                        if ImageboardConfig::is_cached(server)? {
+                            logged_in.push(server);
+                        }
+                    }
+                    logged_in
+                } else {
+                    vec![auth_server(&args.imageboard)?]
+                };
+
+                for server in servers {
+                    let config = Self::refresh(server, &client!(server), secret_store()?).await?;
+
+                    println!(
+                        "{} {} {}",
+                        "Refreshed".bold(),
+                        server.to_string().green().bold(),
+                        format!(
+                            "({} blacklisted tags)",
+                            config.user_data.blacklisted_tags.len()
+                        )
+                        .bold()
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// One line for every server in `servers` with its saved login, if there's one.
+    ///
+    /// Old auth caches with the credentials inside them are moved to `store` on the way.
+    pub(crate) async fn status(
+        servers: &[&ServerConfig],
+        store: &dyn SecretStore,
+    ) -> Result<Vec<String>, CliError> {
+        let mut lines = Vec::with_capacity(servers.len());
+
+        for server in servers {
+            let config = if ImageboardConfig::is_cached(server)? {
+                ImageboardConfig::load(server, store).await?
+            } else {
+                None
+            };
+
+            let line = match config {
+                Some(config) => format!(
+                    "{:<16} logged in as {} (id {}, {} blacklisted tags) with credentials in the {}",
+                    server.name,
+                    config.user_data.name,
+                    config.user_data.id,
+                    config.user_data.blacklisted_tags.len(),
+                    store.name()
+                ),
+                None => format!("{:<16} not logged in", server.name),
+            };
+
+            lines.push(line);
+        }
+
+        Ok(lines)
+    }
+
+    /// Logs into `imageboard` again with the saved credentials, updating the cached blacklist.
+    pub(crate) async fn refresh(
+        imageboard: &ServerConfig,
+        client: &Client,
+        store: &dyn SecretStore,
+    ) -> Result<ImageboardConfig, CliError> {
+        let Some(mut config) = ImageboardConfig::load(imageboard, store).await? else {
+            return Err(CliError::NotLoggedIn {
+                server: imageboard.name.clone(),
+            });
+        };
+
+        config.authenticate(client).await?;
+        config.save(store).await?;
+
+        Ok(config)
+    }
+}
+
+/// Every server that supports logging in, sorted by name.
+fn auth_servers<'a>() -> Vec<&'a ServerConfig> {
+    let mut servers = get_servers()
+        .values()
+        .filter(|server| server.auth_url.is_some())
+        .collect::<Vec<_>>();
+    servers.sort_by(|a, b| a.name.cmp(&b.name));
+
+    servers
+}
+
+fn auth_server(imageboard: &ServerConfig) -> Result<&ServerConfig, CliError> {
+    if imageboard.auth_url.is_none() {
+        return Err(auth::Error::AuthUnsupported.into());
+    }
+
+    Ok(imageboard)
+}
+
+/// Username and API key to log into `imageboard` with, asking only for the missing ones.
+fn read_credentials(
+    imageboard: &ServerConfig,
+    username: Option<&str>,
+    api_key_stdin: bool,
+) -> Result<Credentials, CliError> {
+    let username = username
+        .map(String::from)
+        .or_else(|| env::var("IBDL_USERNAME").ok());
+
+    let api_key = if api_key_stdin {
+        let line = stdin().lines().next().ok_or(CliError::MissingApiKey)??;
+        Some(line)
+    } else {
+        env::var("IBDL_API_KEY").ok()
+    };
+
+    if username.is_none() || api_key.is_none() {
+        println!(
+            "{} {}",
+            "Logging into:".bold(),
+            imageboard.to_string().green().bold()
+        );
+    }
+
+    let username = match username {
+        Some(username) => username,
+        None => Input::with_theme(&ColorfulTheme::default())
+            .with_prompt(username_prompt(imageboard))
+            .interact()?,
+    };
+
+    let api_key = match api_key {
+        Some(api_key) => api_key,
+        None => Password::with_theme(&ColorfulTheme::default())
+            .with_prompt(api_key_prompt(imageboard))
+            .interact()?,
+    };
+
+    if api_key.trim().is_empty() {
+        return Err(CliError::MissingApiKey);
+    }
+
+    Ok(Credentials { username, api_key })
+}
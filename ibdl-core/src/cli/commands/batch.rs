@@ -0,0 +1,250 @@
+use std::path::PathBuf;
+use std::slice;
+use std::sync::{atomic::AtomicU64, Arc};
+
+use clap::Args;
+use futures::StreamExt;
+use ibdl_common::{
+    events::{self, emit, Event},
+    log::debug,
+    tokio::{
+        join,
+        sync::mpsc::{channel, unbounded_channel},
+    },
+};
+use owo_colors::OwoColorize;
+
+use crate::{
+    batch::{BatchJob, BatchManifest, JobMode},
+    cli::{extra::get_servers, Cli},
+    error::CliError,
+};
+
+#[derive(Debug, Args)]
+pub struct Batch {
+    /// TOML or JSON file with the jobs to run
+    #[clap(value_parser, value_name = "MANIFEST")]
+    pub manifest: PathBuf,
+
+    /// Number of jobs to run at the same time. Overrides the value set in the manifest
+    ///
+    /// [default: 1]
+    #[clap(short = 'j', long, value_name = "NUMBER", value_parser(clap::value_parser!(u8).range(1..=10)))]
+    pub parallel: Option<u8>,
+}
+
+/// Outcome of a single job.
+#[derive(Debug)]
+pub(crate) struct JobReport {
+    pub(crate) downloaded: u64,
+    pub(crate) removed: u64,
+}
+
+impl Batch {
+    /// Runs every job in the manifest, continuing with the others if one of them fails, and prints
+    /// a summary of all of them at the end.
+    pub async fn run(&self, args: &Cli) -> Result<(), CliError> {
+        let manifest = BatchManifest::load(&self.manifest)?;
+
+        if manifest.jobs.is_empty() {
+            return Err(CliError::BatchFileError {
+                message: String::from("no jobs in manifest"),
+            });
+        }
+
+        let parallel = self
+            .parallel
+            .map(usize::from)
+            .or(manifest.parallel)
+            .unwrap_or(1);
+
+        debug!(
+            "Running {} jobs, {} at a time",
+            manifest.jobs.len(),
+            parallel
+        );
+
+        let results = run_jobs(&manifest.jobs, parallel, args).await;
+
+        print_summary(&manifest.jobs, args, &results);
+
+        Ok(())
+    }
+}
+
+/// Runs `jobs`, `parallel` of them at a time, and returns the outcome of each one next to its
+/// position in `jobs`.
+pub(crate) async fn run_jobs(
+    jobs: &[BatchJob],
+    parallel: usize,
+    args: &Cli,
+) -> Vec<(usize, Result<JobReport, CliError>)> {
+    let mut results: Vec<(usize, Result<JobReport, CliError>)> =
+        futures::stream::iter(jobs.iter().enumerate())
+            .map(|(idx, job)| async move { (idx, run_job(job, args).await) })
+            .buffer_unordered(parallel)
+            .collect()
+            .await;
+
+    results.sort_by_key(|(idx, _)| *idx);
+
+    results
+}
+
+async fn run_job(job: &BatchJob, args: &Cli) -> Result<JobReport, CliError> {
+    let imageboard = &match &job.imageboard {
+        Some(name) => {
+            args.configure_server(get_servers().get(name).ok_or(CliError::ServerNotExists)?)
+        }
+        None => args.imageboard.clone(),
+    };
+
+    let output = job.output_path(imageboard.server)?;
+
+    let (channel_tx, channel_rx) = unbounded_channel();
+    let (length_sender, length_channel) = channel(args.simultaneous_downloads as usize);
+
+    let (ext, client, (title, summary)) = match job.mode {
+        JobMode::Search => {
+            let search = job.search();
+            let (ext, client) = search
+                .setup_extractor(
+                    imageboard,
+                    false,
+                    args.ugoira.is_some(),
+                    None,
+                    channel_tx,
+                    length_sender,
+                )
+                .await?;
+            (ext, client, search.comic_info(slice::from_ref(imageboard)))
+        }
+        JobMode::Pool => {
+            let pool = job.pool()?;
+            let (ext, client, pool_name) = pool
+                .setup_extractor(
+                    imageboard,
+                    false,
+                    args.ugoira.is_some(),
+                    channel_tx,
+                    length_sender,
+                )
+                .await?;
+            (ext, client, pool.comic_info(imageboard, pool_name))
+        }
+        JobMode::Post => {
+            let post = job.post()?;
+            let (ext, client) = post
+                .setup_extractor(
+                    imageboard,
+                    false,
+                    args.ugoira.is_some(),
+                    channel_tx,
+                    length_sender,
+                )
+                .await?;
+            (ext, client, post.comic_info(imageboard))
+        }
+    };
+
+    let mut qw = args.setup_queue(imageboard, client, job.cbz, job.mode == JobMode::Pool)?;
+    qw.comic_info(title, Some(summary));
+
+    let asd = qw.setup_async_downloader(
+        output,
+        Arc::new(AtomicU64::new(0)),
+        channel_rx,
+        length_channel,
+    );
+
+    let (Ok(removed), Ok(downloaded)) = join!(ext, asd) else {
+        return Err(CliError::ImpossibleExecutionPath);
+    };
+
+    Ok(JobReport {
+        downloaded: downloaded?,
+        removed: removed?,
+    })
+}
+
+fn print_summary(jobs: &[BatchJob], args: &Cli, results: &[(usize, Result<JobReport, CliError>)]) {
+    // Always emitted, so failed jobs are counted in the exit code
+    for (idx, result) in results {
+        let job = &jobs[*idx];
+        let error = result.as_ref().err().map(ToString::to_string);
+        let (downloaded, removed) = result
+            .as_ref()
+            .map_or((0, 0), |report| (report.downloaded, report.removed));
+
+        emit(&Event::JobFinished {
+            server: job.imageboard.as_ref().unwrap_or(&args.imageboard.name),
+            job: &job.label(),
+            downloaded,
+            removed,
+            error: error.as_deref(),
+        });
+    }
+
+    if events::json_enabled() {
+        emit(&Event::Summary(events::summary()));
+        return;
+    }
+
+    let mut total_down = 0;
+    let mut total_black = 0;
+    let mut failed = 0;
+
+    println!("{}", "Batch summary:".underline().bold().blue());
+
+    for (idx, result) in results {
+        let job = &jobs[*idx];
+        let server = job.imageboard.as_ref().unwrap_or(&args.imageboard.name);
+
+        let status = match result {
+            Ok(report) => {
+                total_down += report.downloaded;
+                total_black += report.removed;
+                format!("{} files downloaded", report.downloaded)
+                    .green()
+                    .to_string()
+            }
+            Err(error) => {
+                failed += 1;
+                format!("failed: {error}").red().to_string()
+            }
+        };
+
+        println!(
+            "{:>4} {:<16} {} - {}",
+            format!("#{}", idx + 1).bold(),
+            format!("[{server}]").bold().green(),
+            job.label().bold().blue(),
+            status
+        );
+    }
+
+    println!(
+        "{} {} {}",
+        total_down.to_string().bold().blue(),
+        "files".bold().blue(),
+        "downloaded".bold()
+    );
+
+    if total_black > 0 {
+        println!(
+            "{} {}",
+            total_black.to_string().bold().red(),
+            "found posts with blacklisted tags were not downloaded."
+                .bold()
+                .red()
+        );
+    }
+
+    if failed > 0 {
+        println!(
+            "{} {}",
+            failed.to_string().bold().red(),
+            "jobs failed".bold().red()
+        );
+    }
+}
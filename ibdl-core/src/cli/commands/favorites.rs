@@ -0,0 +1,189 @@
+use clap::Args;
+use ibdl_common::{
+    post::{extension::Extension, rating::Rating, Post},
+    reqwest::Client,
+    tokio::sync::mpsc::{Sender, UnboundedSender},
+    ImageBoards,
+};
+use ibdl_extractors::{
+    auth::ImageboardConfig, extractor_config::ServerConfig,
+    imageboards::gelbooru::GelbooruExtractor, prelude::*,
+};
+
+use crate::{
+    cli::{extra::auth_imgboard, Cli},
+    error::CliError,
+    RatingArg,
+};
+
+#[derive(Debug, Args)]
+pub struct Favorites {
+    /// Id of the user whose favorites are downloaded
+    ///
+    /// Defaults to the account logged into the server
+    #[clap(value_parser, value_name = "USER_ID")]
+    pub user_id: Option<u64>,
+
+    /// Set a max number of posts to download.
+    ///
+    /// [max: 65535]
+    #[clap(short, long, value_parser, help_heading = "DOWNLOAD")]
+    pub limit: Option<u16>,
+
+    /// Disable blacklist filtering
+    #[clap(long, value_parser, default_value_t = false, help_heading = "GENERAL")]
+    pub disable_blacklist: bool,
+
+    /// Exclude posts with these tags
+    #[clap(short, long, value_parser, help_heading = "GENERAL")]
+    pub exclude: Vec<String>,
+
+    /// Force the extractor to only fetch posts with the selected extension
+    #[clap(long, value_parser, help_heading = "DOWNLOAD", global = true)]
+    pub force_extension: Option<String>,
+
+    /// Do not download animated gifs or video files
+    #[clap(
+        long,
+        value_parser,
+        default_value_t = false,
+        help_heading = "SAVE",
+        global = true
+    )]
+    pub no_animated: bool,
+
+    /// Download images from the safe version of the selected Imageboard.
+    ///
+    /// Useful if you only want to download posts with "safe" rating.
+    #[clap(
+        long,
+        action,
+        default_value_t = false,
+        help_heading = "GENERAL",
+        global = true
+    )]
+    pub safe_mode: bool,
+
+    /// Download posts with the selected rating. Can be used multiple times to download posts with other ratings
+    #[clap(
+        short,
+        long,
+        value_parser,
+        help_heading = "GENERAL",
+        conflicts_with("safe_mode"),
+        global = true
+    )]
+    pub rating: Vec<RatingArg>,
+
+    /// Do not download posts with an unknown rating
+    #[clap(
+        long,
+        value_parser,
+        default_value_t = false,
+        help_heading = "SAVE",
+        global = true
+    )]
+    pub ignore_unknown: bool,
+}
+
+impl Favorites {
+    #[inline]
+    fn selected_ratings(&self) -> Vec<Rating> {
+        let mut ratings: Vec<Rating> = Vec::with_capacity(4);
+        if self.rating.is_empty() {
+            ratings.push(Rating::Safe);
+
+            if !self.safe_mode {
+                ratings.push(Rating::Questionable);
+                ratings.push(Rating::Explicit)
+            }
+        } else {
+            self.rating.iter().for_each(|item| ratings.push(item.0));
+        };
+
+        if !self.ignore_unknown {
+            ratings.push(Rating::Unknown);
+        }
+        ratings
+    }
+
+    /// Title and summary of the `ComicInfo.xml` of cbz files.
+    pub fn comic_info(&self, imageboard: &ServerConfig, user_id: u64) -> (String, String) {
+        let title = format!("Favorites of user {user_id}");
+        let summary = format!(
+            "Favorites of user {user_id} from {}",
+            imageboard.pretty_name
+        );
+        (title, summary)
+    }
+
+    /// Id of the user whose favorites are downloaded: the one given, or the account logged into
+    /// `imageboard`.
+    pub async fn user_id(&self, imageboard: &ServerConfig) -> Result<u64, CliError> {
+        if let Some(user_id) = self.user_id {
+            return Ok(user_id);
+        }
+
+        ImageboardConfig::cached_user(imageboard)
+            .await?
+            .map(|user| user.id)
+            .ok_or_else(|| CliError::MissingUserId {
+                server: imageboard.name.clone(),
+            })
+    }
+
+    pub async fn init_extractor(
+        &self,
+        args: &Cli,
+        channel_tx: UnboundedSender<Post>,
+        length_tx: Sender<u64>,
+    ) -> Result<(ExtractorThreadHandle, Client, u64), CliError> {
+        self.setup_extractor(&args.imageboard, args.auth, channel_tx, length_tx)
+            .await
+    }
+
+    /// Starts the extractor thread for `imageboard`, also returning the id of the user.
+    pub async fn setup_extractor(
+        &self,
+        imageboard: &ServerConfig,
+        auth: bool,
+        channel_tx: UnboundedSender<Post>,
+        length_tx: Sender<u64>,
+    ) -> Result<(ExtractorThreadHandle, Client, u64), CliError> {
+        let ratings = self.selected_ratings();
+        let extension = self.force_extension.as_deref().map(Extension::guess_format);
+
+        match imageboard.server {
+            ImageBoards::GelbooruV0_2 | ImageBoards::Gelbooru => {
+                let mut unit = GelbooruExtractor::new_with_config(
+                    &[""],
+                    &ratings,
+                    self.disable_blacklist,
+                    !self.no_animated,
+                    imageboard.clone(),
+                );
+                auth_imgboard(auth, &mut unit).await?;
+
+                let user_id = self.user_id(imageboard).await?;
+
+                unit.exclude_tags(&self.exclude);
+
+                if let Some(ext) = extension {
+                    unit.force_extension(ext);
+                }
+
+                unit.setup_favorites_download(Some(user_id));
+
+                let client = unit.client();
+
+                let ext_thd =
+                    unit.setup_fetch_thread(channel_tx, None, self.limit, Some(length_tx));
+
+                Ok((ext_thd, client, user_id))
+            }
+            ImageBoards::Danbooru | ImageBoards::E621 | ImageBoards::Moebooru => {
+                Err(CliError::ExtractorUnsupportedMode)
+            }
+        }
+    }
+}
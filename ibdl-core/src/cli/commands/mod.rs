@@ -1,3 +1,9 @@
+pub mod archive;
+pub mod auth;
+pub mod batch;
+pub mod favorites;
 pub mod pool;
 pub mod post;
+pub mod retry;
 pub mod search;
+pub mod subscription;
@@ -1,12 +1,16 @@
 use clap::Args;
 use ibdl_common::{
-    post::{rating::Rating, Post},
+    post::{extension::Extension, rating::Rating, Post},
     reqwest::Client,
     tokio::sync::mpsc::{Sender, UnboundedSender},
     ImageBoards,
 };
 use ibdl_extractors::{
-    imageboards::{danbooru::DanbooruExtractor, e621::E621Extractor},
+    extractor_config::ServerConfig,
+    imageboards::{
+        danbooru::DanbooruExtractor, e621::E621Extractor, gelbooru::GelbooruExtractor,
+        moebooru::MoebooruExtractor,
+    },
     prelude::*,
 };
 
@@ -130,32 +134,71 @@ impl Pool {
         ratings
     }
 
+    /// Title and summary of the `ComicInfo.xml` of cbz files, using the pool name as the title
+    /// when available.
+    pub fn comic_info(
+        &self,
+        imageboard: &ServerConfig,
+        pool_name: Option<String>,
+    ) -> (String, String) {
+        let title = pool_name
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| format!("Pool #{}", self.pool_id));
+        let summary = format!("Pool #{} from {}", self.pool_id, imageboard.pretty_name);
+        (title, summary)
+    }
+
     pub async fn init_extractor(
         &self,
         args: &Cli,
         channel_tx: UnboundedSender<Post>,
         length_tx: Sender<u64>,
-    ) -> Result<(ExtractorThreadHandle, Client), CliError> {
+    ) -> Result<(ExtractorThreadHandle, Client, Option<String>), CliError> {
+        self.setup_extractor(
+            &args.imageboard,
+            args.auth,
+            args.ugoira.is_some(),
+            channel_tx,
+            length_tx,
+        )
+        .await
+    }
+
+    /// Starts the extractor thread for `imageboard`, also returning the name of the pool.
+    ///
+    /// `ugoira` also fetches the frame delays of Danbooru ugoira posts, needed to convert them.
+    pub async fn setup_extractor(
+        &self,
+        imageboard: &ServerConfig,
+        auth: bool,
+        ugoira: bool,
+        channel_tx: UnboundedSender<Post>,
+        length_tx: Sender<u64>,
+    ) -> Result<(ExtractorThreadHandle, Client, Option<String>), CliError> {
         let ratings = self.selected_ratings();
+        let extension = self.force_extension.as_deref().map(Extension::guess_format);
 
-        match args.imageboard.server {
+        match imageboard.server {
             ImageBoards::Danbooru => {
                 let mut unit = DanbooruExtractor::new_with_config(
                     &[""],
                     &ratings,
                     self.disable_blacklist,
                     !self.no_animated,
-                    args.imageboard.clone(),
+                    imageboard.clone(),
                 );
-                auth_imgboard(args.auth, &mut unit).await?;
+                auth_imgboard(auth, &mut unit).await?;
 
                 unit.exclude_tags(&self.exclude);
+                unit.fetch_ugoira_frames(ugoira);
 
-                if let Some(ext) = args.get_extension() {
+                if let Some(ext) = extension {
                     unit.force_extension(ext);
                 }
 
                 unit.setup_pool_download(Some(self.pool_id), self.latest_first);
+                unit.fetch_pool_idxs(self.pool_id, self.limit).await?;
+                let pool_name = unit.pool_name();
 
                 let client = unit.client();
 
@@ -166,7 +209,7 @@ impl Pool {
                     Some(length_tx),
                 );
 
-                Ok((ext_thd, client))
+                Ok((ext_thd, client, pool_name))
             }
             ImageBoards::E621 => {
                 let mut unit = E621Extractor::new_with_config(
@@ -174,18 +217,52 @@ impl Pool {
                     &ratings,
                     self.disable_blacklist,
                     !self.no_animated,
-                    args.imageboard.clone(),
+                    imageboard.clone(),
+                );
+
+                auth_imgboard(auth, &mut unit).await?;
+
+                unit.exclude_tags(&self.exclude);
+
+                if let Some(ext) = extension {
+                    unit.force_extension(ext);
+                }
+
+                unit.setup_pool_download(Some(self.pool_id), self.latest_first);
+                unit.fetch_pool_idxs(self.pool_id, self.limit).await?;
+                let pool_name = unit.pool_name();
+
+                let client = unit.client();
+
+                let ext_thd = unit.setup_fetch_thread(
+                    channel_tx,
+                    self.start_page,
+                    self.limit,
+                    Some(length_tx),
+                );
+
+                Ok((ext_thd, client, pool_name))
+            }
+            ImageBoards::Moebooru => {
+                let mut unit = MoebooruExtractor::new_with_config(
+                    &[""],
+                    &ratings,
+                    self.disable_blacklist,
+                    !self.no_animated,
+                    imageboard.clone(),
                 );
 
-                auth_imgboard(args.auth, &mut unit).await?;
+                auth_imgboard(auth, &mut unit).await?;
 
                 unit.exclude_tags(&self.exclude);
 
-                if let Some(ext) = args.get_extension() {
+                if let Some(ext) = extension {
                     unit.force_extension(ext);
                 }
 
                 unit.setup_pool_download(Some(self.pool_id), self.latest_first);
+                unit.fetch_pool_idxs(self.pool_id, self.limit).await?;
+                let pool_name = unit.pool_name();
 
                 let client = unit.client();
 
@@ -196,10 +273,39 @@ impl Pool {
                     Some(length_tx),
                 );
 
-                Ok((ext_thd, client))
+                Ok((ext_thd, client, pool_name))
             }
-            ImageBoards::GelbooruV0_2 | ImageBoards::Gelbooru | ImageBoards::Moebooru => {
-                Err(CliError::ExtractorUnsupportedMode)
+            ImageBoards::GelbooruV0_2 | ImageBoards::Gelbooru => {
+                let mut unit = GelbooruExtractor::new_with_config(
+                    &[""],
+                    &ratings,
+                    self.disable_blacklist,
+                    !self.no_animated,
+                    imageboard.clone(),
+                );
+
+                auth_imgboard(auth, &mut unit).await?;
+
+                unit.exclude_tags(&self.exclude);
+
+                if let Some(ext) = extension {
+                    unit.force_extension(ext);
+                }
+
+                unit.setup_pool_download(Some(self.pool_id), self.latest_first);
+                unit.fetch_pool_idxs(self.pool_id, self.limit).await?;
+                let pool_name = unit.pool_name();
+
+                let client = unit.client();
+
+                let ext_thd = unit.setup_fetch_thread(
+                    channel_tx,
+                    self.start_page,
+                    self.limit,
+                    Some(length_tx),
+                );
+
+                Ok((ext_thd, client, pool_name))
             }
         }
     }
@@ -11,8 +11,10 @@ use ibdl_common::{
     },
     ImageBoards,
 };
+use ibdl_extractors::extractor_config::ServerConfig;
 use ibdl_extractors::imageboards::{
     danbooru::DanbooruExtractor, e621::E621Extractor, gelbooru::GelbooruExtractor,
+    moebooru::MoebooruExtractor,
 };
 use ibdl_extractors::prelude::*;
 use owo_colors::OwoColorize;
@@ -31,7 +33,7 @@ pub struct Post {
         conflicts_with("post_file"),
         required = true
     )]
-    posts: Vec<u32>,
+    pub posts: Vec<u32>,
 
     /// Download a list of posts from a file (one post id per line)
     #[clap(
@@ -40,26 +42,50 @@ pub struct Post {
         value_parser,
         conflicts_with("posts")
     )]
-    post_file: Option<PathBuf>,
+    pub post_file: Option<PathBuf>,
 }
 
 impl Post {
+    /// Title and summary of the `ComicInfo.xml` of cbz files.
+    pub fn comic_info(&self, imageboard: &ServerConfig) -> (String, String) {
+        let title = format!("{} posts", imageboard.pretty_name);
+        let summary = format!("Selected posts from {}", imageboard.pretty_name);
+        (title, summary)
+    }
+
     pub async fn init_extractor(
         &self,
         args: &Cli,
         channel_tx: UnboundedSender<Pst>,
         length_tx: Sender<u64>,
     ) -> Result<(ExtractorThreadHandle, Client), CliError> {
-        match args.imageboard.server {
+        self.setup_extractor(
+            &args.imageboard,
+            args.auth,
+            args.ugoira.is_some(),
+            channel_tx,
+            length_tx,
+        )
+        .await
+    }
+
+    /// Starts the extractor thread for `imageboard`.
+    ///
+    /// `ugoira` also fetches the frame delays of Danbooru ugoira posts, needed to convert them.
+    pub async fn setup_extractor(
+        &self,
+        imageboard: &ServerConfig,
+        auth: bool,
+        ugoira: bool,
+        channel_tx: UnboundedSender<Pst>,
+        length_tx: Sender<u64>,
+    ) -> Result<(ExtractorThreadHandle, Client), CliError> {
+        match imageboard.server {
             ImageBoards::Danbooru => {
-                let mut unit = DanbooruExtractor::new_with_config(
-                    &[""],
-                    &[],
-                    true,
-                    true,
-                    args.imageboard.clone(),
-                );
-                auth_imgboard(args.auth, &mut unit).await?;
+                let mut unit =
+                    DanbooruExtractor::new_with_config(&[""], &[], true, true, imageboard.clone());
+                auth_imgboard(auth, &mut unit).await?;
+                unit.fetch_ugoira_frames(ugoira);
 
                 let client = unit.client();
 
@@ -103,8 +129,8 @@ impl Post {
             }
             ImageBoards::E621 => {
                 let mut unit =
-                    E621Extractor::new_with_config(&[""], &[], true, true, args.imageboard.clone());
-                auth_imgboard(args.auth, &mut unit).await?;
+                    E621Extractor::new_with_config(&[""], &[], true, true, imageboard.clone());
+                auth_imgboard(auth, &mut unit).await?;
 
                 let client = unit.client();
                 let ext_thd = {
@@ -146,13 +172,9 @@ impl Post {
                 Ok((ext_thd, client))
             }
             ImageBoards::GelbooruV0_2 | ImageBoards::Gelbooru => {
-                let unit = GelbooruExtractor::new_with_config(
-                    &[""],
-                    &[],
-                    true,
-                    true,
-                    args.imageboard.clone(),
-                );
+                let mut unit =
+                    GelbooruExtractor::new_with_config(&[""], &[], true, true, imageboard.clone());
+                auth_imgboard(auth, &mut unit).await?;
 
                 let client = unit.client();
                 let ext_thd = {
@@ -219,30 +241,48 @@ impl Post {
                 // Ok((ext_thd, client))
             }
             ImageBoards::Moebooru => {
-                Err(CliError::ExtractorUnsupportedMode)
-
-                // let mut unit = MoebooruExtractor::new(
-                //     &args.tags,
-                //     &ratings,
-                //     args.disable_blacklist,
-                //     !args.no_animated,
-                // );
-                // let client = unit.client();
+                let mut unit =
+                    MoebooruExtractor::new_with_config(&[""], &[], true, true, imageboard.clone());
+                auth_imgboard(auth, &mut unit).await?;
 
-                // unit.exclude_tags(&args.exclude);
+                let client = unit.client();
+                let ext_thd = {
+                    if !self.posts.is_empty() {
+                        unit.setup_async_post_fetch(
+                            channel_tx,
+                            PostFetchMethod::Multiple(self.posts.clone()),
+                            length_tx,
+                        )
+                    } else if let Some(path) = &self.post_file {
+                        let posts = fs::read_to_string(&path).await?;
+                        let ids = Vec::from_iter(posts.lines().filter_map(|line| {
+                            line.parse::<u32>().map_or_else(
+                                |_| {
+                                    warn!(
+                                        "Failed to parse line {} into a post id",
+                                        line.bright_blue().bold()
+                                    );
+                                    None
+                                },
+                                Some,
+                            )
+                        }));
 
-                // if let Some(ext) = args.get_extension() {
-                //     unit.force_extension(ext);
-                // }
+                        if ids.is_empty() {
+                            return Err(CliError::NoPostsInInput);
+                        }
 
-                // let ext_thd = unit.setup_fetch_thread(
-                //     channel_tx,
-                //     args.start_page,
-                //     args.limit,
-                //     Some(length_tx),
-                // );
+                        unit.setup_async_post_fetch(
+                            channel_tx,
+                            PostFetchMethod::Multiple(ids),
+                            length_tx,
+                        )
+                    } else {
+                        return Err(CliError::NoPostsInInput);
+                    }
+                };
 
-                // Ok((ext_thd, client))
+                Ok((ext_thd, client))
             }
         }
     }
@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{atomic::AtomicU64, Arc};
+
+use clap::Args;
+use ibdl_common::{
+    client,
+    events::{self, emit, Event},
+    log::debug,
+    post::Post,
+    reqwest::Client,
+    tokio::sync::mpsc::{channel, unbounded_channel},
+};
+use ibdl_extractors::extractor_config::ServerConfig;
+use owo_colors::OwoColorize;
+
+use crate::{
+    async_queue::PostSources,
+    cli::{commands::post::Post as PostCommand, extra::get_servers, Cli},
+    error::{CliError, QueueError},
+    report::{FailedPost, FailureReport},
+};
+
+#[derive(Debug, Args)]
+pub struct Retry {
+    /// Report of failed posts saved by an earlier download
+    #[clap(value_parser, value_name = "REPORT")]
+    pub report: PathBuf,
+}
+
+impl Retry {
+    /// Downloads the posts of the report again into the output they were meant for.
+    ///
+    /// Posts are fetched again from their servers, since the file URLs might have changed. Pool
+    /// posts are downloaded from the saved URLs instead, as their ids were replaced by their page
+    /// numbers.
+    pub async fn run(&self, args: &Cli) -> Result<(), CliError> {
+        self.run_with_servers(args, get_servers()).await
+    }
+
+    /// Same as [`run`](Self::run), looking up the servers of the report in `servers`.
+    pub(crate) async fn run_with_servers(
+        &self,
+        args: &Cli,
+        servers: &HashMap<String, ServerConfig>,
+    ) -> Result<(), CliError> {
+        let report = FailureReport::load(&self.report)?;
+
+        if report.cbz {
+            return Err(CliError::RetryCbzUnsupported);
+        }
+
+        if report.posts.is_empty() {
+            return Err(CliError::NoPostsInInput);
+        }
+
+        let sources = PostSources::default();
+        let mut posts = Vec::with_capacity(report.posts.len());
+        let mut first_server = None;
+
+        for (server, failed) in by_server(&report.posts) {
+            let imageboard =
+                args.configure_server(servers.get(server).ok_or(CliError::ServerNotExists)?);
+
+            debug!("Retrying {} posts from {}", failed.len(), server);
+
+            let (found, client) = if report.pool {
+                let client = client!(imageboard);
+                (failed.into_iter().cloned().collect(), client)
+            } else {
+                let ids = failed
+                    .iter()
+                    .map(|post| u32::try_from(post.id))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(QueueError::from)?;
+
+                fetch_posts(args, &imageboard, ids).await?
+            };
+
+            let imageboard = Arc::new(imageboard);
+
+            for post in found {
+                if sources.insert(&post, &imageboard, &client) {
+                    posts.push(post);
+                }
+            }
+
+            first_server.get_or_insert((imageboard, client));
+        }
+
+        let Some((imageboard, client)) = first_server else {
+            return Err(CliError::ImpossibleExecutionPath);
+        };
+
+        let (channel_tx, channel_rx) = unbounded_channel();
+        let (length_sender, length_channel) = channel(1);
+
+        length_sender
+            .send(posts.len() as u64)
+            .await
+            .map_err(|_| CliError::ImpossibleExecutionPath)?;
+
+        for post in posts {
+            channel_tx
+                .send(post)
+                .map_err(|_| CliError::ImpossibleExecutionPath)?;
+        }
+
+        drop(channel_tx);
+        drop(length_sender);
+
+        let mut qw = args.setup_queue(&imageboard, client, false, report.pool)?;
+        qw.post_sources(sources);
+
+        let downloaded = qw
+            .setup_async_downloader(
+                report.output,
+                Arc::new(AtomicU64::new(0)),
+                channel_rx,
+                length_channel,
+            )
+            .await
+            .map_err(|_| CliError::ImpossibleExecutionPath)??;
+
+        if events::json_enabled() {
+            emit(&Event::Summary(events::summary()));
+        } else {
+            println!(
+                "{} {} {}",
+                downloaded.to_string().bold().blue(),
+                "files".bold().blue(),
+                "downloaded".bold()
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Failed posts grouped by the server they come from, in the order the servers first appear.
+fn by_server(posts: &[FailedPost]) -> Vec<(&str, Vec<&Post>)> {
+    let mut servers: Vec<(&str, Vec<&Post>)> = Vec::new();
+
+    for failed in posts {
+        match servers
+            .iter_mut()
+            .find(|(server, _)| *server == failed.server)
+        {
+            Some((_, posts)) => posts.push(&failed.post),
+            None => servers.push((&failed.server, vec![&failed.post])),
+        }
+    }
+
+    servers
+}
+
+/// Fetches the posts with `ids` from `imageboard` like the `post` command does, returning them
+/// with the client used.
+async fn fetch_posts(
+    args: &Cli,
+    imageboard: &ServerConfig,
+    ids: Vec<u32>,
+) -> Result<(Vec<Post>, Client), CliError> {
+    let (post_tx, mut post_rx) = unbounded_channel();
+    let (length_tx, _length_rx) = channel(ids.len().max(1));
+
+    let command = PostCommand {
+        posts: ids,
+        post_file: None,
+    };
+
+    let (ext, client) = command
+        .setup_extractor(
+            imageboard,
+            args.auth,
+            args.ugoira.is_some(),
+            post_tx,
+            length_tx,
+        )
+        .await?;
+
+    ext.await.map_err(|_| CliError::ImpossibleExecutionPath)??;
+
+    let mut posts = Vec::new();
+
+    while let Ok(post) = post_rx.try_recv() {
+        posts.push(post);
+    }
+
+    Ok((posts, client))
+}
@@ -1,17 +1,27 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
 use clap::Args;
 use ibdl_common::{
-    post::{rating::Rating, Post},
+    post::{extension::Extension, rating::Rating, Post},
     reqwest::Client,
-    tokio::sync::mpsc::{Sender, UnboundedSender},
+    tokio::{
+        spawn,
+        sync::mpsc::{channel, unbounded_channel, Sender, UnboundedSender},
+        task::JoinHandle,
+    },
     ImageBoards,
 };
+use ibdl_extractors::extractor_config::ServerConfig;
 use ibdl_extractors::imageboards::{
     danbooru::DanbooruExtractor, e621::E621Extractor, gelbooru::GelbooruExtractor,
     moebooru::MoebooruExtractor,
 };
 use ibdl_extractors::prelude::*;
+use ibdl_extractors::query::Query;
 
 use crate::{
+    async_queue::{init_counters, PostSources},
     cli::{extra::auth_imgboard, Cli},
     error::CliError,
     RatingArg,
@@ -20,6 +30,10 @@ use crate::{
 #[derive(Debug, Args)]
 pub struct TagSearch {
     /// Tags to search
+    ///
+    /// Supports `~tag` (any of), `-tag` (none of), `cat*` wildcards, `( ... )` groups and the
+    /// `rating:`, `ext:`, `id:` and `md5:` metatags. Whatever doesn't fit in the tag limit of the
+    /// server is checked after downloading the post list
     #[clap(value_parser, required = true)]
     pub tags: Vec<String>,
 
@@ -97,6 +111,32 @@ pub struct TagSearch {
     pub ignore_unknown: bool,
 }
 
+/// How the search went in one of the servers of a multi-server search.
+#[derive(Debug)]
+pub struct ServerSummary {
+    pub server: ServerConfig,
+    /// Posts found, including the ones already found in other servers
+    pub found: u64,
+    /// Posts already found in another server
+    pub duplicates: u64,
+    /// Posts removed by the blacklist
+    pub removed: u64,
+    /// Error that stopped the search in this server
+    pub error: Option<String>,
+}
+
+impl ServerSummary {
+    const fn new(server: ServerConfig) -> Self {
+        Self {
+            server,
+            found: 0,
+            duplicates: 0,
+            removed: 0,
+            error: None,
+        }
+    }
+}
+
 impl TagSearch {
     #[inline]
     fn selected_ratings(&self) -> Vec<Rating> {
@@ -118,31 +158,188 @@ impl TagSearch {
         ratings
     }
 
+    /// Title and summary of the `ComicInfo.xml` of cbz files.
+    pub fn comic_info(&self, imageboards: &[ServerConfig]) -> (String, String) {
+        let tags = self.tags.join(" ");
+        let servers = imageboards
+            .iter()
+            .map(|server| server.pretty_name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let summary = format!("Posts from {servers} tagged with: {tags}");
+        (tags, summary)
+    }
+
     pub async fn init_extractor(
         &self,
         args: &Cli,
         channel_tx: UnboundedSender<Post>,
         length_tx: Sender<u64>,
     ) -> Result<(ExtractorThreadHandle, Client), CliError> {
+        self.setup_extractor(
+            &args.imageboard,
+            args.auth,
+            args.ugoira.is_some(),
+            None,
+            channel_tx,
+            length_tx,
+        )
+        .await
+    }
+
+    /// Starts an extractor thread for every server in `servers`, each one with its own progress
+    /// line, and sends the posts of all of them to `channel_tx`.
+    ///
+    /// Posts with a MD5 already found in another server are skipped. The returned [`PostSources`]
+    /// tells the [`Queue`](crate::async_queue::Queue) which server each post came from, and the
+    /// handle resolves to the summary of every server once all of them are done. A server failing
+    /// doesn't stop the others, its error is kept in its summary instead.
+    pub async fn init_multi_extractor(
+        &self,
+        servers: &[ServerConfig],
+        auth: bool,
+        ugoira: bool,
+        channel_tx: UnboundedSender<Post>,
+        length_tx: Sender<u64>,
+    ) -> Result<(JoinHandle<Vec<ServerSummary>>, Client, PostSources), CliError> {
+        Query::from_tags(&self.tags)?;
+
+        let first = servers.first().ok_or(CliError::ServerNotExists)?;
+        let counters = init_counters(0, first.server);
+        let sources = PostSources::default();
+
+        let mut clients = HashMap::with_capacity(servers.len());
+        let mut first_error = None;
+        let mut tasks = Vec::with_capacity(servers.len());
+
+        for server in servers {
+            let line = counters.add_server_line(&server.pretty_name);
+            let mut summary = ServerSummary::new(server.clone());
+
+            let (post_tx, mut post_rx) = unbounded_channel();
+            let (server_length_tx, mut server_length_rx) = channel(length_tx.max_capacity());
+
+            let (ext, client) = match self
+                .setup_extractor(server, auth, ugoira, None, post_tx, server_length_tx)
+                .await
+            {
+                Ok(extractor) => extractor,
+                Err(error) => {
+                    line.finish_with_message(format!("failed: {error}"));
+                    summary.error = Some(error.to_string());
+                    first_error.get_or_insert(error);
+                    tasks.push(spawn(async move { summary }));
+                    continue;
+                }
+            };
+
+            clients.insert(server.name.clone(), client.clone());
+
+            // The length of the queue only grows with the posts not found in other servers
+            spawn(async move { while server_length_rx.recv().await.is_some() {} });
+
+            let sources = sources.clone();
+            let channel_tx = channel_tx.clone();
+            let length_tx = length_tx.clone();
+            let config = Arc::new(server.clone());
+
+            tasks.push(spawn(async move {
+                while let Some(post) = post_rx.recv().await {
+                    summary.found += 1;
+
+                    if sources.insert(&post, &config, &client) {
+                        let _ = length_tx.send(1).await;
+                        let _ = channel_tx.send(post);
+                    } else {
+                        summary.duplicates += 1;
+                    }
+
+                    line.set_message(format!(
+                        "{} posts found, {} duplicates",
+                        summary.found, summary.duplicates
+                    ));
+                }
+
+                match ext.await {
+                    Ok(Ok(removed)) => summary.removed = removed,
+                    Ok(Err(error)) => summary.error = Some(error.to_string()),
+                    Err(error) => summary.error = Some(error.to_string()),
+                }
+
+                line.finish_with_message(format!(
+                    "{} posts found, {} duplicates, {} removed by blacklist",
+                    summary.found, summary.duplicates, summary.removed
+                ));
+
+                summary
+            }));
+        }
+
+        // Files are downloaded with the client of the server they came from, this one is only
+        // used for posts missing from the sources
+        let Some(client) = servers
+            .iter()
+            .find_map(|server| clients.get(&server.name).cloned())
+        else {
+            return Err(first_error.unwrap_or(CliError::ServerNotExists));
+        };
+
+        let handle = spawn(async move {
+            let mut summaries = Vec::with_capacity(tasks.len());
+
+            for task in tasks {
+                if let Ok(summary) = task.await {
+                    summaries.push(summary);
+                }
+            }
+
+            summaries
+        });
+
+        Ok((handle, client, sources))
+    }
+
+    /// Starts the extractor thread for `imageboard`.
+    ///
+    /// If `since_id` is set, only posts with a higher id will be fetched. `ugoira` also fetches the
+    /// frame delays of Danbooru ugoira posts, needed to convert them.
+    pub async fn setup_extractor(
+        &self,
+        imageboard: &ServerConfig,
+        auth: bool,
+        ugoira: bool,
+        since_id: Option<u64>,
+        channel_tx: UnboundedSender<Post>,
+        length_tx: Sender<u64>,
+    ) -> Result<(ExtractorThreadHandle, Client), CliError> {
+        // Extractors send invalid queries as they are, so catch them here
+        Query::from_tags(&self.tags)?;
+
         let ratings = self.selected_ratings();
+        let extension = self.force_extension.as_deref().map(Extension::guess_format);
 
-        match args.imageboard.server {
+        match imageboard.server {
             ImageBoards::Danbooru => {
                 let mut unit = DanbooruExtractor::new_with_config(
                     &self.tags,
                     &ratings,
                     self.disable_blacklist,
                     !self.no_animated,
-                    args.imageboard.clone(),
+                    imageboard.clone(),
                 );
-                auth_imgboard(args.auth, &mut unit).await?;
+                auth_imgboard(auth, &mut unit).await?;
 
                 unit.exclude_tags(&self.exclude);
+                unit.fetch_ugoira_frames(ugoira);
 
-                if let Some(ext) = args.get_extension() {
+                if let Some(ext) = extension {
                     unit.force_extension(ext);
                 }
 
+                if let Some(id) = since_id {
+                    unit.newer_than(id);
+                }
+
                 let client = unit.client();
 
                 let ext_thd = unit.setup_fetch_thread(
@@ -160,16 +357,20 @@ impl TagSearch {
                     &ratings,
                     self.disable_blacklist,
                     !self.no_animated,
-                    args.imageboard.clone(),
+                    imageboard.clone(),
                 );
-                auth_imgboard(args.auth, &mut unit).await?;
+                auth_imgboard(auth, &mut unit).await?;
 
                 unit.exclude_tags(&self.exclude);
 
-                if let Some(ext) = args.get_extension() {
+                if let Some(ext) = extension {
                     unit.force_extension(ext);
                 }
 
+                if let Some(id) = since_id {
+                    unit.newer_than(id);
+                }
+
                 let client = unit.client();
 
                 let ext_thd = unit.setup_fetch_thread(
@@ -187,15 +388,20 @@ impl TagSearch {
                     &ratings,
                     self.disable_blacklist,
                     !self.no_animated,
-                    args.imageboard.clone(),
+                    imageboard.clone(),
                 );
+                auth_imgboard(auth, &mut unit).await?;
 
                 unit.exclude_tags(&self.exclude);
 
-                if let Some(ext) = args.get_extension() {
+                if let Some(ext) = extension {
                     unit.force_extension(ext);
                 }
 
+                if let Some(id) = since_id {
+                    unit.newer_than(id);
+                }
+
                 let client = unit.client();
 
                 let ext_thd = unit.setup_fetch_thread(
@@ -213,16 +419,22 @@ impl TagSearch {
                     &ratings,
                     self.disable_blacklist,
                     !self.no_animated,
-                    args.imageboard.clone(),
+                    imageboard.clone(),
                 );
+                auth_imgboard(auth, &mut unit).await?;
+
                 let client = unit.client();
 
                 unit.exclude_tags(&self.exclude);
 
-                if let Some(ext) = args.get_extension() {
+                if let Some(ext) = extension {
                     unit.force_extension(ext);
                 }
 
+                if let Some(id) = since_id {
+                    unit.newer_than(id);
+                }
+
                 let ext_thd = unit.setup_fetch_thread(
                     channel_tx,
                     self.start_page,
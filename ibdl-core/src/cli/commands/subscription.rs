@@ -0,0 +1,342 @@
+use std::path::absolute;
+use std::sync::{atomic::AtomicU64, Arc};
+
+use clap::{Args, Subcommand};
+use ibdl_common::{
+    events::{self, emit, Event},
+    log::debug,
+    tokio::{
+        join, spawn,
+        sync::mpsc::{channel, unbounded_channel},
+    },
+};
+use ibdl_extractors::error::ExtractorError;
+use owo_colors::OwoColorize;
+
+use crate::{
+    cli::{commands::search::TagSearch, extra::get_servers, Cli},
+    error::CliError,
+    generate_output_path,
+    report::FailureReport,
+    subscription::{Subscription, Subscriptions},
+    RatingArg,
+};
+
+#[derive(Debug, Args)]
+pub struct Subscribe {
+    #[clap(subcommand)]
+    pub action: SubscribeAction,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SubscribeAction {
+    /// Save a tag search to be downloaded with `update`
+    ///
+    /// Posts are saved in the directory set with `-o`, or in `<current dir>/<imageboard>/<tags>` otherwise
+    Add(TagSearch),
+    /// List all saved subscriptions
+    List,
+    /// Remove a saved subscription
+    Remove {
+        /// Tags of the subscription to remove
+        #[clap(value_parser, required = true)]
+        tags: Vec<String>,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct Update {
+    /// Only update subscriptions from this server
+    #[clap(long, value_name = "SERVER")]
+    pub server: Option<String>,
+}
+
+impl Subscribe {
+    pub fn run(&self, args: &Cli) -> Result<(), CliError> {
+        let mut subs = Subscriptions::load_default()?;
+
+        match &self.action {
+            SubscribeAction::Add(search) => {
+                let output = match &args.output {
+                    Some(path) => path.clone(),
+                    None => generate_output_path(
+                        &std::env::current_dir()?,
+                        args.imageboard.server,
+                        &search.tags,
+                        false,
+                        None,
+                    ),
+                };
+
+                let sub = Subscription {
+                    server: args.imageboard.name.clone(),
+                    tags: search.tags.clone(),
+                    output: absolute(output)?,
+                    last_id: None,
+                    exclude: search.exclude.clone(),
+                    ratings: search.rating.iter().map(|rating| rating.0).collect(),
+                    safe_mode: search.safe_mode,
+                    ignore_unknown: search.ignore_unknown,
+                    no_animated: search.no_animated,
+                    disable_blacklist: search.disable_blacklist,
+                    force_extension: search.force_extension.clone(),
+                };
+
+                let msg = if subs.add(sub) {
+                    "Subscription updated:"
+                } else {
+                    "Subscribed to:"
+                };
+
+                subs.save()?;
+
+                println!(
+                    "{} {} {}",
+                    msg.bold(),
+                    format!("[{}]", args.imageboard.name).bold().green(),
+                    search.tags.join(" ").bold().blue()
+                );
+            }
+            SubscribeAction::List => {
+                for sub in &subs.list {
+                    println!(
+                        "{:<16} {} -> {} (last post: {})",
+                        format!("[{}]", sub.server).bold().green(),
+                        sub.tags.join(" ").bold().blue(),
+                        sub.output.display().bold(),
+                        sub.last_id
+                            .map_or_else(|| String::from("none"), |id| id.to_string())
+                            .yellow()
+                    );
+                }
+
+                println!(
+                    "{} {}",
+                    subs.list.len().to_string().bold().blue(),
+                    "saved subscriptions".bold()
+                );
+            }
+            SubscribeAction::Remove { tags } => {
+                if subs.remove(&args.imageboard.name, tags) {
+                    subs.save()?;
+                    println!(
+                        "{} {} {}",
+                        "Removed subscription:".bold(),
+                        format!("[{}]", args.imageboard.name).bold().green(),
+                        tags.join(" ").bold().blue()
+                    );
+                } else {
+                    println!("{}", "No subscription found for these tags".bold().red());
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Update {
+    /// Downloads all new posts from every saved subscription, saving the last seen post id of each
+    /// one as soon as it's done.
+    pub async fn run(&self, args: &Cli) -> Result<(), CliError> {
+        let mut subs = Subscriptions::load_default()?;
+
+        let mut total_down = 0;
+
+        for idx in 0..subs.list.len() {
+            let sub = subs.list[idx].clone();
+
+            if self.server.as_ref().is_some_and(|srv| *srv != sub.server) {
+                continue;
+            }
+
+            let Some(imageboard) = get_servers()
+                .get(&sub.server)
+                .map(|server| args.configure_server(server))
+            else {
+                report_update(&sub, Err(&format!("unknown server {}", sub.server)));
+                continue;
+            };
+
+            if !events::json_enabled() {
+                println!(
+                    "{} {} {}",
+                    "Updating".bold(),
+                    format!("[{}]", sub.server).bold().green(),
+                    sub.tags.join(" ").bold().blue()
+                );
+            }
+
+            let (channel_tx, channel_rx) = unbounded_channel();
+            let (post_tx, mut post_rx) = unbounded_channel();
+            let (length_sender, length_channel) = channel(args.simultaneous_downloads as usize);
+
+            let extractor = search_for(&sub)
+                .setup_extractor(
+                    &imageboard,
+                    args.auth,
+                    args.ugoira.is_some(),
+                    sub.last_id,
+                    post_tx,
+                    length_sender,
+                )
+                .await;
+
+            // A subscription failing to start doesn't stop the rest of them
+            let (ext, client) = match extractor {
+                Ok(extractor) => extractor,
+                Err(error) => {
+                    report_update(&sub, Err(&error.to_string()));
+                    continue;
+                }
+            };
+
+            // Keep track of every post sent to the downloader
+            let sent = spawn(async move {
+                let mut sent = Vec::new();
+                while let Some(post) = post_rx.recv().await {
+                    sent.push(post.id);
+                    if channel_tx.send(post).is_err() {
+                        break;
+                    }
+                }
+                sent
+            });
+
+            let qw = match args.setup_queue(&imageboard, client, false, false) {
+                Ok(qw) => qw,
+                Err(error) => {
+                    ext.abort();
+                    report_update(&sub, Err(&error.to_string()));
+                    continue;
+                }
+            };
+
+            let asd = qw.setup_async_downloader(
+                sub.output.clone(),
+                Arc::new(AtomicU64::new(0)),
+                channel_rx,
+                length_channel,
+            );
+
+            let (Ok(ext_res), Ok(sent), Ok(down_res)) = join!(ext, sent, asd) else {
+                return Err(CliError::ImpossibleExecutionPath);
+            };
+
+            let removed = match ext_res {
+                Ok(removed) => removed,
+                Err(ExtractorError::ZeroPosts) => 0,
+                Err(error) => {
+                    report_update(&sub, Err(&error.to_string()));
+                    continue;
+                }
+            };
+
+            let downloaded = match down_res {
+                Ok(downloaded) => downloaded,
+                Err(error) => {
+                    report_update(&sub, Err(&error.to_string()));
+                    continue;
+                }
+            };
+
+            // Failed posts only show up in the report, and have to be fetched again next time
+            let report = FailureReport::path_for(&sub.output);
+            let failed = match report.exists().then(|| FailureReport::load(&report)) {
+                Some(Ok(report)) => report.posts.iter().map(|failed| failed.post.id).collect(),
+                Some(Err(error)) => {
+                    report_update(&sub, Err(&error.to_string()));
+                    continue;
+                }
+                None => Vec::new(),
+            };
+
+            let newest = newest_saved(sub.last_id, &sent, &failed);
+            debug!("Newest post for {:?}: {:?}", sub.tags, newest);
+
+            total_down += downloaded;
+            subs.list[idx].last_id = newest;
+            subs.save()?;
+
+            report_update(&sub, Ok((downloaded, removed)));
+        }
+
+        if events::json_enabled() {
+            emit(&Event::Summary(events::summary()));
+            return Ok(());
+        }
+
+        println!(
+            "{} {} {}",
+            total_down.to_string().bold().blue(),
+            "files".bold().blue(),
+            "downloaded".bold()
+        );
+
+        Ok(())
+    }
+}
+
+/// Prints how the update of `sub` went, or emits it as a `job_finished` event.
+///
+/// `result` has the number of downloaded files and the posts removed by the blacklist.
+fn report_update(sub: &Subscription, result: Result<(u64, u64), &str>) {
+    let (downloaded, removed) = result.unwrap_or_default();
+
+    // Always emitted, so failed updates are counted in the exit code
+    emit(&Event::JobFinished {
+        server: &sub.server,
+        job: &sub.tags.join(" "),
+        downloaded,
+        removed,
+        error: result.err(),
+    });
+
+    if events::json_enabled() {
+        return;
+    }
+
+    match result {
+        Ok((downloaded, _)) => println!(
+            "{} {}",
+            downloaded.to_string().bold().blue(),
+            "new files downloaded".bold()
+        ),
+        Err(error) => println!(
+            "{} {}",
+            "Failed to update subscription:".bold().red(),
+            error
+        ),
+    }
+}
+
+/// Id to resume a subscription from after downloading the posts in `sent`, of which the ones in
+/// `failed` couldn't be saved.
+///
+/// Only advances up to the newest post older than every failed one, so the next update fetches the
+/// failed posts again.
+pub(crate) fn newest_saved(last_id: Option<u64>, sent: &[u64], failed: &[u64]) -> Option<u64> {
+    let oldest_failed = failed.iter().min();
+
+    sent.iter()
+        .copied()
+        .filter(|id| oldest_failed.is_none_or(|failed| id < failed))
+        .max()
+        .max(last_id)
+}
+
+/// Recreates the tag search a subscription was made with.
+fn search_for(sub: &Subscription) -> TagSearch {
+    TagSearch {
+        tags: sub.tags.clone(),
+        limit: None,
+        disable_blacklist: sub.disable_blacklist,
+        start_page: None,
+        exclude: sub.exclude.clone(),
+        force_extension: sub.force_extension.clone(),
+        no_animated: sub.no_animated,
+        safe_mode: sub.safe_mode,
+        rating: sub.ratings.iter().copied().map(RatingArg).collect(),
+        ignore_unknown: sub.ignore_unknown,
+    }
+}
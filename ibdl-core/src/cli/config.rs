@@ -0,0 +1,390 @@
+//! Defaults for the command line options, read from `ibdl.toml`.
+//!
+//! # Config File
+//! `ibdl.toml` lives next to `servers.toml` and can set any option of the command line by its long
+//! name (or the field name for options with only a short flag), with `-` or `_` between words.
+//! Named profiles in `[profile.<name>]` tables are applied on top of them with `--profile <name>`:
+//!
+//! ```toml
+//! simultaneous_downloads = 10
+//! id = true
+//!
+//! [profile.sd-training]
+//! annotate = true
+//! rating = ["safe"]
+//! no_animated = true
+//! ```
+//!
+//! Options given in the command line always win over the profile, and the profile over the
+//! defaults. Options of other subcommands are ignored, so the same file works for all of them.
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fmt::{self, Display};
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+
+use clap::{
+    parser::ValueSource, Arg, ArgAction, ArgMatches, Command, CommandFactory, FromArgMatches,
+};
+use ibdl_common::{
+    log::debug,
+    serde::{self, Deserialize},
+};
+use toml::{Table, Value};
+
+use crate::error::CliError;
+
+use super::{extra::config_dir, Cli};
+
+const CONFIG_FILE: &str = "ibdl.toml";
+
+/// Options that only make sense for a single run.
+const NOT_CONFIGURABLE: [&str; 5] = ["help", "version", "servers", "profile", "print_config"];
+
+fn config_error(message: impl ToString) -> CliError {
+    CliError::ConfigFileError {
+        message: message.to_string(),
+    }
+}
+
+/// Default options and named profiles of `ibdl.toml`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(crate = "self::serde")]
+pub struct ConfigFile {
+    #[serde(default, rename = "profile")]
+    pub profiles: BTreeMap<String, Table>,
+    /// Options applied to every run
+    #[serde(flatten)]
+    pub defaults: Table,
+}
+
+/// Where the value of an option comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Origin {
+    CommandLine,
+    ConfigFile,
+    Profile(String),
+    Default,
+}
+
+impl Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CommandLine => write!(f, "command line"),
+            Self::ConfigFile => write!(f, "{CONFIG_FILE}"),
+            Self::Profile(name) => write!(f, "profile {name}"),
+            Self::Default => write!(f, "default"),
+        }
+    }
+}
+
+/// Value of every option of a run after merging the command line with the config file, printed
+/// by `--print-config` as TOML.
+#[derive(Debug, Clone, Default)]
+pub struct EffectiveConfig {
+    pub options: Vec<(String, Value, Origin)>,
+}
+
+impl EffectiveConfig {
+    /// Value and origin of the option `key`.
+    pub fn get(&self, key: &str) -> Option<(&Value, &Origin)> {
+        self.options
+            .iter()
+            .find(|(name, _, _)| name == key)
+            .map(|(_, value, origin)| (value, origin))
+    }
+}
+
+impl Display for EffectiveConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (key, value, origin) in &self.options {
+            writeln!(f, "{:<40} # {origin}", format!("{key} = {value}"))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl ConfigFile {
+    /// Returns the default location of the config file.
+    pub fn default_path() -> PathBuf {
+        config_dir().join(Path::new(CONFIG_FILE))
+    }
+
+    /// Loads the config file from the default location, if there's one.
+    pub fn load_default() -> Result<Self, CliError> {
+        let path = Self::default_path();
+
+        if !path.exists() {
+            debug!("No config file at {}", path.display());
+            return Ok(Self::default());
+        }
+
+        Self::load(&path)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, CliError> {
+        let data = read_to_string(path)?;
+        data.parse()
+    }
+
+    /// Options of the defaults with the ones of `profile` on top, with where each one comes from.
+    fn options(&self, profile: Option<&str>) -> Result<Vec<(String, Value, Origin)>, CliError> {
+        let mut options = self
+            .defaults
+            .iter()
+            .map(|(key, value)| (normalize(key), (value.clone(), Origin::ConfigFile)))
+            .collect::<BTreeMap<_, _>>();
+
+        if let Some(name) = profile {
+            let profile = self
+                .profiles
+                .get(name)
+                .ok_or_else(|| config_error(format!("profile `{name}` not found")))?;
+
+            for (key, value) in profile {
+                options.insert(
+                    normalize(key),
+                    (value.clone(), Origin::Profile(name.to_string())),
+                );
+            }
+        }
+
+        Ok(options
+            .into_iter()
+            .map(|(key, (value, origin))| (key, value, origin))
+            .collect())
+    }
+}
+
+impl std::str::FromStr for ConfigFile {
+    type Err = CliError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        toml::from_str(s).map_err(config_error)
+    }
+}
+
+impl Cli {
+    /// Parses the command line like [`Parser::parse`](clap::Parser::parse), filling the options that weren't given in it
+    /// with the ones of [`ConfigFile`] and the profile selected with `--profile`.
+    ///
+    /// With `--print-config`, prints the merged options and exits instead.
+    pub fn parse_with_config() -> Result<Self, CliError> {
+        let config = ConfigFile::load_default()?;
+
+        match Self::parse_from_with_config(std::env::args_os(), &config) {
+            Ok((args, effective)) => {
+                if args.print_config {
+                    print!("{effective}");
+                    exit(0);
+                }
+
+                Ok(args)
+            }
+            Err(CliError::InvalidArguments { source }) => source.exit(),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Parses `argv` with the options of `config` as defaults.
+    pub fn parse_from_with_config<I, T>(
+        argv: I,
+        config: &ConfigFile,
+    ) -> Result<(Self, EffectiveConfig), CliError>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let mut argv = argv.into_iter().map(Into::into).collect::<Vec<OsString>>();
+
+        let mut root = Self::command();
+        root.build();
+
+        let matches = root.clone().try_get_matches_from(&argv)?;
+        let (command, leaf) = leaf_command(&root, &matches);
+
+        let profile = leaf.get_one::<String>("profile").map(String::as_str);
+        let mut injected = BTreeMap::new();
+        let mut extra = Vec::new();
+
+        for (key, value, origin) in config.options(profile)? {
+            let Some(arg) = find_arg(command, &key) else {
+                if !configurable_anywhere(&root, &key) {
+                    return Err(config_error(format!("unknown option `{key}`")));
+                }
+
+                debug!("Option {key} doesn't apply to this command");
+                continue;
+            };
+
+            if given_in_command_line(command, leaf, arg) {
+                continue;
+            }
+
+            extra.extend(arg_tokens(arg, &key, &value)?);
+            injected.insert(arg.get_id().to_string(), origin);
+        }
+
+        // Everything after `--` is taken as positional values
+        let end = argv
+            .iter()
+            .position(|token| token == "--")
+            .unwrap_or(argv.len());
+        argv.splice(end..end, extra);
+
+        let matches = root.clone().try_get_matches_from(&argv)?;
+        let args = Self::from_arg_matches(&matches)?;
+        let (command, leaf) = leaf_command(&root, &matches);
+
+        let effective = EffectiveConfig {
+            options: command
+                .get_arguments()
+                .filter(|arg| is_configurable(arg))
+                .filter_map(|arg| {
+                    let id = arg.get_id().as_str();
+                    let value = arg_value(arg, leaf)?;
+
+                    let origin = match leaf.value_source(id)? {
+                        ValueSource::DefaultValue => Origin::Default,
+                        _ => injected.get(id).cloned().unwrap_or(Origin::CommandLine),
+                    };
+
+                    Some((option_key(arg), value, origin))
+                })
+                .collect(),
+        };
+
+        Ok((args, effective))
+    }
+}
+
+/// Innermost subcommand of the run and its matches.
+fn leaf_command<'a>(root: &'a Command, matches: &'a ArgMatches) -> (&'a Command, &'a ArgMatches) {
+    let (mut command, mut leaf) = (root, matches);
+
+    while let Some((name, sub)) = leaf.subcommand() {
+        match command.find_subcommand(name) {
+            Some(subcommand) => (command, leaf) = (subcommand, sub),
+            None => break,
+        }
+    }
+
+    (command, leaf)
+}
+
+/// `key` with `_` between words, like the field names of the options.
+fn normalize(key: &str) -> String {
+    key.replace('-', "_")
+}
+
+/// Name of `arg` in the config file.
+fn option_key(arg: &Arg) -> String {
+    arg.get_long()
+        .map_or_else(|| arg.get_id().to_string(), normalize)
+}
+
+fn is_configurable(arg: &Arg) -> bool {
+    !arg.is_positional() && !NOT_CONFIGURABLE.contains(&arg.get_id().as_str())
+}
+
+fn find_arg<'a>(command: &'a Command, key: &str) -> Option<&'a Arg> {
+    command
+        .get_arguments()
+        .filter(|arg| is_configurable(arg))
+        .find(|arg| option_key(arg) == key || arg.get_id().as_str() == key)
+}
+
+fn configurable_anywhere(command: &Command, key: &str) -> bool {
+    find_arg(command, key).is_some()
+        || command
+            .get_subcommands()
+            .any(|subcommand| configurable_anywhere(subcommand, key))
+}
+
+/// Checks if `arg`, or an option it conflicts with, was given in the command line.
+fn given_in_command_line(command: &Command, matches: &ArgMatches, arg: &Arg) -> bool {
+    let from_cli =
+        |arg: &Arg| matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine);
+
+    from_cli(arg)
+        || command
+            .get_arguments()
+            .filter(|other| from_cli(other))
+            .any(|other| {
+                command.get_arg_conflicts_with(other).contains(&arg)
+                    || command.get_arg_conflicts_with(arg).contains(&other)
+            })
+}
+
+/// Command line tokens that set `arg` to `value`.
+fn arg_tokens(arg: &Arg, key: &str, value: &Value) -> Result<Vec<OsString>, CliError> {
+    let flag = arg.get_long().map_or_else(
+        || format!("-{}", arg.get_short().unwrap_or_default()),
+        |long| format!("--{long}"),
+    );
+
+    let values = match value {
+        Value::Array(values) => values.iter().collect(),
+        value => vec![value],
+    };
+
+    let mut tokens = Vec::new();
+
+    for value in values {
+        let value = match value {
+            Value::String(value) => value.clone(),
+            Value::Integer(value) => value.to_string(),
+            Value::Float(value) => value.to_string(),
+            Value::Boolean(set) if !arg.get_action().takes_values() => {
+                // Flags can only be turned on
+                if *set {
+                    tokens.push(OsString::from(&flag));
+                }
+                continue;
+            }
+            Value::Boolean(value) => value.to_string(),
+            Value::Datetime(_) | Value::Array(_) | Value::Table(_) => {
+                return Err(config_error(format!("invalid value for `{key}`")));
+            }
+        };
+
+        if arg.get_long().is_some() {
+            tokens.push(OsString::from(format!("{flag}={value}")));
+        } else {
+            tokens.push(OsString::from(&flag));
+            tokens.push(OsString::from(value));
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Value of `arg` in `matches`, as the config file would set it.
+fn arg_value(arg: &Arg, matches: &ArgMatches) -> Option<Value> {
+    let values = matches
+        .get_raw(arg.get_id().as_str())?
+        .map(|raw| toml_value(&raw.to_string_lossy()))
+        .collect::<Vec<_>>();
+
+    if matches!(arg.get_action(), ArgAction::Append) {
+        Some(Value::Array(values))
+    } else {
+        values.into_iter().next()
+    }
+}
+
+fn toml_value(raw: &str) -> Value {
+    raw.parse()
+        .map(Value::Integer)
+        .or_else(|_| raw.parse().map(Value::Boolean))
+        .ok()
+        .or_else(|| {
+            raw.parse()
+                .ok()
+                .filter(|_| raw.contains('.'))
+                .map(Value::Float)
+        })
+        .unwrap_or_else(|| Value::String(raw.to_string()))
+}
@@ -1,29 +1,27 @@
 use std::{
     collections::HashMap,
-    env, io,
+    env,
     path::{Path, PathBuf},
 };
 
 use crate::error::CliError;
 use dialoguer::{theme::ColorfulTheme, Input, Password};
-use ibdl_common::{
-    bincode::deserialize,
-    directories::ProjectDirs,
-    log::{debug, warn},
-    reqwest::Client,
-    tokio::fs::{read, remove_file},
-    ImageBoards,
-};
+use ibdl_common::{directories::ProjectDirs, log::debug, reqwest::Client, ImageBoards};
 use ibdl_extractors::prelude::{Auth, Extractor};
 use ibdl_extractors::{
-    auth::ImageboardConfig,
+    auth::{self, Credentials, ImageboardConfig, KeyringStore, SecretStore, VaultStore},
     extractor_config::{serialize::read_server_cfg_file, ServerConfig, DEFAULT_SERVERS},
 };
+use once_cell::sync::OnceCell;
 use owo_colors::OwoColorize;
 use std::fs;
 
 use super::AVAILABLE_SERVERS;
 
+const VAULT_FILE: &str = "credentials.vault";
+
+static SECRET_STORE: OnceCell<Box<dyn SecretStore>> = OnceCell::new();
+
 pub async fn auth_prompt(
     auth_state: bool,
     imageboard: &ServerConfig,
@@ -37,86 +35,169 @@ pub async fn auth_prompt(
         );
 
         let username: String = Input::with_theme(&ColorfulTheme::default())
-            .with_prompt("Username")
+            .with_prompt(username_prompt(imageboard))
             .interact()?;
 
         let api_key: String = Password::with_theme(&ColorfulTheme::default())
-            .with_prompt("API Key")
+            .with_prompt(api_key_prompt(imageboard))
             .interact()?;
 
-        let mut at = ImageboardConfig::new(
-            get_servers().get(&imageboard.name).unwrap().clone(),
-            username.trim().to_string(),
-            api_key.trim().to_string(),
-        );
+        let credentials = Credentials { username, api_key };
 
-        at.authenticate(client).await?;
+        login(
+            get_servers().get(&imageboard.name).unwrap_or(imageboard),
+            client,
+            &credentials,
+            secret_store()?,
+        )
+        .await?;
 
         return Ok(());
     }
     Ok(())
 }
 
+/// Name of the first credential of `imageboard`. Gelbooru-based servers take the numeric user id
+/// shown next to the API key in the account options.
+pub const fn username_prompt(imageboard: &ServerConfig) -> &'static str {
+    match imageboard.server {
+        ImageBoards::Gelbooru => "User ID",
+        _ => "Username",
+    }
+}
+
+/// Name of the second credential of `imageboard`. Moebooru servers take the password of the
+/// account, which is hashed before it's sent or saved.
+pub const fn api_key_prompt(imageboard: &ServerConfig) -> &'static str {
+    match imageboard.server {
+        ImageBoards::Moebooru => "Password",
+        _ => "API Key",
+    }
+}
+
+/// Checks `credentials` with `imageboard` and saves the login in `store` for later runs.
+pub async fn login(
+    imageboard: &ServerConfig,
+    client: &Client,
+    credentials: &Credentials,
+    store: &dyn SecretStore,
+) -> Result<ImageboardConfig, CliError> {
+    let mut config = ImageboardConfig::new(
+        imageboard.clone(),
+        credentials.username.trim().to_string(),
+        credentials.api_key.trim().to_string(),
+    );
+
+    config.authenticate(client).await?;
+    config.save(store).await?;
+
+    Ok(config)
+}
+
 pub async fn auth_imgboard<E>(ask: bool, extractor: &mut E) -> Result<(), CliError>
 where
     E: Auth + Extractor + Send,
 {
     let imageboard = extractor.config();
+
+    if imageboard.auth_url.is_none() {
+        debug!("{} doesn't support authentication", imageboard.name);
+        return Ok(());
+    }
+
     let client = extractor.client();
     auth_prompt(ask, &imageboard, &client).await?;
 
-    if let Some(creds) = read_config_from_fs(&imageboard).await? {
-        extractor.auth(creds).await?;
+    if !ImageboardConfig::is_cached(&imageboard)? {
+        debug!("Running without authentication");
         return Ok(());
     }
 
+    if let Some(creds) = ImageboardConfig::load(&imageboard, secret_store()?).await? {
+        extractor.auth(creds).await?;
+    }
+
     Ok(())
 }
 
-/// Reads and parses the authentication cache from the path provided by `auth_cache_dir`.
+/// Returns the store that keeps the credentials of every server.
 ///
-/// Returns `None` if the file is corrupted or does not exist.
-pub async fn read_config_from_fs(
-    imageboard: &ServerConfig,
-) -> Result<Option<ImageboardConfig>, io::Error> {
-    let cfg_path = ImageBoards::auth_cache_dir()?.join(PathBuf::from(imageboard.to_string()));
-    if let Ok(config_auth) = read(&cfg_path).await {
-        debug!("Authentication cache found");
-
-        if let Ok(rd) = deserialize::<ImageboardConfig>(&config_auth) {
-            debug!("Authentication cache decoded.");
-            debug!("User id: {}", rd.user_data.id);
-            debug!("Username: {}", rd.user_data.name);
-            debug!("Blacklisted tags: '{:?}'", rd.user_data.blacklisted_tags);
-            return Ok(Some(rd));
-        } else {
-            warn!(
-                "{}",
-                "Auth cache is invalid or empty. Running without authentication"
-            );
-            debug!("Removing corrupted file");
-            remove_file(cfg_path).await?;
-            return Ok(None);
-        };
-    };
-    debug!("Running without authentication");
-    Ok(None)
+/// The system keyring is used when there's one, and a vault encrypted with a passphrase otherwise.
+/// The `IBDL_SECRET_STORE` environment variable can force either of them with `keyring` or `vault`.
+pub fn secret_store() -> Result<&'static dyn SecretStore, CliError> {
+    SECRET_STORE
+        .get_or_try_init(|| {
+            let store: Box<dyn SecretStore> = match env::var("IBDL_SECRET_STORE")
+                .unwrap_or_default()
+                .as_str()
+            {
+                "keyring" => Box::new(KeyringStore::connect().ok_or(CliError::KeyringUnavailable)?),
+                "vault" => Box::new(vault_store()?),
+                "" => match KeyringStore::connect() {
+                    Some(keyring) => Box::new(keyring),
+                    None => Box::new(vault_store()?),
+                },
+                name => {
+                    return Err(CliError::UnknownSecretStore {
+                        name: name.to_string(),
+                    })
+                }
+            };
+
+            debug!("Keeping credentials in the {}", store.name());
+            Ok(store)
+        })
+        .map(AsRef::as_ref)
+}
+
+fn vault_store() -> Result<VaultStore, CliError> {
+    let path = ImageBoards::auth_cache_dir()?.join(Path::new(VAULT_FILE));
+
+    Ok(VaultStore::new(path, Box::new(vault_passphrase)))
+}
+
+/// Takes the passphrase of the credential vault from `IBDL_VAULT_PASSPHRASE`, or asks for it.
+fn vault_passphrase(creating: bool) -> Result<String, auth::Error> {
+    if let Ok(passphrase) = env::var("IBDL_VAULT_PASSPHRASE") {
+        return Ok(passphrase);
+    }
+
+    let theme = ColorfulTheme::default();
+    let mut prompt = Password::with_theme(&theme).with_prompt("Credential vault passphrase");
+
+    if creating {
+        prompt = prompt.with_confirmation("Repeat the passphrase", "Passphrases don't match");
+    }
+
+    prompt
+        .interact()
+        .map_err(|error| auth::Error::SecretStoreError {
+            store: "credential vault",
+            message: error.to_string(),
+        })
+}
+
+/// Directory of `servers.toml` and `ibdl.toml`, created if it doesn't exist.
+///
+/// Can be changed with the `IBDL_SERVER_CFG` environment variable.
+pub fn config_dir() -> PathBuf {
+    let cfg_path = PathBuf::from(env::var("IBDL_SERVER_CFG").unwrap_or_else(|_| {
+        let cdir = ProjectDirs::from("com", "FerrahWolfeh", "imageboard-downloader").unwrap();
+        cdir.config_dir().to_string_lossy().to_string()
+    }));
+
+    if !cfg_path.exists() {
+        fs::create_dir_all(&cfg_path).unwrap();
+    }
+
+    cfg_path
 }
 
 pub fn get_servers<'a>() -> &'a HashMap<String, ServerConfig> {
     AVAILABLE_SERVERS.get_or_init(|| {
         let mut servers = DEFAULT_SERVERS.clone();
 
-        let cfg_path = PathBuf::from(env::var("IBDL_SERVER_CFG").unwrap_or_else(|_| {
-            let cdir = ProjectDirs::from("com", "FerrahWolfeh", "imageboard-downloader").unwrap();
-            cdir.config_dir().to_string_lossy().to_string()
-        }));
-
-        if !cfg_path.exists() {
-            fs::create_dir_all(&cfg_path).unwrap();
-        }
-
-        let cfg_path = cfg_path.join(Path::new("servers.toml"));
+        let cfg_path = config_dir().join(Path::new("servers.toml"));
 
         read_server_cfg_file(&cfg_path, &mut servers);
 
@@ -138,3 +219,10 @@ pub fn validate_imageboard(input: &str) -> Result<ServerConfig, String> {
         |server| Ok(server.clone()),
     )
 }
+
+pub fn validate_rate_limit(input: &str) -> Result<f32, String> {
+    match input.parse::<f32>() {
+        Ok(limit) if limit > 0.0 && limit.is_finite() => Ok(limit),
+        _ => Err(String::from("Rate limit must be a positive number")),
+    }
+}
@@ -1,23 +1,55 @@
 // 20002709
 use ibdl_common::post::{extension::Extension, NameType};
+use ibdl_common::reqwest::Client;
 use ibdl_extractors::extractor_config::ServerConfig;
+use ibdl_extractors::prelude::ExtractorFeatures;
 use once_cell::sync::OnceCell;
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{stdout, BufWriter, Write},
+    path::PathBuf,
+};
 
 use clap::{Parser, Subcommand};
 
-use crate::generate_output_path_precise;
+use crate::{
+    archive::DownloadArchive,
+    async_queue::{FilenameTemplate, GroupBy, Hooks, MetadataFormat, Queue, UgoiraFormat},
+    error::{CliError, QueueError},
+    generate_output_path_precise,
+    listing::ListFormat,
+    progress_bars::ProgressMode,
+    similar::{HashAlgorithm, SimilarAction, SimilarImages, SimilarIndex},
+};
 
 use self::{
-    commands::{pool::Pool, post::Post, search::TagSearch},
-    extra::validate_imageboard,
+    commands::{
+        archive::Archive,
+        auth::Auth,
+        batch::Batch,
+        favorites::Favorites,
+        pool::Pool,
+        post::Post,
+        retry::Retry,
+        search::TagSearch,
+        subscription::{Subscribe, Update},
+    },
+    extra::{get_servers, validate_imageboard, validate_rate_limit},
 };
 
 pub mod commands;
+pub mod config;
 pub(crate) mod extra;
 
 pub static AVAILABLE_SERVERS: OnceCell<HashMap<String, ServerConfig>> = OnceCell::new();
 
+/// Download archive shared by every queue of the run, since it can only be opened once
+static ARCHIVE: OnceCell<DownloadArchive> = OnceCell::new();
+
+/// Index of similar images shared by every queue of the run, since it can only be opened once
+static SIMILAR_INDEX: OnceCell<SimilarIndex> = OnceCell::new();
+
 #[derive(Debug, Subcommand)]
 pub enum Commands {
     /// Search and download posts with tags
@@ -26,6 +58,20 @@ pub enum Commands {
     Pool(Pool),
     /// Download a single or multiple specific posts
     Post(Post),
+    /// Download the favorites of a user
+    Favorites(Favorites),
+    /// List or prune the download archive
+    Archive(Archive),
+    /// Save, list or remove tag searches to be downloaded with `update`
+    Subscribe(Subscribe),
+    /// Download only the new posts of every saved subscription
+    Update(Update),
+    /// Run many searches, pools and posts listed in a TOML or JSON manifest
+    Batch(Batch),
+    /// Download again the posts that failed in an earlier download, listed in its failure report
+    Retry(Retry),
+    /// Log into a server, or show or remove the saved logins
+    Auth(Auth),
 }
 
 #[derive(Parser, Debug)]
@@ -36,14 +82,85 @@ pub struct Cli {
 
     /// Specify which website to download from
     ///
+    /// Searches can use many websites separated by commas, like `danbooru,e621`. Files found in
+    /// more than one of them are only downloaded once.
+    ///
     /// Default websites include: ["danbooru", "e621", "gelbooru", "rule34", "realbooru", "konachan"]
-    #[clap(short, long, ignore_case = true, default_value_t = ServerConfig::default(), global = true, value_parser = validate_imageboard)]
+    #[clap(
+        short = 'i',
+        long = "imageboard",
+        value_name = "IMAGEBOARD",
+        value_delimiter = ',',
+        ignore_case = true,
+        default_values_t = [ServerConfig::default()],
+        global = true,
+        value_parser = validate_imageboard
+    )]
+    pub imageboards: Vec<ServerConfig>,
+
+    /// Search every website that supports tag searches
+    #[clap(long, global = true, conflicts_with = "imageboards")]
+    pub all_servers: bool,
+
+    /// First of the selected websites, set by [`Cli::select_servers`]
+    #[clap(skip)]
     pub imageboard: ServerConfig,
 
     /// Print all available servers and exit
     #[clap(long, global = true)]
     pub servers: bool,
 
+    /// Apply the options of a profile from `ibdl.toml`. Options given in the command line win
+    #[clap(long, value_name = "NAME", help_heading = "GENERAL", global = true)]
+    pub profile: Option<String>,
+
+    /// Print the options of this run, merged from `ibdl.toml` and the command line, and exit
+    #[clap(long, help_heading = "GENERAL", global = true)]
+    pub print_config: bool,
+
+    /// How to show the progress of downloads
+    ///
+    /// `json` writes one event per line to stdout, like `{"event":"download_finished",...}`,
+    /// and a summary with the count of every outcome at the end
+    #[clap(
+        long,
+        value_enum,
+        value_name = "MODE",
+        default_value_t = ProgressMode::Bars,
+        conflicts_with = "dry_run",
+        help_heading = "GENERAL",
+        global = true
+    )]
+    pub progress: ProgressMode,
+
+    /// Only list the posts that would be downloaded, with counts per rating and extension
+    ///
+    /// Runs the search with every filter, but writes the posts out instead of downloading them
+    #[clap(long, alias = "list", help_heading = "GENERAL", global = true)]
+    pub dry_run: bool,
+
+    /// Format of the posts listed by `--dry-run`
+    #[clap(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        default_value_t = ListFormat::Table,
+        requires = "dry_run",
+        help_heading = "GENERAL",
+        global = true
+    )]
+    pub list_format: ListFormat,
+
+    /// File to write the posts listed by `--dry-run` to, instead of the terminal
+    #[clap(
+        long,
+        value_name = "FILE",
+        requires = "dry_run",
+        help_heading = "GENERAL",
+        global = true
+    )]
+    pub list_output: Option<PathBuf>,
+
     /// Where to save files (If the path doesn't exist, it will be created.)
     #[clap(short = 'o', value_name = "PATH", help_heading = "SAVE", global = true)]
     pub output: Option<PathBuf>,
@@ -82,6 +199,31 @@ pub struct Cli {
     )]
     pub save_file_as_id: bool,
 
+    /// Template for the names of saved files, like `{artist}/{id}_{md5:8}.{ext}`
+    ///
+    /// Fields: {id}, {index}, {md5}, {ext}, {server}, {rating}, {artist}, {copyright} and {character}.
+    /// Every `/` creates a subdirectory. Overrides `--id`
+    #[clap(
+        long,
+        value_name = "TEMPLATE",
+        value_parser = clap::value_parser!(FilenameTemplate),
+        help_heading = "SAVE",
+        global = true
+    )]
+    pub filename: Option<FilenameTemplate>,
+
+    /// Save files into subdirectories named after their rating, first tag of a type or extension
+    ///
+    /// Posts without a tag of the selected type are saved to `Unknown`
+    #[clap(
+        long,
+        value_enum,
+        value_name = "GROUP",
+        help_heading = "SAVE",
+        global = true
+    )]
+    pub group_by: Option<GroupBy>,
+
     /// Save posts inside a cbz file.
     ///
     /// Will ask to overwrite the destination file.
@@ -104,6 +246,131 @@ pub struct Cli {
     )]
     pub annotate: bool,
 
+    /// Convert ugoira posts from a zip of frames into an animation
+    ///
+    /// Only for servers that provide the frame delays, like Danbooru. Not applied to cbz files
+    #[clap(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        help_heading = "SAVE",
+        global = true
+    )]
+    pub ugoira: Option<UgoiraFormat>,
+
+    /// Keep the zip of converted ugoira posts next to the animation
+    #[clap(
+        long,
+        value_parser,
+        default_value_t = false,
+        requires = "ugoira",
+        help_heading = "SAVE",
+        global = true
+    )]
+    pub keep_ugoira_zip: bool,
+
+    /// Save a metadata file with the full post info next to every downloaded file
+    #[clap(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        help_heading = "SAVE",
+        global = true
+    )]
+    pub metadata: Option<MetadataFormat>,
+
+    /// Skip posts already recorded in the download archive and record new downloads into it
+    ///
+    /// Lets downloaded files be moved or sorted without being downloaded again in later runs
+    #[clap(
+        long,
+        value_parser,
+        default_value_t = false,
+        help_heading = "DOWNLOAD",
+        global = true
+    )]
+    pub archive: bool,
+
+    /// Check every downloaded image against the images saved before, and skip, hardlink or only
+    /// report the ones that look the same
+    ///
+    /// Finds resized or re-encoded copies that have a different MD5. Not applied to cbz files
+    #[clap(
+        long,
+        value_enum,
+        value_name = "ACTION",
+        help_heading = "DOWNLOAD",
+        global = true
+    )]
+    pub similar: Option<SimilarAction>,
+
+    /// How similar, in percent, two images must be for `--similar` to consider them the same
+    #[clap(
+        long,
+        value_name = "PERCENT",
+        value_parser = clap::value_parser!(u8).range(50..=100),
+        default_value_t = 90,
+        requires = "similar",
+        help_heading = "DOWNLOAD",
+        global = true
+    )]
+    pub similarity: u8,
+
+    /// Perceptual hash used by `--similar`
+    #[clap(
+        long,
+        value_enum,
+        value_name = "ALGORITHM",
+        default_value_t = HashAlgorithm::DHash,
+        requires = "similar",
+        help_heading = "DOWNLOAD",
+        global = true
+    )]
+    pub similar_hash: HashAlgorithm,
+
+    /// How many times a failed request is retried before giving up
+    ///
+    /// Overrides the retry policy of the selected server
+    #[clap(long, value_name = "NUMBER", help_heading = "DOWNLOAD", global = true)]
+    pub retries: Option<u32>,
+
+    /// Maximum number of API requests sent to the server every second
+    ///
+    /// Defaults to the limit of the selected server, if it has one
+    #[clap(
+        long,
+        value_name = "REQUESTS",
+        value_parser = validate_rate_limit,
+        help_heading = "DOWNLOAD",
+        global = true
+    )]
+    pub rate_limit: Option<f32>,
+
+    /// Command to run after every file saved to the output directory. Can be used multiple times
+    ///
+    /// The file path and post info are passed in `IBDL_*` environment variables and as JSON in stdin.
+    /// Not run for files saved inside cbz files
+    #[clap(
+        long,
+        value_name = "COMMAND",
+        action = clap::ArgAction::Append,
+        help_heading = "HOOKS",
+        global = true
+    )]
+    pub on_file: Vec<String>,
+
+    /// Command to run after every finished download job. Can be used multiple times
+    ///
+    /// The output path, server and number of downloaded files are passed in `IBDL_*` environment variables and as JSON in stdin
+    #[clap(
+        long,
+        value_name = "COMMAND",
+        action = clap::ArgAction::Append,
+        help_heading = "HOOKS",
+        global = true
+    )]
+    pub on_job: Vec<String>,
+
     /// Always overwrite output
     #[clap(
         short = 'y',
@@ -124,6 +391,150 @@ impl Cli {
         }
     }
 
+    /// Applies [`configure_server`](Self::configure_server) to the selected websites, replacing
+    /// them with every server with tag search support if `--all-servers` is set.
+    pub fn select_servers(&mut self) -> Result<(), CliError> {
+        if self.all_servers {
+            let mut servers = get_servers()
+                .values()
+                .filter(|server| {
+                    server
+                        .extractor_features()
+                        .contains(ExtractorFeatures::TagSearch)
+                })
+                .cloned()
+                .collect::<Vec<_>>();
+            servers.sort_by(|a, b| a.name.cmp(&b.name));
+            self.imageboards = servers;
+        }
+
+        let mut seen = HashSet::new();
+        self.imageboards
+            .retain(|server| seen.insert(server.name.clone()));
+
+        if self.imageboards.len() > 1 && !matches!(self.mode, Commands::Search(_)) {
+            return Err(CliError::MultipleServers);
+        }
+
+        if self.dry_run
+            && !matches!(
+                self.mode,
+                Commands::Search(_)
+                    | Commands::Pool(_)
+                    | Commands::Post(_)
+                    | Commands::Favorites(_)
+            )
+        {
+            return Err(CliError::DryRunUnsupported);
+        }
+
+        self.imageboards = self
+            .imageboards
+            .iter()
+            .map(|server| self.configure_server(server))
+            .collect();
+
+        self.imageboard = self.imageboards.first().cloned().unwrap_or_default();
+
+        Ok(())
+    }
+
+    /// Applies the retry and rate limit options to a copy of `server`.
+    pub fn configure_server(&self, server: &ServerConfig) -> ServerConfig {
+        let mut server = server.clone();
+
+        if let Some(retries) = self.retries {
+            server.retry.attempts = retries;
+        }
+
+        if self.rate_limit.is_some() {
+            server.rate_limit = self.rate_limit;
+        }
+
+        server
+    }
+
+    /// Opens the index of similar images if `--similar` is set.
+    pub fn similar_images(&self) -> Result<Option<SimilarImages>, QueueError> {
+        self.similar
+            .map(|action| {
+                Ok(SimilarImages {
+                    index: SIMILAR_INDEX
+                        .get_or_try_init(|| SimilarIndex::open_default(self.similar_hash))?
+                        .clone(),
+                    action,
+                    threshold: self.similarity,
+                })
+            })
+            .transpose()
+    }
+
+    /// Creates a queue downloading from `imageboard` with the save options shared by every mode.
+    pub fn setup_queue(
+        &self,
+        imageboard: &ServerConfig,
+        client: Client,
+        cbz: bool,
+        pool: bool,
+    ) -> Result<Queue, CliError> {
+        let mut qw = Queue::new(
+            imageboard.clone(),
+            self.simultaneous_downloads,
+            Some(client),
+            cbz,
+            pool,
+            self.name_type(),
+            self.annotate,
+        );
+
+        if self.archive {
+            qw.use_archive(
+                ARCHIVE
+                    .get_or_try_init(DownloadArchive::open_default)?
+                    .clone(),
+            );
+        }
+
+        if let Some(similar) = self.similar_images()? {
+            qw.detect_similar(similar);
+        }
+
+        if let Some(format) = self.metadata {
+            qw.save_metadata(format);
+        }
+
+        qw.hooks(self.hooks());
+
+        if let Some(template) = &self.filename {
+            qw.filename_template(template.clone());
+        }
+
+        if let Some(group) = self.group_by {
+            qw.group_by(group);
+        }
+
+        if let Some(format) = self.ugoira {
+            qw.convert_ugoira(format, self.keep_ugoira_zip);
+        }
+
+        Ok(qw)
+    }
+
+    /// Opens the destination of `--dry-run` listings, the terminal unless `--list-output` is set.
+    pub fn list_output(&self) -> Result<Box<dyn Write + Send>, CliError> {
+        Ok(match &self.list_output {
+            Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+            None => Box::new(BufWriter::new(stdout())),
+        })
+    }
+
+    pub fn hooks(&self) -> Hooks {
+        Hooks {
+            on_file: self.on_file.clone(),
+            on_job: self.on_job.clone(),
+        }
+    }
+
     pub fn get_extension(&self) -> Option<Extension> {
         match &self.mode {
             Commands::Search(args) => {
@@ -136,7 +547,18 @@ impl Cli {
                     return Some(Extension::guess_format(ext));
                 }
             }
-            Commands::Post(_) => {}
+            Commands::Favorites(args) => {
+                if let Some(ext) = &args.force_extension {
+                    return Some(Extension::guess_format(ext));
+                }
+            }
+            Commands::Post(_)
+            | Commands::Archive(_)
+            | Commands::Subscribe(_)
+            | Commands::Update(_)
+            | Commands::Batch(_)
+            | Commands::Retry(_)
+            | Commands::Auth(_) => {}
         }
         None
     }
@@ -1,7 +1,7 @@
 use std::{io, num::TryFromIntError};
 
 use ibdl_common::post::error::PostError;
-use ibdl_extractors::error::ExtractorError;
+use ibdl_extractors::error::{ExtractorError, QueryError};
 use thiserror::Error;
 
 #[allow(clippy::enum_variant_names)]
@@ -19,6 +19,9 @@ pub enum QueueError {
     #[error("Failed to serialize data into summary file: {error}")]
     SummarySerializeFail { error: String },
 
+    #[error("Failed to serialize post metadata: {error}")]
+    MetadataSerializeFail { error: String },
+
     #[error("Failed to deserialize summary file: {error}")]
     SummaryDeserializeFail { error: String },
 
@@ -42,6 +45,18 @@ pub enum QueueError {
 
     #[error("Failed to download Post")]
     PostDownloadError(#[from] PostError),
+
+    #[error("Failed to access download archive: {message}")]
+    ArchiveError { message: String },
+
+    #[error("Failed to access similar image index: {message}")]
+    SimilarIndexError { message: String },
+
+    #[error("Failed to convert ugoira: {message}")]
+    UgoiraConversion { message: String },
+
+    #[error("Failed to read or write failure report: {message}")]
+    FailureReportError { message: String },
 }
 
 #[allow(clippy::enum_variant_names)]
@@ -71,6 +86,21 @@ pub enum CliError {
         source: io::Error,
     },
 
+    #[error("No system keyring available. Start one or set IBDL_SECRET_STORE=vault")]
+    KeyringUnavailable,
+
+    #[error("Unknown secret store `{name}`, expected `keyring` or `vault`")]
+    UnknownSecretStore { name: String },
+
+    #[error("Not logged into {server}. Log in with `auth login` first")]
+    NotLoggedIn { server: String },
+
+    #[error("No user id given and not logged into {server}. Pass one or log in with `auth login`")]
+    MissingUserId { server: String },
+
+    #[error("No API key given")]
+    MissingApiKey,
+
     #[error("Whatever you did, it definetly shouldn't happen...")]
     ImpossibleExecutionPath,
 
@@ -83,6 +113,45 @@ pub enum CliError {
     #[error("Selected server does not exist.")]
     ServerNotExists,
 
+    #[error("Only searches can use more than one imageboard at once")]
+    MultipleServers,
+
+    #[error("Only searches, pools and posts can be listed with --dry-run")]
+    DryRunUnsupported,
+
+    #[error("Failed posts of cbz downloads can't be retried into the same file")]
+    RetryCbzUnsupported,
+
     #[error("No posts given")]
     NoPostsInInput,
+
+    #[error("Failed to read or write subscriptions file: {message}")]
+    SubscriptionFileError { message: String },
+
+    #[error("Invalid config file: {message}")]
+    ConfigFileError { message: String },
+
+    #[error("{source}")]
+    InvalidArguments {
+        #[from]
+        source: clap::Error,
+    },
+
+    #[error("Failed to read batch file: {message}")]
+    BatchFileError { message: String },
+
+    #[error("Invalid batch job: {message}")]
+    InvalidBatchJob { message: String },
+
+    #[error("Invalid search query: {source}")]
+    InvalidQuery {
+        #[from]
+        source: QueryError,
+    },
+
+    #[error("{source}")]
+    QueueFail {
+        #[from]
+        source: QueueError,
+    },
 }
@@ -10,10 +10,17 @@ pub use owo_colors;
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
 
+pub mod archive;
 pub mod async_queue;
+pub mod batch;
 pub mod cli;
 pub mod error;
+pub mod listing;
 pub mod progress_bars;
+pub mod report;
+pub mod similar;
+pub mod subscription;
+mod test;
 
 #[derive(Debug, Clone, Copy)]
 #[repr(transparent)]
@@ -0,0 +1,209 @@
+//! Listing of the posts a download would save, without downloading them.
+//!
+//! # Dry Runs
+//! With `--dry-run`, the extractor runs as usual, with the blacklist, rating and extension filters
+//! applied, but the posts it sends go to a [`PostList`] instead of the
+//! [`Queue`](crate::async_queue::Queue). Every post is written to the output as soon as it
+//! arrives, as a line of JSON, a CSV row or a table row, and the [`ListSummary`] returned at the
+//! end counts them by rating and extension.
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::Arc;
+
+use clap::ValueEnum;
+use ibdl_common::{
+    log::debug,
+    post::Post,
+    serde::{self, Serialize},
+    serde_json,
+    tokio::{
+        spawn,
+        sync::mpsc::{Receiver, UnboundedReceiver},
+        task::JoinHandle,
+    },
+};
+use ibdl_extractors::extractor_config::ServerConfig;
+
+use crate::async_queue::PostSources;
+use crate::error::QueueError;
+
+/// Format the posts of a dry run are written in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ListFormat {
+    /// Aligned columns, for reading in the terminal
+    #[default]
+    Table,
+    /// One JSON object per line with the full post info
+    Jsonl,
+    /// Comma separated values with a header row
+    Csv,
+}
+
+/// Posts listed by a [`PostList`], counted by rating and extension.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ListSummary {
+    pub posts: u64,
+    pub ratings: BTreeMap<String, u64>,
+    pub extensions: BTreeMap<String, u64>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "self::serde")]
+struct ListedPost<'a> {
+    #[serde(flatten)]
+    post: &'a Post,
+    /// Name of the server the post would be downloaded from
+    server: &'a str,
+    /// Web page of the post
+    post_url: String,
+}
+
+/// Sink that takes the place of the [`Queue`](crate::async_queue::Queue) in a dry run, writing
+/// every post it receives instead of downloading it.
+pub struct PostList {
+    imageboard: Arc<ServerConfig>,
+    format: ListFormat,
+    sources: Option<PostSources>,
+}
+
+impl PostList {
+    pub fn new(imageboard: ServerConfig, format: ListFormat) -> Self {
+        Self {
+            imageboard: Arc::new(imageboard),
+            format,
+            sources: None,
+        }
+    }
+
+    /// Take the server of every post from the [`PostSources`] of a multi-server search.
+    pub fn post_sources(&mut self, sources: PostSources) -> &mut Self {
+        self.sources = Some(sources);
+        self
+    }
+
+    /// Writes every post received from `channel_rx` into `output` until the extractor is done.
+    ///
+    /// `length_rx` is only drained, since there's no progress bar to update.
+    pub fn setup_list_writer(
+        self,
+        output: Box<dyn Write + Send>,
+        channel_rx: UnboundedReceiver<Post>,
+        length_rx: Receiver<u64>,
+    ) -> JoinHandle<Result<ListSummary, QueueError>> {
+        spawn(async move {
+            debug!("Post list writer initialized");
+
+            let mut length_rx = length_rx;
+            spawn(async move { while length_rx.recv().await.is_some() {} });
+
+            self.write_posts(output, channel_rx).await
+        })
+    }
+
+    async fn write_posts(
+        &self,
+        mut output: Box<dyn Write + Send>,
+        mut channel_rx: UnboundedReceiver<Post>,
+    ) -> Result<ListSummary, QueueError> {
+        let mut summary = ListSummary::default();
+
+        match self.format {
+            ListFormat::Table => writeln!(
+                output,
+                "{:<12} {:>10}  {:<12}  {:<4}  {:<32}  URL",
+                "SERVER", "ID", "RATING", "EXT", "MD5"
+            )?,
+            ListFormat::Csv => {
+                writeln!(output, "server,id,rating,extension,md5,url,post_url,tags")?
+            }
+            ListFormat::Jsonl => {}
+        }
+
+        while let Some(post) = channel_rx.recv().await {
+            let server = self.server(&post);
+
+            self.write_post(&mut output, &post, &server)?;
+
+            summary.posts += 1;
+            *summary.ratings.entry(post.rating.to_string()).or_default() += 1;
+            *summary
+                .extensions
+                .entry(post.extension.to_string())
+                .or_default() += 1;
+        }
+
+        output.flush()?;
+
+        Ok(summary)
+    }
+
+    fn write_post(
+        &self,
+        output: &mut impl Write,
+        post: &Post,
+        server: &ServerConfig,
+    ) -> Result<(), QueueError> {
+        match self.format {
+            ListFormat::Table => writeln!(
+                output,
+                "{:<12} {:>10}  {:<12}  {:<4}  {:<32}  {}",
+                server.name, post.id, post.rating, post.extension, post.md5, post.url
+            )?,
+            ListFormat::Jsonl => {
+                let line = serde_json::to_string(&ListedPost {
+                    post,
+                    server: &server.name,
+                    post_url: server.post_page_url(post.id),
+                })
+                .map_err(|error| QueueError::MetadataSerializeFail {
+                    error: error.to_string(),
+                })?;
+
+                writeln!(output, "{line}")?;
+            }
+            ListFormat::Csv => {
+                let tags = post
+                    .tags
+                    .iter()
+                    .map(|tag| tag.tag())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+
+                let row = [
+                    server.name.clone(),
+                    post.id.to_string(),
+                    post.rating.to_string(),
+                    post.extension.to_string(),
+                    post.md5.clone(),
+                    post.url.clone(),
+                    server.post_page_url(post.id),
+                    tags,
+                ]
+                .iter()
+                .map(|field| csv_field(field))
+                .collect::<Vec<_>>()
+                .join(",");
+
+                writeln!(output, "{row}")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn server(&self, post: &Post) -> Arc<ServerConfig> {
+        self.sources
+            .as_ref()
+            .and_then(|sources| sources.get(post))
+            .map_or_else(|| self.imageboard.clone(), |(server, _)| server)
+    }
+}
+
+/// Quotes `field` if it has characters with a meaning in CSV.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
@@ -1,5 +1,7 @@
+use clap::ValueEnum;
 use ibdl_common::{
-    tokio::{spawn, sync::mpsc::Receiver},
+    events,
+    tokio::{spawn, sync::mpsc::Receiver, task::JoinHandle},
     ImageBoards,
 };
 use indicatif::{
@@ -16,6 +18,16 @@ use std::{
 
 const PROGRESS_CHARS: &str = "━━";
 
+/// How the progress of downloads is shown
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ProgressMode {
+    /// Progress bars and colored messages in the terminal
+    #[default]
+    Bars,
+    /// One JSON event per line on stdout, without progress bars
+    Json,
+}
+
 struct BarTemplates {
     pub main: &'static str,
     pub download: &'static str,
@@ -70,7 +82,7 @@ impl ProgressCounter {
         bar.enable_steady_tick(Duration::from_millis(100));
 
         // Initialize the bars
-        let multi = MultiProgress::new();
+        let multi = multi_progress();
         let main = multi.add(bar);
 
         Self {
@@ -88,7 +100,7 @@ impl ProgressCounter {
         bar.enable_steady_tick(Duration::from_millis(100));
 
         // Initialize the bars
-        let multi = MultiProgress::new();
+        let multi = multi_progress();
         let main = multi.add(bar);
 
         Self {
@@ -99,6 +111,13 @@ impl ProgressCounter {
         }
     }
 
+    /// Resets the main progress bar after it was finished, so the same counters can be reused for another download.
+    pub fn restart(&self, len: u64) {
+        self.main.reset();
+        self.main.set_length(len);
+        self.main.enable_steady_tick(Duration::from_millis(100));
+    }
+
     /// Adds a download bar under the main progress bar. Will use the predefined style present in the ['ImageBoards' enum](ibdl_common::ImageBoards)
     pub fn add_download_bar(&self, len: u64, imageboard: ImageBoards) -> ProgressBar {
         let template = BarTemplates::new(imageboard);
@@ -116,6 +135,19 @@ impl ProgressCounter {
         self.multi.add(bar)
     }
 
+    /// Adds a status line under the main progress bar for one of the servers of a search.
+    pub fn add_server_line(&self, name: &str) -> ProgressBar {
+        let style = ProgressStyle::default_spinner()
+            .template("{spinner:.blue.bold} {prefix:.bold} {msg}")
+            .unwrap();
+        let bar = ProgressBar::new_spinner().with_style(style);
+        bar.set_prefix(name.to_string());
+        bar.set_draw_target(ProgressDrawTarget::stderr());
+        bar.enable_steady_tick(Duration::from_millis(100));
+
+        self.multi.add(bar)
+    }
+
     pub async fn init_length_updater(&self, channel: Receiver<u64>) {
         let mut channel = channel;
         let cloned_bar = self.main.clone();
@@ -128,18 +160,24 @@ impl ProgressCounter {
         .unwrap();
     }
 
-    pub async fn init_download_counter(&self, channel: Receiver<bool>) {
+    /// Counts the downloaded posts sent through `channel`.
+    ///
+    /// The returned handle resolves to the number of posts downloaded through this channel alone once it's closed.
+    pub async fn init_download_counter(&self, channel: Receiver<bool>) -> JoinHandle<u64> {
         let mut channel = channel;
         let cloned_bar = self.main.clone();
         let cloned_mtx = self.downloaded_mtx.clone();
         spawn(async move {
+            let mut count = 0;
             while let Some(downloaded) = channel.recv().await {
                 if downloaded {
                     cloned_bar.inc(1);
                     cloned_mtx.fetch_add(1, Ordering::SeqCst);
+                    count += 1;
                 }
             }
-        });
+            count
+        })
     }
 
     pub fn increment_counters(&self, delta: u64) {
@@ -148,6 +186,15 @@ impl ProgressCounter {
     }
 }
 
+/// Bars are hidden while JSON events are written instead, so only the events reach the terminal.
+fn multi_progress() -> MultiProgress {
+    if events::json_enabled() {
+        MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+    } else {
+        MultiProgress::new()
+    }
+}
+
 fn master_progress_style(templates: &BarTemplates) -> ProgressStyle {
     ProgressStyle::default_bar()
         .template(templates.main)
@@ -0,0 +1,151 @@
+//! Report of the posts a [`Queue`](crate::async_queue::Queue) failed to download.
+//!
+//! # Failure Reports
+//! Every post that fails to download is recorded with the kind of error that stopped it. Once the
+//! queue is done, the [`FailureReport`] is saved as `failed_posts.json` inside the output
+//! directory. A later run into the same output without failures removes it.
+//!
+//! The `retry` subcommand reads the report and downloads exactly those posts again, into the same
+//! output they were meant for.
+//!
+//! Finished cbz files can't be added to, so downloads into them don't save a report.
+use std::fs::{read_to_string, remove_file, write};
+use std::path::{Path, PathBuf};
+
+use ibdl_common::{
+    log::debug,
+    post::{error::PostError, Post},
+    serde::{self, Deserialize, Serialize},
+    serde_json,
+};
+
+use crate::error::QueueError;
+
+const REPORT_FILE: &str = "failed_posts.json";
+
+fn report_error(error: impl ToString) -> QueueError {
+    QueueError::FailureReportError {
+        message: error.to_string(),
+    }
+}
+
+/// Broad cause of a failed download.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "self::serde", rename_all = "snake_case")]
+pub enum FailureKind {
+    /// The server doesn't have the original file anymore
+    NotFound,
+    /// The downloaded file doesn't match the MD5 given by the server
+    HashMismatch,
+    /// The connection failed or was interrupted
+    Network,
+    /// The file couldn't be written to the output
+    Filesystem,
+    Other,
+}
+
+impl FailureKind {
+    /// Kind of the error `error` a download stopped with.
+    pub const fn of(error: &QueueError) -> Self {
+        match error {
+            QueueError::PostDownloadError(error) => match error {
+                PostError::RemoteFileNotFound => Self::NotFound,
+                PostError::HashMismatch { .. } => Self::HashMismatch,
+                PostError::ConnectionFail { .. } | PostError::ChunkDownloadFail { .. } => {
+                    Self::Network
+                }
+                PostError::FileIOError { .. } | PostError::ZipFileWriteError { .. } => {
+                    Self::Filesystem
+                }
+                _ => Self::Other,
+            },
+            QueueError::IOError { .. }
+            | QueueError::DirCreationError { .. }
+            | QueueError::ZipIOError { .. } => Self::Filesystem,
+            _ => Self::Other,
+        }
+    }
+}
+
+/// A post that failed to download, with the error that stopped it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "self::serde")]
+pub struct FailedPost {
+    /// Name of the server the post was downloaded from, as listed by `--servers`
+    pub server: String,
+    pub kind: FailureKind,
+    pub error: String,
+    #[serde(flatten)]
+    pub post: Post,
+}
+
+impl FailedPost {
+    pub fn new(server: &str, post: &Post, error: &QueueError) -> Self {
+        // The wrapper only says the download failed, the reason is in the inner error
+        let message = match error {
+            QueueError::PostDownloadError(error) => error.to_string(),
+            error => error.to_string(),
+        };
+
+        Self {
+            server: server.to_string(),
+            kind: FailureKind::of(error),
+            error: message,
+            post: post.clone(),
+        }
+    }
+}
+
+/// Posts that failed to download into a single output.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "self::serde")]
+pub struct FailureReport {
+    /// Directory the posts were meant for
+    pub output: PathBuf,
+    /// Only set in reports of cbz files saved by older versions, which can't be retried
+    #[serde(default)]
+    pub cbz: bool,
+    /// Set if the posts come from a pool. The id of every post is its page number instead
+    #[serde(default)]
+    pub pool: bool,
+    pub posts: Vec<FailedPost>,
+}
+
+impl FailureReport {
+    /// Location of the report of the downloads into the directory `output`.
+    pub fn path_for(output: &Path) -> PathBuf {
+        output.join(REPORT_FILE)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, QueueError> {
+        let data = read_to_string(path)?;
+        serde_json::from_str(&data).map_err(report_error)
+    }
+
+    /// Saves the report next to its output, returning where it was saved.
+    pub fn save(&self) -> Result<PathBuf, QueueError> {
+        let path = Self::path_for(&self.output);
+        let data = serde_json::to_string_pretty(self).map_err(report_error)?;
+
+        write(&path, data)?;
+        debug!(
+            "Saved {} failed posts to {}",
+            self.posts.len(),
+            path.display()
+        );
+
+        Ok(path)
+    }
+
+    /// Removes the report of the downloads into `output`, if there's one.
+    pub fn remove(output: &Path) -> Result<(), QueueError> {
+        let path = Self::path_for(output);
+
+        if path.exists() {
+            remove_file(&path)?;
+            debug!("Removed failure report {}", path.display());
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,126 @@
+//! Perceptual hashes of decoded images.
+use clap::ValueEnum;
+use image::{imageops::FilterType, DynamicImage, GrayImage};
+
+/// Side of the image used to compute the DCT for [`HashAlgorithm::PHash`].
+const DCT_SIZE: usize = 32;
+
+/// Side of the low frequency block of the DCT kept in the hash.
+const HASH_SIZE: usize = 8;
+
+/// Algorithm used to compute the perceptual hash of images.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum HashAlgorithm {
+    /// Compares the brightness of neighbouring pixels. Fast, and good at finding resized copies
+    #[default]
+    #[value(name = "dhash")]
+    DHash,
+    /// Compares the low frequencies of the image. Slower, but also finds re-encoded or slightly
+    /// edited copies
+    #[value(name = "phash")]
+    PHash,
+}
+
+impl HashAlgorithm {
+    /// Name the hashes of this algorithm are stored under in the index.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::DHash => "dhash",
+            Self::PHash => "phash",
+        }
+    }
+
+    /// Computes the 64 bit hash of `image`.
+    pub fn hash(self, image: &DynamicImage) -> u64 {
+        match self {
+            Self::DHash => dhash(image),
+            Self::PHash => phash(image),
+        }
+    }
+}
+
+/// Similarity between two hashes, from 0 to 100.
+pub const fn similarity(a: u64, b: u64) -> u8 {
+    // At most 64 differing bits, so the result always fits
+    (100 - (a ^ b).count_ones() * 100 / 64) as u8
+}
+
+fn grayscale(image: &DynamicImage, width: u32, height: u32) -> GrayImage {
+    image
+        .resize_exact(width, height, FilterType::Triangle)
+        .into_luma8()
+}
+
+fn dhash(image: &DynamicImage) -> u64 {
+    let pixels = grayscale(image, HASH_SIZE as u32 + 1, HASH_SIZE as u32);
+
+    let mut hash = 0;
+
+    for y in 0..HASH_SIZE as u32 {
+        for x in 0..HASH_SIZE as u32 {
+            let brighter = pixels.get_pixel(x, y)[0] < pixels.get_pixel(x + 1, y)[0];
+            hash = hash << 1 | u64::from(brighter);
+        }
+    }
+
+    hash
+}
+
+fn phash(image: &DynamicImage) -> u64 {
+    let pixels = grayscale(image, DCT_SIZE as u32, DCT_SIZE as u32);
+
+    let input = pixels
+        .pixels()
+        .map(|pixel| f64::from(pixel[0]))
+        .collect::<Vec<_>>();
+
+    let dct = dct_2d(&input);
+
+    let low = (0..HASH_SIZE)
+        .flat_map(|y| (0..HASH_SIZE).map(move |x| (x, y)))
+        .map(|(x, y)| dct[y * DCT_SIZE + x])
+        .collect::<Vec<_>>();
+
+    // The first coefficient is the average brightness, which would skew the median
+    let mut sorted = low[1..].to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let median = sorted[sorted.len() / 2];
+
+    low.iter()
+        .fold(0, |hash, value| hash << 1 | u64::from(*value > median))
+}
+
+/// DCT-II of a `DCT_SIZE` x `DCT_SIZE` matrix, done as a DCT of every row and then every column.
+fn dct_2d(input: &[f64]) -> Vec<f64> {
+    let factors = (0..DCT_SIZE)
+        .flat_map(|k| {
+            (0..DCT_SIZE).map(move |n| {
+                ((std::f64::consts::PI / DCT_SIZE as f64) * (n as f64 + 0.5) * k as f64).cos()
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let dct_1d = |get: &dyn Fn(usize) -> f64, k: usize| {
+        (0..DCT_SIZE)
+            .map(|n| get(n) * factors[k * DCT_SIZE + n])
+            .sum::<f64>()
+    };
+
+    let mut rows = vec![0.0; DCT_SIZE * DCT_SIZE];
+
+    for y in 0..DCT_SIZE {
+        for k in 0..DCT_SIZE {
+            rows[y * DCT_SIZE + k] = dct_1d(&|n| input[y * DCT_SIZE + n], k);
+        }
+    }
+
+    let mut output = vec![0.0; DCT_SIZE * DCT_SIZE];
+
+    for x in 0..DCT_SIZE {
+        for k in 0..DCT_SIZE {
+            output[k * DCT_SIZE + x] = dct_1d(&|n| rows[n * DCT_SIZE + x], k);
+        }
+    }
+
+    output
+}
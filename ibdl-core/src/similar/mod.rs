@@ -0,0 +1,209 @@
+//! Persistent index of the perceptual hashes of downloaded images.
+//!
+//! # Similar Images
+//! MD5 hashes only catch exact copies, while the same image is often uploaded resized or
+//! re-encoded to different imageboards. When enabled with `--similar`, the
+//! [`Queue`](crate::async_queue::Queue) computes a perceptual hash of every image once it's
+//! downloaded, before it's moved into the output directory, and compares it with the hashes of
+//! the images saved before. Images at least as similar as the threshold to one of them are
+//! skipped, hardlinked to it or only reported, depending on the [`SimilarAction`].
+//!
+//! The index lives in `$XDG_CONFIG_HOME/imageboard-downloader/similar.redb` (or inside
+//! `IBDL_CACHE_DIR` when set). Hashes of each [`HashAlgorithm`] are kept apart.
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use clap::ValueEnum;
+use ibdl_common::{
+    log::debug,
+    tokio::{fs::read, task::spawn_blocking},
+    ImageBoards,
+};
+use redb::{Database, ReadableTable, TableDefinition};
+
+use crate::error::QueueError;
+
+use self::hash::similarity;
+pub use self::hash::HashAlgorithm;
+
+mod hash;
+
+/// `(algorithm, absolute file path)` -> perceptual hash
+const HASHES: TableDefinition<(&str, &str), u64> = TableDefinition::new("hashes");
+
+const INDEX_FILE: &str = "similar.redb";
+
+fn db_error<E: Into<redb::Error>>(error: E) -> QueueError {
+    QueueError::SimilarIndexError {
+        message: error.into().to_string(),
+    }
+}
+
+/// What to do with a downloaded image that looks like one saved before.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum SimilarAction {
+    /// Don't save the image
+    #[default]
+    Skip,
+    /// Save a hardlink to the image saved before instead
+    Hardlink,
+    /// Save the image anyway and print which image it looks like
+    Report,
+}
+
+/// Previously saved image that looks like a new one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimilarFile {
+    pub path: PathBuf,
+    /// From 0 to 100
+    pub similarity: u8,
+}
+
+/// Handle to the index of perceptual hashes. Cheap to clone and safe to share between download
+/// tasks.
+#[derive(Clone)]
+pub struct SimilarIndex {
+    db: Arc<Database>,
+    algorithm: HashAlgorithm,
+    hashes: Arc<Mutex<Vec<(PathBuf, u64)>>>,
+}
+
+/// Options of the similar image check done by the [`Queue`](crate::async_queue::Queue).
+#[derive(Clone)]
+pub struct SimilarImages {
+    pub index: SimilarIndex,
+    pub action: SimilarAction,
+    /// Minimum similarity, from 0 to 100, for two images to be considered the same
+    pub threshold: u8,
+}
+
+impl SimilarIndex {
+    /// Returns the default location of the index file.
+    pub fn default_path() -> Result<PathBuf, QueueError> {
+        Ok(ImageBoards::auth_cache_dir()?.join(Path::new(INDEX_FILE)))
+    }
+
+    /// Opens the index at the default location, creating it if needed.
+    pub fn open_default(algorithm: HashAlgorithm) -> Result<Self, QueueError> {
+        Self::open(&Self::default_path()?, algorithm)
+    }
+
+    /// Opens the index at `path`, creating it if needed, and loads the hashes of `algorithm`.
+    pub fn open(path: &Path, algorithm: HashAlgorithm) -> Result<Self, QueueError> {
+        debug!("Opening similar image index at {}", path.display());
+        let db = Database::create(path).map_err(db_error)?;
+
+        let txn = db.begin_write().map_err(db_error)?;
+        txn.open_table(HASHES).map_err(db_error)?;
+        txn.commit().map_err(db_error)?;
+
+        let mut hashes = Vec::new();
+
+        {
+            let txn = db.begin_read().map_err(db_error)?;
+            let table = txn.open_table(HASHES).map_err(db_error)?;
+
+            for item in table.iter().map_err(db_error)? {
+                let (key, hash) = item.map_err(db_error)?;
+                let (name, file) = key.value();
+
+                if name == algorithm.name() {
+                    hashes.push((PathBuf::from(file), hash.value()));
+                }
+            }
+        }
+
+        debug!("Loaded {} {} hashes", hashes.len(), algorithm.name());
+
+        Ok(Self {
+            db: Arc::new(db),
+            algorithm,
+            hashes: Arc::new(Mutex::new(hashes)),
+        })
+    }
+
+    /// Computes the perceptual hash of the image in `data`, if it can be decoded.
+    pub fn hash(&self, data: &[u8]) -> Option<u64> {
+        let image = image::load_from_memory(data).ok()?;
+        Some(self.algorithm.hash(&image))
+    }
+
+    /// Looks for the saved image most similar to `hash`, if any is at least `threshold` similar.
+    ///
+    /// Unless one is found, `path` is added to the index with `hash`. With `always_insert`, it's
+    /// added either way.
+    pub fn find_or_insert(
+        &self,
+        path: &Path,
+        hash: u64,
+        threshold: u8,
+        always_insert: bool,
+    ) -> Result<Option<SimilarFile>, QueueError> {
+        let mut hashes = self.hashes.lock().unwrap();
+
+        // Files removed since they were indexed can't be linked to, nor be a duplicate anymore
+        let found = hashes
+            .iter()
+            .filter(|(file, _)| file != path)
+            .map(|(file, saved)| (file, similarity(hash, *saved)))
+            .filter(|(file, similarity)| *similarity >= threshold && file.exists())
+            .max_by_key(|(_, similarity)| *similarity)
+            .map(|(file, similarity)| SimilarFile {
+                path: file.clone(),
+                similarity,
+            });
+
+        if found.is_none() || always_insert {
+            self.write(path, hash)?;
+            hashes.retain(|(file, _)| file != path);
+            hashes.push((path.to_path_buf(), hash));
+        }
+        drop(hashes);
+
+        Ok(found)
+    }
+
+    fn write(&self, path: &Path, hash: u64) -> Result<(), QueueError> {
+        let file = path.to_string_lossy();
+
+        let txn = self.db.begin_write().map_err(db_error)?;
+        {
+            let mut table = txn.open_table(HASHES).map_err(db_error)?;
+            table
+                .insert((self.algorithm.name(), file.as_ref()), hash)
+                .map_err(db_error)?;
+        }
+        txn.commit().map_err(db_error)?;
+
+        debug!("Indexed {} hash of {}", self.algorithm.name(), file);
+        Ok(())
+    }
+}
+
+impl SimilarImages {
+    /// Checks the downloaded `file` against the index, as if it was already saved at `output`.
+    ///
+    /// Returns the image it looks like, if any. Files that aren't images are never similar.
+    pub async fn check(
+        &self,
+        file: &Path,
+        output: &Path,
+    ) -> Result<Option<SimilarFile>, QueueError> {
+        let data = read(file).await?;
+        let output = std::path::absolute(output)?;
+        let index = self.index.clone();
+        let threshold = self.threshold;
+        let always_insert = self.action == SimilarAction::Report;
+
+        // Decoding and resizing the image takes a while, so keep it off the async threads
+        spawn_blocking(move || {
+            index.hash(&data).map_or(Ok(None), |hash| {
+                index.find_or_insert(&output, hash, threshold, always_insert)
+            })
+        })
+        .await
+        .map_err(|error| QueueError::SimilarIndexError {
+            message: error.to_string(),
+        })?
+    }
+}
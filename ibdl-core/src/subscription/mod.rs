@@ -0,0 +1,143 @@
+//! Saved tag searches that can be updated incrementally.
+//!
+//! # Subscriptions
+//! Every subscription stores the highest post id downloaded for its `(server, tags)` pair. When
+//! updating it, the extractor is told to only map posts [newer than](ibdl_extractors::prelude::Extractor::newer_than)
+//! that id, so only the first few pages are scanned instead of doing a full crawl every time.
+//!
+//! Subscriptions are saved in `$XDG_CONFIG_HOME/imageboard-downloader/subscriptions.toml` (or inside
+//! `IBDL_CACHE_DIR` when set) and can be managed with the `subscribe` and `update` subcommands.
+use std::fs::{read_to_string, write};
+use std::path::{Path, PathBuf};
+
+use ibdl_common::{
+    log::debug,
+    post::rating::Rating,
+    serde::{self, Deserialize, Serialize},
+    ImageBoards,
+};
+
+use crate::error::CliError;
+
+const SUBSCRIPTIONS_FILE: &str = "subscriptions.toml";
+
+/// A saved tag search and the search options it was created with.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "self::serde")]
+pub struct Subscription {
+    /// Name of the server, as listed by `--servers`
+    pub server: String,
+    pub tags: Vec<String>,
+    /// Directory where new posts are saved
+    pub output: PathBuf,
+    /// Highest post id downloaded so far. `None` if the subscription was never updated.
+    pub last_id: Option<u64>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    #[serde(default)]
+    pub ratings: Vec<Rating>,
+    #[serde(default)]
+    pub safe_mode: bool,
+    #[serde(default)]
+    pub ignore_unknown: bool,
+    #[serde(default)]
+    pub no_animated: bool,
+    #[serde(default)]
+    pub disable_blacklist: bool,
+    pub force_extension: Option<String>,
+}
+
+impl Subscription {
+    /// Checks if this subscription is for the same search as `tags` in `server`.
+    pub fn matches(&self, server: &str, tags: &[String]) -> bool {
+        self.server == server && self.tags == tags
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(crate = "self::serde")]
+struct SubscriptionFile {
+    #[serde(default, rename = "subscription")]
+    subscriptions: Vec<Subscription>,
+}
+
+/// List of subscriptions backed by a toml file.
+#[derive(Debug)]
+pub struct Subscriptions {
+    path: PathBuf,
+    pub list: Vec<Subscription>,
+}
+
+impl Subscriptions {
+    /// Returns the default location of the subscriptions file.
+    pub fn default_path() -> Result<PathBuf, CliError> {
+        Ok(ImageBoards::auth_cache_dir()?.join(Path::new(SUBSCRIPTIONS_FILE)))
+    }
+
+    /// Loads the subscriptions from the default location.
+    pub fn load_default() -> Result<Self, CliError> {
+        Self::load(Self::default_path()?)
+    }
+
+    /// Loads the subscriptions saved in `path`. Returns an empty list if the file doesn't exist.
+    pub fn load(path: PathBuf) -> Result<Self, CliError> {
+        if !path.exists() {
+            debug!("No subscriptions file found at {}", path.display());
+            return Ok(Self { path, list: vec![] });
+        }
+
+        let contents = read_to_string(&path)?;
+        let file: SubscriptionFile =
+            toml::from_str(&contents).map_err(|error| CliError::SubscriptionFileError {
+                message: error.to_string(),
+            })?;
+
+        Ok(Self {
+            path,
+            list: file.subscriptions,
+        })
+    }
+
+    /// Writes all subscriptions back into the file they were loaded from.
+    pub fn save(&self) -> Result<(), CliError> {
+        let file = SubscriptionFile {
+            subscriptions: self.list.clone(),
+        };
+
+        let contents =
+            toml::to_string_pretty(&file).map_err(|error| CliError::SubscriptionFileError {
+                message: error.to_string(),
+            })?;
+
+        write(&self.path, contents)?;
+        debug!("Saved {} subscriptions", self.list.len());
+        Ok(())
+    }
+
+    /// Adds `subscription` to the list, replacing the one with the same search if it exists.
+    ///
+    /// The last seen post id of the replaced subscription is kept.
+    ///
+    /// Returns `true` if an existing subscription was replaced.
+    pub fn add(&mut self, mut subscription: Subscription) -> bool {
+        if let Some(existing) = self
+            .list
+            .iter_mut()
+            .find(|sub| sub.matches(&subscription.server, &subscription.tags))
+        {
+            subscription.last_id = existing.last_id;
+            *existing = subscription;
+            return true;
+        }
+
+        self.list.push(subscription);
+        false
+    }
+
+    /// Removes the subscription for `tags` in `server`. Returns `true` if it existed.
+    pub fn remove(&mut self, server: &str, tags: &[String]) -> bool {
+        let original = self.list.len();
+        self.list.retain(|sub| !sub.matches(server, tags));
+        self.list.len() < original
+    }
+}
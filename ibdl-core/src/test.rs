@@ -0,0 +1,1806 @@
+#![cfg(test)]
+// Filename templates use the same braces as format strings
+#![allow(clippy::literal_string_with_formatting_args)]
+//! Offline download tests.
+//!
+//! A local [`MockServer`] stands in for Danbooru, serving a small post list whose files are also
+//! served by it, so the whole path from the extractor to the files written by the [`Queue`] can
+//! be checked without network access.
+use std::fs::{read, read_to_string, File};
+use std::io::{Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicU64;
+use std::sync::{Arc, Mutex};
+
+use clap::Parser;
+use ibdl_common::post::{extension::Extension, rating::Rating, NameType, Post};
+use ibdl_common::tokio::sync::mpsc::{channel, unbounded_channel};
+use ibdl_common::{events, reqwest::Client, serde_json, tokio, ImageBoards};
+use ibdl_extractors::auth::{Credentials, ImageboardConfig, VaultStore};
+use ibdl_extractors::extractor_config::{ServerConfig, DEFAULT_SERVERS};
+use ibdl_extractors::imageboards::danbooru::DanbooruExtractor;
+use ibdl_extractors::prelude::*;
+use image::codecs::gif::GifDecoder;
+use image::imageops::FilterType;
+use image::{AnimationDecoder, ImageFormat};
+use image_webp::WebPDecoder;
+use tempfile::TempDir;
+use wiremock::matchers::{header, method, path, path_regex, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zip::write::FileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+use crate::archive::DownloadArchive;
+use crate::async_queue::embed::{embed, is_embedded};
+use crate::async_queue::metadata::{post_metadata, write_metadata};
+use crate::async_queue::{
+    convert_ugoira, FilenameTemplate, GroupBy, Hooks, MetadataFormat, Queue, UgoiraFormat,
+};
+use crate::batch::{BatchManifest, JobMode};
+use crate::cli::commands::auth::Auth;
+use crate::cli::commands::batch::run_jobs;
+use crate::cli::commands::subscription::newest_saved;
+use crate::cli::config::{ConfigFile, Origin};
+use crate::cli::extra::login;
+use crate::cli::{Cli, Commands};
+use crate::error::{CliError, QueueError};
+use crate::listing::{ListFormat, ListSummary, PostList};
+use crate::report::{FailureKind, FailureReport};
+use crate::similar::{HashAlgorithm, SimilarAction, SimilarImages, SimilarIndex};
+
+const MD5S: [&str; 3] = [
+    "04870961fb546fc38ce759b2bedc69d6",
+    "5a9df4ee08b78b2c795a8dfbaa672402",
+    "a3b9e361d119c7eefa73bc8398885dae",
+];
+
+/// Files of `danbooru_similar_list.json`, large and detailed enough for perceptual hashes.
+const SIMILAR_MD5S: [&str; 3] = [
+    "95bd1ed06c2c5efb7d91094023696e96",
+    "6869a381fd8981d5c99a9fd988703a4c",
+    "098b618e03088e05ed36eb67319b6267",
+];
+
+fn mock_responses() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../assets/mock_responses")
+}
+
+fn json(body: String) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_raw(body, "application/json")
+}
+
+/// Serves the download fixtures, with the file urls of the posts pointing back to the server.
+async fn mock_server() -> MockServer {
+    mock_server_with("danbooru_download_list.json", MD5S).await
+}
+
+/// Same as [`mock_server`], serving the post list `list` with the files in `md5s`.
+async fn mock_server_with(list: &str, md5s: [&str; 3]) -> MockServer {
+    let server = MockServer::start().await;
+
+    let list = read_to_string(mock_responses().join(list))
+        .unwrap()
+        .replace("{{server}}", &server.uri());
+
+    for page in ["1", "2"] {
+        let body = if page == "1" {
+            list.clone()
+        } else {
+            String::from("[]")
+        };
+
+        Mock::given(method("GET"))
+            .and(path("/posts.json"))
+            .and(query_param("page", page))
+            .respond_with(json(body))
+            .mount(&server)
+            .await;
+    }
+
+    Mock::given(method("GET"))
+        .and(path("/pools/777.json"))
+        .respond_with(json(
+            read_to_string(mock_responses().join("danbooru_download_pool.json")).unwrap(),
+        ))
+        .mount(&server)
+        .await;
+
+    for md5 in md5s {
+        let image = read(mock_responses().join(format!("images/{md5}.png"))).unwrap();
+
+        Mock::given(method("GET"))
+            .and(path(format!("/data/{md5}.png")))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(image, "image/png"))
+            .expect(1)
+            .mount(&server)
+            .await;
+    }
+
+    server
+}
+
+fn mock_config(server: &MockServer) -> ServerConfig {
+    let mut config = DEFAULT_SERVERS.get("danbooru").unwrap().clone();
+    let uri = server.uri();
+
+    config.base_url.clone_from(&uri);
+    config.post_url = Some(format!("{uri}/posts/"));
+    config.post_list_url = Some(format!("{uri}/posts.json"));
+    config.pool_idx_url = Some(format!("{uri}/pools"));
+    config.rate_limit = None;
+    config.retry.backoff_ms = 1;
+    config.retry.jitter = false;
+
+    config
+}
+
+/// Runs the extractor and the queue like the `search` and `pool` commands do.
+async fn download(
+    server: &MockServer,
+    output: PathBuf,
+    cbz: bool,
+    pool: Option<u32>,
+) -> (u64, u64) {
+    download_with(server, output, cbz, pool, |_| {}).await
+}
+
+/// Same as [`download`], letting `configure` change the queue before it starts.
+async fn download_with(
+    server: &MockServer,
+    output: PathBuf,
+    cbz: bool,
+    pool: Option<u32>,
+    configure: impl FnOnce(&mut Queue),
+) -> (u64, u64) {
+    let config = mock_config(server);
+
+    let mut extractor =
+        DanbooruExtractor::new_with_config(&["mock_character"], &[], true, false, config.clone());
+
+    let title = if let Some(id) = pool {
+        extractor.setup_pool_download(Some(id), false);
+        extractor.fetch_pool_idxs(id, None).await.unwrap();
+        extractor.pool_name().unwrap()
+    } else {
+        String::from("mock_character")
+    };
+
+    let client = extractor.client();
+    let (channel_tx, channel_rx) = unbounded_channel();
+    let (length_tx, length_rx) = channel(8);
+
+    let ext = extractor.setup_fetch_thread(channel_tx, None, None, Some(length_tx));
+
+    let mut queue = Queue::new(
+        config,
+        2,
+        Some(client),
+        cbz,
+        pool.is_some(),
+        NameType::MD5,
+        false,
+    );
+    queue.comic_info(title, Some(String::from("Mock summary")));
+    configure(&mut queue);
+
+    let downloader =
+        queue.setup_async_downloader(output, Arc::new(AtomicU64::new(0)), channel_rx, length_rx);
+
+    let (removed, downloaded) = tokio::join!(ext, downloader);
+
+    (downloaded.unwrap().unwrap(), removed.unwrap().unwrap())
+}
+
+fn read_zip_file(archive: &mut ZipArchive<File>, name: &str) -> Vec<u8> {
+    let mut file = archive.by_name(name).unwrap();
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf).unwrap();
+    buf
+}
+
+#[tokio::test]
+async fn download_to_folder() {
+    let server = mock_server().await;
+    let tmp = TempDir::new().unwrap();
+    let output = tmp.path().join("mock_character");
+
+    let (downloaded, removed) = download(&server, output.clone(), false, None).await;
+
+    assert_eq!(downloaded, 3);
+    assert_eq!(removed, 0);
+
+    for md5 in MD5S {
+        let expected = read(mock_responses().join(format!("images/{md5}.png"))).unwrap();
+        assert_eq!(read(output.join(format!("{md5}.png"))).unwrap(), expected);
+    }
+}
+
+#[tokio::test]
+async fn download_to_cbz() {
+    let server = mock_server().await;
+    let tmp = TempDir::new().unwrap();
+    let output = tmp.path().join("mock_character.cbz");
+
+    let (downloaded, _) = download(&server, output.clone(), true, None).await;
+
+    assert_eq!(downloaded, 3);
+
+    let mut archive = ZipArchive::new(File::open(&output).unwrap()).unwrap();
+
+    for (rating, md5) in ["Safe", "Questionable", "Explicit"].into_iter().zip(MD5S) {
+        let expected = read(mock_responses().join(format!("images/{md5}.png"))).unwrap();
+        assert_eq!(
+            read_zip_file(&mut archive, &format!("{rating}/{md5}.png")),
+            expected
+        );
+    }
+
+    let comic_info = String::from_utf8(read_zip_file(&mut archive, "ComicInfo.xml")).unwrap();
+
+    assert!(comic_info.contains("<Title>mock_character</Title>"));
+    assert!(!comic_info.contains("<Series>"));
+    assert!(comic_info.contains("<PageCount>3</PageCount>"));
+    assert!(comic_info.contains("<AgeRating>Adults Only 18+</AgeRating>"));
+    assert!(comic_info.contains("<Writer>mock artist, other artist</Writer>"));
+    assert!(comic_info.contains("<Characters>mock character</Characters>"));
+}
+
+#[tokio::test]
+async fn download_pool_to_cbz() {
+    let server = mock_server().await;
+    let tmp = TempDir::new().unwrap();
+    let output = tmp.path().join("777.cbz");
+
+    let (downloaded, _) = download(&server, output.clone(), true, Some(777)).await;
+
+    assert_eq!(downloaded, 3);
+
+    let mut archive = ZipArchive::new(File::open(&output).unwrap()).unwrap();
+
+    // Pages are named after their position in the pool
+    for (page, md5) in MD5S.into_iter().enumerate() {
+        let expected = read(mock_responses().join(format!("images/{md5}.png"))).unwrap();
+        assert_eq!(
+            read_zip_file(&mut archive, &format!("{page:06}.png")),
+            expected
+        );
+    }
+
+    let comic_info = String::from_utf8(read_zip_file(&mut archive, "ComicInfo.xml")).unwrap();
+
+    assert!(comic_info.contains("<Series>Mock Comic Pool</Series>"));
+    assert!(comic_info.contains("<Page Image=\"0\" Type=\"FrontCover\""));
+}
+
+#[tokio::test]
+async fn download_retries_failed_files() {
+    let server = mock_server().await;
+    let tmp = TempDir::new().unwrap();
+    let output = tmp.path().join("mock_character");
+
+    Mock::given(method("GET"))
+        .and(path(format!("/data/{}.png", MD5S[0])))
+        .respond_with(ResponseTemplate::new(503))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let (downloaded, _) = download(&server, output.clone(), false, None).await;
+
+    assert_eq!(downloaded, 3);
+    assert!(output.join(format!("{}.png", MD5S[0])).exists());
+}
+
+#[tokio::test]
+async fn download_reports_server_errors_as_network_failures() {
+    let server = mock_server().await;
+    let tmp = TempDir::new().unwrap();
+    let output = tmp.path().join("mock_character");
+    let broken = format!("{}.png", MD5S[0]);
+
+    Mock::given(method("GET"))
+        .and(path(format!("/data/{broken}")))
+        .respond_with(ResponseTemplate::new(503))
+        .with_priority(1)
+        .mount(&server)
+        .await;
+
+    let (downloaded, _) = download(&server, output.clone(), false, None).await;
+
+    assert_eq!(downloaded, 2);
+    assert!(!output.join(&broken).exists());
+    assert!(!output.join(format!("{broken}.part")).exists());
+
+    let report = FailureReport::load(&FailureReport::path_for(&output)).unwrap();
+
+    assert_eq!(report.posts.len(), 1);
+    assert_eq!(report.posts[0].kind, FailureKind::Network);
+    assert_eq!(report.posts[0].post.md5, MD5S[0]);
+
+    // The file mock of the failing post is never reached
+    server.reset().await;
+}
+
+/// Leaves the first `len` bytes of `md5` in a `.part` file inside `output`, like an interrupted
+/// download, and returns the whole file.
+fn partial_download(output: &Path, md5: &str, len: usize) -> Vec<u8> {
+    let image = read(mock_responses().join(format!("images/{md5}.png"))).unwrap();
+
+    std::fs::create_dir_all(output).unwrap();
+    std::fs::write(output.join(format!("{md5}.png.part")), &image[..len]).unwrap();
+
+    image
+}
+
+/// `Range` header of every request the server got for the file of `md5`.
+async fn file_ranges(server: &MockServer, md5: &str) -> Vec<Option<String>> {
+    server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter(|request| request.url.path() == format!("/data/{md5}.png"))
+        .map(|request| {
+            request
+                .headers
+                .get("Range")
+                .map(|range| range.to_str().unwrap().to_string())
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn download_resumes_partial_file() {
+    let server = mock_server().await;
+    let tmp = TempDir::new().unwrap();
+    let output = tmp.path().join("mock_character");
+    let image = partial_download(&output, MD5S[0], 40);
+
+    Mock::given(method("GET"))
+        .and(path(format!("/data/{}.png", MD5S[0])))
+        .and(header("Range", "bytes=40-"))
+        .respond_with(ResponseTemplate::new(206).set_body_raw(image[40..].to_vec(), "image/png"))
+        .with_priority(1)
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let (downloaded, _) = download(&server, output.clone(), false, None).await;
+
+    assert_eq!(downloaded, 3);
+    assert_eq!(
+        read(output.join(format!("{}.png", MD5S[0]))).unwrap(),
+        image
+    );
+    assert!(!output.join(format!("{}.png.part", MD5S[0])).exists());
+
+    // The file is never requested from the start
+    assert_eq!(
+        file_ranges(&server, MD5S[0]).await,
+        [Some(String::from("bytes=40-"))]
+    );
+    server.reset().await;
+}
+
+#[tokio::test]
+async fn download_restarts_when_range_is_ignored() {
+    let server = mock_server().await;
+    let tmp = TempDir::new().unwrap();
+    let output = tmp.path().join("mock_character");
+    let image = read(mock_responses().join(format!("images/{}.png", MD5S[0]))).unwrap();
+
+    // A part that doesn't belong to the file, so appending to it would break the hash
+    std::fs::create_dir_all(&output).unwrap();
+    std::fs::write(output.join(format!("{}.png.part", MD5S[0])), b"stale").unwrap();
+
+    Mock::given(method("GET"))
+        .and(path(format!("/data/{}.png", MD5S[0])))
+        .and(header("Range", "bytes=5-"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(image.clone(), "image/png"))
+        .with_priority(1)
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let (downloaded, _) = download(&server, output.clone(), false, None).await;
+
+    assert_eq!(downloaded, 3);
+    assert_eq!(
+        read(output.join(format!("{}.png", MD5S[0]))).unwrap(),
+        image
+    );
+    assert!(!output.join(format!("{}.png.part", MD5S[0])).exists());
+
+    assert_eq!(
+        file_ranges(&server, MD5S[0]).await,
+        [Some(String::from("bytes=5-"))]
+    );
+    server.reset().await;
+}
+
+#[tokio::test]
+async fn download_finishes_complete_part_on_range_not_satisfiable() {
+    let server = mock_server().await;
+    let tmp = TempDir::new().unwrap();
+    let output = tmp.path().join("mock_character");
+
+    // One part holds the whole file, the other is the right size but corrupted
+    let image = read(mock_responses().join(format!("images/{}.png", MD5S[0]))).unwrap();
+    partial_download(&output, MD5S[0], image.len());
+
+    let mut corrupted = read(mock_responses().join(format!("images/{}.png", MD5S[1]))).unwrap();
+    corrupted[0] ^= 0xff;
+    std::fs::write(output.join(format!("{}.png.part", MD5S[1])), &corrupted).unwrap();
+
+    for (md5, len) in [(MD5S[0], image.len()), (MD5S[1], corrupted.len())] {
+        Mock::given(method("GET"))
+            .and(path(format!("/data/{md5}.png")))
+            .and(header("Range", format!("bytes={len}-").as_str()))
+            .respond_with(ResponseTemplate::new(416))
+            .with_priority(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+    }
+
+    let (downloaded, _) = download(&server, output.clone(), false, None).await;
+
+    assert_eq!(downloaded, 2);
+    assert_eq!(
+        read(output.join(format!("{}.png", MD5S[0]))).unwrap(),
+        image
+    );
+
+    // The corrupted part is thrown away, so the next run starts over
+    assert!(!output.join(format!("{}.png", MD5S[1])).exists());
+    assert!(!output.join(format!("{}.png.part", MD5S[1])).exists());
+
+    let report = FailureReport::load(&FailureReport::path_for(&output)).unwrap();
+    assert_eq!(report.posts.len(), 1);
+    assert_eq!(report.posts[0].post.md5, MD5S[1]);
+
+    for (md5, len) in [(MD5S[0], image.len()), (MD5S[1], corrupted.len())] {
+        assert_eq!(
+            file_ranges(&server, md5).await,
+            [Some(format!("bytes={len}-"))]
+        );
+    }
+    server.reset().await;
+}
+
+#[tokio::test]
+async fn failed_downloads_are_reported_and_retried() {
+    let server = mock_server().await;
+    let tmp = TempDir::new().unwrap();
+    let output = tmp.path().join("mock_character");
+    let missing = format!("{}.png", MD5S[0]);
+
+    Mock::given(method("GET"))
+        .and(path(format!("/data/{missing}")))
+        .respond_with(ResponseTemplate::new(404))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let (downloaded, _) = download(&server, output.clone(), false, None).await;
+    assert_eq!(downloaded, 2);
+    assert!(!output.join(&missing).exists());
+
+    let report_path = FailureReport::path_for(&output);
+    let report = FailureReport::load(&report_path).unwrap();
+
+    assert_eq!(report.output, output);
+    assert!(!report.cbz && !report.pool);
+    assert_eq!(report.posts.len(), 1);
+    assert_eq!(report.posts[0].kind, FailureKind::NotFound);
+    assert_eq!(report.posts[0].server, "danbooru");
+    assert_eq!(report.posts[0].post.md5, MD5S[0]);
+
+    // The retry fetches the post again by its id
+    let list: serde_json::Value = serde_json::from_str(
+        &read_to_string(mock_responses().join("danbooru_download_list.json"))
+            .unwrap()
+            .replace("{{server}}", &server.uri()),
+    )
+    .unwrap();
+    let post = list
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|post| post["md5"] == MD5S[0])
+        .unwrap();
+
+    Mock::given(method("GET"))
+        .and(path_regex(format!(r"^/posts/+{}\.json$", post["id"])))
+        .respond_with(json(post.to_string()))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let args = Cli::parse_from(["ibdl", "retry", &report_path.display().to_string()]);
+    let Commands::Retry(retry) = &args.mode else {
+        unreachable!()
+    };
+
+    let servers = [(String::from("danbooru"), mock_config(&server))].into();
+    retry.run_with_servers(&args, &servers).await.unwrap();
+
+    assert_eq!(
+        read(output.join(&missing)).unwrap(),
+        read(mock_responses().join(format!("images/{missing}"))).unwrap()
+    );
+    assert!(!report_path.exists());
+}
+
+#[tokio::test]
+async fn failed_cbz_downloads_are_not_reported() {
+    let server = mock_server().await;
+    let tmp = TempDir::new().unwrap();
+    let output = tmp.path().join("mock_character.cbz");
+
+    Mock::given(method("GET"))
+        .and(path(format!("/data/{}.png", MD5S[0])))
+        .respond_with(ResponseTemplate::new(404))
+        .with_priority(1)
+        .mount(&server)
+        .await;
+
+    let (downloaded, _) = download(&server, output.clone(), true, None).await;
+
+    assert_eq!(downloaded, 2);
+    assert!(output.exists());
+
+    // Nothing but the cbz file is saved, since retry can't add to it
+    assert_eq!(std::fs::read_dir(tmp.path()).unwrap().count(), 1);
+    server.reset().await;
+}
+
+#[test]
+fn subscriptions_only_advance_past_saved_posts() {
+    let sent = [1005, 1004, 1003, 1002];
+
+    assert_eq!(newest_saved(Some(1000), &sent, &[]), Some(1005));
+
+    // Everything from the oldest failed post on is fetched again by the next update
+    assert_eq!(newest_saved(Some(1000), &sent, &[1004]), Some(1003));
+    assert_eq!(newest_saved(Some(1000), &sent, &[1005, 1003]), Some(1002));
+    assert_eq!(newest_saved(Some(1000), &sent, &[1002]), Some(1000));
+    assert_eq!(newest_saved(None, &sent, &[1002]), None);
+    assert_eq!(newest_saved(Some(1000), &[], &[]), Some(1000));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn download_hooks() {
+    let server = mock_server().await;
+    let tmp = TempDir::new().unwrap();
+    let output = tmp.path().join("mock_character");
+    let log = tmp.path().join("hooks.log");
+
+    let hooks = Hooks {
+        on_file: vec![
+            format!(
+                "echo \"$IBDL_POST_ID $IBDL_RATING $(basename \"$IBDL_FILE\")\" >> {}",
+                log.display()
+            ),
+            // Failing hooks don't stop the queue
+            String::from("exit 3"),
+            // Neither do hooks filling their output before reading the input
+            String::from("head -c 1048576 /dev/zero >&2; cat > /dev/null"),
+        ],
+        on_job: vec![format!("cat > {}", tmp.path().join("job.json").display())],
+    };
+
+    let (downloaded, _) = download_with(&server, output.clone(), false, None, |queue| {
+        queue.hooks(hooks);
+    })
+    .await;
+
+    assert_eq!(downloaded, 3);
+
+    let mut lines = read_to_string(&log)
+        .unwrap()
+        .lines()
+        .map(String::from)
+        .collect::<Vec<_>>();
+    lines.sort();
+
+    assert_eq!(
+        lines,
+        [
+            format!("1001 Safe {}.png", MD5S[0]),
+            format!("1002 Questionable {}.png", MD5S[1]),
+            format!("1003 Explicit {}.png", MD5S[2]),
+        ]
+    );
+
+    let job: ibdl_common::serde_json::Value =
+        ibdl_common::serde_json::from_str(&read_to_string(tmp.path().join("job.json")).unwrap())
+            .unwrap();
+
+    assert_eq!(job["downloaded"], 3);
+    assert_eq!(job["server"], "danbooru");
+    assert_eq!(job["output"], output.display().to_string());
+}
+
+#[tokio::test]
+async fn download_with_filename_template() {
+    let server = mock_server().await;
+    let tmp = TempDir::new().unwrap();
+    let output = tmp.path().join("mock_character");
+
+    // Files saved by an older run under the default name are moved to the template path
+    std::fs::create_dir_all(&output).unwrap();
+    std::fs::copy(
+        mock_responses().join(format!("images/{}.png", MD5S[2])),
+        output.join(format!("{}.png", MD5S[2])),
+    )
+    .unwrap();
+
+    let template: FilenameTemplate = "{server}/{artist}/{id}_{md5:8}.{ext}".parse().unwrap();
+
+    let (downloaded, _) = download_with(&server, output.clone(), false, None, |queue| {
+        queue
+            .filename_template(template)
+            .save_metadata(MetadataFormat::Json);
+    })
+    .await;
+
+    assert_eq!(downloaded, 3);
+
+    // The moved file is never requested
+    let requests = server.received_requests().await.unwrap();
+    assert!(!requests
+        .iter()
+        .any(|request| request.url.path().contains(MD5S[2])));
+    server.reset().await;
+
+    for (id, artist, md5) in [
+        (1001, "mock_artist", MD5S[0]),
+        (1002, "mock_artist", MD5S[1]),
+        (1003, "other_artist", MD5S[2]),
+    ] {
+        let dir = output.join("danbooru").join(artist);
+        let expected = read(mock_responses().join(format!("images/{md5}.png"))).unwrap();
+
+        assert_eq!(
+            read(dir.join(format!("{id}_{}.png", &md5[..8]))).unwrap(),
+            expected
+        );
+        assert!(!output.join(format!("{md5}.png")).exists());
+    }
+
+    // Sidecar files follow the template too
+    assert!(output
+        .join(format!("danbooru/mock_artist/1001_{}.json", &MD5S[0][..8]))
+        .exists());
+}
+
+#[tokio::test]
+async fn download_to_cbz_with_filename_template() {
+    let server = mock_server().await;
+    let tmp = TempDir::new().unwrap();
+    let output = tmp.path().join("mock_character.cbz");
+
+    let template: FilenameTemplate = "{rating}/{id}".parse().unwrap();
+
+    let (downloaded, _) = download_with(&server, output.clone(), true, None, |queue| {
+        queue.filename_template(template);
+    })
+    .await;
+
+    assert_eq!(downloaded, 3);
+
+    let mut archive = ZipArchive::new(File::open(&output).unwrap()).unwrap();
+
+    for (id, (rating, md5)) in
+        (1001..).zip(["Safe", "Questionable", "Explicit"].into_iter().zip(MD5S))
+    {
+        let expected = read(mock_responses().join(format!("images/{md5}.png"))).unwrap();
+        assert_eq!(
+            read_zip_file(&mut archive, &format!("{rating}/{id}.png")),
+            expected
+        );
+    }
+}
+
+#[tokio::test]
+async fn download_grouped_by_artist() {
+    let server = mock_server().await;
+    let tmp = TempDir::new().unwrap();
+    let output = tmp.path().join("mock_character");
+
+    let (downloaded, _) = download_with(&server, output.clone(), false, None, |queue| {
+        queue.group_by(GroupBy::Artist);
+    })
+    .await;
+
+    assert_eq!(downloaded, 3);
+
+    for (artist, md5) in ["mock_artist", "mock_artist", "other_artist"]
+        .into_iter()
+        .zip(MD5S)
+    {
+        assert!(output.join(artist).join(format!("{md5}.png")).exists());
+    }
+}
+
+#[tokio::test]
+async fn download_to_cbz_grouped_by_extension() {
+    let server = mock_server().await;
+    let tmp = TempDir::new().unwrap();
+    let output = tmp.path().join("mock_character.cbz");
+
+    let (downloaded, _) = download_with(&server, output.clone(), true, None, |queue| {
+        queue.group_by(GroupBy::Extension);
+    })
+    .await;
+
+    assert_eq!(downloaded, 3);
+
+    let mut archive = ZipArchive::new(File::open(&output).unwrap()).unwrap();
+
+    for md5 in MD5S {
+        let expected = read(mock_responses().join(format!("images/{md5}.png"))).unwrap();
+        assert_eq!(
+            read_zip_file(&mut archive, &format!("png/{md5}.png")),
+            expected
+        );
+    }
+
+    // The rating directories are only added when grouping by rating
+    assert!(archive.by_name("Safe/").is_err());
+}
+
+#[test]
+fn batch_manifest_in_toml_and_json() {
+    let tmp = TempDir::new().unwrap();
+
+    let toml = tmp.path().join("jobs.toml");
+    std::fs::write(
+        &toml,
+        r#"
+            parallel = 2
+
+            [[job]]
+            mode = "search"
+            tags = ["mock_character"]
+
+            [[job]]
+            imageboard = "e621"
+            mode = "pool"
+            pool_id = 777
+            output = "pools/777"
+            cbz = true
+            ratings = ["Safe", "questionable"]
+            limit = 10
+            latest_first = true
+        "#,
+    )
+    .unwrap();
+
+    let manifest = BatchManifest::load(&toml).unwrap();
+    assert_eq!(manifest.parallel, Some(2));
+    assert_eq!(manifest.jobs.len(), 2);
+
+    let [search, pool] = &manifest.jobs[..] else {
+        unreachable!()
+    };
+
+    // Options not set in a job keep their defaults
+    assert_eq!(search.imageboard, None);
+    assert_eq!(search.mode, JobMode::Search);
+    assert!(!search.cbz);
+    assert_eq!(search.search().tags, ["mock_character"]);
+    assert!(search.search().rating.is_empty());
+
+    assert_eq!(pool.imageboard.as_deref(), Some("e621"));
+    assert_eq!(pool.label(), "pool 777");
+    assert_eq!(
+        pool.output_path(ImageBoards::E621).unwrap(),
+        PathBuf::from("pools/777.cbz")
+    );
+
+    let pool = pool.pool().unwrap();
+    assert_eq!(pool.pool_id, 777);
+    assert_eq!(pool.limit, Some(10));
+    assert!(pool.latest_first);
+    assert_eq!(
+        pool.rating
+            .iter()
+            .map(|rating| rating.0)
+            .collect::<Vec<_>>(),
+        [Rating::Safe, Rating::Questionable]
+    );
+
+    let json = tmp.path().join("jobs.json");
+    std::fs::write(
+        &json,
+        r#"{"jobs": [{"mode": "post", "posts": [1001, 1002], "imageboard": "danbooru"}]}"#,
+    )
+    .unwrap();
+
+    let manifest = BatchManifest::load(&json).unwrap();
+    assert_eq!(manifest.parallel, None);
+    assert_eq!(manifest.jobs[0].label(), "2 posts");
+    assert_eq!(manifest.jobs[0].post().unwrap().posts, [1001, 1002]);
+
+    // Jobs missing what their mode needs only fail when they run
+    std::fs::write(
+        &toml,
+        "[[job]]\nmode = \"pool\"\n[[job]]\nmode = \"post\"\n",
+    )
+    .unwrap();
+
+    let manifest = BatchManifest::load(&toml).unwrap();
+    assert!(matches!(
+        manifest.jobs[0].pool(),
+        Err(CliError::InvalidBatchJob { .. })
+    ));
+    assert!(matches!(
+        manifest.jobs[1].post(),
+        Err(CliError::NoPostsInInput)
+    ));
+
+    // Unknown modes and options of the wrong type reject the whole manifest
+    for invalid in [
+        "[[job]]\nmode = \"favorites\"\n",
+        "[[job]]\nmode = \"search\"\ntags = \"not a list\"\n",
+    ] {
+        std::fs::write(&toml, invalid).unwrap();
+        assert!(matches!(
+            BatchManifest::load(&toml),
+            Err(CliError::BatchFileError { .. })
+        ));
+    }
+
+    std::fs::write(&json, "[[job]]").unwrap();
+    assert!(matches!(
+        BatchManifest::load(&json),
+        Err(CliError::BatchFileError { .. })
+    ));
+
+    // So do ratings `--rating` doesn't know, naming the job they're in
+    std::fs::write(
+        &toml,
+        "[[job]]\nmode = \"search\"\ntags = [\"mock_character\"]\nratings = [\"safe\", \"bogus\"]\n",
+    )
+    .unwrap();
+    let Err(CliError::InvalidBatchJob { message }) = BatchManifest::load(&toml) else {
+        panic!("unknown rating was accepted");
+    };
+    assert_eq!(
+        message,
+        "job 1 (mock_character) has an unknown rating `bogus`"
+    );
+
+    // And more parallel jobs than `--parallel` allows
+    for parallel in [0, 11] {
+        std::fs::write(
+            &toml,
+            format!("parallel = {parallel}\n[[job]]\nmode = \"post\"\n"),
+        )
+        .unwrap();
+        assert!(matches!(
+            BatchManifest::load(&toml),
+            Err(CliError::BatchFileError { .. })
+        ));
+    }
+}
+
+#[tokio::test]
+async fn batch_continues_after_failed_jobs() {
+    let server = mock_server().await;
+    let tmp = TempDir::new().unwrap();
+    let output = tmp.path().join("mock_character");
+
+    let manifest = tmp.path().join("jobs.json");
+    std::fs::write(
+        &manifest,
+        serde_json::json!({
+            "jobs": [
+                {"mode": "pool"},
+                {"mode": "search", "imageboard": "missing_booru", "tags": ["mock_character"]},
+                {"mode": "search", "tags": ["mock_character"], "output": output, "disable_blacklist": true},
+            ]
+        })
+        .to_string(),
+    )
+    .unwrap();
+
+    let mut args = Cli::parse_from(["ibdl", "batch", &manifest.display().to_string()]);
+    args.imageboard = mock_config(&server);
+
+    let jobs = BatchManifest::load(&manifest).unwrap().jobs;
+    let results = run_jobs(&jobs, 2, &args).await;
+
+    assert_eq!(
+        results.iter().map(|(idx, _)| *idx).collect::<Vec<_>>(),
+        [0, 1, 2]
+    );
+    assert!(matches!(
+        results[0].1,
+        Err(CliError::InvalidBatchJob { .. })
+    ));
+    assert!(matches!(results[1].1, Err(CliError::ServerNotExists)));
+
+    let report = results[2].1.as_ref().unwrap();
+    assert_eq!(report.downloaded, 3);
+    assert_eq!(report.removed, 0);
+
+    for md5 in MD5S {
+        assert!(output.join(format!("{md5}.png")).exists());
+    }
+}
+
+fn mock_post(id: u64, md5: &str) -> Post {
+    Post {
+        id,
+        website: ImageBoards::Danbooru,
+        url: format!("https://localhost/data/{md5}.png"),
+        md5: md5.to_string(),
+        extension: Extension::PNG,
+        rating: Rating::Safe,
+        tags: Vec::new(),
+        frame_delays: None,
+    }
+}
+
+#[test]
+fn archive_keeps_posts_across_reopen() {
+    let tmp = TempDir::new().unwrap();
+    let path = tmp.path().join("archive.redb");
+
+    let archive = DownloadArchive::open(&path).unwrap();
+    archive
+        .insert("danbooru", &mock_post(1001, MD5S[0]), "first.png")
+        .unwrap();
+    drop(archive);
+
+    let archive = DownloadArchive::open(&path).unwrap();
+    assert!(archive
+        .contains("danbooru", &mock_post(1001, MD5S[0]))
+        .unwrap());
+
+    // Entries are keyed by server, id and md5
+    assert!(!archive.contains("e621", &mock_post(1001, MD5S[0])).unwrap());
+    assert!(!archive
+        .contains("danbooru", &mock_post(1002, MD5S[0]))
+        .unwrap());
+    assert!(!archive
+        .contains("danbooru", &mock_post(1001, MD5S[1]))
+        .unwrap());
+
+    let entries = archive.entries(None).unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].file_name, "first.png");
+}
+
+#[tokio::test]
+async fn download_skips_archived_posts() {
+    let tmp = TempDir::new().unwrap();
+    let archive = DownloadArchive::open(&tmp.path().join("archive.redb")).unwrap();
+
+    let first = tmp.path().join("first");
+    let (downloaded, _) =
+        download_with(&mock_server().await, first.clone(), false, None, |queue| {
+            queue.use_archive(archive.clone());
+        })
+        .await;
+
+    assert_eq!(downloaded, 3);
+    assert_eq!(archive.entries(Some("danbooru")).unwrap().len(), 3);
+
+    // Moving the files away doesn't download them again
+    let server = mock_server().await;
+    let second = tmp.path().join("second");
+    download_with(&server, second.clone(), false, None, |queue| {
+        queue.use_archive(archive.clone());
+    })
+    .await;
+
+    assert!(server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .all(|request| !request.url.path().starts_with("/data/")));
+    server.reset().await;
+
+    for md5 in MD5S {
+        assert!(!second.join(format!("{md5}.png")).exists());
+    }
+}
+
+#[test]
+fn archive_export_and_import() {
+    let tmp = TempDir::new().unwrap();
+    let exported = tmp.path().join("archive.jsonl");
+
+    let source = DownloadArchive::open(&tmp.path().join("source.redb")).unwrap();
+    for (idx, md5) in MD5S.into_iter().enumerate() {
+        source
+            .insert("danbooru", &mock_post(1001 + idx as u64, md5), md5)
+            .unwrap();
+    }
+    source
+        .insert("e621", &mock_post(1, MD5S[0]), "e621.png")
+        .unwrap();
+
+    let run = |archive: &DownloadArchive, command: &[&str]| {
+        let args = Cli::parse_from([&["ibdl", "archive"], command].concat());
+        let Commands::Archive(com) = &args.mode else {
+            unreachable!()
+        };
+        com.run_with(&args, archive).unwrap();
+    };
+
+    let file = exported.display().to_string();
+    run(&source, &["export", "--server", "danbooru", &file]);
+    assert_eq!(read_to_string(&exported).unwrap().lines().count(), 3);
+
+    // Posts already in the archive are kept as they are
+    let target = DownloadArchive::open(&tmp.path().join("target.redb")).unwrap();
+    target
+        .insert("danbooru", &mock_post(1001, MD5S[0]), "kept.png")
+        .unwrap();
+
+    run(&target, &["import", &file]);
+
+    let mut entries = target.entries(None).unwrap();
+    entries.sort_by_key(|entry| entry.id);
+
+    assert_eq!(
+        entries
+            .iter()
+            .map(|entry| (entry.id, entry.file_name.as_str()))
+            .collect::<Vec<_>>(),
+        [(1001, "kept.png"), (1002, MD5S[1]), (1003, MD5S[2])]
+    );
+    assert!(entries.iter().all(|entry| entry.server == "danbooru"));
+
+    // Importing twice adds nothing
+    run(&target, &["import", &file]);
+    assert_eq!(target.entries(None).unwrap().len(), 3);
+
+    std::fs::write(&exported, "not json\n").unwrap();
+    assert!(matches!(
+        target.import(std::io::BufReader::new(File::open(&exported).unwrap())),
+        Err(QueueError::ArchiveError { .. })
+    ));
+}
+
+#[tokio::test]
+async fn search_many_servers_downloads_each_file_once() {
+    let servers = [mock_server().await, mock_server().await];
+    let tmp = TempDir::new().unwrap();
+    let output = tmp.path().join("mock_character");
+
+    let configs = servers
+        .iter()
+        .zip(["mock_a", "mock_b"])
+        .map(|(server, name)| {
+            let mut config = mock_config(server);
+            config.name = name.to_string();
+            config
+        })
+        .collect::<Vec<_>>();
+
+    let args = Cli::parse_from(["ibdl", "search", "mock_character", "--disable-blacklist"]);
+    let Commands::Search(search) = &args.mode else {
+        unreachable!()
+    };
+
+    let (channel_tx, channel_rx) = unbounded_channel();
+    let (length_tx, length_rx) = channel(8);
+
+    let (ext, client, sources) = search
+        .init_multi_extractor(&configs, false, false, channel_tx, length_tx)
+        .await
+        .unwrap();
+
+    let mut queue = Queue::new(
+        configs[0].clone(),
+        2,
+        Some(client),
+        false,
+        false,
+        NameType::MD5,
+        false,
+    );
+    queue.filename_template("{server}/{md5}.{ext}".parse::<FilenameTemplate>().unwrap());
+    queue.post_sources(sources);
+
+    let downloader = queue.setup_async_downloader(
+        output.clone(),
+        Arc::new(AtomicU64::new(0)),
+        channel_rx,
+        length_rx,
+    );
+
+    let (summaries, downloaded) = tokio::join!(ext, downloader);
+    let summaries = summaries.unwrap();
+
+    assert_eq!(downloaded.unwrap().unwrap(), 3);
+    assert_eq!(summaries.len(), 2);
+    assert!(summaries.iter().all(|summary| summary.found == 3));
+    assert!(summaries.iter().all(|summary| summary.error.is_none()));
+    assert_eq!(
+        summaries
+            .iter()
+            .map(|summary| summary.duplicates)
+            .sum::<u64>(),
+        3
+    );
+
+    // Every file is saved once, under the server it was first found in
+    let mut fetched = 0;
+
+    for (server, config) in servers.iter().zip(&configs) {
+        let requests = server.received_requests().await.unwrap();
+
+        for request in requests
+            .iter()
+            .filter(|req| req.url.path().starts_with("/data/"))
+        {
+            let file = request.url.path().trim_start_matches("/data/");
+            assert!(output.join(&config.name).join(file).exists());
+            fetched += 1;
+        }
+
+        // Files found first in the other server are never requested
+        server.reset().await;
+    }
+
+    assert_eq!(fetched, 3);
+}
+
+async fn similar_server() -> MockServer {
+    mock_server_with("danbooru_similar_list.json", SIMILAR_MD5S).await
+}
+
+fn similar_images(index: &SimilarIndex, action: SimilarAction) -> SimilarImages {
+    SimilarImages {
+        index: index.clone(),
+        action,
+        threshold: 90,
+    }
+}
+
+#[tokio::test]
+async fn download_skips_similar_images() {
+    let tmp = TempDir::new().unwrap();
+    let index = SimilarIndex::open(&tmp.path().join("similar.redb"), HashAlgorithm::DHash).unwrap();
+
+    let first = tmp.path().join("first");
+    let (downloaded, _) = download_with(
+        &similar_server().await,
+        first.clone(),
+        false,
+        None,
+        |queue| {
+            queue.detect_similar(similar_images(&index, SimilarAction::Skip));
+        },
+    )
+    .await;
+
+    assert_eq!(downloaded, 3);
+
+    // Skipped posts aren't recorded as downloaded
+    let archive = DownloadArchive::open(&tmp.path().join("archive.redb")).unwrap();
+
+    let second = tmp.path().join("second");
+    download_with(
+        &similar_server().await,
+        second.clone(),
+        false,
+        None,
+        |queue| {
+            queue.detect_similar(similar_images(&index, SimilarAction::Skip));
+            queue.use_archive(archive.clone());
+        },
+    )
+    .await;
+
+    assert!(archive.entries(None).unwrap().is_empty());
+
+    for md5 in SIMILAR_MD5S {
+        assert!(first.join(format!("{md5}.png")).exists());
+        assert!(!second.join(format!("{md5}.png")).exists());
+        assert!(!second.join(format!("{md5}.png.part")).exists());
+    }
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn download_links_similar_images() {
+    use std::os::unix::fs::MetadataExt;
+
+    let tmp = TempDir::new().unwrap();
+    let index = SimilarIndex::open(&tmp.path().join("similar.redb"), HashAlgorithm::PHash).unwrap();
+
+    let first = tmp.path().join("first");
+    download_with(
+        &similar_server().await,
+        first.clone(),
+        false,
+        None,
+        |queue| {
+            queue.detect_similar(similar_images(&index, SimilarAction::Hardlink));
+        },
+    )
+    .await;
+
+    let second = tmp.path().join("second");
+    download_with(
+        &similar_server().await,
+        second.clone(),
+        false,
+        None,
+        |queue| {
+            queue.detect_similar(similar_images(&index, SimilarAction::Hardlink));
+        },
+    )
+    .await;
+
+    for md5 in SIMILAR_MD5S {
+        let original = std::fs::metadata(first.join(format!("{md5}.png"))).unwrap();
+        let link = std::fs::metadata(second.join(format!("{md5}.png"))).unwrap();
+        assert_eq!(original.ino(), link.ino());
+    }
+}
+
+#[test]
+fn perceptual_hash_matches_resized_copy() {
+    let tmp = TempDir::new().unwrap();
+    let data = read(mock_responses().join(format!("images/{}.png", SIMILAR_MD5S[0]))).unwrap();
+
+    // Only files that still exist are matched
+    std::fs::write(tmp.path().join("original.png"), &data).unwrap();
+
+    let original = image::load_from_memory(&data).unwrap();
+    let mut resized = Vec::new();
+    original
+        .resize(
+            original.width() * 3 / 4,
+            original.height() * 3 / 4,
+            FilterType::Lanczos3,
+        )
+        .to_rgb8()
+        .write_to(&mut Cursor::new(&mut resized), ImageFormat::Jpeg)
+        .unwrap();
+
+    for algorithm in [HashAlgorithm::DHash, HashAlgorithm::PHash] {
+        let index = SimilarIndex::open(&tmp.path().join(algorithm.name()), algorithm).unwrap();
+        let hash = index.hash(&data).unwrap();
+
+        assert_eq!(
+            index
+                .find_or_insert(&tmp.path().join("original.png"), hash, 90, false)
+                .unwrap(),
+            None
+        );
+
+        let found = index
+            .find_or_insert(
+                &tmp.path().join("resized.jpg"),
+                index.hash(&resized).unwrap(),
+                90,
+                false,
+            )
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(found.path, tmp.path().join("original.png"));
+
+        // The other fixtures are different images
+        for md5 in &SIMILAR_MD5S[1..] {
+            let other = read(mock_responses().join(format!("images/{md5}.png"))).unwrap();
+            assert!(index
+                .find_or_insert(
+                    &tmp.path().join(md5),
+                    index.hash(&other).unwrap(),
+                    90,
+                    false
+                )
+                .unwrap()
+                .is_none());
+        }
+    }
+}
+
+/// Small image encoded as `format`.
+fn encoded_image(format: ImageFormat) -> Vec<u8> {
+    let image = image::load_from_memory(
+        &read(mock_responses().join(format!("images/{}.png", MD5S[0]))).unwrap(),
+    )
+    .unwrap()
+    .to_rgb8();
+
+    let mut data = Vec::new();
+    image.write_to(&mut Cursor::new(&mut data), format).unwrap();
+    data
+}
+
+fn count(haystack: &[u8], needle: &str) -> usize {
+    haystack
+        .windows(needle.len())
+        .filter(|window| *window == needle.as_bytes())
+        .count()
+}
+
+#[tokio::test]
+async fn metadata_is_embedded_once_into_supported_formats() {
+    let tmp = TempDir::new().unwrap();
+    let post = mock_post(1001, MD5S[0]);
+    let config = DEFAULT_SERVERS.get("danbooru").unwrap();
+    let metadata = post_metadata(&post, config, MetadataFormat::Embed).unwrap();
+    let md5_tag = format!("<ibdl:md5>{}</ibdl:md5>", MD5S[0]);
+
+    for (format, ext) in [
+        (ImageFormat::Jpeg, "jpg"),
+        (ImageFormat::Png, "png"),
+        (ImageFormat::WebP, "webp"),
+    ] {
+        let original = encoded_image(format);
+        let file = tmp.path().join(format!("post.{ext}"));
+        std::fs::write(&file, &original).unwrap();
+
+        // Writing the metadata again, like a later run would, leaves the file as it is
+        for _ in 0..2 {
+            write_metadata(&metadata, &post.md5, &file, "post", tmp.path())
+                .await
+                .unwrap();
+        }
+
+        let data = read(&file).unwrap();
+        assert_ne!(data, original, "{ext}");
+        assert!(is_embedded(&data, MD5S[0]), "{ext}");
+        assert_eq!(count(&data, &md5_tag), 1, "{ext}");
+        assert!(!tmp.path().join("post.json").exists(), "{ext}");
+
+        // The image data is untouched
+        let decoded = image::load_from_memory_with_format(&data, format).unwrap();
+        let expected = image::load_from_memory_with_format(&original, format).unwrap();
+        assert_eq!(decoded.to_rgb8(), expected.to_rgb8(), "{ext}");
+    }
+
+    // The PNG keeps valid chunks, with the source next to the XMP packet
+    let png = read(tmp.path().join("post.png")).unwrap();
+    let reader = png::Decoder::new(Cursor::new(png)).read_info().unwrap();
+    let info = reader.info();
+    assert_eq!(info.uncompressed_latin1_text[0].keyword, "Source");
+    assert_eq!(info.uncompressed_latin1_text[0].text, metadata.source);
+    assert_eq!(info.utf8_text[0].keyword, "XML:com.adobe.xmp");
+
+    // Simple WebP files get an extended header, and the RIFF size covers the new chunks
+    let webp = read(tmp.path().join("post.webp")).unwrap();
+    assert_eq!(&webp[12..16], b"VP8X");
+    assert_eq!(webp[20] & 0x04, 0x04);
+    assert_eq!(
+        u32::from_le_bytes(webp[4..8].try_into().unwrap()) as usize,
+        webp.len() - 8
+    );
+    assert!(embed(&webp, metadata.xmp.as_ref().unwrap(), &metadata.source).is_none());
+}
+
+#[tokio::test]
+async fn metadata_of_unsupported_formats_goes_to_sidecar() {
+    let tmp = TempDir::new().unwrap();
+    let post = mock_post(1001, MD5S[0]);
+    let config = DEFAULT_SERVERS.get("danbooru").unwrap();
+    let metadata = post_metadata(&post, config, MetadataFormat::Embed).unwrap();
+
+    let gif = encoded_image(ImageFormat::Gif);
+    let file = tmp.path().join("post.gif");
+    std::fs::write(&file, &gif).unwrap();
+
+    write_metadata(&metadata, &post.md5, &file, "post", tmp.path())
+        .await
+        .unwrap();
+
+    assert_eq!(read(&file).unwrap(), gif);
+
+    let sidecar: serde_json::Value =
+        serde_json::from_str(&read_to_string(tmp.path().join("post.json")).unwrap()).unwrap();
+    assert_eq!(sidecar["id"], 1001);
+    assert_eq!(sidecar["md5"], MD5S[0]);
+    assert_eq!(sidecar["post_url"], metadata.source.as_str());
+
+    // Malformed files of a supported format aren't touched either
+    let mut broken = encoded_image(ImageFormat::Png);
+    broken.truncate(12);
+    assert!(embed(&broken, metadata.xmp.as_ref().unwrap(), &metadata.source).is_none());
+}
+
+/// Zip with one fixture image per frame, named like the frames of Danbooru ugoiras.
+fn ugoira_zip() -> Vec<u8> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+
+    // Stored in reverse, since frames go in the order of their names
+    for (idx, md5) in MD5S.iter().enumerate().rev() {
+        let image = read(mock_responses().join(format!("images/{md5}.png"))).unwrap();
+
+        zip.start_file(format!("{idx:06}.png"), FileOptions::default())
+            .unwrap();
+        zip.write_all(&image).unwrap();
+    }
+
+    zip.finish().unwrap().into_inner()
+}
+
+#[test]
+fn convert_ugoira_to_every_format() {
+    let zip = ugoira_zip();
+    let delays = [80, 80, 160];
+
+    let webp = convert_ugoira(&zip, &delays, UgoiraFormat::Webp).unwrap();
+    let mut decoder = WebPDecoder::new(Cursor::new(webp)).unwrap();
+    assert!(decoder.is_animated());
+    assert_eq!(decoder.num_frames(), 3);
+    assert_eq!(decoder.dimensions(), (4, 4));
+
+    // Frames go in the order of their names, and are stored losslessly
+    let first = image::load_from_memory(
+        &read(mock_responses().join(format!("images/{}.png", MD5S[0]))).unwrap(),
+    )
+    .unwrap()
+    .into_rgba8();
+
+    let mut frame = vec![0; decoder.output_buffer_size().unwrap()];
+    for (idx, delay) in delays.into_iter().enumerate() {
+        assert_eq!(decoder.read_frame(&mut frame).unwrap(), delay);
+
+        if idx == 0 {
+            assert_eq!(frame, first.as_raw().as_slice());
+        }
+    }
+
+    let apng = convert_ugoira(&zip, &delays, UgoiraFormat::Apng).unwrap();
+    let reader = png::Decoder::new(Cursor::new(apng)).read_info().unwrap();
+    assert_eq!(reader.info().animation_control().unwrap().num_frames, 3);
+
+    let gif = convert_ugoira(&zip, &delays, UgoiraFormat::Gif).unwrap();
+    let frames = GifDecoder::new(Cursor::new(gif))
+        .unwrap()
+        .into_frames()
+        .collect_frames()
+        .unwrap();
+    assert_eq!(frames.len(), 3);
+
+    // Every frame needs a delay
+    assert!(convert_ugoira(&zip, &delays[..2], UgoiraFormat::Gif).is_err());
+}
+
+/// Runs the extractor like [`download`], but into a [`PostList`] writing to `output`.
+async fn list(server: &MockServer, output: &Path, format: ListFormat) -> (u64, ListSummary) {
+    let config = mock_config(server);
+
+    let extractor =
+        DanbooruExtractor::new_with_config(&["mock_character"], &[], true, false, config.clone());
+
+    let (channel_tx, channel_rx) = unbounded_channel();
+    let (length_tx, length_rx) = channel(1);
+
+    let ext = extractor.setup_fetch_thread(channel_tx, None, None, Some(length_tx));
+
+    let writer = PostList::new(config, format).setup_list_writer(
+        Box::new(File::create(output).unwrap()),
+        channel_rx,
+        length_rx,
+    );
+
+    let (removed, listed) = tokio::join!(ext, writer);
+
+    (removed.unwrap().unwrap(), listed.unwrap().unwrap())
+}
+
+#[tokio::test]
+async fn dry_run_lists_posts_without_downloading() {
+    let server = mock_server().await;
+    let tmp = TempDir::new().unwrap();
+
+    let jsonl = tmp.path().join("posts.jsonl");
+    let (removed, summary) = list(&server, &jsonl, ListFormat::Jsonl).await;
+
+    assert_eq!(removed, 0);
+    assert_eq!(summary.posts, 3);
+    assert_eq!(summary.extensions.get("png"), Some(&3));
+    assert_eq!(summary.ratings.values().sum::<u64>(), 3);
+
+    let lines = read_to_string(&jsonl).unwrap();
+    let posts = lines
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .collect::<Vec<_>>();
+
+    assert_eq!(posts.len(), 3);
+    for post in &posts {
+        assert!(MD5S.contains(&post["md5"].as_str().unwrap()));
+        assert_eq!(post["server"], "danbooru");
+        assert!(post["post_url"]
+            .as_str()
+            .unwrap()
+            .ends_with(&format!("/posts/{}", post["id"].as_u64().unwrap())));
+    }
+
+    let csv = tmp.path().join("posts.csv");
+    list(&server, &csv, ListFormat::Csv).await;
+
+    let rows = read_to_string(&csv).unwrap();
+    let mut rows = rows.lines();
+    assert_eq!(
+        rows.next(),
+        Some("server,id,rating,extension,md5,url,post_url,tags")
+    );
+    assert_eq!(rows.count(), 3);
+
+    // Nothing was downloaded
+    assert!(server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .all(|request| !request.url.path().starts_with("/data/")));
+    server.reset().await;
+}
+
+/// Events written by every test, since they all share the same sink.
+#[derive(Clone, Default)]
+struct EventLog(Arc<Mutex<Vec<u8>>>);
+
+impl Write for EventLog {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl EventLog {
+    /// Events about files inside `dir`.
+    fn events_in(&self, dir: &Path) -> Vec<serde_json::Value> {
+        let dir = dir.display().to_string();
+        let log = String::from_utf8(self.0.lock().unwrap().clone()).unwrap();
+
+        log.lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .filter(|event| {
+                [&event["file"], &event["output"]]
+                    .iter()
+                    .any(|path| path.as_str().is_some_and(|path| path.starts_with(&dir)))
+            })
+            .collect()
+    }
+}
+
+#[tokio::test]
+async fn download_emits_json_events() {
+    let log = EventLog::default();
+    assert!(events::enable_json(Box::new(log.clone())));
+
+    let tmp = TempDir::new().unwrap();
+    let output = tmp.path().join("events");
+
+    download(&mock_server().await, output.clone(), false, None).await;
+
+    let server = mock_server().await;
+    download(&server, output.clone(), false, None).await;
+    server.reset().await;
+
+    let events = log.events_in(&output);
+    let names = |name: &str| {
+        events
+            .iter()
+            .filter(|event| event["event"] == name)
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(names("job_started").len(), 2);
+    assert_eq!(names("download_started").len(), 3);
+    assert_eq!(names("download_finished").len(), 3);
+    assert!(names("download_failed").is_empty());
+
+    let skipped = names("download_skipped");
+    assert_eq!(skipped.len(), 3);
+    assert!(skipped.iter().all(|event| event["reason"] == "exists"));
+
+    for md5 in MD5S {
+        let file = output.join(format!("{md5}.png")).display().to_string();
+        assert!(names("download_finished")
+            .iter()
+            .any(|event| event["file"] == file.as_str()));
+    }
+}
+
+#[test]
+fn config_file_fills_options_not_given() {
+    let config = r#"
+        simultaneous_downloads = 10
+        id = true
+        limit = 50
+
+        [profile.sd-training]
+        annotate = true
+        rating = ["safe"]
+        no-animated = true
+        imageboard = "e621"
+    "#
+    .parse::<ConfigFile>()
+    .unwrap();
+
+    let (args, effective) = Cli::parse_from_with_config(
+        [
+            "ibdl",
+            "-d",
+            "4",
+            "--profile",
+            "sd-training",
+            "search",
+            "mock_character",
+        ],
+        &config,
+    )
+    .unwrap();
+
+    let Commands::Search(search) = &args.mode else {
+        unreachable!()
+    };
+
+    assert_eq!(args.simultaneous_downloads, 4);
+    assert!(args.save_file_as_id && args.annotate);
+    assert_eq!(args.imageboards[0].name, "e621");
+    assert_eq!(search.limit, Some(50));
+    assert!(search.no_animated);
+    assert_eq!(
+        search
+            .rating
+            .iter()
+            .map(|rating| rating.0)
+            .collect::<Vec<_>>(),
+        [Rating::Safe]
+    );
+
+    let origin = |key| effective.get(key).unwrap().1.clone();
+    assert_eq!(origin("simultaneous_downloads"), Origin::CommandLine);
+    assert_eq!(origin("limit"), Origin::ConfigFile);
+    assert_eq!(
+        origin("annotate"),
+        Origin::Profile(String::from("sd-training"))
+    );
+    assert_eq!(origin("cbz"), Origin::Default);
+    assert_eq!(effective.get("id").unwrap().0, &toml::Value::Boolean(true));
+
+    // Options conflicting with the ones given are left out
+    let (args, _) = Cli::parse_from_with_config(
+        [
+            "ibdl",
+            "--profile",
+            "sd-training",
+            "--all-servers",
+            "search",
+            "tag",
+        ],
+        &config,
+    )
+    .unwrap();
+    assert!(args.all_servers);
+
+    // Options of other subcommands don't apply, but unknown ones are rejected
+    assert!(Cli::parse_from_with_config(["ibdl", "retry", "failed_posts.json"], &config).is_ok());
+
+    let unknown = "bogus = 1".parse::<ConfigFile>().unwrap();
+    assert!(matches!(
+        Cli::parse_from_with_config(["ibdl", "search", "tag"], &unknown),
+        Err(CliError::ConfigFileError { .. })
+    ));
+}
+
+#[tokio::test]
+async fn auth_login_refresh_and_logout_of_custom_server() {
+    let cache = TempDir::new().unwrap();
+    std::env::set_var("IBDL_CACHE_DIR", cache.path());
+
+    let server = MockServer::start().await;
+    let profile = read_to_string(mock_responses().join("danbooru_profile.json")).unwrap();
+
+    // The blacklist changes on the website between the login and the refresh
+    Mock::given(method("GET"))
+        .and(path("/profile.json"))
+        .respond_with(json(profile.clone()))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/profile.json"))
+        .respond_with(json(profile.replace("scat\\n", "scat\\nyaoi\\n")))
+        .mount(&server)
+        .await;
+
+    let mut config = mock_config(&server);
+    config.name = String::from("custom_booru");
+    config.pretty_name = String::from("Custom Booru");
+    config.auth_url = Some(format!("{}/profile.json", server.uri()));
+
+    let store = VaultStore::new(
+        cache.path().join("credentials.vault"),
+        Box::new(|_| Ok(String::from("correct horse"))),
+    );
+    let client = Client::new();
+
+    let credentials = Credentials {
+        username: String::from("mock_user"),
+        api_key: String::from(" mock_key\n"),
+    };
+
+    let logged_in = login(&config, &client, &credentials, &store).await.unwrap();
+    assert_eq!(logged_in.user_data.blacklisted_tags, ["guro", "scat"]);
+    assert_eq!(logged_in.api_key, "mock_key");
+
+    let status = Auth::status(&[&config], &store).await.unwrap();
+    assert!(status[0].contains("logged in as mock_user (id 123456, 2 blacklisted tags)"));
+
+    let refreshed = Auth::refresh(&config, &client, &store).await.unwrap();
+    assert_eq!(
+        refreshed.user_data.blacklisted_tags,
+        ["guro", "scat", "yaoi"]
+    );
+
+    let cached = ImageboardConfig::cached_user(&config)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(cached.blacklisted_tags.len(), 3);
+
+    assert!(ImageboardConfig::logout(&config, &store).await.unwrap());
+
+    let status = Auth::status(&[&config], &store).await.unwrap();
+    assert!(status[0].ends_with("not logged in"));
+
+    assert!(matches!(
+        Auth::refresh(&config, &client, &store).await,
+        Err(CliError::NotLoggedIn { .. })
+    ));
+}
+
+#[tokio::test]
+async fn favorites_need_a_user_id_or_a_login() {
+    // A server nobody logged into, wherever the auth cache is
+    let mut config = DEFAULT_SERVERS.get("gelbooru").unwrap().clone();
+    config.name = String::from("favorites_booru");
+
+    let Commands::Favorites(given) = Cli::parse_from(["ibdl", "favorites", "42"]).mode else {
+        unreachable!()
+    };
+    assert_eq!(given.user_id(&config).await.unwrap(), 42);
+
+    let Commands::Favorites(missing) = Cli::parse_from(["ibdl", "favorites"]).mode else {
+        unreachable!()
+    };
+    assert!(matches!(
+        missing.user_id(&config).await,
+        Err(CliError::MissingUserId { .. })
+    ));
+
+    // Only Gelbooru-based servers have favorites pages
+    let (channel_tx, _channel_rx) = unbounded_channel();
+    let (length_tx, _length_rx) = channel(1);
+    assert!(matches!(
+        given
+            .setup_extractor(
+                DEFAULT_SERVERS.get("danbooru").unwrap(),
+                false,
+                channel_tx,
+                length_tx
+            )
+            .await,
+        Err(CliError::ExtractorUnsupportedMode)
+    ));
+}
@@ -0,0 +1,77 @@
+//! [`SecretStore`] backed by the keyring of the operating system.
+use ibdl_common::{log::debug, serde_json};
+use keyring::Entry;
+
+use super::secrets::{store_error, Credentials, SecretStore};
+use super::Error;
+
+const STORE_NAME: &str = "system keyring";
+
+/// Service every item of this program is saved under, to tell them apart from other programs.
+const SERVICE: &str = "imageboard-downloader";
+
+/// Saves credentials in the keyring of the system: the Secret Service on Linux and BSD, the
+/// Keychain on macOS and the Credential Manager on Windows.
+///
+/// Each server is a separate item, with the credentials encoded as JSON in its password.
+#[derive(Debug, Clone, Copy)]
+pub struct KeyringStore;
+
+impl KeyringStore {
+    /// Returns the store if the keyring of the system answers.
+    #[must_use]
+    pub fn connect() -> Option<Self> {
+        // Looking up an item that's never saved only fails when there's no keyring to look into
+        let lookup = Entry::new(SERVICE, "keyring-check").and_then(|entry| entry.get_password());
+
+        match lookup {
+            Ok(_) | Err(keyring::Error::NoEntry) => Some(Self),
+            Err(error) => {
+                debug!("System keyring unavailable: {error}");
+                None
+            }
+        }
+    }
+
+    fn entry(server: &str) -> Result<Entry, Error> {
+        Entry::new(SERVICE, server).map_err(|error| store_error(STORE_NAME, error))
+    }
+}
+
+impl SecretStore for KeyringStore {
+    fn name(&self) -> &'static str {
+        STORE_NAME
+    }
+
+    fn get(&self, server: &str) -> Result<Option<Credentials>, Error> {
+        let secret = match Self::entry(server)?.get_password() {
+            Ok(secret) => secret,
+            Err(keyring::Error::NoEntry) => return Ok(None),
+            Err(error) => return Err(store_error(STORE_NAME, error)),
+        };
+
+        serde_json::from_str(&secret)
+            .map(Some)
+            .map_err(|error| store_error(STORE_NAME, error))
+    }
+
+    fn set(&self, server: &str, credentials: &Credentials) -> Result<(), Error> {
+        let secret =
+            serde_json::to_string(credentials).map_err(|error| store_error(STORE_NAME, error))?;
+
+        Self::entry(server)?
+            .set_password(&secret)
+            .map_err(|error| store_error(STORE_NAME, error))?;
+
+        debug!("Saved credentials of {server} to the {STORE_NAME}");
+        Ok(())
+    }
+
+    fn delete(&self, server: &str) -> Result<(), Error> {
+        match Self::entry(server)?.delete_credential() {
+            // Clearing nothing isn't an error
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(error) => Err(store_error(STORE_NAME, error)),
+        }
+    }
+}
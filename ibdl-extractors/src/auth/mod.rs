@@ -1,12 +1,27 @@
 //! All methods and structs related to user authentication and configuration for imageboard websites
-use bincode::serialize;
-use ibdl_common::{bincode, log, reqwest, tokio};
-use log::debug;
-use reqwest::Client;
-use std::io;
-use std::path::Path;
+//!
+//! # Auth Cache
+//! Logging in saves the [`UserData`] of the account, like its blacklist, in a cache file for every
+//! server, while the username and API key go to a [`SecretStore`]. Caches written by older versions,
+//! with the credentials inside them, are moved to the store the first time they're loaded.
+//!
+//! # Secret Stores
+//! A [`SecretStore`] saves the [`Credentials`] of each server under its name. Two stores are
+//! available:
+//!
+//! * [`KeyringStore`], the keyring of the system: the Secret Service (GNOME Keyring, `KWallet`,
+//!   `KeePassXC`...) on Linux, the Keychain on macOS and the Credential Manager on Windows;
+//! * [`VaultStore`], a file encrypted with a passphrase, for systems without a keyring.
+use bincode::{deserialize, serialize};
+use ibdl_common::{bincode, log, reqwest, serde_json, tokio};
+use log::{debug, info, warn};
+use reqwest::{Client, StatusCode};
+use ring::digest::{digest, SHA1_FOR_LEGACY_USE_ONLY};
+use std::fmt::Write;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
 use thiserror::Error;
-use tokio::fs::OpenOptions;
+use tokio::fs::{read, remove_file, OpenOptions};
 use tokio::io::AsyncWriteExt;
 
 use ibdl_common::ImageBoards;
@@ -15,6 +30,17 @@ use ibdl_common::serde::{self, Deserialize, Serialize};
 
 use crate::extractor_config::{ServerConfig, DEFAULT_SERVERS};
 
+mod keyring;
+mod secrets;
+mod vault;
+
+pub use keyring::KeyringStore;
+pub use secrets::{Credentials, SecretStore};
+pub use vault::{PassphraseProvider, VaultStore};
+
+/// Start of every auth cache file without credentials.
+const CACHE_HEADER: &[u8] = b"IBDL-AUTH-V2";
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum AuthState {
     Authenticated,
@@ -55,11 +81,21 @@ pub enum Error {
 
     #[error("This imageboard does not support authentication.")]
     AuthUnsupported,
+
+    /// Indicates that the [`SecretStore`] couldn't be read or written.
+    #[error("Failed to access the {store}: {message}")]
+    SecretStoreError {
+        store: &'static str,
+        message: String,
+    },
+
+    /// Indicates that the passphrase of the [`VaultStore`] can't decrypt it.
+    #[error("Wrong passphrase for the credential vault")]
+    WrongPassphrase,
 }
 
 /// Struct that defines all user configuration for a specific imageboard.
-#[derive(Serialize, Deserialize, Debug, Clone)]
-#[serde(crate = "self::serde")]
+#[derive(Debug, Clone)]
 pub struct ImageboardConfig {
     /// Used as a identification tag for handling the cache outside of a imageboard downloader
     /// struct.
@@ -81,6 +117,34 @@ pub struct UserData {
     pub blacklisted_tags: Vec<String>,
 }
 
+/// Contents of the auth cache, without the credentials.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "self::serde")]
+struct AuthCache {
+    imageboard: ServerConfig,
+    user_data: UserData,
+}
+
+/// Auth cache written by older versions: the [`ServerConfig`] of that time, followed by the
+/// username, the API key and the [`UserData`].
+type LegacyCache = (LegacyServerConfig, String, String, UserData);
+
+/// Fields of [`ServerConfig`] before rate limits, retries and tag limits were added to it.
+type LegacyServerConfig = (
+    String,
+    String,
+    ImageBoards,
+    String,
+    String,
+    String,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+    u16,
+    Option<String>,
+    Option<String>,
+);
+
 impl Default for ImageboardConfig {
     fn default() -> Self {
         Self {
@@ -111,6 +175,9 @@ impl ImageboardConfig {
         }
     }
 
+    /// Checks the credentials with the server and fetches the [`UserData`] of the account.
+    ///
+    /// Calling it again refreshes the [`UserData`], like a blacklist changed on the website.
     pub async fn authenticate(&mut self, client: &Client) -> Result<(), Error> {
         #[derive(Debug, Serialize, Deserialize)]
         #[serde(crate = "self::serde")]
@@ -126,36 +193,42 @@ impl ImageboardConfig {
         }
 
         let url = match self.imageboard.server {
-            ImageBoards::Danbooru => self.imageboard.auth_url.as_ref().unwrap().to_string(),
+            ImageBoards::Danbooru => self.imageboard.auth_url.as_ref().unwrap().clone(),
             ImageBoards::E621 => format!(
                 "{}{}.json",
                 self.imageboard.auth_url.as_ref().unwrap(),
                 self.username
             ),
-            _ => String::new(),
+            ImageBoards::Gelbooru => return self.authenticate_dapi(client).await,
+            ImageBoards::Moebooru => return self.authenticate_moebooru(client).await,
+            ImageBoards::GelbooruV0_2 => return Err(Error::AuthUnsupported),
         };
 
         debug!("Authenticating to {}", self.imageboard.base_url);
 
-        let req = client
+        let request = client
             .get(url)
-            .basic_auth(&self.username, Some(&self.api_key))
-            .send()
+            .basic_auth(&self.username, Some(&self.api_key));
+
+        let req = self
+            .imageboard
+            .send(request)
             .await?
             .json::<AuthTest>()
             .await?;
 
-        debug!("{:?}", req);
+        debug!("{req:?}");
 
         if req.success.is_some() {
             return Err(Error::InvalidLogin);
         }
 
-        if req.id.is_some() {
+        if let Some(id) = req.id {
             let tag_list = req.blacklisted_tags.unwrap();
 
-            self.user_data.id = req.id.unwrap();
+            self.user_data.id = id;
             self.user_data.name = req.name.unwrap();
+            self.user_data.blacklisted_tags.clear();
 
             for i in tag_list.lines() {
                 if !i.contains("//") {
@@ -165,18 +238,287 @@ impl ImageboardConfig {
 
             debug!("User id: {}", self.user_data.id);
             debug!("Blacklisted tags: '{:?}'", self.user_data.blacklisted_tags);
+        }
+
+        Ok(())
+    }
+
+    /// Checks the `user_id` and `api_key` of a Gelbooru-based server, kept in
+    /// [`username`](Self::username) and [`api_key`](Self::api_key), with a single DAPI request to
+    /// the `auth_url`.
+    ///
+    /// These servers don't expose the account blacklist, so only the user id is saved.
+    async fn authenticate_dapi(&mut self, client: &Client) -> Result<(), Error> {
+        let Ok(user_id) = self.username.trim().parse::<u64>() else {
+            debug!("Gelbooru user ids are numeric, got {}", self.username);
+            return Err(Error::InvalidLogin);
+        };
+
+        debug!("Authenticating to {}", self.imageboard.base_url);
+
+        let request = client
+            .get(self.imageboard.auth_url.as_ref().unwrap())
+            .query(&[
+                ("limit", "1"),
+                ("api_key", &self.api_key),
+                ("user_id", &user_id.to_string()),
+            ]);
 
-            self.write_cache().await?;
+        let response = self.imageboard.send(request).await?;
+
+        if matches!(
+            response.status(),
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+        ) {
+            return Err(Error::InvalidLogin);
         }
 
+        // Rule34 answers rejected credentials with a plain text message instead
+        let body = response.error_for_status()?.text().await?;
+
+        if serde_json::from_str::<serde_json::Value>(&body).is_err() {
+            debug!("{body}");
+            return Err(Error::InvalidLogin);
+        }
+
+        self.user_data.id = user_id;
+        self.user_data.name = user_id.to_string();
+        self.user_data.blacklisted_tags.clear();
+
+        debug!("User id: {}", self.user_data.id);
         Ok(())
     }
 
-    /// Generates a bincode file that contains all the data from `self` and saves
-    /// it in the directory provided by a `ImageBoards::auth_cache_dir()` method.
+    /// Logs into a Moebooru server with the `login` and `password_hash` of the account, looking it
+    /// up in the `auth_url`.
+    ///
+    /// The password in [`api_key`](Self::api_key) is replaced by its salted SHA1 hash, which is
+    /// what these servers take and the only thing saved. Moebooru doesn't expose the account
+    /// blacklist either, and treats a wrong hash as an anonymous request instead of rejecting it.
+    async fn authenticate_moebooru(&mut self, client: &Client) -> Result<(), Error> {
+        #[derive(Debug, Deserialize)]
+        #[serde(crate = "self::serde")]
+        struct MoebooruUser {
+            id: u64,
+            name: String,
+        }
+
+        if !is_password_hash(&self.api_key) {
+            self.api_key = password_hash(&self.imageboard, &self.api_key);
+        }
+
+        debug!("Authenticating to {}", self.imageboard.base_url);
+
+        let request = client
+            .get(self.imageboard.auth_url.as_ref().unwrap())
+            .query(&[
+                ("name", &self.username),
+                ("login", &self.username),
+                ("password_hash", &self.api_key),
+            ]);
+
+        let response = self.imageboard.send(request).await?;
+
+        if matches!(
+            response.status(),
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+        ) {
+            return Err(Error::InvalidLogin);
+        }
+
+        let users = response
+            .error_for_status()?
+            .json::<Vec<MoebooruUser>>()
+            .await?;
+
+        debug!("{users:?}");
+
+        // The name is matched partially, so other accounts may show up as well
+        let Some(user) = users
+            .into_iter()
+            .find(|user| user.name.eq_ignore_ascii_case(&self.username))
+        else {
+            return Err(Error::InvalidLogin);
+        };
+
+        self.user_data.id = user.id;
+        self.user_data.name = user.name;
+        self.user_data.blacklisted_tags.clear();
+
+        debug!("User id: {}", self.user_data.id);
+        Ok(())
+    }
+
+    /// Username and API key of the config.
+    #[must_use]
+    pub fn credentials(&self) -> Credentials {
+        Credentials {
+            username: self.username.clone(),
+            api_key: self.api_key.clone(),
+        }
+    }
+
+    /// Saves the credentials in `store` and the [`UserData`] in the auth cache, so later runs can
+    /// [`load`](Self::load) them.
+    pub async fn save(&self, store: &dyn SecretStore) -> Result<(), Error> {
+        store.set(&self.imageboard.name, &self.credentials())?;
+        self.write_cache().await
+    }
+
+    /// Loads the config of `imageboard` saved by an earlier login, with its credentials taken from
+    /// `store`.
+    ///
+    /// Returns `None` if there's no login saved for the server.
+    pub async fn load(
+        imageboard: &ServerConfig,
+        store: &dyn SecretStore,
+    ) -> Result<Option<Self>, Error> {
+        let Some(user_data) = Self::cached_user(imageboard).await? else {
+            return Self::migrate_legacy_cache(imageboard, store).await;
+        };
+
+        let Some(credentials) = store.get(&imageboard.name)? else {
+            warn!(
+                "Credentials of {} are missing from the {}. Running without authentication",
+                imageboard.name,
+                store.name()
+            );
+            return Ok(None);
+        };
+
+        Ok(Some(Self {
+            imageboard: imageboard.clone(),
+            username: credentials.username,
+            api_key: credentials.api_key,
+            user_data,
+        }))
+    }
+
+    /// Checks if there's an auth cache for `imageboard`, in the current or the old format, without
+    /// reading it.
+    pub fn is_cached(imageboard: &ServerConfig) -> Result<bool, Error> {
+        Ok(Self::cache_paths(imageboard)?
+            .iter()
+            .any(|path| path.exists()))
+    }
+
+    /// Reads the [`UserData`] of `imageboard` from the auth cache, without touching its
+    /// credentials.
+    ///
+    /// Returns `None` if the cache doesn't exist, is corrupted or still in the old format.
+    pub async fn cached_user(imageboard: &ServerConfig) -> Result<Option<UserData>, Error> {
+        let path = Self::cache_path(imageboard)?;
+
+        let data = match read(&path).await {
+            Ok(data) => data,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error.into()),
+        };
+
+        let Some(cache) = data.strip_prefix(CACHE_HEADER) else {
+            return Ok(None);
+        };
+
+        if let Ok(cache) = deserialize::<AuthCache>(cache) {
+            debug!("Authentication cache decoded.");
+            debug!("User id: {}", cache.user_data.id);
+            debug!("Username: {}", cache.user_data.name);
+            debug!("Blacklisted tags: '{:?}'", cache.user_data.blacklisted_tags);
+            return Ok(Some(cache.user_data));
+        }
+
+        warn!("Auth cache is invalid or empty. Running without authentication");
+        debug!("Removing corrupted file");
+        remove_file(path).await?;
+        Ok(None)
+    }
+
+    /// Removes the login of `imageboard`, both from the auth cache and from `store`.
+    ///
+    /// Returns `false` if there was no login saved for the server.
+    pub async fn logout(imageboard: &ServerConfig, store: &dyn SecretStore) -> Result<bool, Error> {
+        let mut removed = store.get(&imageboard.name)?.is_some();
+        store.delete(&imageboard.name)?;
+
+        for path in Self::cache_paths(imageboard)? {
+            if path.exists() {
+                remove_file(&path).await?;
+                debug!("Removed auth cache {}", path.display());
+                removed = true;
+            }
+        }
+
+        Ok(removed)
+    }
+
+    /// Moves the credentials of an auth cache written by an older version to `store`, leaving only
+    /// the [`UserData`] in the cache.
+    async fn migrate_legacy_cache(
+        imageboard: &ServerConfig,
+        store: &dyn SecretStore,
+    ) -> Result<Option<Self>, Error> {
+        for path in Self::cache_paths(imageboard)? {
+            let Ok(data) = read(&path).await else {
+                continue;
+            };
+
+            if data.starts_with(CACHE_HEADER) {
+                continue;
+            }
+
+            let Ok((_, username, api_key, user_data)) = deserialize::<LegacyCache>(&data) else {
+                warn!("Auth cache is invalid or empty. Running without authentication");
+                debug!("Removing corrupted file");
+                remove_file(&path).await?;
+                continue;
+            };
+
+            let config = Self {
+                imageboard: imageboard.clone(),
+                username,
+                api_key,
+                user_data,
+            };
+
+            store.set(&imageboard.name, &config.credentials())?;
+            remove_file(&path).await?;
+            config.write_cache().await?;
+
+            info!(
+                "Moved the credentials of {} from {} to the {}",
+                imageboard.name,
+                path.display(),
+                store.name()
+            );
+
+            return Ok(Some(config));
+        }
+
+        debug!("Running without authentication");
+        Ok(None)
+    }
+
+    fn cache_path(imageboard: &ServerConfig) -> Result<PathBuf, Error> {
+        Ok(ImageBoards::auth_cache_dir()?.join(Path::new(&imageboard.name)))
+    }
+
+    /// Every path the auth cache of `imageboard` has been saved to. Older versions named it after
+    /// the pretty name of the server.
+    fn cache_paths(imageboard: &ServerConfig) -> Result<Vec<PathBuf>, Error> {
+        let mut paths = vec![Self::cache_path(imageboard)?];
+        let legacy = ImageBoards::auth_cache_dir()?.join(Path::new(&imageboard.pretty_name));
+
+        if !paths.contains(&legacy) {
+            paths.push(legacy);
+        }
+
+        Ok(paths)
+    }
+
+    /// Generates a bincode file with the [`UserData`] of `self` and saves it in the directory
+    /// provided by a `ImageBoards::auth_cache_dir()` method.
     async fn write_cache(&self) -> Result<(), Error> {
-        let config_path =
-            ImageBoards::auth_cache_dir()?.join(Path::new(&self.imageboard.pretty_name));
+        let config_path = Self::cache_path(&self.imageboard)?;
         let mut cfg_cache = OpenOptions::new()
             .create(true)
             .truncate(true)
@@ -185,12 +527,49 @@ impl ImageboardConfig {
             .open(&config_path)
             .await?;
 
-        let Ok(bytes) = serialize(&self) else {
+        let cache = AuthCache {
+            imageboard: self.imageboard.clone(),
+            user_data: self.user_data.clone(),
+        };
+
+        let Ok(bytes) = serialize(&cache) else {
             return Err(Error::ConfigEncodeError);
         };
 
+        cfg_cache.write_all(CACHE_HEADER).await?;
         cfg_cache.write_all(&bytes).await?;
         debug!("Wrote auth cache to {}", &config_path.display());
         Ok(())
     }
 }
+
+/// Salt used by Moebooru when the server doesn't set one.
+const MOEBOORU_PASSWORD_SALT: &str = "choujin-steiner--{}--";
+
+/// Hashes `password` the way the Moebooru server `imageboard` expects it in the `password_hash`
+/// parameter: SHA1 of the password wrapped in the [salt](ServerConfig::password_salt) of the
+/// server, in hex.
+#[must_use]
+pub fn password_hash(imageboard: &ServerConfig, password: &str) -> String {
+    let salted = imageboard
+        .password_salt
+        .as_deref()
+        .unwrap_or(MOEBOORU_PASSWORD_SALT)
+        .replace("{}", password);
+
+    digest(&SHA1_FOR_LEGACY_USE_ONLY, salted.as_bytes())
+        .as_ref()
+        .iter()
+        .fold(String::with_capacity(40), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+}
+
+/// Checks if `key` is already a password hash, like the ones saved by earlier logins.
+fn is_password_hash(key: &str) -> bool {
+    key.len() == 40
+        && key
+            .bytes()
+            .all(|byte| byte.is_ascii_digit() || (b'a'..=b'f').contains(&byte))
+}
@@ -0,0 +1,45 @@
+//! Storage for the username and API key of every server, kept apart from the auth cache.
+use std::fmt;
+
+use ibdl_common::serde::{self, Deserialize, Serialize};
+
+use super::Error;
+
+/// Username and API key used to log into a server.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "self::serde")]
+pub struct Credentials {
+    pub username: String,
+    pub api_key: String,
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("username", &self.username)
+            .field("api_key", &"<hidden>")
+            .finish()
+    }
+}
+
+/// Place where the [`Credentials`] of every server are kept.
+pub trait SecretStore: Send + Sync {
+    /// Name of the store, as shown to the user.
+    fn name(&self) -> &'static str;
+
+    /// Returns the credentials saved for `server`, if there are any.
+    fn get(&self, server: &str) -> Result<Option<Credentials>, Error>;
+
+    /// Saves `credentials` for `server`, replacing the ones saved before.
+    fn set(&self, server: &str, credentials: &Credentials) -> Result<(), Error>;
+
+    /// Removes the credentials of `server`. Does nothing if there are none.
+    fn delete(&self, server: &str) -> Result<(), Error>;
+}
+
+pub(super) fn store_error(store: &'static str, message: impl fmt::Display) -> Error {
+    Error::SecretStoreError {
+        store,
+        message: message.to_string(),
+    }
+}
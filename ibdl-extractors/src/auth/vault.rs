@@ -0,0 +1,239 @@
+//! [`SecretStore`] kept in a file encrypted with a passphrase.
+//!
+//! # Vault File
+//! The vault holds the credentials of every server as a single JSON map, encrypted with
+//! ChaCha20-Poly1305. The key is derived from the passphrase with PBKDF2-HMAC-SHA256 and a random
+//! salt, stored in the header of the file together with the nonce:
+//!
+//! ```text
+//! IBDLVLT1 | salt (16 bytes) | nonce (12 bytes) | encrypted map + tag
+//! ```
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use ibdl_common::{log::debug, serde_json};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+
+use super::secrets::{store_error, Credentials, SecretStore};
+use super::Error;
+
+const STORE_NAME: &str = "credential vault";
+
+const MAGIC: &[u8; 8] = b"IBDLVLT1";
+const SALT_LEN: usize = 16;
+const HEADER_LEN: usize = MAGIC.len() + SALT_LEN + NONCE_LEN;
+
+const PBKDF2_ITERATIONS: NonZeroU32 = match NonZeroU32::new(600_000) {
+    Some(iterations) => iterations,
+    None => unreachable!(),
+};
+
+/// Asks for the passphrase of the vault. The argument is `true` if the vault doesn't exist yet and
+/// the passphrase will be used to create it.
+pub type PassphraseProvider = Box<dyn Fn(bool) -> Result<String, Error> + Send + Sync>;
+
+type Entries = BTreeMap<String, Credentials>;
+
+/// Saves credentials in a file encrypted with a passphrase.
+///
+/// The passphrase is only asked for the first time the vault is opened, and the key derived from
+/// it is kept for the rest of the run.
+pub struct VaultStore {
+    path: PathBuf,
+    passphrase: PassphraseProvider,
+    /// Salt of the vault and the key derived from it
+    key: Mutex<Option<([u8; SALT_LEN], LessSafeKey)>>,
+    rng: SystemRandom,
+}
+
+impl VaultStore {
+    #[must_use]
+    pub fn new(path: PathBuf, passphrase: PassphraseProvider) -> Self {
+        Self {
+            path,
+            passphrase,
+            key: Mutex::new(None),
+            rng: SystemRandom::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Runs `action` with the key of the vault with `salt`, deriving it if needed.
+    fn with_key<T>(
+        &self,
+        salt: [u8; SALT_LEN],
+        creating: bool,
+        action: impl FnOnce(&LessSafeKey) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let mut cached = self.key.lock().unwrap();
+
+        if !matches!(&*cached, Some((cached_salt, _)) if *cached_salt == salt) {
+            let passphrase = (self.passphrase)(creating)?;
+
+            let mut key = [0; 32];
+            pbkdf2::derive(
+                pbkdf2::PBKDF2_HMAC_SHA256,
+                PBKDF2_ITERATIONS,
+                &salt,
+                passphrase.as_bytes(),
+                &mut key,
+            );
+
+            let key = UnboundKey::new(&CHACHA20_POLY1305, &key)
+                .map_err(|_| store_error(STORE_NAME, "invalid key"))?;
+
+            *cached = Some((salt, LessSafeKey::new(key)));
+        }
+
+        let Some((_, key)) = cached.as_ref() else {
+            unreachable!()
+        };
+
+        let result = action(key);
+
+        // A wrong passphrase shouldn't be tried again
+        if matches!(result, Err(Error::WrongPassphrase)) {
+            *cached = None;
+        }
+
+        result
+    }
+
+    fn read(&self) -> Result<Entries, Error> {
+        let data = match fs::read(&self.path) {
+            Ok(data) => data,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(Entries::new()),
+            Err(error) => return Err(error.into()),
+        };
+
+        if data.len() < HEADER_LEN || !data.starts_with(MAGIC) {
+            return Err(store_error(
+                STORE_NAME,
+                format!("{} is not a vault file", self.path.display()),
+            ));
+        }
+
+        let (header, sealed) = data.split_at(HEADER_LEN);
+        let mut salt = [0; SALT_LEN];
+        salt.copy_from_slice(&header[MAGIC.len()..MAGIC.len() + SALT_LEN]);
+        let mut nonce = [0; NONCE_LEN];
+        nonce.copy_from_slice(&header[MAGIC.len() + SALT_LEN..]);
+
+        let mut buffer = sealed.to_vec();
+
+        let plain = self.with_key(salt, false, |key| {
+            key.open_in_place(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(&header[..MAGIC.len() + SALT_LEN]),
+                &mut buffer,
+            )
+            .map(|plain| plain.to_vec())
+            .map_err(|_| Error::WrongPassphrase)
+        })?;
+
+        serde_json::from_slice(&plain).map_err(|error| store_error(STORE_NAME, error))
+    }
+
+    fn write(&self, entries: &Entries) -> Result<(), Error> {
+        if entries.is_empty() {
+            if self.path.exists() {
+                fs::remove_file(&self.path)?;
+                debug!("Removed empty vault {}", self.path.display());
+            }
+            return Ok(());
+        }
+
+        // Keep the salt of the vault, so the passphrase isn't asked again
+        let salt = match &*self.key.lock().unwrap() {
+            Some((salt, _)) if self.path.exists() => *salt,
+            _ => {
+                let mut salt = [0; SALT_LEN];
+                self.fill_random(&mut salt)?;
+                salt
+            }
+        };
+
+        let mut nonce = [0; NONCE_LEN];
+        self.fill_random(&mut nonce)?;
+
+        let mut data = Vec::with_capacity(HEADER_LEN);
+        data.extend_from_slice(MAGIC);
+        data.extend_from_slice(&salt);
+        data.extend_from_slice(&nonce);
+
+        let mut buffer =
+            serde_json::to_vec(entries).map_err(|error| store_error(STORE_NAME, error))?;
+
+        self.with_key(salt, !self.path.exists(), |key| {
+            key.seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(&data[..MAGIC.len() + SALT_LEN]),
+                &mut buffer,
+            )
+            .map_err(|_| store_error(STORE_NAME, "encryption failed"))
+        })?;
+
+        data.append(&mut buffer);
+
+        // Replace the vault in a single step, so an interrupted write doesn't lose it
+        let temp_path = self.path.with_extension("tmp");
+        let mut options = OpenOptions::new();
+        options.create(true).truncate(true).write(true);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+
+        let mut file = options.open(&temp_path)?;
+        file.write_all(&data)?;
+        file.sync_all()?;
+        fs::rename(&temp_path, &self.path)?;
+
+        debug!("Wrote vault {}", self.path.display());
+        Ok(())
+    }
+
+    fn fill_random(&self, buffer: &mut [u8]) -> Result<(), Error> {
+        self.rng
+            .fill(buffer)
+            .map_err(|_| store_error(STORE_NAME, "no random numbers available"))
+    }
+}
+
+impl SecretStore for VaultStore {
+    fn name(&self) -> &'static str {
+        STORE_NAME
+    }
+
+    fn get(&self, server: &str) -> Result<Option<Credentials>, Error> {
+        Ok(self.read()?.remove(server))
+    }
+
+    fn set(&self, server: &str, credentials: &Credentials) -> Result<(), Error> {
+        let mut entries = self.read()?;
+        entries.insert(server.to_string(), credentials.clone());
+        self.write(&entries)
+    }
+
+    fn delete(&self, server: &str) -> Result<(), Error> {
+        let mut entries = self.read()?;
+
+        if entries.remove(server).is_some() {
+            self.write(&entries)?;
+        }
+
+        Ok(())
+    }
+}
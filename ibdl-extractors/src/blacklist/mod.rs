@@ -5,7 +5,8 @@
 //! the global blacklist implements a filter to exclude from the download queue all posts with unwanted tags.
 //!
 //! ## Config file
-//! The global blacklist is created in `$XDG_CONFIG_HOME/imageboard-downloader/blacklist.toml`
+//! The global blacklist is created in `$XDG_CONFIG_HOME/imageboard-downloader/blacklist.toml`,
+//! next to the auth cache (so `IBDL_CACHE_DIR` moves it as well)
 //!
 //! The user can define the tags as follows
 //! ```toml
@@ -30,16 +31,16 @@
 //! With this, the user can input all tags that they do not want to download. In case a post has
 //! any of the tags set in the blacklist, it will be removed from the download queue.
 use ahash::AHashSet;
-use ibdl_common::directories::ProjectDirs;
 use ibdl_common::log::{debug, warn};
 use ibdl_common::post::extension::Extension;
 use ibdl_common::post::rating::Rating;
 use ibdl_common::post::tags::{Tag, TagType};
 use ibdl_common::post::Post;
 use ibdl_common::serde::{self, Deserialize, Serialize};
-use ibdl_common::tokio::fs::{create_dir_all, read_to_string, File};
+use ibdl_common::tokio::fs::{read_to_string, File};
 use ibdl_common::tokio::io::AsyncWriteExt;
 use ibdl_common::tokio::time::Instant;
+use ibdl_common::ImageBoards;
 use std::collections::HashMap;
 use std::path::Path;
 use toml::from_str;
@@ -67,13 +68,7 @@ impl GlobalBlacklist {
     /// Parses the blacklist config file and fills the struct. If the file does not exist (deleted
     /// or first run), it will be created.
     pub async fn get() -> Result<Self, ExtractorError> {
-        let cache_dir = ProjectDirs::from("com", "ferrahwolfeh", "imageboard-downloader").unwrap();
-
-        let cfold = cache_dir.config_dir();
-
-        if !cfold.exists() {
-            create_dir_all(cfold).await?;
-        }
+        let cfold = ImageBoards::auth_cache_dir()?;
 
         let dir = cfold.join(Path::new("blacklist.toml"));
 
@@ -175,7 +170,7 @@ impl BlacklistFilter {
             original_list.retain(|c| self.selected_ratings.binary_search(&c.rating).is_ok());
 
             let safe_counter = original_size - original_list.len();
-            debug!("Removed {} posts with non-selected ratings", safe_counter);
+            debug!("Removed {safe_counter} posts with non-selected ratings");
 
             removed += safe_counter as u64;
         }
@@ -198,12 +193,12 @@ impl BlacklistFilter {
                 });
             }
 
-            debug!("Blacklist removed {} posts", bp);
+            debug!("Blacklist removed {bp} posts");
             removed += bp as u64;
         }
 
         debug!("Filtering took {:?}", start.elapsed());
-        debug!("Removed total of {} posts", removed);
+        debug!("Removed total of {removed} posts");
 
         (removed, original_list)
     }
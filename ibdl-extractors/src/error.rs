@@ -62,3 +62,21 @@ pub enum ExtractorError {
         source: SendError<u64>,
     },
 }
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum QueryError {
+    #[error("Unclosed group in search query. Add a ')' after the last tag of the group")]
+    UnclosedGroup,
+
+    #[error("Found ')' without a matching '(' in search query")]
+    UnmatchedClose,
+
+    #[error("Invalid value for {key}: {value}")]
+    InvalidMetaValue { key: String, value: String },
+
+    #[error("{term} can only be used as a top level search term, outside of groups and ~ terms")]
+    NestedServerTerm { term: String },
+
+    #[error("Search query needs at least one tag outside of groups, ~ terms and rating:, ext:, id: or md5: filters to send to the server")]
+    NoServerTerm,
+}
@@ -16,6 +16,7 @@ bitflags! {
         const SinglePostFetch = 0b0000_0100;
         const PoolDownload = 0b0000_1000;
         const Auth = 0b0001_0000;
+        const FavoritesDownload = 0b0010_0000;
     }
 }
 
@@ -81,14 +82,41 @@ pub trait PostFetchAsync {
     ) -> JoinHandle<Result<u64, ExtractorError>>;
 }
 
+/// Name and ordered post list of a pool
+#[derive(Debug, Clone)]
+pub struct PoolInfo {
+    pub name: String,
+    pub post_ids: Vec<u64>,
+}
+
 pub trait PoolExtract {
+    /// Fetches the position of every post of the pool.
+    ///
+    /// The result is cached, so calling this before starting the extractor thread won't fetch the pool twice.
     fn fetch_pool_idxs(
         &mut self,
         pool_id: u32,
         limit: Option<u16>,
     ) -> impl Future<Output = Result<HashMap<u64, usize>, ExtractorError>> + Send;
 
-    fn parse_pool_ids(&self, raw_json: String) -> Result<Vec<u64>, ExtractorError>;
+    fn parse_pool(&self, raw_json: String) -> Result<PoolInfo, ExtractorError>;
+
+    /// Name of the pool, available after calling [`fetch_pool_idxs`](PoolExtract::fetch_pool_idxs)
+    fn pool_name(&self) -> Option<String>;
 
     fn setup_pool_download(&mut self, pool_id: Option<u32>, last_first: bool);
 }
+
+/// Capability to download the posts a user added to their favorites.
+pub trait FavoritesExtract {
+    /// Fetches the ids of the posts on `page` of the favorites of `user_id`, in the order the
+    /// imageboard lists them. Pages start at 0, and the ones past the last are empty.
+    fn fetch_favorite_ids(
+        &self,
+        user_id: u64,
+        page: u16,
+    ) -> impl Future<Output = Result<Vec<u64>, ExtractorError>> + Send;
+
+    /// Makes the extractor thread download the favorites of `user_id` instead of searching tags.
+    fn setup_favorites_download(&mut self, user_id: Option<u64>);
+}
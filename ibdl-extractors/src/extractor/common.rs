@@ -1,17 +1,61 @@
-use ibdl_common::log::debug;
+use ibdl_common::{log::debug, post::Post};
 use std::fmt::Display;
 
+use crate::extractor_config::ServerConfig;
+use crate::query::{PostFilter, Query};
+
 pub fn convert_tags_to_string<S>(tags: &[S]) -> (Vec<String>, String)
 where
     S: ToString + Display,
 {
-    let mut strvec: Vec<String> = Vec::with_capacity(tags.len());
-    for s in &mut strvec {
-        let s1 = (*s).to_string();
-        *s = s1;
-    }
-    let tag_string = strvec.join("+");
+    let strvec: Vec<String> = tags.iter().map(ToString::to_string).collect();
+    let tag_string = strvec.join(" ");
 
-    debug!("Tag List: {}", tag_string);
+    debug!("Tag List: {tag_string}");
     (strvec, tag_string)
 }
+
+/// Removes every post with an id equal to or lower than `last_seen`.
+///
+/// Returns `true` if anything was removed, meaning the following pages only have already seen posts.
+pub fn remove_seen_posts(posts: &mut Vec<Post>, last_seen: Option<u64>) -> bool {
+    let Some(last_id) = last_seen else {
+        return false;
+    };
+
+    let size = posts.len();
+    posts.retain(|post| post.id > last_id);
+
+    posts.len() < size
+}
+
+/// Parses `tags` as a [`Query`].
+///
+/// Tags that aren't a valid query are sent to the server as they are, like before queries
+/// existed. Frontends should check them with [`Query::from_tags`] first to report the error.
+pub fn parse_query<S: ToString>(tags: &[S]) -> Query {
+    Query::from_tags(tags).unwrap_or_else(|error| {
+        debug!("Invalid query, sending tags as they are: {error}");
+        Query::verbatim(tags)
+    })
+}
+
+/// Splits `query` into the tag string sent to the server of `config` and the filter for the posts
+/// it returns.
+///
+/// `reserved` tags of the server limit are left for terms added by the extractor itself.
+pub fn split_query(query: &Query, config: &ServerConfig, reserved: usize) -> (String, PostFilter) {
+    let limit = config
+        .tag_limit
+        .map(|limit| usize::from(limit).saturating_sub(reserved));
+
+    let (server_tags, filter) = query.split(limit);
+    let tag_string = server_tags.join(" ");
+
+    debug!("Server tags: {tag_string}");
+    if !filter.is_empty() {
+        debug!("Local filter: {filter:?}");
+    }
+
+    (tag_string, filter)
+}
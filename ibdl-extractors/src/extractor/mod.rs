@@ -65,6 +65,11 @@ pub trait Extractor {
     /// Forces the extractor to only map posts that have the specified extension
     fn force_extension(&mut self, extension: Extension) -> &mut Self;
 
+    /// Only map posts with an id higher than `post_id`, stopping the search once older posts are reached.
+    ///
+    /// The id filter is also sent to the server when it doesn't exceed the imageboard's tag limit.
+    fn newer_than(&mut self, post_id: u64) -> &mut Self;
+
     /// Pretty similar to `search`, but instead returns the raw post list instead of a [`PostQueue`](ibdl_common::post::PostQueue)
     fn get_post_list(
         &self,
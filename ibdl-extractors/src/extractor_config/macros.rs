@@ -1,6 +1,6 @@
 #[macro_export]
 macro_rules! server_config {
-    ($name:expr, $pretty_name:expr, $server:expr, $client:expr, $ext:expr, $base_url:expr, $post_url:expr, $post_list_url:expr, $pool_idx_url:expr, $max_post_limit:expr, $auth_url:expr, $image_url: expr) => {
+    ($name:expr, $pretty_name:expr, $server:expr, $client:expr, $ext:expr, $base_url:expr, $post_url:expr, $post_list_url:expr, $pool_idx_url:expr, $max_post_limit:expr, $auth_url:expr, $image_url: expr, $rate_limit: expr, $tag_limit: expr, $password_salt: expr) => {
         ServerConfig {
             name: String::from($name),
             pretty_name: String::from($pretty_name),
@@ -14,6 +14,10 @@ macro_rules! server_config {
             max_post_limit: $max_post_limit,
             auth_url: $auth_url,
             image_url: $image_url,
+            rate_limit: $rate_limit,
+            retry: RetryPolicy::default(),
+            tag_limit: $tag_limit,
+            password_salt: $password_salt,
         }
     };
 }
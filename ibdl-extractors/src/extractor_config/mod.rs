@@ -2,6 +2,8 @@ use crate::extractor::caps::ExtractorFeatures;
 use crate::extractor::Extractor;
 use crate::imageboards::prelude::*;
 use crate::server_config;
+use ibdl_common::reqwest::{Error as ReqwestError, RequestBuilder, Response};
+use ibdl_common::retry::{RateLimiter, RetryPolicy};
 use ibdl_common::serde;
 use ibdl_common::{
     serde::{Deserialize, Serialize},
@@ -57,7 +59,7 @@ pub mod macros;
 pub mod serialize;
 
 pub static DEFAULT_SERVERS: Lazy<HashMap<String, ServerConfig>> = Lazy::new(|| {
-    let mut hmap = HashMap::with_capacity(6);
+    let mut hmap = HashMap::with_capacity(7);
     hmap.insert(
         "danbooru".to_string(),
         server_config!(
@@ -72,6 +74,9 @@ pub static DEFAULT_SERVERS: Lazy<HashMap<String, ServerConfig>> = Lazy::new(|| {
             Some(String::from("https://danbooru.donmai.us/pools")),
             200,
             Some(String::from("https://danbooru.donmai.us/profile.json")),
+            None,
+            Some(10.0),
+            Some(2),
             None
         ),
     );
@@ -89,6 +94,9 @@ pub static DEFAULT_SERVERS: Lazy<HashMap<String, ServerConfig>> = Lazy::new(|| {
             Some(String::from("https://e621.net/pools")),
             320,
             Some(String::from("https://e621.net/users/")),
+            None,
+            Some(2.0),
+            Some(40),
             None
         ),
     );
@@ -105,8 +113,15 @@ pub static DEFAULT_SERVERS: Lazy<HashMap<String, ServerConfig>> = Lazy::new(|| {
                 "https://gelbooru.com/index.php?page=dapi&s=post&q=index&json=1"
             )),
             "https://gelbooru.com/index.php?page=dapi&s=post&q=index&json=1",
-            None,
+            Some(String::from(
+                "https://gelbooru.com/index.php?page=pool&s=show"
+            )),
             100,
+            Some(String::from(
+                "https://gelbooru.com/index.php?page=dapi&s=post&q=index&json=1"
+            )),
+            None,
+            None,
             None,
             None
         ),
@@ -124,8 +139,15 @@ pub static DEFAULT_SERVERS: Lazy<HashMap<String, ServerConfig>> = Lazy::new(|| {
                 "https://api.rule34.xxx/index.php?page=dapi&s=post&q=index&json=1"
             )),
             "https://api.rule34.xxx/index.php?page=dapi&s=post&q=index&json=1",
-            None,
+            Some(String::from(
+                "https://rule34.xxx/index.php?page=pool&s=show"
+            )),
             1000,
+            Some(String::from(
+                "https://api.rule34.xxx/index.php?page=dapi&s=post&q=index&json=1"
+            )),
+            None,
+            None,
             None,
             None
         ),
@@ -146,6 +168,9 @@ pub static DEFAULT_SERVERS: Lazy<HashMap<String, ServerConfig>> = Lazy::new(|| {
             None,
             1000,
             None,
+            None,
+            None,
+            None,
             None
         ),
     );
@@ -158,12 +183,35 @@ pub static DEFAULT_SERVERS: Lazy<HashMap<String, ServerConfig>> = Lazy::new(|| {
             DEFAULT_CLI_UA,
             DEFAULT_EXT_UA,
             "https://konachan.com",
-            None,
+            Some(String::from("https://konachan.com/post.json")),
             "https://konachan.com/post.json",
+            Some(String::from("https://konachan.com/pool/show.json")),
+            100,
+            Some(String::from("https://konachan.com/user.json")),
+            None,
             None,
+            Some(6),
+            Some(String::from("So-I-Heard-You-Like-Mupkids-?--{}--"))
+        ),
+    );
+    hmap.insert(
+        "yandere".to_string(),
+        server_config!(
+            "yandere",
+            "Yande.re",
+            ImageBoards::Moebooru,
+            DEFAULT_CLI_UA,
+            DEFAULT_EXT_UA,
+            "https://yande.re",
+            Some(String::from("https://yande.re/post.json")),
+            "https://yande.re/post.json",
+            Some(String::from("https://yande.re/pool/show.json")),
             100,
+            Some(String::from("https://yande.re/user.json")),
             None,
-            None
+            None,
+            Some(6),
+            Some(String::from("choujin-steiner--{}--"))
         ),
     );
     hmap
@@ -184,6 +232,23 @@ pub struct ServerConfig {
     pub max_post_limit: u16,
     pub auth_url: Option<String>,
     pub image_url: Option<String>,
+    /// Maximum number of API requests sent to the server every second
+    #[serde(default)]
+    pub rate_limit: Option<f32>,
+    /// How failed requests to the server are retried
+    #[serde(default)]
+    pub retry: RetryPolicy,
+    /// Maximum number of tags the server accepts in a single search
+    ///
+    /// Anything that doesn't fit is checked by the extractor instead.
+    #[serde(default)]
+    pub tag_limit: Option<u16>,
+    /// Text Moebooru servers hash the password of the account with, where `{}` stands for the
+    /// password
+    ///
+    /// Defaults to the one of Moebooru itself, `choujin-steiner--{}--`.
+    #[serde(default)]
+    pub password_salt: Option<String>,
 }
 
 impl ServerConfig {
@@ -197,6 +262,33 @@ impl ServerConfig {
             ImageBoards::Gelbooru | ImageBoards::GelbooruV0_2 => GelbooruExtractor::features(),
         }
     }
+
+    /// Returns the URL of the web page of the post with the given `id`.
+    #[must_use]
+    pub fn post_page_url(&self, id: u64) -> String {
+        let base_url = self.base_url.trim_end_matches('/');
+
+        match self.server {
+            ImageBoards::Danbooru | ImageBoards::E621 => self.post_url.as_ref().map_or_else(
+                || format!("{base_url}/posts/{id}"),
+                |url| format!("{url}{id}"),
+            ),
+            ImageBoards::Gelbooru | ImageBoards::GelbooruV0_2 => {
+                format!("{base_url}/index.php?page=post&s=view&id={id}")
+            }
+            ImageBoards::Moebooru => format!("{base_url}/post/show/{id}"),
+        }
+    }
+
+    /// Sends an API request to the server, keeping inside its [rate limit](Self::rate_limit) and
+    /// retrying it according to its [retry policy](Self::retry).
+    pub async fn send(&self, request: RequestBuilder) -> Result<Response, ReqwestError> {
+        let limiter = self
+            .rate_limit
+            .map(|limit| RateLimiter::for_server(&self.name, limit));
+
+        self.retry.send(request, limiter.as_deref()).await
+    }
 }
 
 impl Default for ServerConfig {
@@ -214,6 +306,10 @@ impl Default for ServerConfig {
             max_post_limit: 200,
             auth_url: Some(String::from("https://danbooru.donmai.us/profile.json")),
             image_url: None,
+            rate_limit: Some(10.0),
+            retry: RetryPolicy::default(),
+            tag_limit: Some(2),
+            password_salt: None,
         }
     }
 }
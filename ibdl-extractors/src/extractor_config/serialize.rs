@@ -1,5 +1,6 @@
 use ibdl_common::{
     log::debug,
+    retry::RetryPolicy,
     serde::{self, Deserialize},
     ImageBoards,
 };
@@ -31,6 +32,11 @@ struct Server {
     max_post_limit: u16,
     auth_url: Option<String>,
     image_url: Option<String>,
+    rate_limit: Option<f32>,
+    #[serde(default)]
+    retry: RetryPolicy,
+    tag_limit: Option<u16>,
+    password_salt: Option<String>,
 }
 
 pub fn read_server_cfg_file<S: std::hash::BuildHasher>(
@@ -62,9 +68,13 @@ pub fn read_server_cfg_file<S: std::hash::BuildHasher>(
             max_post_limit: data.max_post_limit,
             auth_url: data.auth_url,
             image_url: data.image_url,
+            rate_limit: data.rate_limit,
+            retry: data.retry,
+            tag_limit: data.tag_limit,
+            password_salt: data.password_salt,
         };
         smap.insert(id, config);
     }
 
-    debug!("Configured servers: {:?}", smap);
+    debug!("Configured servers: {smap:?}");
 }
@@ -5,18 +5,21 @@
 //! - Native blacklist (defined in user profile page)
 //!
 use self::models::DanbooruPost;
+use ahash::HashMap;
 
 use crate::auth::{AuthState, ImageboardConfig};
 use crate::extractor::caps::{Auth, ExtractorFeatures, SinglePostFetch};
+use crate::extractor::common::{parse_query, remove_seen_posts, split_query};
 use crate::extractor::Extractor;
 use crate::extractor_config::{ServerConfig, DEFAULT_SERVERS};
+use crate::query::{PostFilter, Query};
 use crate::{blacklist::BlacklistFilter, error::ExtractorError};
 use ibdl_common::post::extension::Extension;
 use ibdl_common::reqwest::Method;
 use ibdl_common::serde_json;
 use ibdl_common::tokio::time::{sleep, Instant};
 use ibdl_common::{
-    client, join_tags,
+    client,
     log::debug,
     post::{rating::Rating, Post, PostQueue},
     reqwest::Client,
@@ -27,10 +30,12 @@ use std::time::Duration;
 
 mod models;
 mod pool;
+mod ugoira;
 mod unsync;
 
 /// Main object to download posts
 #[derive(Debug, Clone)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct DanbooruExtractor {
     client: Client,
     tags: Vec<String>,
@@ -41,11 +46,16 @@ pub struct DanbooruExtractor {
     disable_blacklist: bool,
     total_removed: u64,
     map_videos: bool,
+    fetch_ugoira_frames: bool,
     excluded_tags: Vec<String>,
     selected_extension: Option<Extension>,
-    extra_tags: Vec<String>,
+    last_seen_id: Option<u64>,
+    query: Query,
+    filter: PostFilter,
     pool_id: Option<u32>,
     pool_last_items_first: bool,
+    pool_name: Option<String>,
+    pool_idxs: Option<HashMap<u64, usize>>,
     server_cfg: ServerConfig,
 }
 
@@ -64,27 +74,11 @@ impl Extractor for DanbooruExtractor {
         // Use common client for all connections with a set User-Agent
         let client = client!(config);
 
-        let mut strvec: Vec<String> = tags
-            .iter()
-            .map(|t| {
-                let st: String = t.to_string();
-                st
-            })
-            .collect();
+        let strvec: Vec<String> = tags.iter().map(ToString::to_string).collect();
+        debug!("Tag List: {strvec:?}");
 
-        let extra_tags = if strvec.len() > 2 {
-            strvec.split_off(1)
-        } else {
-            Vec::with_capacity(strvec.len().saturating_sub(2))
-        };
-
-        debug!("Tag List: {:?}", strvec);
-        if !extra_tags.is_empty() {
-            debug!("Extra tags: {:?}", extra_tags);
-        }
-
-        // Merge all tags in the URL format
-        let tag_string = join_tags!(strvec);
+        let query = parse_query(tags);
+        let (tag_string, filter) = split_query(&query, &config, 0);
 
         Self {
             client,
@@ -96,11 +90,16 @@ impl Extractor for DanbooruExtractor {
             disable_blacklist,
             total_removed: 0,
             map_videos,
+            fetch_ugoira_frames: false,
             excluded_tags: vec![],
             selected_extension: None,
-            extra_tags,
+            last_seen_id: None,
+            query,
+            filter,
             pool_id: None,
             pool_last_items_first: false,
+            pool_name: None,
+            pool_idxs: None,
             server_cfg: config,
         }
     }
@@ -118,27 +117,11 @@ impl Extractor for DanbooruExtractor {
         // Use common client for all connections with a set User-Agent
         let client = client!(config);
 
-        let mut strvec: Vec<String> = tags
-            .iter()
-            .map(|t| {
-                let st: String = t.to_string();
-                st
-            })
-            .collect();
-
-        let extra_tags = if strvec.len() > 2 {
-            strvec.split_off(1)
-        } else {
-            Vec::with_capacity(strvec.len().saturating_sub(2))
-        };
-
-        debug!("Tag List: {:?}", strvec);
-        if !extra_tags.is_empty() {
-            debug!("Extra tags: {:?}", extra_tags);
-        }
+        let strvec: Vec<String> = tags.iter().map(ToString::to_string).collect();
+        debug!("Tag List: {strvec:?}");
 
-        // Merge all tags in the URL format
-        let tag_string = join_tags!(strvec);
+        let query = parse_query(tags);
+        let (tag_string, filter) = split_query(&query, &config, 0);
 
         Self {
             client,
@@ -150,22 +133,30 @@ impl Extractor for DanbooruExtractor {
             disable_blacklist,
             total_removed: 0,
             map_videos,
+            fetch_ugoira_frames: false,
             excluded_tags: vec![],
             selected_extension: None,
-            extra_tags,
+            last_seen_id: None,
+            query,
+            filter,
             pool_id: None,
             pool_last_items_first: false,
+            pool_name: None,
+            pool_idxs: None,
             server_cfg: config,
         }
     }
 
     async fn search(&mut self, page: u16) -> Result<PostQueue, ExtractorError> {
         let mut posts = self.get_post_list(page, None).await?;
+        posts.retain(|post| self.filter.matches(post));
 
         if posts.is_empty() {
             return Err(ExtractorError::ZeroPosts);
         }
 
+        self.fetch_frame_delays(&mut posts).await;
+
         posts.sort();
         posts.reverse();
 
@@ -204,15 +195,19 @@ impl Extractor for DanbooruExtractor {
         loop {
             let position = start_page.map_or(page, |n| page + n);
 
-            debug!("Scanning page {}", position);
+            debug!("Scanning page {position}");
 
-            let posts = self.get_post_list(position, limit).await?;
+            let mut posts = self.get_post_list(position, limit).await?;
             let size = posts.len();
 
             if size == 0 {
                 break;
             }
 
+            let seen_reached = remove_seen_posts(&mut posts, self.last_seen_id);
+
+            posts.retain(|post| self.filter.matches(post));
+
             let mut list = if !self.disable_blacklist || !self.download_ratings.is_empty() {
                 let (removed, posts) = blacklist.filter(posts);
                 self.total_removed += removed;
@@ -221,6 +216,8 @@ impl Extractor for DanbooruExtractor {
                 posts
             };
 
+            self.fetch_frame_delays(&mut list).await;
+
             fvec.append(&mut list);
 
             if let Some(num) = limit {
@@ -229,6 +226,11 @@ impl Extractor for DanbooruExtractor {
                 }
             }
 
+            if seen_reached {
+                debug!("Reached already seen posts");
+                break;
+            }
+
             if page == 100 {
                 break;
             }
@@ -253,7 +255,7 @@ impl Extractor for DanbooruExtractor {
     }
 
     fn exclude_tags(&mut self, tags: &[String]) -> &mut Self {
-        self.excluded_tags = tags.to_vec();
+        self.excluded_tags.extend(tags.iter().cloned());
         self
     }
 
@@ -262,6 +264,16 @@ impl Extractor for DanbooruExtractor {
         self
     }
 
+    fn newer_than(&mut self, post_id: u64) -> &mut Self {
+        self.last_seen_id = Some(post_id);
+
+        // Leave room for the id filter in the tag limit
+        let (tag_string, filter) = split_query(&self.query, &self.server_cfg, 1);
+        self.tag_string = format!("{tag_string} id:>{post_id}").trim().to_string();
+        self.filter = filter;
+        self
+    }
+
     async fn get_post_list(
         &self,
         page: u16,
@@ -269,7 +281,7 @@ impl Extractor for DanbooruExtractor {
     ) -> Result<Vec<Post>, ExtractorError> {
         if self.server_cfg.post_list_url.is_none() {
             return Err(ExtractorError::UnsupportedOperation);
-        };
+        }
 
         let mut request = self
             .client
@@ -277,18 +289,18 @@ impl Extractor for DanbooruExtractor {
 
         // Fetch item list from page
         if self.auth_state.is_auth() {
-            debug!("[AUTH] Fetching posts from page {}", page);
+            debug!("[AUTH] Fetching posts from page {page}");
             request = request.basic_auth(&self.auth.username, Some(&self.auth.api_key));
         } else {
-            debug!("Fetching posts from page {}", page);
-        };
+            debug!("Fetching posts from page {page}");
+        }
 
         let page_post_count = {
-            limit.map_or(self.server_cfg.max_post_limit as u16, |count| {
-                if count < self.server_cfg.max_post_limit as u16 {
+            limit.map_or(self.server_cfg.max_post_limit, |count| {
+                if count < self.server_cfg.max_post_limit {
                     count
                 } else {
-                    self.server_cfg.max_post_limit as u16
+                    self.server_cfg.max_post_limit
                 }
             })
         };
@@ -299,7 +311,7 @@ impl Extractor for DanbooruExtractor {
             ("tags", &self.tag_string),
         ]);
 
-        let post_array = req.send().await?.text().await?;
+        let post_array = self.server_cfg.send(req).await?.text().await?;
 
         let start_point = Instant::now();
 
@@ -308,7 +320,8 @@ impl Extractor for DanbooruExtractor {
         let end_iter = start_point.elapsed();
 
         debug!("List size: {}", mtx.len());
-        debug!("Post mapping took {:?}", end_iter);
+        debug!("Post mapping took {end_iter:?}");
+
         Ok(mtx)
     }
 
@@ -336,6 +349,7 @@ impl Extractor for DanbooruExtractor {
                 extension: Extension::guess_format(&c.file_ext.unwrap()),
                 tags: tag_list,
                 rating,
+                frame_delays: None,
             }
         });
 
@@ -380,6 +394,11 @@ impl SinglePostFetch for DanbooruExtractor {
     fn map_post(&self, raw_json: String) -> Result<Post, ExtractorError> {
         let parsed_json: DanbooruPost = serde_json::from_str::<DanbooruPost>(raw_json.as_str())?;
 
+        // Deleted, missing or restricted posts don't have a file
+        if parsed_json.file_url.is_none() {
+            return Err(ExtractorError::ZeroPosts);
+        }
+
         let tag_list = parsed_json.map_tags();
 
         let rt = parsed_json.rating.unwrap();
@@ -397,6 +416,7 @@ impl SinglePostFetch for DanbooruExtractor {
             extension: Extension::guess_format(&parsed_json.file_ext.unwrap()),
             tags: tag_list,
             rating,
+            frame_delays: None,
         };
 
         Ok(post)
@@ -405,7 +425,7 @@ impl SinglePostFetch for DanbooruExtractor {
     async fn get_post(&mut self, post_id: u32) -> Result<Post, ExtractorError> {
         if self.server_cfg.post_url.is_none() {
             return Err(ExtractorError::UnsupportedOperation);
-        };
+        }
 
         let url = format!(
             "{}/{}.json",
@@ -415,24 +435,28 @@ impl SinglePostFetch for DanbooruExtractor {
 
         // Fetch item list from page
         let req = if self.auth_state.is_auth() {
-            debug!("[AUTH] Fetching post {}", post_id);
+            debug!("[AUTH] Fetching post {post_id}");
             self.client
                 .get(url)
                 .basic_auth(&self.auth.username, Some(&self.auth.api_key))
         } else {
-            debug!("Fetching post {}", post_id);
+            debug!("Fetching post {post_id}");
             self.client.get(url)
         };
 
-        let post_array = req.send().await?.text().await?;
+        let post_array = self.server_cfg.send(req).await?.text().await?;
 
         let start_point = Instant::now();
 
-        let mtx = self.map_post(post_array)?;
+        let mut mtx = self.map_post(post_array)?;
 
         let end_iter = start_point.elapsed();
 
-        debug!("Post mapping took {:?}", end_iter);
+        debug!("Post mapping took {end_iter:?}");
+
+        self.fetch_frame_delays(std::slice::from_mut(&mut mtx))
+            .await;
+
         Ok(mtx)
     }
 
@@ -6,6 +6,8 @@ use ibdl_common::{
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(crate = "self::serde")]
 pub struct DanbooruPoolList {
+    #[serde(default)]
+    pub name: String,
     pub post_ids: Vec<u64>,
 }
 
@@ -25,6 +27,28 @@ pub struct DanbooruPost {
     pub rating: Option<String>,
 }
 
+/// Post fetched with `only=media_metadata`.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "self::serde")]
+pub struct DanbooruPostMetadata {
+    pub media_metadata: Option<DanbooruMediaMetadata>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "self::serde")]
+pub struct DanbooruMediaMetadata {
+    #[serde(default)]
+    pub metadata: DanbooruFileMetadata,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(crate = "self::serde")]
+pub struct DanbooruFileMetadata {
+    /// Delay of every frame of an ugoira, in milliseconds
+    #[serde(rename = "Ugoira:FrameDelays")]
+    pub frame_delays: Option<Vec<u32>>,
+}
+
 impl DanbooruPost {
     pub fn map_tags(&self) -> Vec<Tag> {
         let mut tags = Vec::with_capacity(64);
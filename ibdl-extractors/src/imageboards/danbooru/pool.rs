@@ -6,7 +6,7 @@ use ibdl_common::{
 
 use super::{models::DanbooruPoolList, DanbooruExtractor};
 use crate::error::ExtractorError;
-use crate::extractor::caps::PoolExtract;
+use crate::extractor::caps::{PoolExtract, PoolInfo};
 
 impl PoolExtract for DanbooruExtractor {
     async fn fetch_pool_idxs(
@@ -14,9 +14,14 @@ impl PoolExtract for DanbooruExtractor {
         pool_id: u32,
         limit: Option<u16>,
     ) -> Result<HashMap<u64, usize>, ExtractorError> {
+        if let Some(idxs) = &self.pool_idxs {
+            debug!("Using cached post ids from pool {pool_id}");
+            return Ok(idxs.clone());
+        }
+
         if self.server_cfg.pool_idx_url.is_none() {
             return Err(ExtractorError::UnsupportedOperation);
-        };
+        }
 
         let url = format!(
             "{}/{}.json",
@@ -26,18 +31,19 @@ impl PoolExtract for DanbooruExtractor {
 
         // Fetch item list from page
         let req = if self.auth_state.is_auth() {
-            debug!("[AUTH] Fetching post ids from pool {}", pool_id);
+            debug!("[AUTH] Fetching post ids from pool {pool_id}");
             self.client
                 .get(url)
                 .basic_auth(&self.auth.username, Some(&self.auth.api_key))
         } else {
-            debug!("Fetching post ids from pool {}", pool_id);
+            debug!("Fetching post ids from pool {pool_id}");
             self.client.get(url)
         };
 
-        let post_array = req.send().await?.text().await?;
+        let post_array = self.server_cfg.send(req).await?.text().await?;
 
-        let mut mtx = self.parse_pool_ids(post_array)?;
+        let pool = self.parse_pool(post_array)?;
+        let mut mtx = pool.post_ids;
 
         if self.pool_last_items_first {
             mtx.reverse();
@@ -53,20 +59,33 @@ impl PoolExtract for DanbooruExtractor {
             .map(|(position, id)| (*id, position))
             .collect::<HashMap<u64, usize>>();
 
-        trace!("Pool post positions: {:#?}", position_map);
+        trace!("Pool post positions: {position_map:#?}");
         debug!("Pool size: {}", position_map.len());
+
+        self.pool_name = Some(pool.name.replace('_', " "));
+        self.pool_idxs = Some(position_map.clone());
+
         Ok(position_map)
     }
 
-    fn parse_pool_ids(&self, raw_json: String) -> Result<Vec<u64>, ExtractorError> {
+    fn parse_pool(&self, raw_json: String) -> Result<PoolInfo, ExtractorError> {
         let parsed_json: DanbooruPoolList =
             serde_json::from_str::<DanbooruPoolList>(raw_json.as_str())?;
 
-        Ok(parsed_json.post_ids)
+        Ok(PoolInfo {
+            name: parsed_json.name,
+            post_ids: parsed_json.post_ids,
+        })
+    }
+
+    fn pool_name(&self) -> Option<String> {
+        self.pool_name.clone()
     }
 
     fn setup_pool_download(&mut self, pool_id: Option<u32>, last_first: bool) {
         self.pool_id = pool_id;
         self.pool_last_items_first = last_first;
+        self.pool_name = None;
+        self.pool_idxs = None;
     }
 }
@@ -0,0 +1,64 @@
+use ibdl_common::{
+    log::debug,
+    post::{extension::Extension, Post},
+    serde_json,
+};
+
+use super::{models::DanbooruPostMetadata, DanbooruExtractor};
+use crate::error::ExtractorError;
+
+impl DanbooruExtractor {
+    /// Fetch the frame delays of ugoira posts, needed to convert them into animations.
+    ///
+    /// Danbooru only lists them in the metadata of each post, so this takes an extra request for
+    /// every ugoira that gets past the blacklist and rating filters. Off by default.
+    pub fn fetch_ugoira_frames(&mut self, fetch: bool) -> &mut Self {
+        self.fetch_ugoira_frames = fetch;
+        self
+    }
+
+    /// Fetches the frame delays of every ugoira post in `posts`, if enabled with
+    /// [`fetch_ugoira_frames`](Self::fetch_ugoira_frames).
+    ///
+    /// Posts whose delays can't be fetched are left without them.
+    pub(super) async fn fetch_frame_delays(&self, posts: &mut [Post]) {
+        if !self.fetch_ugoira_frames {
+            return;
+        }
+
+        for post in posts
+            .iter_mut()
+            .filter(|post| post.extension == Extension::Ugoira && post.frame_delays.is_none())
+        {
+            match self.frame_delays(post.id).await {
+                Ok(delays) => post.frame_delays = delays,
+                Err(error) => debug!("Failed to fetch frame delays of post {}: {error}", post.id),
+            }
+        }
+    }
+
+    async fn frame_delays(&self, post_id: u64) -> Result<Option<Vec<u32>>, ExtractorError> {
+        let Some(post_url) = &self.server_cfg.post_url else {
+            return Ok(None);
+        };
+
+        debug!("Fetching frame delays of post {post_id}");
+
+        let mut request = self
+            .client
+            .get(format!("{post_url}/{post_id}.json"))
+            .query(&[("only", "media_metadata")]);
+
+        if self.auth_state.is_auth() {
+            request = request.basic_auth(&self.auth.username, Some(&self.auth.api_key));
+        }
+
+        let body = self.server_cfg.send(request).await?.text().await?;
+        let parsed = serde_json::from_str::<DanbooruPostMetadata>(&body)?;
+
+        Ok(parsed
+            .media_metadata
+            .and_then(|media| media.metadata.frame_delays)
+            .filter(|delays| !delays.is_empty()))
+    }
+}
@@ -1,5 +1,8 @@
+use std::slice;
+
 use ahash::{HashMap, HashMapExt};
 use ibdl_common::{
+    events::{emit, Event},
     log::debug,
     post::Post,
     tokio::{
@@ -13,6 +16,7 @@ use super::DanbooruExtractor;
 use crate::extractor::caps::{
     AsyncFetch, PoolExtract, PostFetchAsync, PostFetchMethod, SinglePostFetch,
 };
+use crate::extractor::common::remove_seen_posts;
 use crate::extractor::Extractor;
 use crate::{blacklist::BlacklistFilter, error::ExtractorError};
 
@@ -39,12 +43,12 @@ impl AsyncFetch for ExtractorUnit {
         )
         .await?;
 
-        let mut pool_idxs = HashMap::with_capacity(512);
-
-        if let Some(p_id) = self.pool_id {
+        let pool_idxs = if let Some(p_id) = self.pool_id {
             self.tag_string = format!("pool:{p_id}");
-            pool_idxs = self.fetch_pool_idxs(p_id, limit).await?;
-        }
+            self.fetch_pool_idxs(p_id, limit).await?
+        } else {
+            HashMap::with_capacity(512)
+        };
 
         let mut has_posts: bool = false;
         let mut total_posts_sent: u16 = 0;
@@ -57,6 +61,12 @@ impl AsyncFetch for ExtractorUnit {
             let mut posts = self.get_post_list(position, limit).await?;
             let size = posts.len();
 
+            emit(&Event::PageFetched {
+                server: &self.server_cfg.name,
+                page: position,
+                posts: size,
+            });
+
             if size == 0 {
                 if !has_posts {
                     return Err(ExtractorError::ZeroPosts);
@@ -65,18 +75,23 @@ impl AsyncFetch for ExtractorUnit {
                 break;
             }
 
-            if !self.extra_tags.is_empty() {
-                posts.retain(|post| {
-                    post.tags
-                        .iter()
-                        .all(|tag| self.extra_tags.contains(&tag.tag()))
-                });
-            }
+            let seen_reached = remove_seen_posts(&mut posts, self.last_seen_id);
+
+            posts.retain(|post| self.filter.matches(post));
 
             let mut list = if self.disable_blacklist || self.download_ratings.is_empty() {
                 posts
             } else {
                 let (removed, posts) = blacklist.filter(posts);
+
+                if removed > 0 {
+                    emit(&Event::BlacklistRemoved {
+                        server: &self.server_cfg.name,
+                        page: position,
+                        removed,
+                    });
+                }
+
                 self.total_removed += removed;
                 posts
             };
@@ -92,6 +107,9 @@ impl AsyncFetch for ExtractorUnit {
                     }
                 }
 
+                // Only for posts that made it this far, since it takes a request per ugoira
+                self.fetch_frame_delays(slice::from_mut(i)).await;
+
                 if self.pool_id.is_some() {
                     if let Some(page_num) = pool_idxs.get(&i.id) {
                         i.id = *page_num as u64;
@@ -109,11 +127,16 @@ impl AsyncFetch for ExtractorUnit {
 
             if let Some(num) = limit {
                 if total_posts_sent >= num {
-                    debug!("Target post count of {} reached.", num);
+                    debug!("Target post count of {num} reached.");
                     break;
                 }
             }
 
+            if seen_reached {
+                debug!("Reached already seen posts");
+                break;
+            }
+
             if page == 100 {
                 debug!("Max number of pages reached");
                 break;
@@ -6,11 +6,13 @@
 //!
 use crate::auth::{AuthState, ImageboardConfig};
 use crate::extractor_config::{ServerConfig, DEFAULT_SERVERS};
+use crate::query::{PostFilter, Query};
+use ahash::HashMap;
 use ibdl_common::post::extension::Extension;
 use ibdl_common::reqwest::{Client, Method};
 use ibdl_common::serde_json;
 use ibdl_common::{
-    client, join_tags,
+    client,
     log::debug,
     post::{rating::Rating, Post, PostQueue},
     tokio, ImageBoards,
@@ -20,6 +22,7 @@ use std::time::Duration;
 use tokio::time::{sleep, Instant};
 
 use crate::extractor::caps::ExtractorFeatures;
+use crate::extractor::common::{parse_query, remove_seen_posts, split_query};
 use crate::extractor::Extractor;
 use crate::imageboards::e621::models::E621SinglePostTopLevel;
 use crate::prelude::{Auth, SinglePostFetch};
@@ -39,6 +42,8 @@ pub struct E621Extractor {
     client: Client,
     tags: Vec<String>,
     tag_string: String,
+    query: Query,
+    filter: PostFilter,
     auth_state: AuthState,
     auth: ImageboardConfig,
     download_ratings: Vec<Rating>,
@@ -47,8 +52,11 @@ pub struct E621Extractor {
     map_videos: bool,
     excluded_tags: Vec<String>,
     selected_extension: Option<Extension>,
+    last_seen_id: Option<u64>,
     pool_id: Option<u32>,
     pool_last_items_first: bool,
+    pool_name: Option<String>,
+    pool_idxs: Option<HashMap<u64, usize>>,
     server_cfg: ServerConfig,
 }
 
@@ -67,22 +75,18 @@ impl Extractor for E621Extractor {
         // Use common client for all connections with a set User-Agent
         let client = client!(config);
 
-        let strvec: Vec<String> = tags
-            .iter()
-            .map(|t| {
-                let st: String = t.to_string();
-                st
-            })
-            .collect();
+        let strvec: Vec<String> = tags.iter().map(ToString::to_string).collect();
+        debug!("Tag List: {strvec:?}");
 
-        // Merge all tags in the URL format
-        let tag_string = join_tags!(strvec);
-        debug!("Tag List: {}", tag_string);
+        let query = parse_query(tags);
+        let (tag_string, filter) = split_query(&query, &config, 0);
 
         Self {
             client,
             tags: strvec,
             tag_string,
+            query,
+            filter,
             auth_state: AuthState::NotAuthenticated,
             auth: ImageboardConfig::default(),
             download_ratings: download_ratings.to_vec(),
@@ -91,8 +95,11 @@ impl Extractor for E621Extractor {
             map_videos,
             excluded_tags: vec![],
             selected_extension: None,
+            last_seen_id: None,
             pool_id: None,
             pool_last_items_first: false,
+            pool_name: None,
+            pool_idxs: None,
             server_cfg: config,
         }
     }
@@ -110,22 +117,18 @@ impl Extractor for E621Extractor {
         // Use common client for all connections with a set User-Agent
         let client = client!(config);
 
-        let strvec: Vec<String> = tags
-            .iter()
-            .map(|t| {
-                let st: String = t.to_string();
-                st
-            })
-            .collect();
+        let strvec: Vec<String> = tags.iter().map(ToString::to_string).collect();
+        debug!("Tag List: {strvec:?}");
 
-        // Merge all tags in the URL format
-        let tag_string = join_tags!(strvec);
-        debug!("Tag List: {}", tag_string);
+        let query = parse_query(tags);
+        let (tag_string, filter) = split_query(&query, &config, 0);
 
         Self {
             client,
             tags: strvec,
             tag_string,
+            query,
+            filter,
             auth_state: AuthState::NotAuthenticated,
             auth: ImageboardConfig::default(),
             download_ratings: download_ratings.to_vec(),
@@ -134,14 +137,18 @@ impl Extractor for E621Extractor {
             map_videos,
             excluded_tags: vec![],
             selected_extension: None,
+            last_seen_id: None,
             pool_id: None,
             pool_last_items_first: false,
+            pool_name: None,
+            pool_idxs: None,
             server_cfg: config,
         }
     }
 
     async fn search(&mut self, page: u16) -> Result<PostQueue, ExtractorError> {
         let mut posts = self.get_post_list(page, None).await?;
+        posts.retain(|post| self.filter.matches(post));
 
         if posts.is_empty() {
             return Err(ExtractorError::ZeroPosts);
@@ -185,13 +192,17 @@ impl Extractor for E621Extractor {
         loop {
             let position = start_page.map_or(page, |n| page + n);
 
-            let posts = self.get_post_list(position, limit).await?;
+            let mut posts = self.get_post_list(position, limit).await?;
             let size = posts.len();
 
             if size == 0 {
                 break;
             }
 
+            let seen_reached = remove_seen_posts(&mut posts, self.last_seen_id);
+
+            posts.retain(|post| self.filter.matches(post));
+
             let mut list = if !self.disable_blacklist || !self.download_ratings.is_empty() {
                 let (removed, posts) = blacklist.filter(posts);
                 self.total_removed += removed;
@@ -208,6 +219,11 @@ impl Extractor for E621Extractor {
                 }
             }
 
+            if seen_reached {
+                debug!("Reached already seen posts");
+                break;
+            }
+
             if size < 320 || page == 100 {
                 break;
             }
@@ -237,7 +253,7 @@ impl Extractor for E621Extractor {
     }
 
     fn exclude_tags(&mut self, tags: &[String]) -> &mut Self {
-        self.excluded_tags = tags.to_vec();
+        self.excluded_tags.extend(tags.iter().cloned());
         self
     }
 
@@ -246,6 +262,16 @@ impl Extractor for E621Extractor {
         self
     }
 
+    fn newer_than(&mut self, post_id: u64) -> &mut Self {
+        self.last_seen_id = Some(post_id);
+
+        // Leave room for the id filter in the tag limit
+        let (tag_string, filter) = split_query(&self.query, &self.server_cfg, 1);
+        self.tag_string = format!("{tag_string} id:>{post_id}").trim().to_string();
+        self.filter = filter;
+        self
+    }
+
     async fn get_post_list(
         &self,
         page: u16,
@@ -253,7 +279,7 @@ impl Extractor for E621Extractor {
     ) -> Result<Vec<Post>, ExtractorError> {
         if self.server_cfg.post_list_url.is_none() {
             return Err(ExtractorError::UnsupportedOperation);
-        };
+        }
 
         let mut request = self
             .client
@@ -261,18 +287,18 @@ impl Extractor for E621Extractor {
 
         // Fetch item list from page
         if self.auth_state.is_auth() {
-            debug!("[AUTH] Fetching posts from page {}", page);
+            debug!("[AUTH] Fetching posts from page {page}");
             request = request.basic_auth(&self.auth.username, Some(&self.auth.api_key));
         } else {
-            debug!("Fetching posts from page {}", page);
-        };
+            debug!("Fetching posts from page {page}");
+        }
 
         let page_post_count = {
-            limit.map_or(self.server_cfg.max_post_limit as u16, |count| {
-                if count < self.server_cfg.max_post_limit as u16 {
+            limit.map_or(self.server_cfg.max_post_limit, |count| {
+                if count < self.server_cfg.max_post_limit {
                     count
                 } else {
-                    self.server_cfg.max_post_limit as u16
+                    self.server_cfg.max_post_limit
                 }
             })
         };
@@ -283,10 +309,10 @@ impl Extractor for E621Extractor {
             ("tags", &self.tag_string),
         ]);
 
-        let items = req.send().await?.text().await?;
+        let items = self.server_cfg.send(req).await?.text().await?;
 
         #[cfg(debug_assertions)]
-        debug!("{}", items);
+        debug!("{items}");
 
         let start_point = Instant::now();
 
@@ -317,6 +343,7 @@ impl Extractor for E621Extractor {
                 extension: Extension::guess_format(&c.file.ext.clone().unwrap()),
                 tags: tag_list,
                 rating: Rating::from_rating_str(&c.rating),
+                frame_delays: None,
             };
 
             post_list.push(unit);
@@ -376,6 +403,7 @@ impl SinglePostFetch for E621Extractor {
                 extension: Extension::guess_format(&c.post.file.ext.clone().unwrap()),
                 tags: tag_list,
                 rating: Rating::from_rating_str(&c.post.rating),
+                frame_delays: None,
             };
             Ok(unit)
         } else {
@@ -386,7 +414,7 @@ impl SinglePostFetch for E621Extractor {
     async fn get_post(&mut self, post_id: u32) -> Result<Post, ExtractorError> {
         if self.server_cfg.post_url.is_none() {
             return Err(ExtractorError::UnsupportedOperation);
-        };
+        }
 
         let url = format!(
             "{}/{}.json",
@@ -396,19 +424,19 @@ impl SinglePostFetch for E621Extractor {
 
         // Fetch item list from page
         let req = if self.auth_state.is_auth() {
-            debug!("[AUTH] Fetching post {}", post_id);
+            debug!("[AUTH] Fetching post {post_id}");
             self.client
                 .get(url)
                 .basic_auth(&self.auth.username, Some(&self.auth.api_key))
         } else {
-            debug!("Fetching post {}", post_id);
+            debug!("Fetching post {post_id}");
             self.client.get(url)
         };
 
-        let post_array = req.send().await?.text().await?;
+        let post_array = self.server_cfg.send(req).await?.text().await?;
 
         #[cfg(debug_assertions)]
-        debug!("{}", post_array);
+        debug!("{post_array}");
 
         let start_point = Instant::now();
 
@@ -416,7 +444,7 @@ impl SinglePostFetch for E621Extractor {
 
         let end_iter = start_point.elapsed();
 
-        debug!("Post mapping took {:?}", end_iter);
+        debug!("Post mapping took {end_iter:?}");
         Ok(mtx)
     }
 
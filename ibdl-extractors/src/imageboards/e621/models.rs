@@ -34,6 +34,7 @@ pub struct E621File {
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(crate = "self::serde")]
+#[allow(dead_code)]
 pub struct E621AuthUser {
     pub success: Option<bool>,
     pub message: Option<String>,
@@ -80,5 +81,7 @@ impl Tags {
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(crate = "self::serde")]
 pub struct E621PoolList {
+    #[serde(default)]
+    pub name: String,
     pub post_ids: Vec<u64>,
 }
@@ -1,5 +1,6 @@
 use ahash::{HashMap, HashMapExt};
 use ibdl_common::{
+    events::{emit, Event},
     log::debug,
     post::Post,
     tokio::{
@@ -13,6 +14,7 @@ use std::time::Duration;
 
 use super::E621Extractor;
 use crate::extractor::caps::PostFetchMethod;
+use crate::extractor::common::remove_seen_posts;
 use crate::extractor::Extractor;
 use crate::prelude::{AsyncFetch, PoolExtract, PostFetchAsync, SinglePostFetch};
 use crate::{blacklist::BlacklistFilter, error::ExtractorError};
@@ -38,12 +40,12 @@ impl AsyncFetch for ExtractorUnit {
         )
         .await?;
 
-        let mut pool_idxs = HashMap::with_capacity(512);
-
-        if let Some(p_id) = self.pool_id {
+        let pool_idxs = if let Some(p_id) = self.pool_id {
             self.tag_string = format!("pool:{p_id}");
-            pool_idxs = self.fetch_pool_idxs(p_id, limit).await?;
-        }
+            self.fetch_pool_idxs(p_id, limit).await?
+        } else {
+            HashMap::with_capacity(512)
+        };
 
         let mut has_posts: bool = false;
         let mut total_posts_sent: u16 = 0;
@@ -55,9 +57,15 @@ impl AsyncFetch for ExtractorUnit {
         loop {
             let position = start_page.map_or(page, |n| page + n);
 
-            let posts = self.get_post_list(position, limit).await?;
+            let mut posts = self.get_post_list(position, limit).await?;
             let size = posts.len();
 
+            emit(&Event::PageFetched {
+                server: &self.server_cfg.name,
+                page: position,
+                posts: size,
+            });
+
             if size == 0 {
                 if !has_posts {
                     return Err(ExtractorError::ZeroPosts);
@@ -66,8 +74,21 @@ impl AsyncFetch for ExtractorUnit {
                 break;
             }
 
+            let seen_reached = remove_seen_posts(&mut posts, self.last_seen_id);
+
+            posts.retain(|post| self.filter.matches(post));
+
             let mut list = if !self.disable_blacklist || !self.download_ratings.is_empty() {
                 let (removed, posts) = blacklist.filter(posts);
+
+                if removed > 0 {
+                    emit(&Event::BlacklistRemoved {
+                        server: &self.server_cfg.name,
+                        page: position,
+                        removed,
+                    });
+                }
+
                 self.total_removed += removed;
                 posts
             } else {
@@ -102,11 +123,16 @@ impl AsyncFetch for ExtractorUnit {
 
             if let Some(num) = limit {
                 if total_posts_sent >= num {
-                    debug!("Target post count of {} reached.", num);
+                    debug!("Target post count of {num} reached.");
                     break;
                 }
             }
 
+            if seen_reached {
+                debug!("Reached already seen posts");
+                break;
+            }
+
             if page == 100 {
                 break;
             }
@@ -66,7 +66,7 @@ impl Extractor for GelbooruV0_2Extractor {
 
         // Merge all tags in the URL format
         let tag_string = join_tags!(strvec);
-        debug!("Tag List: {}", tag_string);
+        debug!("Tag List: {tag_string}");
 
         Self {
             client,
@@ -108,7 +108,7 @@ impl Extractor for GelbooruV0_2Extractor {
 
         // Merge all tags in the URL format
         let tag_string = join_tags!(strvec);
-        debug!("Tag List: {}", tag_string);
+        debug!("Tag List: {tag_string}");
 
         Self {
             client,
@@ -222,7 +222,7 @@ impl Extractor for GelbooruV0_2Extractor {
     }
 
     fn exclude_tags(&mut self, tags: &[String]) -> &mut Self {
-        self.excluded_tags = tags.to_vec();
+        self.excluded_tags.extend(tags.iter().cloned());
         self
     }
 
@@ -231,6 +231,13 @@ impl Extractor for GelbooruV0_2Extractor {
         self
     }
 
+    fn newer_than(&mut self, post_id: u64) -> &mut Self {
+        self.tag_string = format!("{} id:>{post_id}", self.tag_string)
+            .trim()
+            .to_string();
+        self
+    }
+
     async fn get_post_list(
         &self,
         page: u16,
@@ -238,7 +245,7 @@ impl Extractor for GelbooruV0_2Extractor {
     ) -> Result<Vec<Post>, ExtractorError> {
         if self.server_cfg.post_list_url.is_none() {
             return Err(ExtractorError::UnsupportedOperation);
-        };
+        }
 
         let page_post_count = {
             limit.map_or(self.server_cfg.max_post_limit, |count| {
@@ -250,21 +257,19 @@ impl Extractor for GelbooruV0_2Extractor {
             })
         };
 
-        let items = self
+        let request = self
             .client
             .get(self.server_cfg.post_list_url.as_ref().unwrap())
             .query(&[
                 ("tags", &self.tag_string),
                 ("pid", &page.to_string()),
                 ("limit", &page_post_count.to_string()),
-            ])
-            .send()
-            .await?
-            .text()
-            .await?;
+            ]);
+
+        let items = self.server_cfg.send(request).await?.text().await?;
 
         #[cfg(debug_assertions)]
-        debug!("{}", items);
+        debug!("{items}");
 
         self.map_posts(items)
     }
@@ -317,6 +322,7 @@ impl Extractor for GelbooruV0_2Extractor {
                     extension: Extension::guess_format(&ext),
                     rating,
                     tags,
+                    frame_delays: None,
                 };
 
                 post_mtx.push(unit);
@@ -1,6 +1,7 @@
 use std::time::Duration;
 
 use ibdl_common::{
+    events::{emit, Event},
     log::debug,
     post::Post,
     tokio::{
@@ -13,7 +14,7 @@ use ibdl_common::{
 
 use super::GelbooruV0_2Extractor;
 use crate::extractor::Extractor;
-use crate::prelude::{AsyncFetch};
+use crate::prelude::AsyncFetch;
 use crate::{blacklist::BlacklistFilter, error::ExtractorError};
 
 // A quick alias so I can copy-paste stuff faster
@@ -50,6 +51,12 @@ impl AsyncFetch for ExtractorUnit {
             let posts = self.get_post_list(position, limit).await?;
             let size = posts.len();
 
+            emit(&Event::PageFetched {
+                server: &self.server_cfg.name,
+                page: position + 1,
+                posts: size,
+            });
+
             if size == 0 {
                 if !has_posts {
                     return Err(ExtractorError::ZeroPosts);
@@ -60,6 +67,15 @@ impl AsyncFetch for ExtractorUnit {
 
             let list = if !self.disable_blacklist || !self.download_ratings.is_empty() {
                 let (removed, posts) = blacklist.filter(posts);
+
+                if removed > 0 {
+                    emit(&Event::BlacklistRemoved {
+                        server: &self.server_cfg.name,
+                        page: position + 1,
+                        removed,
+                    });
+                }
+
                 self.total_removed += removed;
                 posts
             } else {
@@ -86,7 +102,7 @@ impl AsyncFetch for ExtractorUnit {
 
             if let Some(num) = limit {
                 if total_posts_sent >= num {
-                    debug!("Target post count of {} reached.", num);
+                    debug!("Target post count of {num} reached.");
                     break;
                 }
             }
@@ -120,4 +136,4 @@ impl AsyncFetch for ExtractorUnit {
                 .await
         })
     }
-}
\ No newline at end of file
+}
@@ -5,14 +5,23 @@
 //! * `Imageboards::Realbooru`
 //! * `Imageboards::Gelbooru`
 //!
+//! # Authentication
+//! Gelbooru and Rule34 ask for the `api_key` and `user_id` shown in the account options in every
+//! DAPI request. Once [`Auth::auth`] is called they're added to all of them. Both sites keep the
+//! account blacklist in the browser only, so the local blacklist is the one that applies.
 //!
+//! # Pools and Favorites
+//! DAPI has no endpoints for pools or favorites, so their post ids are scraped from the pool page
+//! at the `pool_idx_url` and from the favorites pages of the user. Every post is then fetched on
+//! its own through DAPI.
 
 // NOTE: https://gelbooru.com/index.php?page=dapi&s=tag&q=index&json=1&names=folinic_(arknights)%20arknights%20ru_zhai%20highres%20black_hair
 // This is to search all tags and their meanings.
 // I've to do an enum based on this thing.
 
+use ahash::HashMap;
 use ibdl_common::post::extension::Extension;
-use ibdl_common::reqwest::Client;
+use ibdl_common::reqwest::{Client, RequestBuilder};
 use ibdl_common::serde_json::{self};
 use ibdl_common::tokio::time::{sleep, Instant};
 use ibdl_common::{
@@ -24,31 +33,46 @@ use ibdl_common::{
 use std::fmt::Display;
 use std::time::Duration;
 
-use crate::extractor::caps::ExtractorFeatures;
-use crate::extractor::common::convert_tags_to_string;
+use crate::auth::{AuthState, ImageboardConfig};
+use crate::extractor::caps::{Auth, ExtractorFeatures};
+use crate::extractor::common::{
+    convert_tags_to_string, parse_query, remove_seen_posts, split_query,
+};
 use crate::extractor::Extractor;
 use crate::extractor_config::{ServerConfig, DEFAULT_SERVERS};
 use crate::imageboards::gelbooru::models::GelbooruTopLevel;
 use crate::prelude::SinglePostFetch;
+use crate::query::{PostFilter, Query};
 use crate::{blacklist::BlacklistFilter, error::ExtractorError};
 
+#[allow(dead_code)]
 mod gelbooru_old;
 mod models;
+mod pool;
+mod scrape;
 mod unsync;
 
 pub struct GelbooruExtractor {
     client: Client,
     tags: Vec<String>,
     tag_string: String,
+    query: Query,
+    filter: PostFilter,
     disable_blacklist: bool,
     total_removed: u64,
     download_ratings: Vec<Rating>,
     map_videos: bool,
     excluded_tags: Vec<String>,
     selected_extension: Option<Extension>,
+    last_seen_id: Option<u64>,
     server_cfg: ServerConfig,
-    // auth: ImageboardConfig,
-    // auth_state: AuthState
+    auth: ImageboardConfig,
+    auth_state: AuthState,
+    pool_id: Option<u32>,
+    pool_last_items_first: bool,
+    pool_name: Option<String>,
+    pool_idxs: Option<HashMap<u64, usize>>,
+    favorites_user: Option<u64>,
 }
 
 impl Extractor for GelbooruExtractor {
@@ -69,21 +93,31 @@ impl Extractor for GelbooruExtractor {
             .build()
             .unwrap();
 
-        let (string_vec, tag_string) = convert_tags_to_string(tags);
+        let (string_vec, _) = convert_tags_to_string(tags);
+        let query = parse_query(tags);
+        let (tag_string, filter) = split_query(&query, &config, 0);
 
         Self {
             client,
             tags: string_vec,
             tag_string,
+            query,
+            filter,
             disable_blacklist,
             total_removed: 0,
             download_ratings: download_ratings.to_vec(),
             map_videos,
             excluded_tags: vec![],
             selected_extension: None,
+            last_seen_id: None,
             server_cfg: config,
-            // auth_state: AuthState::NotAuthenticated,
-            // auth: ImageboardConfig::default()
+            auth: ImageboardConfig::default(),
+            auth_state: AuthState::NotAuthenticated,
+            pool_id: None,
+            pool_last_items_first: false,
+            pool_name: None,
+            pool_idxs: None,
+            favorites_user: None,
         }
     }
 
@@ -103,26 +137,37 @@ impl Extractor for GelbooruExtractor {
             .build()
             .unwrap();
 
-        let (strvec, tag_string) = convert_tags_to_string(tags);
+        let (strvec, _) = convert_tags_to_string(tags);
+        let query = parse_query(tags);
+        let (tag_string, filter) = split_query(&query, &config, 0);
 
         Self {
             client,
             tags: strvec,
             tag_string,
+            query,
+            filter,
             disable_blacklist,
             total_removed: 0,
             download_ratings: download_ratings.to_vec(),
             map_videos,
             excluded_tags: vec![],
             selected_extension: None,
+            last_seen_id: None,
             server_cfg: config,
-            // auth_state: AuthState::NotAuthenticated,
-            // auth: ImageboardConfig::default()
+            auth: ImageboardConfig::default(),
+            auth_state: AuthState::NotAuthenticated,
+            pool_id: None,
+            pool_last_items_first: false,
+            pool_name: None,
+            pool_idxs: None,
+            favorites_user: None,
         }
     }
 
     async fn search(&mut self, page: u16) -> Result<PostQueue, ExtractorError> {
         let mut posts = self.get_post_list(page, None).await?;
+        posts.retain(|post| self.filter.matches(post));
 
         if posts.is_empty() {
             return Err(ExtractorError::ZeroPosts);
@@ -148,7 +193,7 @@ impl Extractor for GelbooruExtractor {
     ) -> Result<PostQueue, ExtractorError> {
         let blacklist = BlacklistFilter::new(
             self.server_cfg.clone(),
-            &Vec::default(),
+            &self.excluded_tags,
             &self.download_ratings,
             self.disable_blacklist,
             !self.map_videos,
@@ -167,13 +212,17 @@ impl Extractor for GelbooruExtractor {
         loop {
             let position = start_page.map_or(page - 1, |n| page + n - 1);
 
-            let posts = self.get_post_list(position, limit).await?;
+            let mut posts = self.get_post_list(position, limit).await?;
             let size = posts.len();
 
             if size == 0 {
                 break;
             }
 
+            let seen_reached = remove_seen_posts(&mut posts, self.last_seen_id);
+
+            posts.retain(|post| self.filter.matches(post));
+
             let mut list = if !self.disable_blacklist || !self.download_ratings.is_empty() {
                 let (removed, posts) = blacklist.filter(posts);
                 self.total_removed += removed;
@@ -190,6 +239,11 @@ impl Extractor for GelbooruExtractor {
                 }
             }
 
+            if seen_reached {
+                debug!("Reached already seen posts");
+                break;
+            }
+
             if size < self.server_cfg.max_post_limit as usize || page == 100 {
                 break;
             }
@@ -219,7 +273,7 @@ impl Extractor for GelbooruExtractor {
     }
 
     fn exclude_tags(&mut self, tags: &[String]) -> &mut Self {
-        self.excluded_tags = tags.to_vec();
+        self.excluded_tags.extend(tags.iter().cloned());
         self
     }
 
@@ -228,6 +282,16 @@ impl Extractor for GelbooruExtractor {
         self
     }
 
+    fn newer_than(&mut self, post_id: u64) -> &mut Self {
+        self.last_seen_id = Some(post_id);
+
+        // Leave room for the id filter in the tag limit
+        let (tag_string, filter) = split_query(&self.query, &self.server_cfg, 1);
+        self.tag_string = format!("{tag_string} id:>{post_id}").trim().to_string();
+        self.filter = filter;
+        self
+    }
+
     async fn get_post_list(
         &self,
         page: u16,
@@ -235,7 +299,7 @@ impl Extractor for GelbooruExtractor {
     ) -> Result<Vec<Post>, ExtractorError> {
         if self.server_cfg.post_list_url.is_none() {
             return Err(ExtractorError::UnsupportedOperation);
-        };
+        }
 
         let page_post_count = {
             limit.map_or(self.server_cfg.max_post_limit, |count| {
@@ -247,31 +311,35 @@ impl Extractor for GelbooruExtractor {
             })
         };
 
-        let items = self
-            .client
-            .get(self.server_cfg.post_list_url.as_ref().unwrap())
-            .query(&[
-                ("tags", &self.tag_string),
-                ("pid", &page.to_string()),
-                ("limit", &page_post_count.to_string()),
-            ])
-            .send()
-            .await?
-            .text()
-            .await?;
+        let request = self.authorize(
+            self.client
+                .get(self.server_cfg.post_list_url.as_ref().unwrap())
+                .query(&[
+                    ("tags", &self.tag_string),
+                    ("pid", &page.to_string()),
+                    ("limit", &page_post_count.to_string()),
+                ]),
+        );
+
+        let items = self.server_cfg.send(request).await?.text().await?;
 
         #[cfg(debug_assertions)]
-        debug!("{}", items);
+        debug!("{items}");
 
         self.map_posts(items)
     }
 
     fn map_posts(&self, raw_json: String) -> Result<Vec<Post>, ExtractorError> {
+        // Rule34 answers pages past the last one with nothing at all
+        if raw_json.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+
         let parsed_json: GelbooruTopLevel =
             serde_json::from_str::<GelbooruTopLevel>(raw_json.as_str())?;
 
         let batch = parsed_json
-            .post
+            .posts()
             .into_iter()
             .filter(|c| c.file_url.is_some());
 
@@ -292,6 +360,7 @@ impl Extractor for GelbooruExtractor {
                 extension: Extension::guess_format(&extension),
                 tags: tag_list,
                 rating,
+                frame_delays: None,
             }
         });
 
@@ -311,7 +380,7 @@ impl Extractor for GelbooruExtractor {
     }
 
     fn features() -> ExtractorFeatures {
-        ExtractorFeatures::from_bits_truncate(0b0000_0111) // AsyncFetch + TagSearch + SinglePostFetch
+        ExtractorFeatures::from_bits_truncate(0b0011_1111) // AsyncFetch + TagSearch + SinglePostFetch + PoolDownload + Auth + FavoritesDownload
     }
 
     fn config(&self) -> ServerConfig {
@@ -319,18 +388,33 @@ impl Extractor for GelbooruExtractor {
     }
 }
 
-// impl Auth for GelbooruExtractor {
-//     async fn auth(&mut self, config: ImageboardConfig) -> Result<(), ExtractorError> {
-//         let mut cfg = config;
-//
-//         self.excluded_tags
-//             .append(&mut cfg.user_data.blacklisted_tags);
-//
-//         self.auth = cfg;
-//         self.auth_state = AuthState::Authenticated;
-//         Ok(())
-//     }
-// }
+impl GelbooruExtractor {
+    /// Adds the credentials of the user to a DAPI request, if logged in.
+    fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        if self.auth_state.is_auth() {
+            debug!("[AUTH] Sending DAPI request as user {}", self.auth.username);
+            request.query(&[
+                ("api_key", &self.auth.api_key),
+                ("user_id", &self.auth.username),
+            ])
+        } else {
+            request
+        }
+    }
+}
+
+impl Auth for GelbooruExtractor {
+    async fn auth(&mut self, config: ImageboardConfig) -> Result<(), ExtractorError> {
+        let mut cfg = config;
+
+        self.excluded_tags
+            .append(&mut cfg.user_data.blacklisted_tags);
+
+        self.auth = cfg;
+        self.auth_state = AuthState::Authenticated;
+        Ok(())
+    }
+}
 
 impl SinglePostFetch for GelbooruExtractor {
     fn map_post(&self, _raw_json: String) -> Result<Post, ExtractorError> {
@@ -340,15 +424,15 @@ impl SinglePostFetch for GelbooruExtractor {
     async fn get_post(&mut self, post_id: u32) -> Result<Post, ExtractorError> {
         if self.server_cfg.post_url.is_none() {
             return Err(ExtractorError::UnsupportedOperation);
-        };
+        }
 
-        let url = format!(
-            "{}/{}.json",
-            self.server_cfg.post_url.as_ref().unwrap(),
-            post_id
+        let request = self.authorize(
+            self.client
+                .get(self.server_cfg.post_url.as_ref().unwrap())
+                .query(&[("id", post_id)]),
         );
 
-        let items = self.client.get(&url).send().await?.text().await?;
+        let items = self.server_cfg.send(request).await?.text().await?;
 
         let start_point = Instant::now();
 
@@ -359,7 +443,7 @@ impl SinglePostFetch for GelbooruExtractor {
             |post| {
                 let end_iter = start_point.elapsed();
 
-                debug!("Post mapping took {:?}", end_iter);
+                debug!("Post mapping took {end_iter:?}");
                 Ok(post.clone())
             },
         )
@@ -3,16 +3,32 @@ use ibdl_common::{
     serde::{self, Deserialize, Serialize},
 };
 
+/// Post list of the DAPI. Gelbooru wraps the posts in an object, while Rule34 sends them as they
+/// are.
 #[derive(Serialize, Deserialize, Debug)]
-#[serde(crate = "self::serde")]
-pub struct GelbooruTopLevel {
-    pub post: Vec<GelbooruPost>,
+#[serde(crate = "self::serde", untagged)]
+pub enum GelbooruTopLevel {
+    Wrapped {
+        /// Missing when there are no posts in the page
+        #[serde(default)]
+        post: Vec<GelbooruPost>,
+    },
+    List(Vec<GelbooruPost>),
+}
+
+impl GelbooruTopLevel {
+    pub fn posts(self) -> Vec<GelbooruPost> {
+        match self {
+            Self::Wrapped { post } | Self::List(post) => post,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(crate = "self::serde")]
 pub struct GelbooruPost {
     pub id: Option<u64>,
+    #[serde(alias = "hash")]
     pub md5: Option<String>,
     pub file_url: Option<String>,
     pub tags: Option<String>,
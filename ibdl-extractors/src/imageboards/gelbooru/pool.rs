@@ -0,0 +1,129 @@
+use ahash::HashMap;
+use ibdl_common::log::{debug, trace};
+
+use super::{scrape, GelbooruExtractor};
+use crate::error::ExtractorError;
+use crate::extractor::caps::{FavoritesExtract, PoolExtract, PoolInfo};
+
+/// Posts shown in every page of the favorites.
+const FAVORITES_PAGE_SIZE: u16 = 50;
+
+impl PoolExtract for GelbooruExtractor {
+    async fn fetch_pool_idxs(
+        &mut self,
+        pool_id: u32,
+        limit: Option<u16>,
+    ) -> Result<HashMap<u64, usize>, ExtractorError> {
+        if let Some(idxs) = &self.pool_idxs {
+            debug!("Using cached post ids from pool {pool_id}");
+            return Ok(idxs.clone());
+        }
+
+        if self.server_cfg.pool_idx_url.is_none() {
+            return Err(ExtractorError::UnsupportedOperation);
+        }
+
+        debug!("Scraping post ids from pool {pool_id}");
+
+        // There's no API for pools, so the ids come from the pool page
+        let req = self
+            .client
+            .get(self.server_cfg.pool_idx_url.as_ref().unwrap())
+            .query(&[("id", pool_id)]);
+
+        let page = self
+            .server_cfg
+            .send(req)
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+
+        let pool = self.parse_pool(page)?;
+        let mut mtx = pool.post_ids;
+
+        if mtx.is_empty() {
+            return Err(ExtractorError::ZeroPosts);
+        }
+
+        if self.pool_last_items_first {
+            mtx.reverse();
+        }
+
+        if let Some(limit_post) = limit {
+            mtx.truncate(limit_post as usize);
+        }
+
+        let position_map = mtx
+            .iter()
+            .enumerate()
+            .map(|(position, id)| (*id, position))
+            .collect::<HashMap<u64, usize>>();
+
+        trace!("Pool post positions: {position_map:#?}");
+        debug!("Pool size: {}", position_map.len());
+
+        self.pool_name = Some(pool.name.replace('_', " ")).filter(|name| !name.is_empty());
+        self.pool_idxs = Some(position_map.clone());
+
+        Ok(position_map)
+    }
+
+    /// Reads the name and posts of the pool from the HTML of its page.
+    fn parse_pool(&self, raw_json: String) -> Result<PoolInfo, ExtractorError> {
+        Ok(PoolInfo {
+            name: scrape::pool_name(&raw_json).unwrap_or_default(),
+            post_ids: scrape::post_ids(&raw_json),
+        })
+    }
+
+    fn pool_name(&self) -> Option<String> {
+        self.pool_name.clone()
+    }
+
+    fn setup_pool_download(&mut self, pool_id: Option<u32>, last_first: bool) {
+        self.pool_id = pool_id;
+        self.pool_last_items_first = last_first;
+        self.pool_name = None;
+        self.pool_idxs = None;
+    }
+}
+
+impl FavoritesExtract for GelbooruExtractor {
+    async fn fetch_favorite_ids(
+        &self,
+        user_id: u64,
+        page: u16,
+    ) -> Result<Vec<u64>, ExtractorError> {
+        let url = format!(
+            "{}/index.php",
+            self.server_cfg.base_url.trim_end_matches('/')
+        );
+
+        debug!("Scraping page {page} of the favorites of user {user_id}");
+
+        let req = self.client.get(url).query(&[
+            ("page", "favorites"),
+            ("s", "view"),
+            ("id", &user_id.to_string()),
+            (
+                "pid",
+                &(u32::from(page) * u32::from(FAVORITES_PAGE_SIZE)).to_string(),
+            ),
+        ]);
+
+        let page = self
+            .server_cfg
+            .send(req)
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+
+        Ok(scrape::post_ids(&page))
+    }
+
+    fn setup_favorites_download(&mut self, user_id: Option<u64>) {
+        self.favorites_user = user_id;
+    }
+}
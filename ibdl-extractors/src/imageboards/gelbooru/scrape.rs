@@ -0,0 +1,64 @@
+//! Scraping of the pages Gelbooru-based sites have no API for, like pools and favorites.
+
+/// Markers that come right before the id of a post in the thumbnails of a page: the id of the
+/// thumbnail link, or the link itself. Links to other pages, like the ones of the paginator, also
+/// have an `id`, so only post links count.
+const POST_ID_MARKERS: [&str; 3] = [
+    "id=\"p",
+    "page=post&amp;s=view&amp;id=",
+    "page=post&s=view&id=",
+];
+
+/// Ids of the posts in the thumbnails of `html`, in the order they show up.
+pub fn post_ids(html: &str) -> Vec<u64> {
+    let mut found = POST_ID_MARKERS
+        .iter()
+        .flat_map(|marker| {
+            html.match_indices(marker).filter_map(|(start, marker)| {
+                let rest = &html[start + marker.len()..];
+                let end = rest
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(rest.len());
+
+                rest[..end].parse::<u64>().ok().map(|id| (start, id))
+            })
+        })
+        .collect::<Vec<_>>();
+
+    found.sort_unstable();
+
+    let mut ids: Vec<u64> = Vec::with_capacity(found.len());
+
+    for (_, id) in found {
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+
+    ids
+}
+
+/// Name of the pool in a pool page, taken from its heading.
+pub fn pool_name(html: &str) -> Option<String> {
+    const PREFIXES: [&str; 2] = ["Pool:", "Now Viewing:"];
+
+    html.split('<')
+        .filter(|tag| tag.starts_with("h2>") || tag.starts_with("h3>") || tag.starts_with("h4>"))
+        .map(|tag| tag[3..].trim())
+        .find_map(|text| {
+            PREFIXES
+                .iter()
+                .find_map(|prefix| text.strip_prefix(prefix))
+                .map(|name| unescape(name.trim()))
+        })
+        .filter(|name| !name.is_empty())
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&quot;", "\"")
+        .replace("&#039;", "'")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
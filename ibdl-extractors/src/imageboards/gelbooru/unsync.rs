@@ -1,6 +1,7 @@
 use std::time::Duration;
 
 use ibdl_common::{
+    events::{emit, Event},
     log::debug,
     post::Post,
     tokio::{
@@ -13,8 +14,9 @@ use ibdl_common::{
 
 use super::GelbooruExtractor;
 use crate::extractor::caps::PostFetchMethod;
+use crate::extractor::common::remove_seen_posts;
 use crate::extractor::Extractor;
-use crate::prelude::{AsyncFetch, PostFetchAsync, SinglePostFetch};
+use crate::prelude::{AsyncFetch, FavoritesExtract, PoolExtract, PostFetchAsync, SinglePostFetch};
 use crate::{blacklist::BlacklistFilter, error::ExtractorError};
 
 // A quick alias so I can copy paste stuff faster
@@ -38,6 +40,12 @@ impl AsyncFetch for ExtractorUnit {
         )
         .await?;
 
+        if self.pool_id.is_some() || self.favorites_user.is_some() {
+            return self
+                .fetch_listed(&blacklist, sender_channel, limit, post_counter)
+                .await;
+        }
+
         let mut has_posts: bool = false;
         let mut total_posts_sent: u16 = 0;
 
@@ -48,9 +56,15 @@ impl AsyncFetch for ExtractorUnit {
         loop {
             let position = start_page.map_or(page - 1, |n| page + n - 1);
 
-            let posts = self.get_post_list(position, limit).await?;
+            let mut posts = self.get_post_list(position, limit).await?;
             let size = posts.len();
 
+            emit(&Event::PageFetched {
+                server: &self.server_cfg.name,
+                page: position + 1,
+                posts: size,
+            });
+
             if size == 0 {
                 if !has_posts {
                     return Err(ExtractorError::ZeroPosts);
@@ -59,8 +73,21 @@ impl AsyncFetch for ExtractorUnit {
                 break;
             }
 
+            let seen_reached = remove_seen_posts(&mut posts, self.last_seen_id);
+
+            posts.retain(|post| self.filter.matches(post));
+
             let list = if !self.disable_blacklist || !self.download_ratings.is_empty() {
                 let (removed, posts) = blacklist.filter(posts);
+
+                if removed > 0 {
+                    emit(&Event::BlacklistRemoved {
+                        server: &self.server_cfg.name,
+                        page: position + 1,
+                        removed,
+                    });
+                }
+
                 self.total_removed += removed;
                 posts
             } else {
@@ -87,11 +114,16 @@ impl AsyncFetch for ExtractorUnit {
 
             if let Some(num) = limit {
                 if total_posts_sent >= num {
-                    debug!("Target post count of {} reached.", num);
+                    debug!("Target post count of {num} reached.");
                     break;
                 }
             }
 
+            if seen_reached {
+                debug!("Reached already seen posts");
+                break;
+            }
+
             if page == 100 {
                 break;
             }
@@ -148,3 +180,144 @@ impl PostFetchAsync for ExtractorUnit {
         })
     }
 }
+
+impl ExtractorUnit {
+    /// Sends the posts of the pool or the favorites set up for download, fetching them one by one
+    /// in the order the site lists them.
+    ///
+    /// Pool posts take their position in the pool as their id, so they're saved in order.
+    async fn fetch_listed(
+        &mut self,
+        blacklist: &BlacklistFilter,
+        sender_channel: UnboundedSender<Post>,
+        limit: Option<u16>,
+        post_counter: Option<Sender<u64>>,
+    ) -> Result<u64, ExtractorError> {
+        let pool = match self.pool_id {
+            Some(pool_id) => Some(self.fetch_pool_idxs(pool_id, limit).await?),
+            None => None,
+        };
+
+        let mut has_posts: bool = false;
+        let mut total_posts_sent: u16 = 0;
+
+        let mut page = 0;
+
+        // Sites that clamp `pid` keep serving their last page of favorites
+        let mut last_ids = Vec::new();
+
+        debug!("Async extractor thread initialized");
+
+        loop {
+            let ids = match (&pool, self.favorites_user) {
+                (Some(positions), _) => {
+                    let mut ids = positions.keys().copied().collect::<Vec<_>>();
+                    ids.sort_by_key(|id| positions[id]);
+                    ids
+                }
+                (None, Some(user_id)) => self.fetch_favorite_ids(user_id, page).await?,
+                (None, None) => break,
+            };
+
+            emit(&Event::PageFetched {
+                server: &self.server_cfg.name,
+                page: page + 1,
+                posts: ids.len(),
+            });
+
+            if ids.is_empty() {
+                break;
+            }
+
+            if ids == last_ids {
+                debug!("Page {} repeats the previous one", page + 1);
+                break;
+            }
+
+            let mut removed = 0;
+
+            for &id in &ids {
+                if let Some(num) = limit {
+                    if total_posts_sent >= num {
+                        break;
+                    }
+                }
+
+                let Ok(post_id) = u32::try_from(id) else {
+                    continue;
+                };
+
+                let post = match self.get_post(post_id).await {
+                    Ok(post) => post,
+                    // Deleted posts are still listed in pools and favorites
+                    Err(ExtractorError::ZeroPosts) => {
+                        debug!("Post {id} is gone. Skipping");
+                        continue;
+                    }
+                    Err(error) => return Err(error),
+                };
+
+                let list = if !self.disable_blacklist || !self.download_ratings.is_empty() {
+                    let (post_removed, posts) = blacklist.filter(vec![post]);
+                    removed += post_removed;
+                    posts
+                } else {
+                    vec![post]
+                };
+
+                for mut post in list {
+                    if let Some(positions) = &pool {
+                        post.id = positions[&id] as u64;
+                    }
+
+                    has_posts = true;
+                    sender_channel.send(post)?;
+                    total_posts_sent += 1;
+                    if let Some(counter) = &post_counter {
+                        counter.send(1).await?;
+                    }
+                }
+            }
+
+            if removed > 0 {
+                emit(&Event::BlacklistRemoved {
+                    server: &self.server_cfg.name,
+                    page: page + 1,
+                    removed,
+                });
+            }
+
+            self.total_removed += removed;
+
+            if let Some(num) = limit {
+                if total_posts_sent >= num {
+                    debug!("Target post count of {num} reached.");
+                    break;
+                }
+            }
+
+            // The whole pool is in a single page
+            if pool.is_some() {
+                break;
+            }
+
+            if page == 100 {
+                break;
+            }
+
+            last_ids = ids;
+            page += 1;
+
+            //debounce
+            debug!("Debouncing API calls by 500 ms");
+            sleep(Duration::from_millis(500)).await;
+        }
+
+        if !has_posts {
+            return Err(ExtractorError::ZeroPosts);
+        }
+
+        debug!("Terminating thread.");
+        Ok(self.total_removed)
+    }
+}
@@ -8,20 +8,31 @@
 //!
 //! ### Example with the `Danbooru` extractor
 //! ```rust
-//! use imageboard_downloader::*;
+//! use ibdl_common::post::rating::Rating;
+//! use ibdl_extractors::auth::ImageboardConfig;
+//! use ibdl_extractors::imageboards::danbooru::DanbooruExtractor;
+//! use ibdl_extractors::prelude::*;
 //!
 //! async fn test() {
 //!     let tags = ["umbreon", "espeon"]; // The tags to search
-//!     
-//!     let safe_mode = false; // Setting this to true, will ignore searching NSFW posts
+//!
+//!     let ratings = [Rating::Safe]; // Only download posts with these ratings. Leave empty to download all of them
 //!
 //!     let disable_blacklist = false; // Will filter all items according to what's set in GBL
 //!
-//!     let mut unit = DanbooruExtractor::new(&tags, safe_mode, disable_blacklist); // Initialize
+//!     let map_videos = true; // Also download videos and animated posts
+//!
+//!     let mut unit = DanbooruExtractor::new(&tags, &ratings, disable_blacklist, map_videos); // Initialize
 //!
-//!     let prompt = true; // If true, will ask the user to input thei username and API key.
+//!     // Log in with the user's API key to also filter posts with their own blacklist
+//!     let mut config = ImageboardConfig::new(
+//!         unit.config(),
+//!         String::from("username"),
+//!         String::from("api_key"),
+//!     );
+//!     config.authenticate(&unit.client()).await.unwrap();
 //!
-//!     unit.auth(prompt).await.unwrap(); // Try to authenticate
+//!     unit.auth(config).await.unwrap(); // Use the credentials for all requests
 //!
 //!     let start_page = Some(1); // Start searching from the first page
 //!
@@ -35,23 +46,21 @@
 //!
 //! ### Example with the `Gelbooru` extractor
 //!
-//! The Gelbooru extractor supports multiple websites, so to use it correctly, an additional option needs to be set.
+//! The Gelbooru extractor supports multiple websites, so to use it correctly, the config of the selected website needs to be passed.
 //!
 //! ```rust
-//! use imageboard_downloader::*;
+//! use ibdl_extractors::extractor_config::DEFAULT_SERVERS;
+//! use ibdl_extractors::imageboards::gelbooru::GelbooruExtractor;
+//! use ibdl_extractors::prelude::*;
 //!
 //! async fn test() {
 //!     let tags = ["umbreon", "espeon"]; // The tags to search
-//!     
-//!     let safe_mode = false; // Setting this to true, will ignore searching NSFW posts
 //!
 //!     let disable_blacklist = false; // Will filter all items according to what's set in GBL
 //!
-//!     let mut unit = GelbooruExtractor::new(&tags, safe_mode, disable_blacklist)
-//!         .set_imageboard(ImageBoards::Rule34).expect("Invalid imageboard"); // Here the imageboard was set to Rule34
-//!
+//!     let server = DEFAULT_SERVERS.get("rule34").unwrap().clone(); // Here the imageboard was set to Rule34
 //!
-//!     let prompt = true; // If true, will ask the user to input thei username and API key.
+//!     let mut unit = GelbooruExtractor::new_with_config(&tags, &[], disable_blacklist, true, server);
 //!
 //!     let start_page = Some(1); // Start searching from the first page
 //!
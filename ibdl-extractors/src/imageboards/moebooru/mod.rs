@@ -1,37 +1,58 @@
 //! Post extractor for `https://konachan.com` and other Moebooru imageboards
+//!
+//! # Authentication
+//! Moebooru takes the `login` of the account and a `password_hash`, the SHA1 of the password
+//! wrapped in a salt of the server, in every request. Once [`Auth::auth`] is called they're added
+//! to all of them. The account blacklist isn't exposed, so the local blacklist is the one that
+//! applies.
+use ahash::HashMap;
 use ibdl_common::post::extension::Extension;
 use ibdl_common::post::tags::{Tag, TagType};
-use ibdl_common::reqwest::Client;
+use ibdl_common::reqwest::{Client, RequestBuilder};
 use ibdl_common::{
-    client, extract_ext_from_url, join_tags,
+    client, extract_ext_from_url,
     log::debug,
     post::{rating::Rating, Post, PostQueue},
     serde_json,
-    tokio::time::Instant,
+    tokio::time::{sleep, Instant},
     ImageBoards,
 };
 use std::fmt::Display;
+use std::time::Duration;
 
-use crate::extractor::caps::ExtractorFeatures;
+use crate::auth::{AuthState, ImageboardConfig};
+use crate::extractor::caps::{Auth, ExtractorFeatures, SinglePostFetch};
+use crate::extractor::common::{parse_query, remove_seen_posts, split_query};
 use crate::extractor::Extractor;
 use crate::extractor_config::{ServerConfig, DEFAULT_SERVERS};
+use crate::query::{PostFilter, Query};
 use crate::{
     blacklist::BlacklistFilter, error::ExtractorError, imageboards::moebooru::models::KonachanPost,
 };
 
 mod models;
+mod pool;
 mod unsync;
 
 pub struct MoebooruExtractor {
     client: Client,
     tags: Vec<String>,
     tag_string: String,
+    query: Query,
+    filter: PostFilter,
     download_ratings: Vec<Rating>,
     disable_blacklist: bool,
     total_removed: u64,
     map_videos: bool,
     excluded_tags: Vec<String>,
     selected_extension: Option<Extension>,
+    last_seen_id: Option<u64>,
+    auth_state: AuthState,
+    auth: ImageboardConfig,
+    pool_id: Option<u32>,
+    pool_last_items_first: bool,
+    pool_name: Option<String>,
+    pool_idxs: Option<HashMap<u64, usize>>,
     server_cfg: ServerConfig,
 }
 
@@ -50,28 +71,31 @@ impl Extractor for MoebooruExtractor {
         // Use common client for all connections with a set User-Agent
         let client = client!(config);
 
-        let strvec: Vec<String> = tags
-            .iter()
-            .map(|t| {
-                let st: String = t.to_string();
-                st
-            })
-            .collect();
+        let strvec: Vec<String> = tags.iter().map(ToString::to_string).collect();
+        debug!("Tag List: {strvec:?}");
 
-        // Merge all tags in the URL format
-        let tag_string = join_tags!(strvec);
-        debug!("Tag List: {}", tag_string);
+        let query = parse_query(tags);
+        let (tag_string, filter) = split_query(&query, &config, 0);
 
         Self {
             client,
             tags: strvec,
             tag_string,
+            query,
+            filter,
             download_ratings: download_ratings.to_vec(),
             disable_blacklist,
             total_removed: 0,
             map_videos,
             excluded_tags: vec![],
             selected_extension: None,
+            last_seen_id: None,
+            auth_state: AuthState::NotAuthenticated,
+            auth: ImageboardConfig::default(),
+            pool_id: None,
+            pool_last_items_first: false,
+            pool_name: None,
+            pool_idxs: None,
             server_cfg: config,
         }
     }
@@ -89,34 +113,38 @@ impl Extractor for MoebooruExtractor {
         // Use common client for all connections with a set User-Agent
         let client = client!(config);
 
-        let strvec: Vec<String> = tags
-            .iter()
-            .map(|t| {
-                let st: String = t.to_string();
-                st
-            })
-            .collect();
+        let strvec: Vec<String> = tags.iter().map(ToString::to_string).collect();
+        debug!("Tag List: {strvec:?}");
 
-        // Merge all tags in the URL format
-        let tag_string = join_tags!(strvec);
-        debug!("Tag List: {}", tag_string);
+        let query = parse_query(tags);
+        let (tag_string, filter) = split_query(&query, &config, 0);
 
         Self {
             client,
             tags: strvec,
             tag_string,
+            query,
+            filter,
             download_ratings: download_ratings.to_vec(),
             disable_blacklist,
             total_removed: 0,
             map_videos,
             excluded_tags: vec![],
             selected_extension: None,
+            last_seen_id: None,
+            auth_state: AuthState::NotAuthenticated,
+            auth: ImageboardConfig::default(),
+            pool_id: None,
+            pool_last_items_first: false,
+            pool_name: None,
+            pool_idxs: None,
             server_cfg: config,
         }
     }
 
     async fn search(&mut self, page: u16) -> Result<PostQueue, ExtractorError> {
         let mut posts = self.get_post_list(page, None).await?;
+        posts.retain(|post| self.filter.matches(post));
 
         if posts.is_empty() {
             return Err(ExtractorError::ZeroPosts);
@@ -142,7 +170,7 @@ impl Extractor for MoebooruExtractor {
     ) -> Result<PostQueue, ExtractorError> {
         let blacklist = BlacklistFilter::new(
             self.server_cfg.clone(),
-            &Vec::default(),
+            &self.excluded_tags,
             &self.download_ratings,
             self.disable_blacklist,
             !self.map_videos,
@@ -160,13 +188,17 @@ impl Extractor for MoebooruExtractor {
         loop {
             let position = start_page.map_or(page, |n| page + n);
 
-            let posts = self.get_post_list(position, limit).await?;
+            let mut posts = self.get_post_list(position, limit).await?;
             let size = posts.len();
 
             if size == 0 {
                 break;
             }
 
+            let seen_reached = remove_seen_posts(&mut posts, self.last_seen_id);
+
+            posts.retain(|post| self.filter.matches(post));
+
             let mut list = if !self.disable_blacklist || !self.download_ratings.is_empty() {
                 let (removed, posts) = blacklist.filter(posts);
                 self.total_removed += removed;
@@ -183,6 +215,11 @@ impl Extractor for MoebooruExtractor {
                 }
             }
 
+            if seen_reached {
+                debug!("Reached already seen posts");
+                break;
+            }
+
             if size < 100 || page == 100 {
                 break;
             }
@@ -208,7 +245,7 @@ impl Extractor for MoebooruExtractor {
     }
 
     fn exclude_tags(&mut self, tags: &[String]) -> &mut Self {
-        self.excluded_tags = tags.to_vec();
+        self.excluded_tags.extend(tags.iter().cloned());
         self
     }
 
@@ -217,6 +254,16 @@ impl Extractor for MoebooruExtractor {
         self
     }
 
+    fn newer_than(&mut self, post_id: u64) -> &mut Self {
+        self.last_seen_id = Some(post_id);
+
+        // Leave room for the id filter in the tag limit
+        let (tag_string, filter) = split_query(&self.query, &self.server_cfg, 1);
+        self.tag_string = format!("{tag_string} id:>{post_id}").trim().to_string();
+        self.filter = filter;
+        self
+    }
+
     async fn get_post_list(
         &self,
         page: u16,
@@ -224,7 +271,7 @@ impl Extractor for MoebooruExtractor {
     ) -> Result<Vec<Post>, ExtractorError> {
         if self.server_cfg.post_list_url.is_none() {
             return Err(ExtractorError::UnsupportedOperation);
-        };
+        }
 
         let page_post_count = {
             limit.map_or(self.server_cfg.max_post_limit, |count| {
@@ -236,18 +283,17 @@ impl Extractor for MoebooruExtractor {
             })
         };
 
-        let items = self
-            .client
-            .get(self.server_cfg.post_list_url.as_ref().unwrap())
-            .query(&[
-                ("page", &page.to_string()),
-                ("limit", &page_post_count.to_string()),
-                ("tags", &self.tag_string),
-            ])
-            .send()
-            .await?
-            .text()
-            .await?;
+        let request = self.authorize(
+            self.client
+                .get(self.server_cfg.post_list_url.as_ref().unwrap())
+                .query(&[
+                    ("page", &page.to_string()),
+                    ("limit", &page_post_count.to_string()),
+                    ("tags", &self.tag_string),
+                ]),
+        );
+
+        let items = self.server_cfg.send(request).await?.text().await?;
 
         let start = Instant::now();
 
@@ -262,7 +308,7 @@ impl Extractor for MoebooruExtractor {
     }
 
     fn map_posts(&self, raw_json: String) -> Result<Vec<Post>, ExtractorError> {
-        let items = serde_json::from_str::<Vec<KonachanPost>>(raw_json.as_str()).unwrap();
+        let items = serde_json::from_str::<Vec<KonachanPost>>(raw_json.as_str())?;
 
         let post_iter = items.iter().filter(|c| c.file_url.is_some());
 
@@ -289,6 +335,7 @@ impl Extractor for MoebooruExtractor {
                 extension: Extension::guess_format(&ext),
                 tags,
                 rating: Rating::from_rating_str(&c.rating),
+                frame_delays: None,
             };
 
             post_mtx.push(unit);
@@ -310,10 +357,87 @@ impl Extractor for MoebooruExtractor {
     }
 
     fn features() -> ExtractorFeatures {
-        ExtractorFeatures::from_bits_truncate(0b0000_0011) // AsyncFetch + TagSearch
+        ExtractorFeatures::from_bits_truncate(0b0001_1111) // AsyncFetch + TagSearch + SinglePostFetch + PoolDownload + Auth (Everything)
     }
 
     fn config(&self) -> ServerConfig {
         self.server_cfg.clone()
     }
 }
+
+impl MoebooruExtractor {
+    /// Adds the login and password hash of the user to a request, if logged in.
+    fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        if self.auth_state.is_auth() {
+            debug!("[AUTH] Sending request as user {}", self.auth.username);
+            request.query(&[
+                ("login", &self.auth.username),
+                ("password_hash", &self.auth.api_key),
+            ])
+        } else {
+            request
+        }
+    }
+}
+
+impl Auth for MoebooruExtractor {
+    async fn auth(&mut self, config: ImageboardConfig) -> Result<(), ExtractorError> {
+        let mut cfg = config;
+
+        self.excluded_tags
+            .append(&mut cfg.user_data.blacklisted_tags);
+
+        self.auth = cfg;
+        self.auth_state = AuthState::Authenticated;
+        Ok(())
+    }
+}
+
+impl SinglePostFetch for MoebooruExtractor {
+    fn map_post(&self, raw_json: String) -> Result<Post, ExtractorError> {
+        // Moebooru has no endpoint for a single post, so it comes in a post list
+        self.map_posts(raw_json)?
+            .into_iter()
+            .next()
+            .ok_or(ExtractorError::ZeroPosts)
+    }
+
+    async fn get_post(&mut self, post_id: u32) -> Result<Post, ExtractorError> {
+        if self.server_cfg.post_url.is_none() {
+            return Err(ExtractorError::UnsupportedOperation);
+        }
+
+        debug!("Fetching post {post_id}");
+
+        let request = self.authorize(
+            self.client
+                .get(self.server_cfg.post_url.as_ref().unwrap())
+                .query(&[("tags", format!("id:{post_id}"))]),
+        );
+
+        let items = self.server_cfg.send(request).await?.text().await?;
+
+        let start_point = Instant::now();
+
+        let post = self.map_post(items)?;
+
+        debug!("Post mapping took {:?}", start_point.elapsed());
+
+        Ok(post)
+    }
+
+    async fn get_posts(&mut self, posts: &[u32]) -> Result<Vec<Post>, ExtractorError> {
+        let mut pvec = Vec::with_capacity(posts.len());
+
+        for post_id in posts {
+            let post = self.get_post(*post_id).await?;
+
+            // This function is pretty heavy on API usage, so let's ease it up a little.
+            debug!("Debouncing API calls by 500 ms");
+            sleep(Duration::from_millis(500)).await;
+
+            pvec.push(post);
+        }
+        Ok(pvec)
+    }
+}
@@ -9,3 +9,16 @@ pub struct KonachanPost {
     pub rating: String,
     pub tags: String,
 }
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "self::serde")]
+pub struct KonachanPool {
+    pub name: String,
+    pub posts: Vec<KonachanPoolPost>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "self::serde")]
+pub struct KonachanPoolPost {
+    pub id: u64,
+}
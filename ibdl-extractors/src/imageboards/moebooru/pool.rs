@@ -0,0 +1,81 @@
+use ahash::HashMap;
+use ibdl_common::{
+    log::{debug, trace},
+    serde_json,
+};
+
+use super::{models::KonachanPool, MoebooruExtractor};
+use crate::error::ExtractorError;
+use crate::extractor::caps::{PoolExtract, PoolInfo};
+
+impl PoolExtract for MoebooruExtractor {
+    async fn fetch_pool_idxs(
+        &mut self,
+        pool_id: u32,
+        limit: Option<u16>,
+    ) -> Result<HashMap<u64, usize>, ExtractorError> {
+        if let Some(idxs) = &self.pool_idxs {
+            debug!("Using cached post ids from pool {pool_id}");
+            return Ok(idxs.clone());
+        }
+
+        if self.server_cfg.pool_idx_url.is_none() {
+            return Err(ExtractorError::UnsupportedOperation);
+        }
+
+        debug!("Fetching post ids from pool {pool_id}");
+
+        let req = self.authorize(
+            self.client
+                .get(self.server_cfg.pool_idx_url.as_ref().unwrap())
+                .query(&[("id", pool_id)]),
+        );
+
+        let pool_json = self.server_cfg.send(req).await?.text().await?;
+
+        let pool = self.parse_pool(pool_json)?;
+        let mut mtx = pool.post_ids;
+
+        if self.pool_last_items_first {
+            mtx.reverse();
+        }
+
+        if let Some(limit_post) = limit {
+            mtx.truncate(limit_post as usize);
+        }
+
+        let position_map = mtx
+            .iter()
+            .enumerate()
+            .map(|(position, id)| (*id, position))
+            .collect::<HashMap<u64, usize>>();
+
+        trace!("Pool post positions: {position_map:#?}");
+        debug!("Pool size: {}", position_map.len());
+
+        self.pool_name = Some(pool.name.replace('_', " "));
+        self.pool_idxs = Some(position_map.clone());
+
+        Ok(position_map)
+    }
+
+    fn parse_pool(&self, raw_json: String) -> Result<PoolInfo, ExtractorError> {
+        let parsed_json = serde_json::from_str::<KonachanPool>(raw_json.as_str())?;
+
+        Ok(PoolInfo {
+            name: parsed_json.name,
+            post_ids: parsed_json.posts.into_iter().map(|post| post.id).collect(),
+        })
+    }
+
+    fn pool_name(&self) -> Option<String> {
+        self.pool_name.clone()
+    }
+
+    fn setup_pool_download(&mut self, pool_id: Option<u32>, last_first: bool) {
+        self.pool_id = pool_id;
+        self.pool_last_items_first = last_first;
+        self.pool_name = None;
+        self.pool_idxs = None;
+    }
+}
@@ -1,4 +1,6 @@
+use ahash::{HashMap, HashMapExt};
 use ibdl_common::{
+    events::{emit, Event},
     log::debug,
     post::Post,
     tokio::{
@@ -9,7 +11,10 @@ use ibdl_common::{
 };
 
 use super::MoebooruExtractor;
-use crate::extractor::caps::AsyncFetch;
+use crate::extractor::caps::{
+    AsyncFetch, PoolExtract, PostFetchAsync, PostFetchMethod, SinglePostFetch,
+};
+use crate::extractor::common::remove_seen_posts;
 use crate::extractor::Extractor;
 use crate::{blacklist::BlacklistFilter, error::ExtractorError};
 
@@ -34,6 +39,13 @@ impl AsyncFetch for ExtractorUnit {
         )
         .await?;
 
+        let pool_idxs = if let Some(p_id) = self.pool_id {
+            self.tag_string = format!("pool:{p_id}");
+            self.fetch_pool_idxs(p_id, limit).await?
+        } else {
+            HashMap::with_capacity(512)
+        };
+
         let mut has_posts: bool = false;
         let mut total_posts_sent: u16 = 0;
 
@@ -44,9 +56,15 @@ impl AsyncFetch for ExtractorUnit {
         loop {
             let position = start_page.map_or(page, |n| page + n);
 
-            let posts = self.get_post_list(position, limit).await?;
+            let mut posts = self.get_post_list(position, limit).await?;
             let size = posts.len();
 
+            emit(&Event::PageFetched {
+                server: &self.server_cfg.name,
+                page: position,
+                posts: size,
+            });
+
             if size == 0 {
                 if !has_posts {
                     return Err(ExtractorError::ZeroPosts);
@@ -55,8 +73,21 @@ impl AsyncFetch for ExtractorUnit {
                 break;
             }
 
+            let seen_reached = remove_seen_posts(&mut posts, self.last_seen_id);
+
+            posts.retain(|post| self.filter.matches(post));
+
             let list = if !self.disable_blacklist || !self.download_ratings.is_empty() {
                 let (removed, posts) = blacklist.filter(posts);
+
+                if removed > 0 {
+                    emit(&Event::BlacklistRemoved {
+                        server: &self.server_cfg.name,
+                        page: position,
+                        removed,
+                    });
+                }
+
                 self.total_removed += removed;
                 posts
             } else {
@@ -67,13 +98,21 @@ impl AsyncFetch for ExtractorUnit {
                 has_posts = true;
             }
 
-            for i in list {
+            for mut i in list {
                 if let Some(num) = limit {
                     if total_posts_sent >= num {
                         break;
                     }
                 }
 
+                if self.pool_id.is_some() {
+                    if let Some(page_num) = pool_idxs.get(&i.id) {
+                        i.id = *page_num as u64;
+                    } else {
+                        continue;
+                    }
+                }
+
                 sender_channel.send(i)?;
                 total_posts_sent += 1;
                 if let Some(counter) = &post_counter {
@@ -83,11 +122,16 @@ impl AsyncFetch for ExtractorUnit {
 
             if let Some(num) = limit {
                 if total_posts_sent >= num {
-                    debug!("Target post count of {} reached.", num);
+                    debug!("Target post count of {num} reached.");
                     break;
                 }
             }
 
+            if seen_reached {
+                debug!("Reached already seen posts");
+                break;
+            }
+
             if page == 100 {
                 break;
             }
@@ -114,3 +158,29 @@ impl AsyncFetch for ExtractorUnit {
         })
     }
 }
+
+impl PostFetchAsync for ExtractorUnit {
+    fn setup_async_post_fetch(
+        self,
+        post_channel: UnboundedSender<Post>,
+        method: PostFetchMethod,
+        length_channel: Sender<u64>,
+    ) -> JoinHandle<Result<u64, ExtractorError>> {
+        spawn(async move {
+            let mut unit = self;
+            match method {
+                PostFetchMethod::Single(p_id) => {
+                    post_channel.send(unit.get_post(p_id).await?)?;
+                    length_channel.send(1).await?;
+                }
+                PostFetchMethod::Multiple(p_ids) => {
+                    for p_id in p_ids {
+                        post_channel.send(unit.get_post(p_id).await?)?;
+                        length_channel.send(1).await?;
+                    }
+                }
+            }
+            Ok(0)
+        })
+    }
+}
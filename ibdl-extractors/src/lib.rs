@@ -15,4 +15,5 @@ mod extractor;
 pub mod extractor_config;
 pub mod imageboards;
 pub mod prelude;
+pub mod query;
 mod test;
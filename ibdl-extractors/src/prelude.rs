@@ -2,7 +2,9 @@ pub use crate::extractor::caps::AsyncFetch;
 pub use crate::extractor::caps::Auth;
 pub use crate::extractor::caps::ExtractorFeatures;
 pub use crate::extractor::caps::ExtractorThreadHandle;
+pub use crate::extractor::caps::FavoritesExtract;
 pub use crate::extractor::caps::PoolExtract;
+pub use crate::extractor::caps::PoolInfo;
 pub use crate::extractor::caps::PostFetchAsync;
 pub use crate::extractor::caps::PostFetchMethod;
 pub use crate::extractor::caps::SinglePostFetch;
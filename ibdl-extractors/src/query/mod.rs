@@ -0,0 +1,267 @@
+//! Search queries evaluated partly by the imageboard and partly by the extractor
+//!
+//! Imageboards limit how many tags a single search can have (Danbooru only allows 2 for
+//! anonymous users) and don't all understand the same syntax. A [`Query`] is parsed once and then
+//! [split](Query::split) into the tags sent to the server, as many as its tag limit allows, and a
+//! [`PostFilter`] that checks everything else against the posts the server returns.
+//!
+//! # Syntax
+//! Terms are separated by spaces and a post must match all of them.
+//! * `tag`: Post has the tag
+//! * `-tag`: Post doesn't have the tag
+//! * `~tag`: Post has at least one of the tags marked with `~` in the same group
+//! * `cat*`: Post has a tag matching the pattern, where `*` matches anything
+//! * `( ... )`: Group of terms. Groups can be negated with `-(` or marked with `~(`
+//! * `rating:safe`, `rating:q,e`: Post has one of the ratings
+//! * `ext:png`, `filetype:jpg,webp`: File has one of the extensions
+//! * `id:42`, `id:>1000`, `id:<=500`, `id:10..20`: ID of the post
+//! * `md5:<hash>`: MD5 hash of the file
+//!
+//! Any other `key:value` term is a metatag only the server understands, like `order:score` or
+//! `score:>100`. Those are always sent to the server, so they can't be used inside groups or `~`
+//! terms.
+//!
+//! The server needs at least one term to search for, so queries made only of groups, `~` terms
+//! and the metatags above are rejected instead of crawling every post of the imageboard.
+//!
+//! # Example
+//! ```
+//! use ibdl_extractors::query::Query;
+//!
+//! let query = Query::parse("kroos_(arknights) ~solo ~1girl -monochrome rating:safe").unwrap();
+//!
+//! // With a limit of 2 tags, the server only gets the first tag and the negated one
+//! let (server_tags, filter) = query.split(Some(2));
+//!
+//! assert_eq!(server_tags, ["kroos_(arknights)", "-monochrome"]);
+//! assert!(!filter.is_empty());
+//! ```
+use std::ops::RangeInclusive;
+
+use ibdl_common::post::{extension::Extension, rating::Rating, Post};
+
+use crate::error::QueryError;
+
+mod parser;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    Tag(String),
+    Pattern(String),
+    Meta(Meta),
+    Server(String),
+    Not(Box<Self>),
+    And(Vec<Self>),
+    Or(Vec<Self>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Meta {
+    Rating(Vec<Rating>),
+    Extension(Vec<Extension>),
+    Id(RangeInclusive<u64>),
+    Md5(String),
+}
+
+/// Parsed search query.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Query {
+    terms: Vec<Expr>,
+}
+
+/// Part of a [`Query`] checked against every post returned by the server.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PostFilter {
+    terms: Vec<Expr>,
+}
+
+impl Query {
+    /// Parses a query written in the [syntax](self#syntax) described above.
+    pub fn parse(query: &str) -> Result<Self, QueryError> {
+        let terms = parser::parse(query)?;
+
+        for term in &terms {
+            let inner = match term {
+                Expr::Not(inner) => inner,
+                term => term,
+            };
+
+            if !matches!(inner, Expr::Server(_)) {
+                inner.check_nested()?;
+            }
+        }
+
+        if !terms.is_empty() && terms.iter().all(|term| term.server_rank().is_none()) {
+            return Err(QueryError::NoServerTerm);
+        }
+
+        Ok(Self { terms })
+    }
+
+    /// Parses the query made by joining `tags` with spaces.
+    pub fn from_tags<S: ToString>(tags: &[S]) -> Result<Self, QueryError> {
+        let query = tags
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        Self::parse(&query)
+    }
+
+    /// Query that sends every one of `tags` to the server as it is.
+    pub fn verbatim<S: ToString>(tags: &[S]) -> Self {
+        let terms = tags
+            .iter()
+            .map(ToString::to_string)
+            .filter(|tag| !tag.trim().is_empty())
+            .map(Expr::Server)
+            .collect();
+
+        Self { terms }
+    }
+
+    /// Splits the query into the tags sent to the server and the filter for the posts it returns.
+    ///
+    /// Server metatags are always sent. Then, while there's room left in `tag_limit`, the server
+    /// gets plain tags, patterns and negated tags, in that order. The rest of the query, including
+    /// every group, `~` term and client metatag, goes into the filter.
+    #[must_use]
+    pub fn split(&self, tag_limit: Option<usize>) -> (Vec<String>, PostFilter) {
+        let limit = tag_limit.unwrap_or(usize::MAX);
+
+        let mut sent = vec![false; self.terms.len()];
+        let mut server_tags = Vec::new();
+
+        let mut candidates = self
+            .terms
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, term)| term.server_rank().map(|rank| (rank, idx)))
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|(rank, _)| *rank);
+
+        for (rank, idx) in candidates {
+            // Server metatags can't be checked locally, so they go even if over the limit
+            if rank > 0 && server_tags.len() >= limit {
+                break;
+            }
+
+            server_tags.push(self.terms[idx].server_tag());
+            sent[idx] = true;
+        }
+
+        let terms = self
+            .terms
+            .iter()
+            .zip(sent)
+            .filter(|(_, sent)| !sent)
+            .map(|(term, _)| term.clone())
+            .collect();
+
+        (server_tags, PostFilter { terms })
+    }
+}
+
+impl PostFilter {
+    /// Checks if `post` matches every term of the filter.
+    #[must_use]
+    pub fn matches(&self, post: &Post) -> bool {
+        self.terms.iter().all(|term| term.matches(post))
+    }
+
+    /// Returns `true` if the filter lets every post through.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+}
+
+impl Expr {
+    /// Order in which top level terms are sent to the server, if they can be sent at all.
+    fn server_rank(&self) -> Option<u8> {
+        match self {
+            Self::Server(_) => Some(0),
+            Self::Tag(_) => Some(1),
+            Self::Pattern(_) => Some(2),
+            Self::Not(inner) => match inner.as_ref() {
+                Self::Server(_) => Some(0),
+                Self::Tag(_) => Some(3),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn server_tag(&self) -> String {
+        match self {
+            Self::Tag(tag) | Self::Pattern(tag) | Self::Server(tag) => tag.clone(),
+            Self::Not(inner) => format!("-{}", inner.server_tag()),
+            _ => unreachable!("Only terms with a server rank are sent to the server"),
+        }
+    }
+
+    /// Fails if a server metatag is used anywhere inside the expression.
+    fn check_nested(&self) -> Result<(), QueryError> {
+        match self {
+            Self::Server(term) => Err(QueryError::NestedServerTerm { term: term.clone() }),
+            Self::Not(inner) => inner.check_nested(),
+            Self::And(terms) | Self::Or(terms) => terms.iter().try_for_each(Self::check_nested),
+            _ => Ok(()),
+        }
+    }
+
+    fn matches(&self, post: &Post) -> bool {
+        match self {
+            Self::Tag(name) => post
+                .tags
+                .iter()
+                .any(|tag| tag.tag().eq_ignore_ascii_case(name)),
+            Self::Pattern(pattern) => post
+                .tags
+                .iter()
+                .any(|tag| glob_match(pattern, &tag.tag().to_lowercase())),
+            Self::Meta(meta) => meta.matches(post),
+            // Already applied by the server
+            Self::Server(_) => true,
+            Self::Not(inner) => !inner.matches(post),
+            Self::And(terms) => terms.iter().all(|term| term.matches(post)),
+            Self::Or(terms) => terms.iter().any(|term| term.matches(post)),
+        }
+    }
+}
+
+impl Meta {
+    fn matches(&self, post: &Post) -> bool {
+        match self {
+            Self::Rating(ratings) => ratings.contains(&post.rating),
+            Self::Extension(extensions) => extensions.contains(&post.extension),
+            Self::Id(range) => range.contains(&post.id),
+            Self::Md5(md5) => post.md5.eq_ignore_ascii_case(md5),
+        }
+    }
+}
+
+/// Matches `text` against `pattern`, where every `*` matches any number of characters.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+
+    let Some(mut rest) = text.strip_prefix(parts.next().unwrap_or_default()) else {
+        return false;
+    };
+
+    let parts = parts.collect::<Vec<_>>();
+
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+
+    for part in middle {
+        match rest.find(part) {
+            Some(idx) => rest = &rest[idx + part.len()..],
+            None => return false,
+        }
+    }
+
+    rest.ends_with(last)
+}
@@ -0,0 +1,249 @@
+//! Parser for the [query syntax](super#syntax).
+//!
+//! Parentheses are part of many tag names, like `kroos_(arknights)`, so they only open or close a
+//! group when they're not balanced inside the word: `(` and `(kroos_(arknights)` open a group,
+//! while `)` and `kroos_(arknights))` close one.
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use ibdl_common::post::{extension::Extension, rating::Rating};
+
+use super::{Expr, Meta};
+use crate::error::QueryError;
+
+#[derive(Debug, PartialEq, Eq)]
+enum Token {
+    Open {
+        negated: bool,
+        any: bool,
+    },
+    Close,
+    Term {
+        negated: bool,
+        any: bool,
+        text: String,
+    },
+}
+
+/// Parses `query` into the list of terms every post must match.
+pub(super) fn parse(query: &str) -> Result<Vec<Expr>, QueryError> {
+    let mut tokens = tokenize(query).into_iter();
+    parse_group(&mut tokens, false)
+}
+
+fn tokenize(query: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut depth = 0_usize;
+
+    for word in query.split_whitespace() {
+        if word == ")" && depth == 0 {
+            tokens.push(Token::Close);
+            continue;
+        }
+
+        let mut rest = word;
+
+        loop {
+            let (negated, any, body) = prefixes(rest);
+
+            if let Some(inner) = body.strip_prefix('(') {
+                if body.matches('(').count() > body.matches(')').count() {
+                    tokens.push(Token::Open { negated, any });
+                    depth += 1;
+                    rest = inner;
+
+                    if rest.is_empty() {
+                        break;
+                    }
+                    continue;
+                }
+            }
+
+            let mut body = body;
+            let mut closes = 0;
+
+            while closes < depth
+                && body.ends_with(')')
+                && body.matches(')').count() > body.matches('(').count()
+            {
+                body = &body[..body.len() - 1];
+                closes += 1;
+            }
+
+            if !body.is_empty() {
+                tokens.push(Token::Term {
+                    negated,
+                    any,
+                    text: body.to_string(),
+                });
+            }
+
+            depth -= closes;
+            tokens.extend((0..closes).map(|_| Token::Close));
+            break;
+        }
+    }
+
+    tokens
+}
+
+/// Splits the `-` and `~` prefixes from `word`.
+fn prefixes(word: &str) -> (bool, bool, &str) {
+    let mut negated = false;
+    let mut any = false;
+    let mut body = word;
+
+    loop {
+        match body.chars().next() {
+            Some('-') if body.len() > 1 && !negated => negated = true,
+            Some('~') if body.len() > 1 && !any => any = true,
+            _ => break,
+        }
+        body = &body[1..];
+    }
+
+    (negated, any, body)
+}
+
+fn parse_group(
+    tokens: &mut impl Iterator<Item = Token>,
+    nested: bool,
+) -> Result<Vec<Expr>, QueryError> {
+    let mut all = Vec::new();
+    let mut any_of = Vec::new();
+
+    loop {
+        let (negated, any, expr) = match tokens.next() {
+            None if nested => return Err(QueryError::UnclosedGroup),
+            None => break,
+            Some(Token::Close) if nested => break,
+            Some(Token::Close) => return Err(QueryError::UnmatchedClose),
+            Some(Token::Open { negated, any }) => {
+                let mut inner = parse_group(tokens, true)?;
+                let expr = if inner.len() == 1 {
+                    inner.remove(0)
+                } else {
+                    Expr::And(inner)
+                };
+                (negated, any, expr)
+            }
+            Some(Token::Term { negated, any, text }) => (negated, any, term(&text)?),
+        };
+
+        let expr = if negated {
+            Expr::Not(Box::new(expr))
+        } else {
+            expr
+        };
+
+        if any {
+            any_of.push(expr);
+        } else {
+            all.push(expr);
+        }
+    }
+
+    // A single `~` term is just a required one
+    match any_of.len() {
+        0 => {}
+        1 => all.append(&mut any_of),
+        _ => all.push(Expr::Or(any_of)),
+    }
+
+    Ok(all)
+}
+
+fn term(text: &str) -> Result<Expr, QueryError> {
+    if let Some((key, value)) = text.split_once(':') {
+        let key = key.to_lowercase();
+
+        let meta = match key.as_str() {
+            "rating" => Some(Meta::Rating(list(
+                &key,
+                value,
+                |value| match Rating::from_rating_str(&value.to_lowercase()) {
+                    Rating::Unknown => None,
+                    rating => Some(rating),
+                },
+            )?)),
+            "ext" | "filetype" => Some(Meta::Extension(list(&key, value, |value| {
+                Extension::from_str(value).ok()
+            })?)),
+            "id" => Some(Meta::Id(
+                id_range(value).ok_or_else(|| invalid(&key, value))?,
+            )),
+            "md5" if !value.is_empty() => Some(Meta::Md5(value.to_lowercase())),
+            "md5" => return Err(invalid(&key, value)),
+            // Left for the server, which might know it
+            key if is_metatag(key, value) => return Ok(Expr::Server(text.to_string())),
+            _ => None,
+        };
+
+        if let Some(meta) = meta {
+            return Ok(Expr::Meta(meta));
+        }
+    }
+
+    let text = text.to_lowercase();
+
+    if text.contains('*') {
+        Ok(Expr::Pattern(text))
+    } else {
+        Ok(Expr::Tag(text))
+    }
+}
+
+/// Checks if `key:value` looks like a metatag rather than a tag with a colon, like `:)` or `>:(`.
+fn is_metatag(key: &str, value: &str) -> bool {
+    !key.is_empty()
+        && !value.is_empty()
+        && key
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || char == '_')
+}
+
+/// Parses a comma separated list of values.
+fn list<T>(
+    key: &str,
+    value: &str,
+    parse: impl Fn(&str) -> Option<T>,
+) -> Result<Vec<T>, QueryError> {
+    value
+        .split(',')
+        .map(|item| parse(item.trim()).ok_or_else(|| invalid(key, value)))
+        .collect()
+}
+
+fn id_range(value: &str) -> Option<RangeInclusive<u64>> {
+    if let Some((start, end)) = value.split_once("..") {
+        return Some(start.parse().ok()?..=end.parse().ok()?);
+    }
+
+    if let Some(id) = value.strip_prefix(">=") {
+        return Some(id.parse().ok()?..=u64::MAX);
+    }
+
+    if let Some(id) = value.strip_prefix("<=") {
+        return Some(0..=id.parse().ok()?);
+    }
+
+    if let Some(id) = value.strip_prefix('>') {
+        let id: u64 = id.parse().ok()?;
+        return Some(id.checked_add(1)?..=u64::MAX);
+    }
+
+    if let Some(id) = value.strip_prefix('<') {
+        let id: u64 = id.parse().ok()?;
+        return Some(0..=id.checked_sub(1)?);
+    }
+
+    let id = value.parse().ok()?;
+    Some(id..=id)
+}
+
+fn invalid(key: &str, value: &str) -> QueryError {
+    QueryError::InvalidMetaValue {
+        key: key.to_string(),
+        value: value.to_string(),
+    }
+}
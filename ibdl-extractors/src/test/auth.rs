@@ -0,0 +1,139 @@
+use std::fs::{read, remove_file, write};
+use std::path::PathBuf;
+
+use ibdl_common::{bincode::serialize, tokio, ImageBoards};
+
+use super::isolate_auth_cache;
+use crate::auth::{Credentials, Error, ImageboardConfig, SecretStore, UserData, VaultStore};
+use crate::extractor_config::DEFAULT_SERVERS;
+
+/// Vault at `name` in the temp dir, opened with `passphrase`.
+fn vault(name: &str, passphrase: &'static str) -> VaultStore {
+    let dir = std::env::temp_dir().join("ibdl-extractors-test");
+    std::fs::create_dir_all(&dir).unwrap();
+
+    VaultStore::new(
+        dir.join(name),
+        Box::new(move |_| Ok(String::from(passphrase))),
+    )
+}
+
+fn contains(haystack: &[u8], needle: &str) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle.as_bytes())
+}
+
+fn credentials() -> Credentials {
+    Credentials {
+        username: String::from("mock_user"),
+        api_key: String::from("mock_key"),
+    }
+}
+
+#[test]
+fn vault_keeps_credentials_encrypted() {
+    let store = vault("round_trip.vault", "correct horse");
+    let _ = remove_file(store.path());
+
+    assert!(store.get("danbooru").unwrap().is_none());
+
+    store.set("danbooru", &credentials()).unwrap();
+    store.set("e621", &credentials()).unwrap();
+
+    let data = read(store.path()).unwrap();
+    assert!(!contains(&data, "mock_key"));
+    assert!(!contains(&data, "mock_user"));
+
+    let reopened = vault("round_trip.vault", "correct horse");
+    assert_eq!(reopened.get("danbooru").unwrap(), Some(credentials()));
+
+    let wrong = vault("round_trip.vault", "battery staple");
+    assert!(matches!(wrong.get("danbooru"), Err(Error::WrongPassphrase)));
+
+    reopened.delete("danbooru").unwrap();
+    assert!(reopened.get("danbooru").unwrap().is_none());
+    assert_eq!(reopened.get("e621").unwrap(), Some(credentials()));
+
+    // The vault goes away with the last credentials
+    reopened.delete("e621").unwrap();
+    assert!(!store.path().exists());
+}
+
+#[tokio::test]
+async fn legacy_cache_moves_credentials_to_store() {
+    isolate_auth_cache();
+
+    let mut config = DEFAULT_SERVERS.get("danbooru").unwrap().clone();
+    config.name = String::from("legacy_danbooru");
+    config.pretty_name = String::from("Legacy Danbooru");
+
+    let store = vault("legacy.vault", "correct horse");
+    let _ = remove_file(store.path());
+
+    let cache_dir = ImageBoards::auth_cache_dir().unwrap();
+    let cache_path = cache_dir.join(&config.name);
+    let legacy_path: PathBuf = cache_dir.join(&config.pretty_name);
+    let _ = remove_file(&cache_path);
+
+    let user_data = UserData {
+        id: 123_456,
+        name: String::from("mock_user"),
+        blacklisted_tags: vec![String::from("guro")],
+    };
+
+    // Layout of the cache written by older versions, named after the pretty name of the server
+    let legacy = (
+        (
+            config.name.clone(),
+            config.pretty_name.clone(),
+            config.server,
+            config.client_user_agent.clone(),
+            config.extractor_user_agent.clone(),
+            config.base_url.clone(),
+            config.post_url.clone(),
+            config.post_list_url.clone(),
+            config.pool_idx_url.clone(),
+            config.max_post_limit,
+            config.auth_url.clone(),
+            config.image_url.clone(),
+        ),
+        String::from("mock_user"),
+        String::from("mock_key"),
+        user_data,
+    );
+    write(&legacy_path, serialize(&legacy).unwrap()).unwrap();
+
+    assert!(ImageboardConfig::is_cached(&config).unwrap());
+
+    let loaded = ImageboardConfig::load(&config, &store)
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(loaded.credentials(), credentials());
+    assert_eq!(loaded.user_data.id, 123_456);
+    assert_eq!(loaded.user_data.blacklisted_tags, ["guro"]);
+
+    assert!(!legacy_path.exists());
+    assert!(!contains(&read(&cache_path).unwrap(), "mock_key"));
+    assert_eq!(store.get(&config.name).unwrap(), Some(credentials()));
+
+    // Later runs read the new cache
+    let cached = ImageboardConfig::cached_user(&config)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(cached.name, "mock_user");
+
+    let reloaded = ImageboardConfig::load(&config, &store)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(reloaded.credentials(), credentials());
+
+    assert!(ImageboardConfig::logout(&config, &store).await.unwrap());
+    assert!(!ImageboardConfig::is_cached(&config).unwrap());
+    assert!(store.get(&config.name).unwrap().is_none());
+    assert!(!ImageboardConfig::logout(&config, &store).await.unwrap());
+}
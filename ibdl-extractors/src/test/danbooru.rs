@@ -0,0 +1,550 @@
+use ibdl_common::{
+    post::{extension::Extension, rating::Rating},
+    tokio, ImageBoards,
+};
+use wiremock::matchers::{basic_auth, method, path, path_regex, query_param};
+use wiremock::{Mock, MockServer};
+
+use super::{
+    fetch_all, isolate_auth_cache, json, json_with_status, mock_config, mock_response, post_list,
+    server_error,
+};
+use crate::auth::ImageboardConfig;
+use crate::error::ExtractorError;
+use crate::extractor::caps::{Auth, PoolExtract, SinglePostFetch};
+use crate::extractor::Extractor;
+use crate::imageboards::danbooru::DanbooruExtractor;
+
+/// Posts with a file url in the sample list
+const LIST_SIZE: usize = 199;
+
+/// Serves the sample list as the first page of results and an empty second page.
+async fn mount_search(server: &MockServer, tags: &str) {
+    Mock::given(method("GET"))
+        .and(path("/posts.json"))
+        .and(query_param("tags", tags))
+        .and(query_param("page", "1"))
+        .respond_with(json(post_list("danbooru")))
+        .mount(server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/posts.json"))
+        .and(query_param("tags", tags))
+        .and(query_param("page", "2"))
+        .respond_with(json(mock_response("empty_list.json")))
+        .mount(server)
+        .await;
+}
+
+#[tokio::test]
+async fn danbooru_search() {
+    let server = MockServer::start().await;
+    mount_search(&server, "1girl solo").await;
+
+    let mut extractor = DanbooruExtractor::new_with_config(
+        &["1girl", "solo"],
+        &[],
+        true,
+        true,
+        mock_config("danbooru", &server),
+    );
+
+    let queue = extractor.search(1).await.unwrap();
+
+    assert_eq!(queue.posts.len(), LIST_SIZE);
+    assert_eq!(queue.imageboard, ImageBoards::Danbooru);
+
+    let first = &queue.posts[0];
+    assert_eq!(first.id, 5_689_892);
+    assert_eq!(first.md5, "6f4ba8c628bde9385a91d16738fc4770");
+    assert_eq!(first.extension.to_string(), "png");
+    assert_eq!(first.rating, Rating::Questionable);
+    assert!(first.tags.iter().any(|tag| tag.tag() == "mizunezumi"));
+
+    // Newest posts first
+    assert!(queue.posts.windows(2).all(|pair| pair[0].id > pair[1].id));
+}
+
+#[tokio::test]
+async fn danbooru_full_search_pagination() {
+    let server = MockServer::start().await;
+
+    for (page, body) in [
+        ("1", post_list("danbooru")),
+        ("2", post_list("danbooru")),
+        ("3", mock_response("empty_list.json")),
+    ] {
+        Mock::given(method("GET"))
+            .and(path("/posts.json"))
+            .and(query_param("page", page))
+            .respond_with(json(body))
+            .expect(1)
+            .mount(&server)
+            .await;
+    }
+
+    let mut extractor = DanbooruExtractor::new_with_config(
+        &["1girl"],
+        &[],
+        true,
+        true,
+        mock_config("danbooru", &server),
+    );
+
+    let queue = extractor.full_search(None, None).await.unwrap();
+
+    assert_eq!(queue.posts.len(), LIST_SIZE * 2);
+}
+
+#[tokio::test]
+async fn danbooru_full_search_start_page_and_ratings() {
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/posts.json"))
+        .and(query_param("page", "4"))
+        .and(query_param("limit", "200"))
+        .respond_with(json(post_list("danbooru")))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/posts.json"))
+        .and(query_param("page", "5"))
+        .respond_with(json(mock_response("empty_list.json")))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let mut extractor = DanbooruExtractor::new_with_config(
+        &["1girl"],
+        &[Rating::Safe],
+        true,
+        true,
+        mock_config("danbooru", &server),
+    );
+
+    let queue = extractor.full_search(Some(3), None).await.unwrap();
+
+    assert_eq!(queue.posts.len(), 82);
+    assert!(queue.posts.iter().all(|post| post.rating == Rating::Safe));
+    assert_eq!(extractor.total_removed(), (LIST_SIZE - 82) as u64);
+}
+
+#[tokio::test]
+async fn danbooru_empty_search() {
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/posts.json"))
+        .respond_with(json(mock_response("empty_list.json")))
+        .mount(&server)
+        .await;
+
+    let config = mock_config("danbooru", &server);
+
+    let mut extractor =
+        DanbooruExtractor::new_with_config(&["nothing"], &[], true, true, config.clone());
+    assert!(matches!(
+        extractor.search(1).await,
+        Err(ExtractorError::ZeroPosts)
+    ));
+
+    let extractor = DanbooruExtractor::new_with_config(&["nothing"], &[], true, true, config);
+    let (result, posts) = fetch_all(extractor, None, None).await;
+    assert!(matches!(result, Err(ExtractorError::ZeroPosts)));
+    assert!(posts.is_empty());
+}
+
+#[tokio::test]
+async fn danbooru_server_error() {
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/posts.json"))
+        .respond_with(server_error())
+        .mount(&server)
+        .await;
+
+    let mut extractor = DanbooruExtractor::new_with_config(
+        &["1girl"],
+        &[],
+        true,
+        true,
+        mock_config("danbooru", &server),
+    );
+
+    assert!(matches!(
+        extractor.search(1).await,
+        Err(ExtractorError::JsonSerializeFail(_))
+    ));
+}
+
+#[tokio::test]
+async fn danbooru_async_fetch_limit() {
+    let server = MockServer::start().await;
+    mount_search(&server, "1girl").await;
+
+    let extractor = DanbooruExtractor::new_with_config(
+        &["1girl"],
+        &[],
+        true,
+        true,
+        mock_config("danbooru", &server),
+    );
+
+    let (result, posts) = fetch_all(extractor, None, Some(20)).await;
+
+    assert_eq!(result.unwrap(), 0);
+    assert_eq!(posts.len(), 20);
+    assert_eq!(posts[0].id, 5_689_892);
+}
+
+#[tokio::test]
+async fn danbooru_newer_than() {
+    let server = MockServer::start().await;
+    mount_search(&server, "1girl id:>5689843").await;
+
+    let mut extractor = DanbooruExtractor::new_with_config(
+        &["1girl"],
+        &[],
+        true,
+        true,
+        mock_config("danbooru", &server),
+    );
+    extractor.newer_than(5_689_843);
+
+    let (result, posts) = fetch_all(extractor, None, None).await;
+
+    assert!(result.is_ok());
+    assert_eq!(posts.len(), 49);
+    assert!(posts.iter().all(|post| post.id > 5_689_843));
+}
+
+#[tokio::test]
+async fn danbooru_get_post() {
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/posts/+5689892\.json$"))
+        .respond_with(json(mock_response("danbooru_post.json")))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/posts/+1\.json$"))
+        .respond_with(json_with_status(
+            404,
+            mock_response("danbooru_not_found.json"),
+        ))
+        .mount(&server)
+        .await;
+
+    let mut extractor = DanbooruExtractor::new_with_config(
+        &[""],
+        &[],
+        true,
+        true,
+        mock_config("danbooru", &server),
+    );
+
+    let post = extractor.get_post(5_689_892).await.unwrap();
+
+    assert_eq!(post.id, 5_689_892);
+    assert_eq!(post.md5, "6f4ba8c628bde9385a91d16738fc4770");
+    assert_eq!(post.website, ImageBoards::Danbooru);
+
+    assert!(matches!(
+        extractor.get_post(1).await,
+        Err(ExtractorError::ZeroPosts)
+    ));
+}
+
+#[tokio::test]
+async fn danbooru_pool() {
+    let server = MockServer::start().await;
+    mount_search(&server, "pool:20145").await;
+
+    Mock::given(method("GET"))
+        .and(path("/pools/20145.json"))
+        .respond_with(json(mock_response("danbooru_pool.json")))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let mut extractor = DanbooruExtractor::new_with_config(
+        &[""],
+        &[],
+        true,
+        true,
+        mock_config("danbooru", &server),
+    );
+    extractor.setup_pool_download(Some(20145), false);
+
+    // Fetching the pool beforehand shouldn't make the extractor thread fetch it again
+    let positions = extractor.fetch_pool_idxs(20145, None).await.unwrap();
+    assert_eq!(positions.get(&5_689_892), Some(&0));
+    assert_eq!(positions.get(&5_689_888), Some(&4));
+    assert_eq!(extractor.pool_name().as_deref(), Some("Test Pool (Mock)"));
+
+    let (result, mut posts) = fetch_all(extractor, None, None).await;
+
+    assert!(result.is_ok());
+    posts.sort_by_key(|post| post.id);
+    assert_eq!(
+        posts.iter().map(|post| post.id).collect::<Vec<_>>(),
+        [0, 1, 2, 3, 4]
+    );
+    assert_eq!(posts[0].md5, "6f4ba8c628bde9385a91d16738fc4770");
+}
+
+#[tokio::test]
+async fn danbooru_pool_latest_first() {
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/pools/20145.json"))
+        .respond_with(json(mock_response("danbooru_pool.json")))
+        .mount(&server)
+        .await;
+
+    let mut extractor = DanbooruExtractor::new_with_config(
+        &[""],
+        &[],
+        true,
+        true,
+        mock_config("danbooru", &server),
+    );
+    extractor.setup_pool_download(Some(20145), true);
+
+    let positions = extractor.fetch_pool_idxs(20145, Some(2)).await.unwrap();
+
+    assert_eq!(positions.len(), 2);
+    assert_eq!(positions.get(&5_689_888), Some(&0));
+    assert_eq!(positions.get(&5_689_889), Some(&1));
+}
+
+#[tokio::test]
+async fn danbooru_auth() {
+    isolate_auth_cache();
+
+    let server = MockServer::start().await;
+    let config = mock_config("danbooru", &server);
+
+    Mock::given(method("GET"))
+        .and(path("/profile.json"))
+        .and(basic_auth("mock_user", "mock_key"))
+        .respond_with(json(mock_response("danbooru_profile.json")))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/posts.json"))
+        .and(basic_auth("mock_user", "mock_key"))
+        .respond_with(json(post_list("danbooru")))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let mut extractor =
+        DanbooruExtractor::new_with_config(&["1girl"], &[], true, true, config.clone());
+
+    let mut auth =
+        ImageboardConfig::new(config, String::from("mock_user"), String::from("mock_key"));
+    auth.authenticate(&extractor.client()).await.unwrap();
+
+    assert_eq!(auth.user_data.id, 123_456);
+    assert_eq!(auth.user_data.name, "mock_user");
+    // Commented out lines are not part of the blacklist
+    assert_eq!(auth.user_data.blacklisted_tags, ["guro", "scat"]);
+
+    extractor.auth(auth).await.unwrap();
+
+    let queue = extractor.search(1).await.unwrap();
+    assert_eq!(queue.posts.len(), LIST_SIZE);
+}
+
+#[tokio::test]
+async fn danbooru_auth_blacklist_survives_excluded_tags() {
+    isolate_auth_cache();
+
+    let server = MockServer::start().await;
+    let config = mock_config("danbooru", &server);
+
+    Mock::given(method("GET"))
+        .and(path("/profile.json"))
+        .respond_with(json(
+            mock_response("danbooru_profile.json").replace("guro", "halo"),
+        ))
+        .mount(&server)
+        .await;
+
+    mount_search(&server, "1girl").await;
+
+    let mut extractor =
+        DanbooruExtractor::new_with_config(&["1girl"], &[], false, true, config.clone());
+
+    let mut auth =
+        ImageboardConfig::new(config, String::from("mock_user"), String::from("mock_key"));
+    auth.authenticate(&extractor.client()).await.unwrap();
+
+    // The excluded tags from the command line are added after the account blacklist
+    extractor.auth(auth).await.unwrap();
+    extractor.exclude_tags(&[String::from("glasses")]);
+
+    let queue = extractor.full_search(None, None).await.unwrap();
+    assert!(!queue.posts.is_empty());
+    assert!(queue.posts.len() < LIST_SIZE);
+
+    for post in &queue.posts {
+        let has = |name: &str| post.tags.iter().any(|tag| tag.tag() == name);
+        assert!(!has("halo"), "account blacklist was dropped");
+        assert!(!has("glasses"), "excluded tag was dropped");
+    }
+}
+
+#[tokio::test]
+async fn danbooru_auth_invalid_login() {
+    isolate_auth_cache();
+
+    let server = MockServer::start().await;
+    let config = mock_config("danbooru", &server);
+
+    Mock::given(method("GET"))
+        .and(path("/profile.json"))
+        .respond_with(json_with_status(
+            401,
+            mock_response("danbooru_invalid_login.json"),
+        ))
+        .mount(&server)
+        .await;
+
+    let extractor = DanbooruExtractor::new_with_config(&["1girl"], &[], true, true, config.clone());
+
+    let mut auth = ImageboardConfig::new(config, String::from("mock_user"), String::from("wrong"));
+
+    assert!(matches!(
+        auth.authenticate(&extractor.client()).await,
+        Err(crate::auth::Error::InvalidLogin)
+    ));
+}
+
+#[tokio::test]
+async fn danbooru_query_over_tag_limit() {
+    let server = MockServer::start().await;
+    // Only the first two tags fit in the anonymous tag limit
+    mount_search(&server, "1girl solo").await;
+
+    let extractor = DanbooruExtractor::new_with_config(
+        &[
+            "1girl",
+            "solo",
+            "~long_hair",
+            "~short_hair",
+            "-animal_ears",
+            "rating:general",
+            "ext:jpg,png",
+        ],
+        &[],
+        true,
+        true,
+        mock_config("danbooru", &server),
+    );
+
+    let (result, posts) = fetch_all(extractor, None, None).await;
+
+    result.unwrap();
+    assert_eq!(posts.len(), 51);
+
+    for post in &posts {
+        let has = |name: &str| post.tags.iter().any(|tag| tag.tag() == name);
+
+        assert!(has("long_hair") || has("short_hair"));
+        assert!(!has("animal_ears"));
+        assert_eq!(post.rating, Rating::Safe);
+    }
+}
+
+#[tokio::test]
+async fn danbooru_ugoira_frame_delays() {
+    let server = MockServer::start().await;
+    mount_search(&server, "1girl solo").await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/posts/+5689795\.json$"))
+        .and(query_param("only", "media_metadata"))
+        .respond_with(json(String::from(
+            r#"{"media_metadata": {"metadata": {"Ugoira:FrameDelays": [80, 80, 160]}}}"#,
+        )))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let mut extractor = DanbooruExtractor::new_with_config(
+        &["1girl", "solo"],
+        &[],
+        true,
+        true,
+        mock_config("danbooru", &server),
+    );
+    extractor.fetch_ugoira_frames(true);
+
+    let queue = extractor.search(1).await.unwrap();
+
+    let ugoira = queue
+        .posts
+        .iter()
+        .find(|post| post.id == 5_689_795)
+        .unwrap();
+
+    assert_eq!(ugoira.extension, Extension::Ugoira);
+    assert_eq!(ugoira.frame_delays, Some(vec![80, 80, 160]));
+
+    // Only ugoira posts need the extra request
+    assert!(queue
+        .posts
+        .iter()
+        .filter(|post| post.id != 5_689_795)
+        .all(|post| post.frame_delays.is_none()));
+}
+
+#[tokio::test]
+async fn danbooru_ugoira_frame_delays_are_opt_in() {
+    let server = MockServer::start().await;
+    mount_search(&server, "1girl").await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/posts/+5689795\.json$"))
+        .and(query_param("only", "media_metadata"))
+        .respond_with(json(String::from("{}")))
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    let mut extractor = DanbooruExtractor::new_with_config(
+        &["1girl"],
+        &[],
+        true,
+        true,
+        mock_config("danbooru", &server),
+    );
+
+    let queue = extractor.full_search(None, None).await.unwrap();
+    assert!(queue.posts.iter().all(|post| post.frame_delays.is_none()));
+
+    // Posts removed by the rating filter don't need them either
+    let mut extractor = DanbooruExtractor::new_with_config(
+        &["1girl"],
+        &[Rating::Safe],
+        true,
+        true,
+        mock_config("danbooru", &server),
+    );
+    extractor.fetch_ugoira_frames(true);
+
+    let queue = extractor.full_search(None, None).await.unwrap();
+    assert!(queue.posts.iter().all(|post| post.id != 5_689_795));
+}
@@ -0,0 +1,256 @@
+use ibdl_common::{post::rating::Rating, tokio, ImageBoards};
+use wiremock::matchers::{basic_auth, method, path, path_regex, query_param};
+use wiremock::{Mock, MockServer};
+
+use super::{
+    fetch_all, isolate_auth_cache, json, json_with_status, mock_config, mock_response, post_list,
+    server_error,
+};
+use crate::auth::ImageboardConfig;
+use crate::error::ExtractorError;
+use crate::extractor::caps::{Auth, PoolExtract, SinglePostFetch};
+use crate::extractor::Extractor;
+use crate::imageboards::e621::E621Extractor;
+
+const LIST_SIZE: usize = 200;
+
+async fn mount_search(server: &MockServer, tags: &str) {
+    Mock::given(method("GET"))
+        .and(path("/posts.json"))
+        .and(query_param("tags", tags))
+        .and(query_param("page", "1"))
+        .respond_with(json(post_list("e621")))
+        .mount(server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/posts.json"))
+        .and(query_param("tags", tags))
+        .and(query_param("page", "2"))
+        .respond_with(json(mock_response("e621_empty.json")))
+        .mount(server)
+        .await;
+}
+
+#[tokio::test]
+async fn e621_search() {
+    let server = MockServer::start().await;
+    mount_search(&server, "solo").await;
+
+    let mut extractor =
+        E621Extractor::new_with_config(&["solo"], &[], true, true, mock_config("e621", &server));
+
+    let queue = extractor.search(1).await.unwrap();
+
+    assert_eq!(queue.posts.len(), LIST_SIZE);
+    assert_eq!(queue.imageboard, ImageBoards::E621);
+
+    let first = &queue.posts[0];
+    assert_eq!(first.id, 3_570_027);
+    assert_eq!(first.md5, "810f14a84a680fb952b28b3ad6204463");
+    assert_eq!(first.rating, Rating::Safe);
+    assert!(first.tags.iter().any(|tag| tag.tag() == "oppai_751"));
+}
+
+#[tokio::test]
+async fn e621_full_search_stops_on_short_page() {
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/posts.json"))
+        .and(query_param("page", "1"))
+        .and(query_param("limit", "320"))
+        .respond_with(json(post_list("e621")))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    // A page with less posts than the limit is the last one
+    Mock::given(method("GET"))
+        .and(path("/posts.json"))
+        .and(query_param("page", "2"))
+        .respond_with(json(post_list("e621")))
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    let mut extractor = E621Extractor::new_with_config(
+        &["solo"],
+        &[Rating::Safe, Rating::Questionable],
+        true,
+        true,
+        mock_config("e621", &server),
+    );
+
+    let queue = extractor.full_search(None, None).await.unwrap();
+
+    assert_eq!(queue.posts.len(), 65 + 47);
+    assert!(queue
+        .posts
+        .iter()
+        .all(|post| post.rating != Rating::Explicit));
+}
+
+#[tokio::test]
+async fn e621_async_fetch() {
+    let server = MockServer::start().await;
+    mount_search(&server, "solo id:>3239195").await;
+
+    let mut extractor =
+        E621Extractor::new_with_config(&["solo"], &[], true, true, mock_config("e621", &server));
+    extractor.newer_than(3_239_195);
+
+    let (result, posts) = fetch_all(extractor, None, None).await;
+
+    assert!(result.is_ok());
+    assert_eq!(posts.len(), 49);
+    assert!(posts.iter().all(|post| post.id > 3_239_195));
+}
+
+#[tokio::test]
+async fn e621_empty_search() {
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/posts.json"))
+        .respond_with(json(mock_response("e621_empty.json")))
+        .mount(&server)
+        .await;
+
+    let extractor =
+        E621Extractor::new_with_config(&["nothing"], &[], true, true, mock_config("e621", &server));
+
+    let (result, posts) = fetch_all(extractor, None, None).await;
+
+    assert!(matches!(result, Err(ExtractorError::ZeroPosts)));
+    assert!(posts.is_empty());
+}
+
+#[tokio::test]
+async fn e621_server_error() {
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/posts.json"))
+        .respond_with(server_error())
+        .mount(&server)
+        .await;
+
+    let mut extractor =
+        E621Extractor::new_with_config(&["solo"], &[], true, true, mock_config("e621", &server));
+
+    assert!(extractor.search(1).await.is_err());
+}
+
+#[tokio::test]
+async fn e621_get_post() {
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/posts/+3570027\.json$"))
+        .respond_with(json(mock_response("e621_post.json")))
+        .mount(&server)
+        .await;
+
+    let mut extractor =
+        E621Extractor::new_with_config(&[""], &[], true, true, mock_config("e621", &server));
+
+    let post = extractor.get_post(3_570_027).await.unwrap();
+
+    assert_eq!(post.id, 3_570_027);
+    assert_eq!(post.md5, "810f14a84a680fb952b28b3ad6204463");
+    assert_eq!(post.website, ImageBoards::E621);
+}
+
+#[tokio::test]
+async fn e621_pool() {
+    let server = MockServer::start().await;
+    mount_search(&server, "pool:36957").await;
+
+    Mock::given(method("GET"))
+        .and(path("/pools/36957.json"))
+        .respond_with(json(mock_response("e621_pool.json")))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let mut extractor =
+        E621Extractor::new_with_config(&[""], &[], true, true, mock_config("e621", &server));
+    extractor.setup_pool_download(Some(36957), false);
+
+    extractor.fetch_pool_idxs(36957, None).await.unwrap();
+    assert_eq!(extractor.pool_name().as_deref(), Some("Mock Comic"));
+
+    let (result, mut posts) = fetch_all(extractor, None, None).await;
+
+    assert!(result.is_ok());
+    posts.sort_by_key(|post| post.id);
+    assert_eq!(
+        posts.iter().map(|post| post.id).collect::<Vec<_>>(),
+        [0, 1, 2, 3]
+    );
+    assert_eq!(posts[0].md5, "810f14a84a680fb952b28b3ad6204463");
+}
+
+#[tokio::test]
+async fn e621_auth() {
+    isolate_auth_cache();
+
+    let server = MockServer::start().await;
+    let config = mock_config("e621", &server);
+
+    Mock::given(method("GET"))
+        .and(path("/users/mock_user.json"))
+        .and(basic_auth("mock_user", "mock_key"))
+        .respond_with(json(mock_response("e621_profile.json")))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/posts/+3570027\.json$"))
+        .and(basic_auth("mock_user", "mock_key"))
+        .respond_with(json(mock_response("e621_post.json")))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let mut extractor = E621Extractor::new_with_config(&[""], &[], true, true, config.clone());
+
+    let mut auth =
+        ImageboardConfig::new(config, String::from("mock_user"), String::from("mock_key"));
+    auth.authenticate(&extractor.client()).await.unwrap();
+
+    assert_eq!(auth.user_data.id, 654_321);
+    assert_eq!(auth.user_data.blacklisted_tags, ["gore", "scat"]);
+
+    extractor.auth(auth).await.unwrap();
+
+    assert!(extractor.get_post(3_570_027).await.is_ok());
+}
+
+#[tokio::test]
+async fn e621_auth_invalid_login() {
+    isolate_auth_cache();
+
+    let server = MockServer::start().await;
+    let config = mock_config("e621", &server);
+
+    Mock::given(method("GET"))
+        .and(path("/users/mock_user.json"))
+        .respond_with(json_with_status(
+            401,
+            mock_response("e621_invalid_login.json"),
+        ))
+        .mount(&server)
+        .await;
+
+    let extractor = E621Extractor::new_with_config(&[""], &[], true, true, config.clone());
+
+    let mut auth = ImageboardConfig::new(config, String::from("mock_user"), String::from("wrong"));
+
+    assert!(matches!(
+        auth.authenticate(&extractor.client()).await,
+        Err(crate::auth::Error::InvalidLogin)
+    ));
+}
@@ -0,0 +1,489 @@
+use ibdl_common::{post::rating::Rating, reqwest::Client, serde_json, tokio, ImageBoards};
+use wiremock::matchers::{method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use super::{fetch_all, json, mock_config, mock_response, post_list, server_error};
+use crate::auth::ImageboardConfig;
+use crate::error::ExtractorError;
+use crate::extractor::caps::{Auth, FavoritesExtract, PoolExtract, SinglePostFetch};
+use crate::extractor::Extractor;
+use crate::imageboards::gelbooru::GelbooruExtractor;
+
+const LIST_SIZE: usize = 100;
+
+/// Gelbooru pages start at 0, so the sample list is served as `pid=0` and an empty page as `pid=1`
+async fn mount_search(server: &MockServer, tags: &str) {
+    Mock::given(method("GET"))
+        .and(path("/index.php"))
+        .and(query_param("s", "post"))
+        .and(query_param("tags", tags))
+        .and(query_param("pid", "0"))
+        .respond_with(json(post_list("gb")))
+        .mount(server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/index.php"))
+        .and(query_param("s", "post"))
+        .and(query_param("tags", tags))
+        .and(query_param("pid", "1"))
+        .respond_with(json(mock_response("gelbooru_empty.json")))
+        .mount(server)
+        .await;
+}
+
+/// Serves the posts of the sample list one by one, like DAPI does for a single id. Post 1 is gone.
+async fn mount_posts(server: &MockServer) {
+    let list: serde_json::Value = serde_json::from_str(&post_list("gb")).unwrap();
+
+    for post in list["post"].as_array().unwrap().iter().take(4) {
+        Mock::given(method("GET"))
+            .and(path("/index.php"))
+            .and(query_param("s", "post"))
+            .and(query_param("id", post["id"].to_string()))
+            .respond_with(json(serde_json::json!({ "post": [post] }).to_string()))
+            .mount(server)
+            .await;
+    }
+
+    Mock::given(method("GET"))
+        .and(path("/index.php"))
+        .and(query_param("s", "post"))
+        .and(query_param("id", "1"))
+        .respond_with(json(mock_response("gelbooru_empty.json")))
+        .mount(server)
+        .await;
+}
+
+fn html(body: String) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_raw(body, "text/html")
+}
+
+#[tokio::test]
+async fn gelbooru_search() {
+    let server = MockServer::start().await;
+    mount_search(&server, "1girl").await;
+
+    let mut extractor = GelbooruExtractor::new_with_config(
+        &["1girl"],
+        &[],
+        true,
+        true,
+        mock_config("gelbooru", &server),
+    );
+
+    let queue = extractor.search(0).await.unwrap();
+
+    assert_eq!(queue.posts.len(), LIST_SIZE);
+    assert_eq!(queue.imageboard, ImageBoards::Gelbooru);
+
+    let first = &queue.posts[0];
+    assert_eq!(first.id, 7_729_468);
+    assert_eq!(first.md5, "e3d9abf637615dcc7c03b07a82fbbd1c");
+    assert_eq!(first.extension.to_string(), "png");
+    assert_eq!(first.rating, Rating::Explicit);
+    assert!(first.tags.iter().any(|tag| tag.tag() == "blonde_hair"));
+}
+
+#[tokio::test]
+async fn gelbooru_full_search_pagination() {
+    let server = MockServer::start().await;
+
+    for (pid, body) in [
+        ("0", post_list("gb")),
+        ("1", post_list("gb")),
+        ("2", mock_response("gelbooru_empty.json")),
+    ] {
+        Mock::given(method("GET"))
+            .and(path("/index.php"))
+            .and(query_param("pid", pid))
+            .and(query_param("limit", "100"))
+            .respond_with(json(body))
+            .expect(1)
+            .mount(&server)
+            .await;
+    }
+
+    let mut extractor = GelbooruExtractor::new_with_config(
+        &["1girl"],
+        &[],
+        true,
+        true,
+        mock_config("gelbooru", &server),
+    );
+
+    let queue = extractor.full_search(None, None).await.unwrap();
+
+    assert_eq!(queue.posts.len(), LIST_SIZE * 2);
+}
+
+#[tokio::test]
+async fn gelbooru_async_fetch_limit() {
+    let server = MockServer::start().await;
+    mount_search(&server, "1girl").await;
+
+    let extractor = GelbooruExtractor::new_with_config(
+        &["1girl"],
+        &[],
+        true,
+        true,
+        mock_config("gelbooru", &server),
+    );
+
+    let (result, posts) = fetch_all(extractor, None, Some(10)).await;
+
+    assert!(result.is_ok());
+    assert_eq!(posts.len(), 10);
+    assert!(posts.iter().all(|post| post.id > 7_729_458));
+}
+
+#[tokio::test]
+async fn gelbooru_empty_search() {
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/index.php"))
+        .respond_with(json(mock_response("gelbooru_empty.json")))
+        .mount(&server)
+        .await;
+
+    let extractor = GelbooruExtractor::new_with_config(
+        &["nothing"],
+        &[],
+        true,
+        true,
+        mock_config("gelbooru", &server),
+    );
+
+    let (result, posts) = fetch_all(extractor, None, None).await;
+
+    assert!(matches!(result, Err(ExtractorError::ZeroPosts)));
+    assert!(posts.is_empty());
+}
+
+#[tokio::test]
+async fn gelbooru_server_error() {
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/index.php"))
+        .respond_with(server_error())
+        .mount(&server)
+        .await;
+
+    let mut extractor = GelbooruExtractor::new_with_config(
+        &["1girl"],
+        &[],
+        true,
+        true,
+        mock_config("gelbooru", &server),
+    );
+
+    assert!(extractor.search(0).await.is_err());
+}
+
+#[tokio::test]
+async fn gelbooru_get_post() {
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/index.php"))
+        .and(query_param("id", "7729468"))
+        .respond_with(json(mock_response("gelbooru_post.json")))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/index.php"))
+        .and(query_param("id", "1"))
+        .respond_with(json(mock_response("gelbooru_empty.json")))
+        .mount(&server)
+        .await;
+
+    let mut extractor = GelbooruExtractor::new_with_config(
+        &[""],
+        &[],
+        true,
+        true,
+        mock_config("gelbooru", &server),
+    );
+
+    let post = extractor.get_post(7_729_468).await.unwrap();
+
+    assert_eq!(post.id, 7_729_468);
+    assert_eq!(post.md5, "e3d9abf637615dcc7c03b07a82fbbd1c");
+
+    assert!(matches!(
+        extractor.get_post(1).await,
+        Err(ExtractorError::ZeroPosts)
+    ));
+}
+
+#[tokio::test]
+async fn gelbooru_auth() {
+    let server = MockServer::start().await;
+    let config = mock_config("gelbooru", &server);
+
+    Mock::given(method("GET"))
+        .and(path("/index.php"))
+        .and(query_param("limit", "1"))
+        .and(query_param("api_key", "mock_key"))
+        .and(query_param("user_id", "123456"))
+        .respond_with(json(mock_response("gelbooru_post.json")))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/index.php"))
+        .and(query_param("tags", "1girl"))
+        .and(query_param("api_key", "mock_key"))
+        .and(query_param("user_id", "123456"))
+        .respond_with(json(post_list("gb")))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let mut extractor =
+        GelbooruExtractor::new_with_config(&["1girl"], &[], true, true, config.clone());
+
+    let mut auth = ImageboardConfig::new(config, String::from("123456"), String::from("mock_key"));
+    auth.authenticate(&extractor.client()).await.unwrap();
+
+    assert_eq!(auth.user_data.id, 123_456);
+    assert!(auth.user_data.blacklisted_tags.is_empty());
+
+    extractor.auth(auth).await.unwrap();
+
+    let queue = extractor.search(0).await.unwrap();
+    assert_eq!(queue.posts.len(), LIST_SIZE);
+}
+
+#[tokio::test]
+async fn gelbooru_auth_invalid_login() {
+    let server = MockServer::start().await;
+    let config = mock_config("gelbooru", &server);
+
+    Mock::given(method("GET"))
+        .and(path("/index.php"))
+        .and(query_param("api_key", "wrong"))
+        .respond_with(ResponseTemplate::new(401))
+        .mount(&server)
+        .await;
+
+    // Rule34 sends a message with a successful status
+    Mock::given(method("GET"))
+        .and(path("/index.php"))
+        .and(query_param("api_key", "missing"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_string(
+                "Missing authentication. Go to api.rule34.xxx for more information",
+            ),
+        )
+        .mount(&server)
+        .await;
+
+    let client = Client::new();
+
+    for (user_id, api_key) in [
+        ("123456", "wrong"),
+        ("123456", "missing"),
+        ("mock_user", "key"),
+    ] {
+        let mut auth =
+            ImageboardConfig::new(config.clone(), String::from(user_id), String::from(api_key));
+
+        assert!(matches!(
+            auth.authenticate(&client).await,
+            Err(crate::auth::Error::InvalidLogin)
+        ));
+    }
+}
+
+#[tokio::test]
+async fn rule34_search() {
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/index.php"))
+        .and(query_param("pid", "0"))
+        .respond_with(json(post_list("r34")))
+        .mount(&server)
+        .await;
+
+    // Pages past the last one are empty, not even an empty list
+    Mock::given(method("GET"))
+        .and(path("/index.php"))
+        .and(query_param("pid", "1"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&server)
+        .await;
+
+    let extractor = GelbooruExtractor::new_with_config(
+        &["1girl"],
+        &[],
+        true,
+        true,
+        mock_config("rule34", &server),
+    );
+
+    let (result, posts) = fetch_all(extractor, None, None).await;
+
+    result.unwrap();
+    assert_eq!(posts.len(), 200);
+    assert!(posts.iter().all(|post| post.md5.len() == 32));
+}
+
+#[tokio::test]
+async fn gelbooru_pool() {
+    let server = MockServer::start().await;
+    mount_posts(&server).await;
+
+    Mock::given(method("GET"))
+        .and(path("/index.php"))
+        .and(query_param("page", "pool"))
+        .and(query_param("id", "4242"))
+        .respond_with(html(mock_response("gelbooru_pool.html")))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let mut extractor = GelbooruExtractor::new_with_config(
+        &[""],
+        &[],
+        true,
+        true,
+        mock_config("gelbooru", &server),
+    );
+    extractor.setup_pool_download(Some(4242), false);
+
+    // Fetching the pool beforehand shouldn't make the extractor thread fetch it again
+    let positions = extractor.fetch_pool_idxs(4242, None).await.unwrap();
+    assert_eq!(positions.len(), 4);
+    assert_eq!(positions.get(&7_729_466), Some(&0));
+    assert_eq!(positions.get(&7_729_467), Some(&3));
+    assert_eq!(
+        extractor.pool_name().as_deref(),
+        Some("Test Pool (Mock) & Friends")
+    );
+
+    let (result, mut posts) = fetch_all(extractor, None, None).await;
+
+    // The deleted post keeps its position, so the others aren't renumbered
+    assert!(result.is_ok());
+    posts.sort_by_key(|post| post.id);
+    assert_eq!(
+        posts.iter().map(|post| post.id).collect::<Vec<_>>(),
+        [0, 2, 3]
+    );
+    assert_eq!(posts[0].md5, "f018354dc6e05352bfe4c5e82d797f86");
+    assert_eq!(posts[1].md5, "e3d9abf637615dcc7c03b07a82fbbd1c");
+}
+
+#[tokio::test]
+async fn gelbooru_pool_latest_first() {
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/index.php"))
+        .and(query_param("page", "pool"))
+        .respond_with(html(mock_response("gelbooru_pool.html")))
+        .mount(&server)
+        .await;
+
+    let mut extractor = GelbooruExtractor::new_with_config(
+        &[""],
+        &[],
+        true,
+        true,
+        mock_config("gelbooru", &server),
+    );
+    extractor.setup_pool_download(Some(4242), true);
+
+    let positions = extractor.fetch_pool_idxs(4242, Some(2)).await.unwrap();
+
+    assert_eq!(positions.len(), 2);
+    assert_eq!(positions.get(&7_729_467), Some(&0));
+    assert_eq!(positions.get(&7_729_468), Some(&1));
+}
+
+#[tokio::test]
+async fn gelbooru_favorites() {
+    let server = MockServer::start().await;
+    mount_posts(&server).await;
+
+    Mock::given(method("GET"))
+        .and(path("/index.php"))
+        .and(query_param("page", "favorites"))
+        .and(query_param("id", "123456"))
+        .and(query_param("pid", "0"))
+        .respond_with(html(mock_response("gelbooru_favorites.html")))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/index.php"))
+        .and(query_param("page", "favorites"))
+        .and(query_param("pid", "50"))
+        .respond_with(html(String::from("<html><body></body></html>")))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let mut extractor = GelbooruExtractor::new_with_config(
+        &[""],
+        &[],
+        true,
+        true,
+        mock_config("gelbooru", &server),
+    );
+
+    assert_eq!(
+        extractor.fetch_favorite_ids(123_456, 0).await.unwrap(),
+        [7_729_465, 7_729_468]
+    );
+
+    extractor.setup_favorites_download(Some(123_456));
+
+    let (result, posts) = fetch_all(extractor, None, None).await;
+
+    // Favorites keep the order of the site, and their own ids
+    assert!(result.is_ok());
+    assert_eq!(
+        posts.iter().map(|post| post.id).collect::<Vec<_>>(),
+        [7_729_465, 7_729_468]
+    );
+}
+
+#[tokio::test]
+async fn gelbooru_favorites_stop_on_repeated_page() {
+    let server = MockServer::start().await;
+    mount_posts(&server).await;
+
+    // Every `pid` gets the same page, like sites that clamp it to the last one
+    Mock::given(method("GET"))
+        .and(path("/index.php"))
+        .and(query_param("page", "favorites"))
+        .and(query_param("id", "123456"))
+        .respond_with(html(mock_response("gelbooru_favorites.html")))
+        .expect(2)
+        .mount(&server)
+        .await;
+
+    let mut extractor = GelbooruExtractor::new_with_config(
+        &[""],
+        &[],
+        true,
+        true,
+        mock_config("gelbooru", &server),
+    );
+    extractor.setup_favorites_download(Some(123_456));
+
+    let (result, posts) = fetch_all(extractor, None, None).await;
+
+    assert!(result.is_ok());
+    assert_eq!(
+        posts.iter().map(|post| post.id).collect::<Vec<_>>(),
+        [7_729_465, 7_729_468]
+    );
+}
@@ -0,0 +1,129 @@
+#![cfg(test)]
+//! Offline extractor tests.
+//!
+//! Every test starts a local [`MockServer`] serving the fixtures in `assets/` and points the
+//! extractor to it by rewriting the urls of its [`ServerConfig`], so no requests leave the machine.
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+use std::sync::Once;
+
+use ibdl_common::post::Post;
+use ibdl_common::retry::RetryPolicy;
+use ibdl_common::tokio::sync::mpsc::unbounded_channel;
+use wiremock::{MockServer, ResponseTemplate};
+
+use crate::error::ExtractorError;
+use crate::extractor::caps::AsyncFetch;
+use crate::extractor_config::{ServerConfig, DEFAULT_SERVERS};
+
+mod auth;
+mod danbooru;
+mod e621;
+mod gelbooru;
+mod moebooru;
+mod query;
+mod retry;
+
+fn assets_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../assets")
+}
+
+/// One of the sample post lists in `assets/sample_post_lists`
+fn post_list(name: &str) -> String {
+    read_to_string(assets_dir().join(format!("sample_post_lists/test_list_{name}.json"))).unwrap()
+}
+
+/// One of the canned server responses in `assets/mock_responses`
+fn mock_response(name: &str) -> String {
+    read_to_string(assets_dir().join("mock_responses").join(name)).unwrap()
+}
+
+fn json(body: String) -> ResponseTemplate {
+    json_with_status(200, body)
+}
+
+fn json_with_status(status: u16, body: String) -> ResponseTemplate {
+    ResponseTemplate::new(status).set_body_raw(body, "application/json")
+}
+
+fn server_error() -> ResponseTemplate {
+    ResponseTemplate::new(502).set_body_raw(mock_response("server_error.html"), "text/html")
+}
+
+/// Replaces the scheme and host of `url` with the ones of the mock server.
+fn rebase(url: &str, server: &MockServer) -> String {
+    let path = url.split_once("://").map_or(url, |(_, rest)| {
+        rest.find('/').map_or("", |idx| &rest[idx..])
+    });
+
+    format!("{}{path}", server.uri())
+}
+
+/// Config of the built-in server `name`, with all urls pointing to the mock server.
+fn mock_config(name: &str, server: &MockServer) -> ServerConfig {
+    let mut config = DEFAULT_SERVERS.get(name).unwrap().clone();
+
+    config.base_url = rebase(&config.base_url, server);
+
+    for url in [
+        &mut config.post_url,
+        &mut config.post_list_url,
+        &mut config.pool_idx_url,
+        &mut config.auth_url,
+        &mut config.image_url,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        *url = rebase(url, server);
+    }
+
+    config.rate_limit = None;
+    config.retry = fast_retry();
+
+    config
+}
+
+/// Retries without waiting, so tests with failing responses finish quickly.
+const fn fast_retry() -> RetryPolicy {
+    RetryPolicy {
+        attempts: 2,
+        backoff_ms: 1,
+        max_backoff_ms: 1,
+        jitter: false,
+    }
+}
+
+/// Keeps the auth cache written while testing logins out of the user's config dir.
+fn isolate_auth_cache() {
+    static INIT: Once = Once::new();
+
+    INIT.call_once(|| {
+        std::env::set_var(
+            "IBDL_CACHE_DIR",
+            std::env::temp_dir().join("ibdl-extractors-test"),
+        );
+    });
+}
+
+/// Runs the async extractor thread of `unit` to completion, returning its result and every post it
+/// sent through the channel.
+async fn fetch_all<E: AsyncFetch>(
+    unit: E,
+    start_page: Option<u16>,
+    limit: Option<u16>,
+) -> (Result<u64, ExtractorError>, Vec<Post>) {
+    let (tx, mut rx) = unbounded_channel();
+
+    let result = unit
+        .setup_fetch_thread(tx, start_page, limit, None)
+        .await
+        .unwrap();
+
+    let mut posts = Vec::new();
+    while let Some(post) = rx.recv().await {
+        posts.push(post);
+    }
+
+    (result, posts)
+}
@@ -0,0 +1,331 @@
+use ibdl_common::{post::rating::Rating, reqwest::Client, tokio, ImageBoards};
+use wiremock::matchers::{method, path, query_param};
+use wiremock::{Mock, MockServer};
+
+use super::{
+    fetch_all, isolate_auth_cache, json, mock_config, mock_response, post_list, server_error,
+};
+use crate::auth::{password_hash, ImageboardConfig};
+use crate::error::ExtractorError;
+use crate::extractor::caps::{Auth, PoolExtract, SinglePostFetch};
+use crate::extractor::Extractor;
+use crate::extractor_config::DEFAULT_SERVERS;
+use crate::imageboards::moebooru::MoebooruExtractor;
+
+/// SHA1 of `mock_password` with the salt of Konachan
+const KONACHAN_HASH: &str = "770c46618c2e2fddec278af2f3e876ee8b0821a8";
+
+const LIST_SIZE: usize = 200;
+
+async fn mount_search(server: &MockServer, tags: &str) {
+    Mock::given(method("GET"))
+        .and(path("/post.json"))
+        .and(query_param("tags", tags))
+        .and(query_param("page", "1"))
+        .respond_with(json(post_list("konachan")))
+        .mount(server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/post.json"))
+        .and(query_param("tags", tags))
+        .and(query_param("page", "2"))
+        .respond_with(json(mock_response("empty_list.json")))
+        .mount(server)
+        .await;
+}
+
+#[tokio::test]
+async fn moebooru_search() {
+    let server = MockServer::start().await;
+    mount_search(&server, "1girl").await;
+
+    let mut extractor = MoebooruExtractor::new_with_config(
+        &["1girl"],
+        &[],
+        true,
+        true,
+        mock_config("konachan", &server),
+    );
+
+    let queue = extractor.search(1).await.unwrap();
+
+    assert_eq!(queue.posts.len(), LIST_SIZE);
+    assert_eq!(queue.imageboard, ImageBoards::Moebooru);
+
+    let first = &queue.posts[0];
+    assert_eq!(first.id, 347_443);
+    assert_eq!(first.md5, "afe80d2e8ab810a8b1193e39dd4f658b");
+    assert_eq!(first.extension.to_string(), "png");
+    assert_eq!(first.rating, Rating::Safe);
+    assert!(first.tags.iter().any(|tag| tag.tag() == "mahjong_soul"));
+}
+
+#[tokio::test]
+async fn moebooru_full_search_pagination() {
+    let server = MockServer::start().await;
+
+    for (page, body) in [
+        ("1", post_list("konachan")),
+        ("2", post_list("konachan")),
+        ("3", mock_response("empty_list.json")),
+    ] {
+        Mock::given(method("GET"))
+            .and(path("/post.json"))
+            .and(query_param("page", page))
+            .respond_with(json(body))
+            .expect(1)
+            .mount(&server)
+            .await;
+    }
+
+    let mut extractor = MoebooruExtractor::new_with_config(
+        &["1girl"],
+        &[Rating::Explicit],
+        true,
+        true,
+        mock_config("konachan", &server),
+    );
+
+    let queue = extractor.full_search(None, None).await.unwrap();
+
+    assert_eq!(queue.posts.len(), 17 * 2);
+    assert!(queue
+        .posts
+        .iter()
+        .all(|post| post.rating == Rating::Explicit));
+}
+
+#[tokio::test]
+async fn moebooru_async_fetch() {
+    let server = MockServer::start().await;
+    mount_search(&server, "1girl id:>347327").await;
+
+    let mut extractor = MoebooruExtractor::new_with_config(
+        &["1girl"],
+        &[],
+        true,
+        true,
+        mock_config("konachan", &server),
+    );
+    extractor.newer_than(347_327);
+
+    let (result, posts) = fetch_all(extractor, None, None).await;
+
+    assert!(result.is_ok());
+    assert_eq!(posts.len(), 99);
+    assert!(posts.iter().all(|post| post.id > 347_327));
+}
+
+#[tokio::test]
+async fn moebooru_empty_search() {
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/post.json"))
+        .respond_with(json(mock_response("empty_list.json")))
+        .mount(&server)
+        .await;
+
+    let extractor = MoebooruExtractor::new_with_config(
+        &["nothing"],
+        &[],
+        true,
+        true,
+        mock_config("konachan", &server),
+    );
+
+    let (result, posts) = fetch_all(extractor, None, None).await;
+
+    assert!(matches!(result, Err(ExtractorError::ZeroPosts)));
+    assert!(posts.is_empty());
+}
+
+#[tokio::test]
+async fn moebooru_server_error() {
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/post.json"))
+        .respond_with(server_error())
+        .mount(&server)
+        .await;
+
+    let mut extractor = MoebooruExtractor::new_with_config(
+        &["1girl"],
+        &[],
+        true,
+        true,
+        mock_config("konachan", &server),
+    );
+
+    assert!(matches!(
+        extractor.search(1).await,
+        Err(ExtractorError::JsonSerializeFail(_))
+    ));
+}
+
+#[tokio::test]
+async fn moebooru_get_post() {
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/post.json"))
+        .and(query_param("tags", "id:347443"))
+        .respond_with(json(mock_response("konachan_post.json")))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/post.json"))
+        .and(query_param("tags", "id:1"))
+        .respond_with(json(mock_response("empty_list.json")))
+        .mount(&server)
+        .await;
+
+    let mut extractor = MoebooruExtractor::new_with_config(
+        &[""],
+        &[],
+        true,
+        true,
+        mock_config("konachan", &server),
+    );
+
+    let post = extractor.get_post(347_443).await.unwrap();
+
+    assert_eq!(post.id, 347_443);
+    assert_eq!(post.md5, "afe80d2e8ab810a8b1193e39dd4f658b");
+    assert_eq!(post.website, ImageBoards::Moebooru);
+
+    assert!(matches!(
+        extractor.get_post(1).await,
+        Err(ExtractorError::ZeroPosts)
+    ));
+}
+
+#[tokio::test]
+async fn moebooru_pool() {
+    let server = MockServer::start().await;
+    mount_search(&server, "pool:1234").await;
+
+    Mock::given(method("GET"))
+        .and(path("/pool/show.json"))
+        .and(query_param("id", "1234"))
+        .respond_with(json(mock_response("konachan_pool.json")))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let mut extractor = MoebooruExtractor::new_with_config(
+        &[""],
+        &[],
+        true,
+        true,
+        mock_config("konachan", &server),
+    );
+    extractor.setup_pool_download(Some(1234), false);
+
+    // Fetching the pool beforehand shouldn't make the extractor thread fetch it again
+    let positions = extractor.fetch_pool_idxs(1234, None).await.unwrap();
+    assert_eq!(positions.get(&347_439), Some(&0));
+    assert_eq!(positions.get(&347_443), Some(&2));
+    assert_eq!(extractor.pool_name().as_deref(), Some("Test Pool (Mock)"));
+
+    let (result, mut posts) = fetch_all(extractor, None, None).await;
+
+    assert!(result.is_ok());
+    posts.sort_by_key(|post| post.id);
+    assert_eq!(
+        posts.iter().map(|post| post.id).collect::<Vec<_>>(),
+        [0, 1, 2]
+    );
+    assert_eq!(posts[2].md5, "afe80d2e8ab810a8b1193e39dd4f658b");
+}
+
+#[test]
+fn moebooru_password_hash() {
+    let konachan = DEFAULT_SERVERS.get("konachan").unwrap();
+    let yandere = DEFAULT_SERVERS.get("yandere").unwrap();
+
+    assert_eq!(password_hash(konachan, "mock_password"), KONACHAN_HASH);
+    assert_eq!(
+        password_hash(yandere, "mock_password"),
+        "5c1b8e8994e050025c7b7ac89bc4a0ab70be0044"
+    );
+}
+
+#[tokio::test]
+async fn moebooru_auth() {
+    isolate_auth_cache();
+
+    let server = MockServer::start().await;
+    let config = mock_config("konachan", &server);
+
+    Mock::given(method("GET"))
+        .and(path("/user.json"))
+        .and(query_param("login", "mock_user"))
+        .and(query_param("password_hash", KONACHAN_HASH))
+        .respond_with(json(mock_response("konachan_user.json")))
+        .expect(2)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/post.json"))
+        .and(query_param("login", "mock_user"))
+        .and(query_param("password_hash", KONACHAN_HASH))
+        .respond_with(json(post_list("konachan")))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let mut extractor =
+        MoebooruExtractor::new_with_config(&["1girl"], &[], true, true, config.clone());
+
+    let mut auth = ImageboardConfig::new(
+        config,
+        String::from("mock_user"),
+        String::from("mock_password"),
+    );
+    auth.authenticate(&extractor.client()).await.unwrap();
+
+    assert_eq!(auth.user_data.id, 123_456);
+    assert_eq!(auth.user_data.name, "mock_user");
+    // Only the hash of the password is kept
+    assert_eq!(auth.api_key, KONACHAN_HASH);
+
+    // Logging in again with the saved hash doesn't hash it twice
+    let mut saved = auth.clone();
+    saved.authenticate(&Client::new()).await.unwrap();
+    assert_eq!(saved.api_key, KONACHAN_HASH);
+
+    extractor.auth(auth).await.unwrap();
+
+    let queue = extractor.search(1).await.unwrap();
+    assert_eq!(queue.posts.len(), LIST_SIZE);
+}
+
+#[tokio::test]
+async fn moebooru_auth_unknown_user() {
+    isolate_auth_cache();
+
+    let server = MockServer::start().await;
+    let config = mock_config("konachan", &server);
+
+    Mock::given(method("GET"))
+        .and(path("/user.json"))
+        .respond_with(json(mock_response("empty_list.json")))
+        .mount(&server)
+        .await;
+
+    let mut auth = ImageboardConfig::new(
+        config,
+        String::from("nobody"),
+        String::from("mock_password"),
+    );
+
+    assert!(matches!(
+        auth.authenticate(&Client::new()).await,
+        Err(crate::auth::Error::InvalidLogin)
+    ));
+}
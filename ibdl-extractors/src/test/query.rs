@@ -0,0 +1,182 @@
+use ibdl_common::post::{extension::Extension, rating::Rating, tags::Tag, tags::TagType, Post};
+use ibdl_common::ImageBoards;
+
+use crate::error::QueryError;
+use crate::query::Query;
+
+fn post(id: u64, rating: Rating, extension: Extension, tags: &[&str]) -> Post {
+    Post {
+        id,
+        website: ImageBoards::Danbooru,
+        md5: String::from("6f4ba8c628bde9385a91d16738fc4770"),
+        url: String::new(),
+        extension,
+        tags: tags
+            .iter()
+            .map(|tag| Tag::new(tag, TagType::General))
+            .collect(),
+        rating,
+        frame_delays: None,
+    }
+}
+
+/// Checks `query` entirely on the client, with only a server metatag sent to the server.
+fn matches(query: &str, post: &Post) -> bool {
+    let (server_tags, filter) = Query::parse(&format!("order:id {query}"))
+        .unwrap()
+        .split(Some(0));
+    assert_eq!(server_tags, ["order:id"]);
+    filter.matches(post)
+}
+
+#[test]
+fn query_tags_and_negation() {
+    let cat = post(1, Rating::Safe, Extension::PNG, &["cat", "solo"]);
+
+    assert!(matches("cat solo", &cat));
+    assert!(matches("CAT", &cat));
+    assert!(!matches("cat dog", &cat));
+    assert!(!matches("cat -solo", &cat));
+    assert!(matches("-dog", &cat));
+}
+
+#[test]
+fn query_any_of() {
+    let cat = post(1, Rating::Safe, Extension::PNG, &["cat", "solo"]);
+
+    assert!(matches("~cat ~dog", &cat));
+    assert!(!matches("~bird ~dog", &cat));
+    assert!(matches("solo ~bird ~cat", &cat));
+    // A single ~ term is required
+    assert!(!matches("~dog", &cat));
+}
+
+#[test]
+fn query_wildcards() {
+    let post = post(1, Rating::Safe, Extension::PNG, &["long_hair", "cat_ears"]);
+
+    assert!(matches("long_*", &post));
+    assert!(matches("*_ears", &post));
+    assert!(matches("c*t*rs", &post));
+    assert!(!matches("short_*", &post));
+    assert!(!matches("-*_hair", &post));
+}
+
+#[test]
+fn query_groups() {
+    let post = post(
+        1,
+        Rating::Safe,
+        Extension::PNG,
+        &["kroos_(arknights)", "solo"],
+    );
+
+    // Parentheses inside tag names don't start groups
+    assert!(matches("kroos_(arknights)", &post));
+    assert!(matches("( kroos_(arknights) solo )", &post));
+    assert!(matches("(kroos_(arknights) solo)", &post));
+    assert!(!matches("-(kroos_(arknights) solo)", &post));
+    assert!(matches("~(cat dog) ~(kroos_(arknights) solo)", &post));
+    assert!(!matches("~(cat dog) ~(bird solo)", &post));
+}
+
+#[test]
+fn query_metatags() {
+    let post = post(1500, Rating::Questionable, Extension::JPG, &["solo"]);
+
+    assert!(matches("rating:q", &post));
+    assert!(matches("rating:safe,questionable", &post));
+    assert!(!matches("rating:explicit", &post));
+    assert!(matches("-rating:e", &post));
+    assert!(matches("ext:jpg", &post));
+    assert!(matches("filetype:png,jpeg", &post));
+    assert!(!matches("ext:png", &post));
+    assert!(matches("id:1500", &post));
+    assert!(matches("id:>1000", &post));
+    assert!(matches("id:>=1500", &post));
+    assert!(!matches("id:<1500", &post));
+    assert!(matches("id:<=1500", &post));
+    assert!(matches("id:1000..2000", &post));
+    assert!(matches("md5:6F4BA8C628BDE9385A91D16738FC4770", &post));
+}
+
+#[test]
+fn query_errors() {
+    assert_eq!(Query::parse("( cat"), Err(QueryError::UnclosedGroup));
+    assert_eq!(Query::parse("cat )"), Err(QueryError::UnmatchedClose));
+    assert_eq!(
+        Query::parse("rating:maybe"),
+        Err(QueryError::InvalidMetaValue {
+            key: String::from("rating"),
+            value: String::from("maybe")
+        })
+    );
+    assert!(Query::parse("id:>abc").is_err());
+    assert!(Query::parse("ext:docx").is_err());
+    assert_eq!(
+        Query::parse("~order:score ~cat"),
+        Err(QueryError::NestedServerTerm {
+            term: String::from("order:score")
+        })
+    );
+
+    // Tags ending in a parenthesis are fine outside of groups
+    assert!(Query::parse(":)").is_ok());
+
+    // The server needs something to search for
+    assert_eq!(Query::parse("~cat ~dog"), Err(QueryError::NoServerTerm));
+    assert_eq!(
+        Query::parse("~(cat solo) ~(dog solo) rating:safe"),
+        Err(QueryError::NoServerTerm)
+    );
+    assert!(Query::parse("").is_ok());
+    assert!(Query::parse("~cat ~dog solo").is_ok());
+    assert!(Query::parse("~cat ~dog score:>100").is_ok());
+}
+
+#[test]
+fn query_unknown_metatags_go_to_the_server() {
+    let query = Query::parse("cat commenter:someone -Status:deleted >:( :d").unwrap();
+
+    let (server_tags, filter) = query.split(Some(0));
+    assert_eq!(server_tags, ["commenter:someone", "-Status:deleted"]);
+
+    // Tags with colons are still checked as tags
+    let post = post(1, Rating::Safe, Extension::PNG, &["cat", ">:(", ":d"]);
+    assert!(filter.matches(&post));
+}
+
+#[test]
+fn query_split() {
+    let query = Query::parse("~cat ~dog solo long_* -monochrome rating:safe order:score").unwrap();
+
+    let (server_tags, filter) = query.split(None);
+    assert_eq!(
+        server_tags,
+        ["order:score", "solo", "long_*", "-monochrome"]
+    );
+    assert!(!filter.is_empty());
+
+    // Server metatags go first and are kept even over the limit
+    let (server_tags, _) = query.split(Some(2));
+    assert_eq!(server_tags, ["order:score", "solo"]);
+
+    let (server_tags, filter) = query.split(Some(0));
+    assert_eq!(server_tags, ["order:score"]);
+
+    // Terms left for the client are still checked
+    let solo = post(
+        1,
+        Rating::Safe,
+        Extension::PNG,
+        &["cat", "solo", "long_hair"],
+    );
+    assert!(filter.matches(&solo));
+    let monochrome = post(
+        2,
+        Rating::Safe,
+        Extension::PNG,
+        &["cat", "long_hair", "monochrome"],
+    );
+    assert!(!filter.matches(&monochrome));
+}
@@ -0,0 +1,159 @@
+use std::time::{Duration, Instant};
+
+use ibdl_common::tokio;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use super::{json, mock_config, mock_response, post_list, server_error};
+use crate::error::ExtractorError;
+use crate::extractor::Extractor;
+use crate::imageboards::danbooru::DanbooruExtractor;
+
+#[tokio::test]
+async fn retry_server_error() {
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/posts.json"))
+        .respond_with(server_error())
+        .up_to_n_times(1)
+        .with_priority(1)
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/posts.json"))
+        .respond_with(json(post_list("danbooru")))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let mut extractor = DanbooruExtractor::new_with_config(
+        &["1girl"],
+        &[],
+        true,
+        true,
+        mock_config("danbooru", &server),
+    );
+
+    assert!(extractor.search(1).await.is_ok());
+}
+
+#[tokio::test]
+async fn retry_gives_up() {
+    let server = MockServer::start().await;
+
+    // The first attempt and two retries
+    Mock::given(method("GET"))
+        .and(path("/posts.json"))
+        .respond_with(server_error())
+        .expect(3)
+        .mount(&server)
+        .await;
+
+    let mut extractor = DanbooruExtractor::new_with_config(
+        &["1girl"],
+        &[],
+        true,
+        true,
+        mock_config("danbooru", &server),
+    );
+
+    assert!(matches!(
+        extractor.search(1).await,
+        Err(ExtractorError::JsonSerializeFail(_))
+    ));
+}
+
+#[tokio::test]
+async fn retry_honors_retry_after() {
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/posts.json"))
+        .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/posts.json"))
+        .respond_with(json(post_list("danbooru")))
+        .mount(&server)
+        .await;
+
+    let mut config = mock_config("danbooru", &server);
+    config.retry.max_backoff_ms = 5_000;
+
+    let mut extractor = DanbooruExtractor::new_with_config(&["1girl"], &[], true, true, config);
+
+    let start = Instant::now();
+
+    assert!(extractor.search(1).await.is_ok());
+    assert!(start.elapsed() >= Duration::from_secs(1));
+}
+
+#[tokio::test]
+async fn retry_after_is_capped() {
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/posts.json"))
+        .respond_with(ResponseTemplate::new(503).insert_header("Retry-After", "3600"))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path("/posts.json"))
+        .respond_with(json(post_list("danbooru")))
+        .mount(&server)
+        .await;
+
+    let mut extractor = DanbooruExtractor::new_with_config(
+        &["1girl"],
+        &[],
+        true,
+        true,
+        mock_config("danbooru", &server),
+    );
+
+    let start = Instant::now();
+
+    assert!(extractor.search(1).await.is_ok());
+    assert!(start.elapsed() < Duration::from_secs(10));
+}
+
+#[tokio::test]
+async fn rate_limit() {
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/posts.json"))
+        .respond_with(json(mock_response("empty_list.json")))
+        .expect(5)
+        .mount(&server)
+        .await;
+
+    let mut config = mock_config("danbooru", &server);
+    // Limiters are shared by name, so keep this one apart from other tests
+    config.name = String::from("rate_limited_danbooru");
+    config.rate_limit = Some(20.0);
+
+    let mut extractor = DanbooruExtractor::new_with_config(&["1girl"], &[], true, true, config);
+
+    let start = Instant::now();
+
+    for page in 1..=5 {
+        assert!(matches!(
+            extractor.search(page).await,
+            Err(ExtractorError::ZeroPosts)
+        ));
+    }
+
+    // Four waits of 50ms between five requests
+    assert!(start.elapsed() >= Duration::from_millis(200));
+}
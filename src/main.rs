@@ -3,22 +3,34 @@ use color_eyre::eyre::{bail, Result};
 use color_eyre::owo_colors::OwoColorize;
 use dialoguer::theme::ColorfulTheme;
 use dialoguer::Confirm;
+use ibdl_common::events::{self, emit, Event, Summary};
 use ibdl_common::tokio::sync::mpsc::{channel, unbounded_channel};
 use ibdl_common::tokio::{self, join};
-use ibdl_core::async_queue::Queue;
-use ibdl_core::clap::Parser;
+use ibdl_core::cli::commands::search::ServerSummary;
 use ibdl_core::cli::{Cli, Commands, AVAILABLE_SERVERS};
+use ibdl_core::listing::{ListSummary, PostList};
+use ibdl_core::progress_bars::ProgressMode;
 use ibdl_extractors::prelude::ExtractorFeatures;
 use once_cell::sync::Lazy;
+use std::collections::BTreeMap;
+use std::io::{stderr, stdout, Write};
 use std::process::exit;
 use std::sync::atomic::AtomicU64;
 use std::sync::Arc;
 
 static POST_COUNTER: Lazy<Arc<AtomicU64>> = Lazy::new(|| Arc::new(AtomicU64::new(0)));
 
+/// Exit code of runs where some posts or jobs failed, but the rest was downloaded.
+const PARTIAL_FAILURE: i32 = 2;
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    let args: Cli = Cli::parse();
+    let mut args = Cli::parse_with_config()?;
+    args.select_servers()?;
+
+    if args.progress == ProgressMode::Json {
+        events::enable_json(Box::new(stdout()));
+    }
 
     if args.servers {
         print_servers()
@@ -27,9 +39,38 @@ async fn main() -> Result<()> {
     env_logger::builder().format_timestamp(None).init();
     color_eyre::install()?;
 
+    match &args.mode {
+        Commands::Archive(com) => {
+            com.run(&args)?;
+            exit(0);
+        }
+        Commands::Subscribe(com) => {
+            com.run(&args)?;
+            exit(0);
+        }
+        Commands::Update(com) => {
+            com.run(&args).await?;
+            exit(exit_code());
+        }
+        Commands::Batch(com) => {
+            com.run(&args).await?;
+            exit(exit_code());
+        }
+        Commands::Retry(com) => {
+            com.run(&args).await?;
+            exit(exit_code());
+        }
+        Commands::Auth(com) => {
+            com.run(&args).await?;
+            exit(0);
+        }
+        _ => {}
+    }
+
     let dirname = args.generate_save_path()?;
 
-    if (dirname.exists() && (dirname.is_file() || dirname.read_dir()?.next().is_some()))
+    if !args.dry_run
+        && (dirname.exists() && (dirname.is_file() || dirname.read_dir()?.next().is_some()))
         && !args.overwrite
     {
         let conf_exists = Confirm::with_theme(&ColorfulTheme::default())
@@ -50,24 +91,110 @@ async fn main() -> Result<()> {
     let (length_sender, length_channel) = channel(args.simultaneous_downloads as usize);
     let mut is_pool = false;
 
-    let (ext, client) = match &args.mode {
-        Commands::Search(com) => com.init_extractor(&args, channel_tx, length_sender).await?,
+    if let Commands::Search(com) = &args.mode {
+        if args.imageboards.len() > 1 {
+            let (ext, client, sources) = com
+                .init_multi_extractor(
+                    &args.imageboards,
+                    args.auth,
+                    args.ugoira.is_some(),
+                    channel_tx,
+                    length_sender,
+                )
+                .await?;
+
+            if args.dry_run {
+                let mut list = PostList::new(args.imageboard.clone(), args.list_format);
+                list.post_sources(sources);
+
+                let writer =
+                    list.setup_list_writer(args.list_output()?, channel_rx, length_channel);
+
+                let (Ok(summaries), Ok(listed)) = join!(ext, writer) else {
+                    bail!("Failed starting threads!")
+                };
+
+                print_list_summary(
+                    &listed?,
+                    summaries.iter().map(|server| server.removed).sum(),
+                );
+                print_server_summaries(&mut stderr(), &summaries)?;
+
+                return Ok(());
+            }
+
+            let mut qw = args.setup_queue(&args.imageboard, client, args.cbz, false)?;
+            let (title, summary) = com.comic_info(&args.imageboards);
+            qw.comic_info(title, Some(summary));
+            qw.post_sources(sources);
+
+            let asd = qw.setup_async_downloader(
+                dirname,
+                POST_COUNTER.clone(),
+                channel_rx,
+                length_channel,
+            );
+
+            let (Ok(summaries), Ok(results)) = join!(ext, asd) else {
+                bail!("Failed starting threads!")
+            };
+
+            let total_down = results?;
+            print_results(
+                total_down,
+                summaries.iter().map(|server| server.removed).sum(),
+                &summaries,
+            )?;
+
+            exit(exit_code());
+        }
+    }
+
+    let (ext, client, (title, summary)) = match &args.mode {
+        Commands::Search(com) => {
+            let (ext, client) = com.init_extractor(&args, channel_tx, length_sender).await?;
+            (ext, client, com.comic_info(&args.imageboards))
+        }
         Commands::Pool(com) => {
             is_pool = true;
-            com.init_extractor(&args, channel_tx, length_sender).await?
+            let (ext, client, pool_name) =
+                com.init_extractor(&args, channel_tx, length_sender).await?;
+            (ext, client, com.comic_info(&args.imageboard, pool_name))
+        }
+        Commands::Post(com) => {
+            let (ext, client) = com.init_extractor(&args, channel_tx, length_sender).await?;
+            (ext, client, com.comic_info(&args.imageboard))
+        }
+        Commands::Favorites(com) => {
+            let (ext, client, user_id) =
+                com.init_extractor(&args, channel_tx, length_sender).await?;
+            (ext, client, com.comic_info(&args.imageboard, user_id))
+        }
+        Commands::Archive(_)
+        | Commands::Subscribe(_)
+        | Commands::Update(_)
+        | Commands::Batch(_)
+        | Commands::Retry(_)
+        | Commands::Auth(_) => {
+            unreachable!("Command is handled before downloading")
         }
-        Commands::Post(com) => com.init_extractor(&args, channel_tx, length_sender).await?,
     };
 
-    let qw = Queue::new(
-        args.imageboard.clone(),
-        args.simultaneous_downloads,
-        Some(client),
-        args.cbz,
-        is_pool,
-        args.name_type(),
-        args.annotate,
-    );
+    if args.dry_run {
+        let list = PostList::new(args.imageboard.clone(), args.list_format);
+        let writer = list.setup_list_writer(args.list_output()?, channel_rx, length_channel);
+
+        let (Ok(removed), Ok(listed)) = join!(ext, writer) else {
+            bail!("Failed starting threads!")
+        };
+
+        print_list_summary(&listed?, removed?);
+
+        return Ok(());
+    }
+
+    let mut qw = args.setup_queue(&args.imageboard, client, args.cbz, is_pool)?;
+    qw.comic_info(title, Some(summary));
 
     let asd = qw.setup_async_downloader(dirname, POST_COUNTER.clone(), channel_rx, length_channel);
 
@@ -75,12 +202,40 @@ async fn main() -> Result<()> {
         bail!("Failed starting threads!")
     };
 
-    print_results(results?, removed?);
+    print_results(results?, removed?, &[])?;
 
-    Ok(())
+    exit(exit_code());
+}
+
+fn exit_code() -> i32 {
+    if events::summary().partial_failure() {
+        PARTIAL_FAILURE
+    } else {
+        0
+    }
 }
 
-fn print_results(total_down: u64, total_black: u64) {
+fn print_results(total_down: u64, total_black: u64, servers: &[ServerSummary]) -> Result<()> {
+    // Always emitted, so servers that failed are counted in the exit code
+    for server in servers {
+        emit(&Event::ServerFinished {
+            server: &server.server.name,
+            found: server.found,
+            duplicates: server.duplicates,
+            removed: server.removed,
+            error: server.error.as_deref(),
+        });
+    }
+
+    if events::json_enabled() {
+        emit(&Event::Summary(Summary {
+            removed: total_black,
+            ..events::summary()
+        }));
+
+        return Ok(());
+    }
+
     println!(
         "{} {} {}",
         total_down.to_string().bold().blue(),
@@ -97,6 +252,89 @@ fn print_results(total_down: u64, total_black: u64) {
                 .red()
         );
     }
+
+    let failed = events::summary().failed;
+
+    if failed > 0 {
+        println!(
+            "{} {}",
+            failed.to_string().bold().red(),
+            "files failed to download.".bold().red()
+        );
+    }
+
+    print_server_summaries(&mut stdout(), servers)
+}
+
+/// Printed to stderr, so the listed posts can be piped from stdout.
+fn print_list_summary(summary: &ListSummary, removed: u64) {
+    eprintln!(
+        "{} {}",
+        summary.posts.to_string().bold().blue(),
+        "posts would be downloaded".bold()
+    );
+
+    let counts = |counts: &BTreeMap<String, u64>| {
+        counts
+            .iter()
+            .map(|(name, count)| format!("{name}: {count}"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    if summary.posts > 0 {
+        eprintln!(
+            " - {} {}",
+            "Ratings:".bold().blue(),
+            counts(&summary.ratings)
+        );
+        eprintln!(
+            " - {} {}",
+            "Extensions:".bold().blue(),
+            counts(&summary.extensions)
+        );
+    }
+
+    if removed > 0 {
+        eprintln!(
+            "{} {}",
+            removed.to_string().bold().red(),
+            "found posts with blacklisted tags were removed."
+                .bold()
+                .red()
+        );
+    }
+}
+
+fn print_server_summaries(out: &mut impl Write, summaries: &[ServerSummary]) -> Result<()> {
+    for summary in summaries {
+        write!(
+            out,
+            "{:<16} {} {}, {} {}",
+            format!("[{}]", summary.server.name).bold().green(),
+            summary.found.to_string().bold().blue(),
+            "found".bold(),
+            summary.duplicates.to_string().bold().yellow(),
+            "duplicates".bold(),
+        )?;
+
+        if summary.removed > 0 {
+            write!(
+                out,
+                ", {} {}",
+                summary.removed.to_string().bold().red(),
+                "removed by blacklist".bold().red()
+            )?;
+        }
+
+        writeln!(out)?;
+
+        if let Some(error) = &summary.error {
+            writeln!(out, "{:<16} {}", "", error.red())?;
+        }
+    }
+
+    Ok(())
 }
 
 fn print_servers() {
@@ -130,6 +368,10 @@ fn print_servers() {
             features.push("Pool Download");
         }
 
+        if ext_feat.contains(ExtractorFeatures::FavoritesDownload) {
+            features.push("Favorites Download");
+        }
+
         println!(
             "{:<16} - {}:\n - {} {}\n - {} {}\n - {} {}\n - {} {:?}\n",
             format!("[{}]", srv),